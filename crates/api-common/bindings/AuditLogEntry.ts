@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditAction } from "./AuditAction";
+
+export interface AuditLogEntry { actor: string, action: AuditAction, target: string, request?: string, createdAt: any, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface AuditLogQuery { actor?: string, target?: string, after?: bigint, before?: bigint, skip?: bigint, limit?: bigint, }
\ No newline at end of file
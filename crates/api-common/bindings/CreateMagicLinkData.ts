@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MagicLinkIntent } from "./MagicLinkIntent";
 
-export interface CreateMagicLinkData { email: string, redirectUri?: string, }
\ No newline at end of file
+export interface CreateMagicLinkData { email: string, redirectUri?: string, intent: MagicLinkIntent, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AppId } from "./AppId";
+
+export interface ExternalClientsQuery { app_id?: AppId, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Group } from "./Group";
+import type { GroupMemberArchive } from "./GroupMemberArchive";
+
+export interface GroupArchive { group: Group, members: Array<GroupMemberArchive>, }
\ No newline at end of file
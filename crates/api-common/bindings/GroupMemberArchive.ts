@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Project } from "./Project";
+import type { User } from "./User";
+
+export interface GroupMemberArchive { user: User, projects: Array<Project>, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GroupArchive } from "./GroupArchive";
+import type { ImportGroupOptions } from "./ImportGroupOptions";
+
+export interface ImportGroupData { archive: GroupArchive, options: ImportGroupOptions, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UsernameCollision } from "./UsernameCollision";
+
+export interface ImportGroupOptions { members: boolean, projects: boolean, onCollision: UsernameCollision, }
\ No newline at end of file
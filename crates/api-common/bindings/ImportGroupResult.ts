@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Group } from "./Group";
+
+export interface ImportGroupResult { group: Group, members: Record<string, string>, skipped: Array<string>, }
\ No newline at end of file
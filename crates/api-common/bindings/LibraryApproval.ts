@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PublishState } from "./PublishState";
+
+export interface LibraryApproval { owner: string, name: string, state: PublishState, }
\ No newline at end of file
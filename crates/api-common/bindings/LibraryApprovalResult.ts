@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LibraryMetadata } from "./LibraryMetadata";
+
+export interface LibraryApprovalResult { owner: string, name: string, library?: LibraryMetadata, error?: string, }
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { PublishState } from "./PublishState";
 
-export interface LibraryMetadata { owner: string, name: string, notes: string, state: PublishState, }
\ No newline at end of file
+export interface LibraryMetadata { owner: string, name: string, notes: string, state: PublishState, downloads: number, }
\ No newline at end of file
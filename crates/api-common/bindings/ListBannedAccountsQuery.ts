@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ListBannedAccountsQuery { username?: string, email?: string, after?: string, limit?: bigint, }
\ No newline at end of file
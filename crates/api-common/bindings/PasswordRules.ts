@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface PasswordRules { minLength: number, requireLowercase: boolean, requireUppercase: boolean, requireDigit: boolean, requireSymbol: boolean, }
\ No newline at end of file
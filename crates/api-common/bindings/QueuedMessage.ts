@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AppId } from "./AppId";
+
+export interface QueuedMessage { appId: AppId, address: string, content: object, createdAt: any, expiresAt: any, }
\ No newline at end of file
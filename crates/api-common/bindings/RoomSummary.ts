@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ProjectId } from "./ProjectId";
+
+export interface RoomSummary { projectId: ProjectId, owner: string, name: string, occupantCount: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Group } from "./Group";
+
+export interface ServiceHostUsage { users: Array<string>, groups: Array<Group>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface SessionInfo { id: string, createdAt: any, userAgent?: string, ipAddress?: string, current: boolean, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SentMessage } from "./SentMessage";
+
+export interface TraceMessages { messages: Array<SentMessage>, truncated: boolean, }
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { ClientId } from "./ClientId";
 
-export interface UpdateProjectData { name: string, clientId?: ClientId, }
\ No newline at end of file
+export interface UpdateProjectData { name: string, clientId?: ClientId, expectedVersion?: bigint, }
\ No newline at end of file
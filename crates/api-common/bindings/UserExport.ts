@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CollaborationInvite } from "./CollaborationInvite";
+import type { LibraryMetadata } from "./LibraryMetadata";
+import type { ProjectMetadata } from "./ProjectMetadata";
+import type { User } from "./User";
+
+export interface UserExport { user: User, projects: Array<ProjectMetadata>, libraries: Array<LibraryMetadata>, friends: Array<string>, collaborationInvites: Array<CollaborationInvite>, serviceSettings: Record<string, string>, }
\ No newline at end of file
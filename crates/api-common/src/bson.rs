@@ -1,7 +1,7 @@
 use crate::{
-    oauth, AppId, ClientId, FriendInvite, FriendLinkState, GroupId, InvitationState, LinkedAccount,
-    MagicLinkId, ProjectId, PublishState, RoleId, RoleMetadata, SaveState, ServiceHost,
-    ServiceHostScope, UserRole,
+    oauth, AppId, AuditAction, ClientId, CollabRole, FriendInvite, FriendLinkState, GroupId,
+    InvitationState, LinkedAccount, MagicLinkId, MagicLinkIntent, ProjectId, PublishState, RoleId,
+    RoleMetadata, SaveState, ServiceHost, ServiceHostScope, UserRole,
 };
 use bson::{doc, Bson, DateTime};
 
@@ -89,6 +89,24 @@ impl From<RoleMetadata> for Bson {
     }
 }
 
+impl From<CollabRole> for Bson {
+    fn from(role: CollabRole) -> Bson {
+        match role {
+            CollabRole::Editor => Bson::String("Editor".into()),
+            CollabRole::Viewer => Bson::String("Viewer".into()),
+        }
+    }
+}
+
+impl From<MagicLinkIntent> for Bson {
+    fn from(intent: MagicLinkIntent) -> Bson {
+        match intent {
+            MagicLinkIntent::Login => Bson::String("login".into()),
+            MagicLinkIntent::Reset => Bson::String("reset".into()),
+        }
+    }
+}
+
 impl From<PublishState> for Bson {
     fn from(state: PublishState) -> Bson {
         match state {
@@ -184,6 +202,21 @@ impl From<MagicLinkId> for Bson {
     }
 }
 
+impl From<AuditAction> for Bson {
+    fn from(action: AuditAction) -> Bson {
+        let name = match action {
+            AuditAction::BanUser => "BanUser",
+            AuditAction::UnbanUser => "UnbanUser",
+            AuditAction::DeleteUser => "DeleteUser",
+            AuditAction::SetProjectState => "SetProjectState",
+            AuditAction::SetLibraryState => "SetLibraryState",
+            AuditAction::UnauthorizeHost => "UnauthorizeHost",
+            AuditAction::Impersonate => "Impersonate",
+        };
+        Bson::String(name.into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,4 +236,55 @@ mod tests {
         let scope: Result<ServiceHostScope, _> = serde_json::from_str(&data);
         assert!(scope.is_ok());
     }
+
+    #[test]
+    fn test_friend_link_state_bson_matches_serde() {
+        let states = [
+            FriendLinkState::Pending,
+            FriendLinkState::Approved,
+            FriendLinkState::Rejected,
+            FriendLinkState::Blocked,
+        ];
+        for state in states {
+            let stored: Bson = state.clone().into();
+            assert_eq!(stored, bson::to_bson(&state).unwrap());
+
+            let parsed: FriendLinkState = bson::from_bson(stored).unwrap();
+            assert_eq!(parsed, state);
+        }
+    }
+
+    #[test]
+    fn test_friend_link_state_from_str() {
+        let states = [
+            FriendLinkState::Pending,
+            FriendLinkState::Approved,
+            FriendLinkState::Rejected,
+            FriendLinkState::Blocked,
+        ];
+        for state in states {
+            let stored: Bson = state.clone().into();
+            let name = stored.as_str().unwrap();
+            let parsed: FriendLinkState = name.parse().unwrap();
+            assert_eq!(parsed, state);
+        }
+    }
+
+    #[test]
+    fn test_save_state_bson_matches_serde() {
+        let states = [
+            (SaveState::Created, "Created"),
+            (SaveState::Transient, "Transient"),
+            (SaveState::Broken, "Broken"),
+            (SaveState::Saved, "Saved"),
+        ];
+        for (state, name) in states {
+            let stored: Bson = state.clone().into();
+            assert_eq!(stored, Bson::String(name.to_owned()));
+            assert_eq!(stored, bson::to_bson(&state).unwrap());
+
+            let parsed: SaveState = bson::from_bson(stored.clone()).unwrap();
+            assert_eq!(Bson::from(parsed), stored);
+        }
+    }
 }
@@ -1,6 +1,8 @@
 #[cfg(feature = "bson")]
 mod bson;
 pub mod oauth;
+pub mod s3;
+pub mod xml;
 
 use core::fmt;
 use derive_more::{Display, Error, FromStr};
@@ -24,6 +26,9 @@ pub struct ClientConfig {
     pub username: Option<String>,
     pub services_hosts: Vec<ServiceHost>,
     pub cloud_url: String,
+    /// Max size (in bytes) of messages sent over the network websocket
+    #[ts(optional)]
+    pub max_message_size: Option<usize>,
 }
 
 #[derive(Deserialize, Serialize, TS)]
@@ -46,6 +51,96 @@ pub struct User {
     pub linked_accounts: Vec<LinkedAccount>,
     #[ts(optional)]
     pub services_hosts: Option<Vec<ServiceHost>>,
+    /// When the account is scheduled to be deleted (if at all)
+    #[ts(skip)]
+    pub delete_at: Option<SystemTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ListUsersQuery {
+    /// Only include users with usernames after this one (for pagination)
+    #[ts(optional)]
+    pub after: Option<String>,
+    /// Max number of users to return
+    #[ts(optional)]
+    pub limit: Option<i64>,
+}
+
+/// Requirements for new passwords
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct PasswordRules {
+    /// Min number of characters
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Require a character which is neither a letter nor a digit
+    pub require_symbol: bool,
+}
+
+impl PasswordRules {
+    /// Check the password against the rules, returning the first rule it breaks
+    pub fn check(&self, password: &str) -> Result<(), PasswordRuleError> {
+        if password.chars().count() < self.min_length {
+            return Err(PasswordRuleError::TooShort {
+                min_length: self.min_length,
+            });
+        }
+
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            return Err(PasswordRuleError::MissingLowercase);
+        }
+
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            return Err(PasswordRuleError::MissingUppercase);
+        }
+
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(PasswordRuleError::MissingDigit);
+        }
+
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            return Err(PasswordRuleError::MissingSymbol);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum PasswordRuleError {
+    #[display(fmt = "Password must be at least {} characters long.", min_length)]
+    TooShort { min_length: usize },
+    #[display(fmt = "Password must contain a lowercase letter.")]
+    MissingLowercase,
+    #[display(fmt = "Password must contain an uppercase letter.")]
+    MissingUppercase,
+    #[display(fmt = "Password must contain a digit.")]
+    MissingDigit,
+    #[display(fmt = "Password must contain a symbol.")]
+    MissingSymbol,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ListBannedAccountsQuery {
+    /// Only include accounts with usernames containing this (case insensitive)
+    #[ts(optional)]
+    pub username: Option<String>,
+    /// Only include accounts with emails containing this (case insensitive)
+    #[ts(optional)]
+    pub email: Option<String>,
+    /// Only include accounts with usernames after this one (for pagination)
+    #[ts(optional)]
+    pub after: Option<String>,
+    /// Max number of accounts to return
+    #[ts(optional)]
+    pub limit: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, TS, Clone)]
@@ -107,6 +202,33 @@ pub struct SentMessage {
     pub content: serde_json::Value,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct TraceMessagesQuery {
+    /// Only include messages after this time (milliseconds since the unix epoch)
+    #[ts(optional)]
+    pub after: Option<i64>,
+    /// Only include messages before this time (milliseconds since the unix epoch)
+    #[ts(optional)]
+    pub before: Option<i64>,
+    /// Number of messages to skip (for pagination)
+    #[ts(optional)]
+    pub skip: Option<u64>,
+    /// Max number of messages to return
+    #[ts(optional)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct TraceMessages {
+    pub messages: Vec<SentMessage>,
+    /// Whether more messages matched the query than were returned
+    pub truncated: bool,
+}
+
 #[derive(TS, Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -119,7 +241,7 @@ pub struct OccupantInvite {
 }
 
 #[derive(Debug, Display, Error, TS)]
-#[display(fmt = "Unable to parse user role. Expected admin, moderator, or user.")]
+#[display(fmt = "Unable to parse user role. Expected admin, moderator, teacher, or user.")]
 #[ts(export)]
 pub struct UserRoleError;
 
@@ -168,6 +290,123 @@ pub struct LoginRequest {
     pub credentials: Credentials,
     #[ts(optional)]
     pub client_id: Option<ClientId>, // TODO: add a secret token for the client?
+    /// TOTP (or backup) code for users with two-factor authentication enabled
+    #[serde(default)]
+    #[ts(optional)]
+    pub two_factor_code: Option<String>,
+    /// Cancel the pending deletion of the account. Logging in is refused for
+    /// accounts scheduled for deletion otherwise.
+    #[serde(default)]
+    pub cancel_deletion: bool,
+}
+
+/// Header identifying the user on whose behalf a request is made (if different
+/// from the logged in user)
+pub const ON_BEHALF_OF_HEADER: &str = "X-On-Behalf-Of";
+
+/// Header identifying a request so errors can be correlated with the server logs
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Body of error responses from the server
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ErrorBody {
+    /// Machine-readable error code (eg, "ProjectNotFoundError")
+    pub code: String,
+    /// Description of the error which can be shown to the user
+    pub message: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub request_id: Option<String>,
+}
+
+/// Privileged (moderator or admin) actions which are recorded in the audit log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum AuditAction {
+    BanUser,
+    UnbanUser,
+    DeleteUser,
+    SetProjectState,
+    SetLibraryState,
+    UnauthorizeHost,
+    /// A request made on behalf of another user
+    Impersonate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AuditLogEntry {
+    /// Username of the user who performed the action
+    pub actor: String,
+    pub action: AuditAction,
+    /// The affected user, project, library, or service host
+    pub target: String,
+    /// Method and path of the request (for impersonated requests)
+    #[serde(default)]
+    #[ts(optional)]
+    pub request: Option<String>,
+    #[ts(type = "any")] // FIXME
+    pub created_at: SystemTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AuditLogQuery {
+    #[ts(optional)]
+    pub actor: Option<String>,
+    #[ts(optional)]
+    pub target: Option<String>,
+    /// Only include entries at or after this time (milliseconds since the unix epoch)
+    #[ts(optional)]
+    pub after: Option<i64>,
+    /// Only include entries before this time (milliseconds since the unix epoch)
+    #[ts(optional)]
+    pub before: Option<i64>,
+    /// Number of entries to skip (for pagination)
+    #[ts(optional)]
+    pub skip: Option<u64>,
+    /// Max number of entries to return
+    #[ts(optional)]
+    pub limit: Option<i64>,
+}
+
+/// An active login session for a user
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SessionInfo {
+    pub id: String,
+    #[ts(type = "any")] // FIXME
+    pub created_at: SystemTime,
+    #[ts(optional)]
+    pub user_agent: Option<String>,
+    #[ts(optional)]
+    pub ip_address: Option<String>,
+    /// Whether this is the session used to make the request
+    pub current: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct RevokeSessionsData {
+    /// The session to revoke. All sessions are revoked if unset.
+    #[ts(optional)]
+    pub session_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct TwoFactorEnrollment {
+    /// URI to add the account to an authenticator app (`otpauth://...`)
+    pub provisioning_uri: String,
+    /// Single-use codes which can be used in place of a TOTP code
+    pub backup_codes: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, TS)]
@@ -208,7 +447,7 @@ pub struct FriendLink {
     pub updated_at: SystemTime,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, TS)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, TS)]
 #[ts(export)]
 pub enum FriendLinkState {
     Pending,
@@ -230,7 +469,8 @@ impl FromStr for FriendLinkState {
     type Err = ParseFriendLinkStateError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        // accept the stored (serialized) names as well as lowercase ones
+        match s.to_lowercase().as_str() {
             "pending" => Ok(FriendLinkState::Pending),
             "approved" => Ok(FriendLinkState::Approved),
             "rejected" => Ok(FriendLinkState::Rejected),
@@ -251,6 +491,27 @@ pub struct FriendInvite {
     pub created_at: SystemTime,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct FriendSummary {
+    pub friends: usize,
+    pub online: usize,
+    pub pending_invites: usize,
+}
+
+/// Friendship status between a user and another user, from the perspective
+/// of the first user.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum FriendshipStatus {
+    Friends,
+    InvitePendingOutgoing,
+    InvitePendingIncoming,
+    Blocked,
+    None,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Display, Hash, TS)]
 #[ts(export)]
 pub struct ProjectId(String);
@@ -286,11 +547,31 @@ pub struct ProjectMetadata {
     pub updated: SystemTime,
     pub state: PublishState,
     pub collaborators: std::vec::Vec<String>,
+    /// Roles of the collaborators. Collaborators without a role are editors.
+    #[serde(default)]
+    pub collaborator_roles: HashMap<String, CollabRole>,
     pub network_traces: Vec<NetworkTraceMetadata>,
     #[ts(type = "any")] // FIXME
     pub origin_time: SystemTime,
     pub save_state: SaveState,
     pub roles: HashMap<RoleId, RoleMetadata>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Version of the project used to detect concurrent updates. This is
+    /// incremented each time the project is edited.
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// Permissions granted to a collaborator on a project
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum CollabRole {
+    /// Can view and edit the project
+    #[default]
+    Editor,
+    /// Can view the project but cannot make changes
+    Viewer,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, TS)]
@@ -310,7 +591,7 @@ pub struct RoleMetadata {
     pub media: String,
 }
 
-#[derive(Deserialize, Serialize, TS)]
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct Project {
@@ -365,6 +646,16 @@ impl RoleData {
     }
 }
 
+/// A saved snapshot of a role which can be restored later
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct RoleVersion {
+    pub id: String,
+    #[ts(type = "any")] // FIXME
+    pub saved_at: SystemTime,
+}
+
 #[derive(Deserialize, Serialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -435,6 +726,14 @@ impl<'de> Visitor<'de> for AppIdVisitor {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[ts(export)]
+pub struct ExternalClientsQuery {
+    /// Only include clients for this app (case-insensitive)
+    #[ts(optional)]
+    pub app_id: Option<AppId>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -451,6 +750,15 @@ pub struct CreateLibraryData {
     pub blocks: String,
 }
 
+#[derive(Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct UpdateLibraryData {
+    #[ts(optional)]
+    pub new_name: Option<String>,
+    #[ts(optional)]
+    pub notes: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, TS)]
 #[ts(export)]
 pub enum PublishState {
@@ -467,6 +775,8 @@ pub struct LibraryMetadata {
     pub name: String,
     pub notes: String,
     pub state: PublishState,
+    #[serde(default)]
+    pub downloads: u32,
 }
 
 impl LibraryMetadata {
@@ -481,10 +791,34 @@ impl LibraryMetadata {
             name,
             notes: notes.unwrap_or_default(),
             state,
+            downloads: 0,
         }
     }
 }
 
+/// Moderation decision for a library pending approval
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct LibraryApproval {
+    pub owner: String,
+    pub name: String,
+    pub state: PublishState,
+}
+
+/// Outcome of a single decision within a bulk library approval
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct LibraryApprovalResult {
+    pub owner: String,
+    pub name: String,
+    /// The updated library if the decision was applied
+    #[ts(optional)]
+    pub library: Option<LibraryMetadata>,
+    /// The reason the decision could not be applied
+    #[ts(optional)]
+    pub error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -568,6 +902,20 @@ pub struct UpdateProjectData {
     pub name: String,
     #[ts(optional)]
     pub client_id: Option<ClientId>,
+    /// Reject the update if the project has changed since this version
+    #[ts(optional)]
+    #[serde(default)]
+    pub expected_version: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Default, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct DuplicateProjectData {
+    /// Name of the copy. Defaults to the name of the original project.
+    #[ts(optional)]
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, TS)]
@@ -577,6 +925,10 @@ pub struct UpdateRoleData {
     pub name: String,
     #[ts(optional)]
     pub client_id: Option<ClientId>,
+    /// Reject the update if the project has changed since this version
+    #[ts(optional)]
+    #[serde(default)]
+    pub expected_version: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, TS)]
@@ -646,6 +998,17 @@ pub struct RoomState {
     pub version: u64,
 }
 
+/// Summary of an active room (used to monitor the network)
+#[derive(Deserialize, Serialize, Clone, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct RoomSummary {
+    pub project_id: ProjectId,
+    pub owner: String,
+    pub name: String,
+    pub occupant_count: usize,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, TS)]
 #[ts(export)]
 pub struct RoleState {
@@ -679,6 +1042,15 @@ pub struct AuthorizedServiceHost {
     pub visibility: ServiceHostScope,
 }
 
+/// Users and groups which have registered an authorized service host.
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ServiceHostUsage {
+    pub users: Vec<String>,
+    pub groups: Vec<Group>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -697,6 +1069,90 @@ pub struct ClientInfo {
     pub state: Option<ClientState>,
 }
 
+/// Everything stored about a user (for data export requests)
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct UserExport {
+    pub user: User,
+    pub projects: Vec<ProjectMetadata>,
+    pub libraries: Vec<LibraryMetadata>,
+    pub friends: Vec<String>,
+    /// Collaboration invites sent or received by the user
+    pub collaboration_invites: Vec<CollaborationInvite>,
+    /// Service settings owned by the user (keyed by service host)
+    pub service_settings: HashMap<String, String>,
+}
+
+/// Archive of a group and its members (eg, at the end of a term or to move
+/// a class to another instance). The latest contents of the members' projects
+/// are included so the archive can be imported on any instance.
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct GroupArchive {
+    pub group: Group,
+    pub members: Vec<GroupMemberArchive>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct GroupMemberArchive {
+    pub user: User,
+    pub projects: Vec<Project>,
+}
+
+/// How to handle members whose username is already taken when importing a group
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum UsernameCollision {
+    /// Do not import the member (or their projects)
+    Skip,
+    /// Import the member using the first available numeric suffix (eg, "user_2")
+    Suffix,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ImportGroupOptions {
+    /// Create new accounts for the members of the group
+    pub members: bool,
+    /// Create the archived projects of the imported members
+    pub projects: bool,
+    pub on_collision: UsernameCollision,
+}
+
+impl Default for ImportGroupOptions {
+    fn default() -> Self {
+        Self {
+            members: true,
+            projects: true,
+            on_collision: UsernameCollision::Skip,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ImportGroupData {
+    pub archive: GroupArchive,
+    pub options: ImportGroupOptions,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ImportGroupResult {
+    pub group: Group,
+    /// Usernames of the imported members keyed by their username in the archive
+    pub members: HashMap<String, String>,
+    /// Usernames of the archived members which were not imported
+    pub skipped: Vec<String>,
+}
+
 /// Service settings for a given user categorized by origin
 #[derive(Deserialize, Serialize, Debug, Clone, TS)]
 #[ts(export)]
@@ -723,6 +1179,21 @@ pub struct SendMessage {
     pub content: Value,
 }
 
+/// Message waiting to be delivered to an external client which is offline
+#[derive(Deserialize, Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct QueuedMessage {
+    pub app_id: AppId,
+    pub address: String,
+    #[ts(type = "object")]
+    pub content: Value,
+    #[ts(type = "any")] // FIXME
+    pub created_at: SystemTime,
+    #[ts(type = "any")] // FIXME
+    pub expires_at: SystemTime,
+}
+
 /// Send message request (for authorized services)
 #[derive(Deserialize, Serialize, Debug, Clone, TS)]
 #[serde(rename_all = "camelCase")]
@@ -793,13 +1264,28 @@ pub struct MagicLinkLoginData {
     pub redirect_uri: Option<String>,
 }
 
+/// What a magic link can be used for
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum MagicLinkIntent {
+    /// Log in as the selected user
+    #[default]
+    Login,
+    /// Set a new password for the selected user (without logging in)
+    Reset,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct CreateMagicLinkData {
     pub email: String,
+    /// Redirect the user to this URL after login. Unused when resetting a password.
     #[ts(optional)]
     pub redirect_uri: Option<String>,
+    #[serde(default)]
+    pub intent: MagicLinkIntent,
 }
 
 #[cfg(test)]
@@ -807,6 +1293,55 @@ mod tests {
     use super::*;
     use uuid::Uuid;
 
+    #[test]
+    fn serialize_magic_link_intent() {
+        let data = CreateMagicLinkData {
+            email: "user@netsblox.org".into(),
+            redirect_uri: None,
+            intent: MagicLinkIntent::Reset,
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["intent"], "reset");
+
+        let data: CreateMagicLinkData =
+            serde_json::from_str(r#"{"email": "user@netsblox.org"}"#).unwrap();
+        assert_eq!(data.intent, MagicLinkIntent::Login);
+    }
+
+    #[test]
+    fn check_password_rules() {
+        let rules = PasswordRules {
+            min_length: 8,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+        };
+
+        assert_eq!(
+            rules.check("aB1!"),
+            Err(PasswordRuleError::TooShort { min_length: 8 })
+        );
+        assert_eq!(
+            rules.check("ABCDEFG1!"),
+            Err(PasswordRuleError::MissingLowercase)
+        );
+        assert_eq!(
+            rules.check("abcdefg1!"),
+            Err(PasswordRuleError::MissingUppercase)
+        );
+        assert_eq!(
+            rules.check("abcdefgH!"),
+            Err(PasswordRuleError::MissingDigit)
+        );
+        assert_eq!(
+            rules.check("abcdefgH1"),
+            Err(PasswordRuleError::MissingSymbol)
+        );
+        assert_eq!(rules.check("abcdefgH1!"), Ok(()));
+        assert_eq!(PasswordRules::default().check(""), Ok(()));
+    }
+
     #[test]
     fn deserialize_project_id() {
         let project_id_str = &format!("\"{}\"", Uuid::new_v4());
@@ -838,6 +1373,29 @@ mod tests {
         assert!(UserRole::Admin == UserRole::Admin);
     }
 
+    #[test]
+    fn parse_all_userroles() {
+        let names = ["user", "teacher", "moderator", "admin"];
+        let parsed: Vec<UserRole> = names.iter().map(|name| name.parse().unwrap()).collect();
+        let deserialized: Vec<UserRole> = names
+            .iter()
+            .map(|name| serde_json::from_str(&format!("\"{}\"", name)).unwrap())
+            .collect();
+
+        assert_eq!(parsed, deserialized);
+        assert_eq!(
+            parsed,
+            vec![
+                UserRole::User,
+                UserRole::Teacher,
+                UserRole::Moderator,
+                UserRole::Admin
+            ]
+        );
+        // roles are listed from least to most privileged
+        assert!(parsed.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
     #[test]
     fn serialize_userroles_as_strings() {
         let role_str = serde_json::to_string(&UserRole::User).unwrap();
@@ -866,4 +1424,25 @@ mod tests {
     fn publish_state_public_eq() {
         assert!(PublishState::Public == PublishState::Public);
     }
+
+    #[test]
+    fn service_settings_groups_round_trip() {
+        let group_id = GroupId::new(Uuid::new_v4().to_string());
+        let settings = ServiceSettings {
+            user: Some("userSettings".into()),
+            member: None,
+            groups: HashMap::from([(group_id.clone(), "groupSettings".into())]),
+        };
+
+        let json = serde_json::to_string(&settings).unwrap();
+        let settings: ServiceSettings = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(settings.user.as_deref(), Some("userSettings"));
+        assert!(settings.member.is_none());
+        assert_eq!(settings.groups.len(), 1);
+        assert_eq!(
+            settings.groups.get(&group_id).map(|s| s.as_str()),
+            Some("groupSettings")
+        );
+    }
 }
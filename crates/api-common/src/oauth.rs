@@ -78,6 +78,16 @@ pub struct Token {
     pub created_at: SystemTime,
 }
 
+/// Request to authorize a client on behalf of a user. The authorization code
+/// is sent to the redirect URI along with the given state.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AuthorizationRequest {
+    pub client_id: ClientId,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub state: String,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct CreateTokenParams {
     pub code: Option<String>,
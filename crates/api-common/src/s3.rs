@@ -0,0 +1,220 @@
+use derive_more::{Display, Error};
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum S3KeyError {
+    #[display(fmt = "S3 key cannot be empty.")]
+    Empty,
+    #[display(fmt = "S3 key cannot start with \"/\".")]
+    LeadingSlash,
+    #[display(fmt = "S3 key cannot contain empty, \".\" or \"..\" segments.")]
+    InvalidSegment,
+    #[display(fmt = "S3 key cannot contain backslashes or control characters.")]
+    InvalidCharacter,
+}
+
+/// Key of an object in S3. Keys are often built from user-controlled names (such
+/// as owners or project names) so they are checked to ensure they cannot escape
+/// the intended prefix.
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display(fmt = "{}", _0)]
+pub struct S3Key(String);
+
+impl S3Key {
+    pub fn new_checked(key: impl Into<String>) -> Result<Self, S3KeyError> {
+        let key = key.into();
+        if key.is_empty() {
+            return Err(S3KeyError::Empty);
+        }
+
+        if key.starts_with('/') {
+            return Err(S3KeyError::LeadingSlash);
+        }
+
+        if key.chars().any(|c| c == '\\' || c.is_control()) {
+            return Err(S3KeyError::InvalidCharacter);
+        }
+
+        if key
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+        {
+            return Err(S3KeyError::InvalidSegment);
+        }
+
+        Ok(Self(key))
+    }
+
+    /// Create a key from the given segments. Segments cannot contain a "/" so
+    /// a name cannot add levels to the key.
+    pub fn from_segments(segments: &[&str]) -> Result<Self, S3KeyError> {
+        if segments.iter().any(|segment| segment.contains('/')) {
+            return Err(S3KeyError::InvalidSegment);
+        }
+
+        Self::new_checked(segments.join("/"))
+    }
+
+    /// Create a key for the given name within this key (as a prefix)
+    pub fn join(&self, name: &str) -> Result<Self, S3KeyError> {
+        if name.contains('/') {
+            return Err(S3KeyError::InvalidSegment);
+        }
+
+        Self::new_checked(format!("{}/{}", self.0, name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Key of the directory containing the code and media for the given role. Each
+/// name is encoded so it is a single (safe) segment of the key.
+pub fn s3_key_for_role(owner: &str, project: &str, role: &str) -> Result<S3Key, S3KeyError> {
+    let is_guest = owner.starts_with('_');
+    let top_level = if is_guest { "guests" } else { "users" };
+    let owner = encode_segment(owner);
+    let project = encode_segment(project);
+    let role = encode_segment(role);
+
+    S3Key::from_segments(&[top_level, &owner, &project, &role])
+}
+
+/// Percent-encode the characters which are not allowed in a segment of a key.
+/// "." and ".." are encoded entirely so they cannot be used for traversal.
+pub fn encode_segment(name: &str) -> String {
+    if name == "." || name == ".." {
+        return name.replace('.', "%2E");
+    }
+
+    let mut encoded = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '%' || c == '/' || c == '\\' || c.is_control() {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Decode a segment encoded with [`encode_segment`].
+pub fn decode_segment(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl From<S3Key> for String {
+    fn from(key: S3Key) -> String {
+        key.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_key() {
+        let key = S3Key::from_segments(&["users", "brian", "My Project", "role1"]).unwrap();
+        assert_eq!(key.as_str(), "users/brian/My Project/role1");
+
+        let key = key.join("code.xml").unwrap();
+        assert_eq!(key.as_str(), "users/brian/My Project/role1/code.xml");
+    }
+
+    #[test]
+    fn test_reject_traversal() {
+        let names = ["..", ".", "../../etc", "a/../b"];
+        for name in names {
+            let result = S3Key::new_checked(format!("users/brian/{}", name));
+            assert_eq!(result, Err(S3KeyError::InvalidSegment), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_reject_slash_in_segment() {
+        let result = S3Key::from_segments(&["users", "brian", "../../guests/alice", "role"]);
+        assert_eq!(result, Err(S3KeyError::InvalidSegment));
+
+        let result = S3Key::from_segments(&["users", "brian", "project/role", "role"]);
+        assert_eq!(result, Err(S3KeyError::InvalidSegment));
+    }
+
+    #[test]
+    fn test_reject_leading_slash() {
+        let result = S3Key::new_checked("/users/brian");
+        assert_eq!(result, Err(S3KeyError::LeadingSlash));
+    }
+
+    #[test]
+    fn test_reject_empty() {
+        assert_eq!(S3Key::new_checked(""), Err(S3KeyError::Empty));
+
+        let result = S3Key::from_segments(&["users", "", "project"]);
+        assert_eq!(result, Err(S3KeyError::InvalidSegment));
+    }
+
+    #[test]
+    fn test_role_key_escapes_names() {
+        let names = ["..", ".", "../../guests/alice", "a/b", "back\\slash", "50%"];
+        for name in names {
+            let key = s3_key_for_role("brian", name, name).unwrap();
+            let segments: Vec<_> = key.as_str().split('/').collect();
+            assert_eq!(segments.len(), 4, "{}", key);
+            assert_eq!(segments[0], "users");
+            assert_eq!(segments[1], "brian");
+            assert_eq!(decode_segment(segments[2]).unwrap(), name);
+            assert_eq!(decode_segment(segments[3]).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_role_key_unicode() {
+        let key = s3_key_for_role("brian", "Projet élève", "角色").unwrap();
+        assert_eq!(key.as_str(), "users/brian/Projet élève/角色");
+    }
+
+    #[test]
+    fn test_role_key_guest() {
+        let key = s3_key_for_role("_client_123", "myProject", "role1").unwrap();
+        assert_eq!(key.as_str(), "guests/_client_123/myProject/role1");
+    }
+
+    #[test]
+    fn test_role_key_empty_name() {
+        let result = s3_key_for_role("brian", "", "role1");
+        assert_eq!(result, Err(S3KeyError::InvalidSegment));
+    }
+
+    #[test]
+    fn test_decode_segment() {
+        assert_eq!(decode_segment("a%2Fb").unwrap(), "a/b");
+        assert_eq!(decode_segment("%2E%2E").unwrap(), "..");
+        assert!(decode_segment("bad%2").is_none());
+        assert!(decode_segment("bad%zz").is_none());
+    }
+
+    #[test]
+    fn test_reject_invalid_characters() {
+        let result = S3Key::new_checked("users\\brian");
+        assert_eq!(result, Err(S3KeyError::InvalidCharacter));
+
+        let result = S3Key::new_checked("users/brian\n/project");
+        assert_eq!(result, Err(S3KeyError::InvalidCharacter));
+    }
+}
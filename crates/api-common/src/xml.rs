@@ -0,0 +1,243 @@
+use derive_more::{Display, Error};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+use crate::RoleData;
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[display(fmt = "Invalid XML: {}", msg)]
+    InvalidXml { msg: String },
+    #[display(fmt = "No roles found.")]
+    NoRoles,
+    #[display(fmt = "Role is missing a name.")]
+    MissingRoleName,
+    #[display(fmt = "Roles cannot be nested.")]
+    NestedRole,
+    #[display(fmt = "Role \"{}\" is missing its project.", name)]
+    MissingProject { name: String },
+    #[display(fmt = "Role \"{}\" is missing its media.", name)]
+    MissingMedia { name: String },
+}
+
+/// A role whose code and media spans are being found
+#[derive(Default)]
+struct PartialRole {
+    name: Option<String>,
+    code_start: Option<usize>,
+    media_start: Option<usize>,
+    media_end: Option<usize>,
+}
+
+impl PartialRole {
+    fn into_role(self, xml: &str) -> Result<RoleData, ParseError> {
+        let name = self.name.ok_or(ParseError::MissingRoleName)?;
+        let code_start = self.code_start;
+        let media = self.media_start.zip(self.media_end);
+        match (code_start, media) {
+            (Some(code_start), Some((media_start, media_end))) if code_start < media_start => {
+                Ok(RoleData {
+                    code: xml[code_start..media_start].to_owned(),
+                    media: xml[media_start..media_end].to_owned(),
+                    name,
+                })
+            }
+            (None, _) => Err(ParseError::MissingProject { name }),
+            // the media must follow the project
+            (Some(_), _) => Err(ParseError::MissingMedia { name }),
+        }
+    }
+}
+
+/// Extract the roles from the XML for a project. Each role contains the
+/// project (code) followed by its media:
+///
+/// `<room name="..."><role name="..."><project>...</project><media>...</media></role></room>`
+pub fn roles_from_project_xml(xml: &str) -> Result<Vec<RoleData>, ParseError> {
+    let mut roles = Vec::new();
+    let mut role: Option<PartialRole> = None;
+    // depth of the current element within the role (the role itself is 0)
+    let mut depth = 0;
+    let mut in_role_tag = false;
+    let mut in_media = false;
+
+    for token in Tokenizer::from(xml) {
+        let token = token.map_err(|err| ParseError::InvalidXml {
+            msg: err.to_string(),
+        })?;
+
+        match token {
+            Token::ElementStart { local, span, .. } => {
+                let is_role = local.as_str() == "role";
+                match role.as_mut() {
+                    Some(_) if is_role => return Err(ParseError::NestedRole),
+                    Some(partial) => {
+                        if depth == 0 {
+                            if local.as_str() == "media" {
+                                partial.media_start = Some(span.start());
+                                in_media = true;
+                            } else if partial.code_start.is_none() {
+                                partial.code_start = Some(span.start());
+                            }
+                        }
+                        depth += 1;
+                    }
+                    None if is_role => {
+                        role = Some(PartialRole::default());
+                        in_role_tag = true;
+                    }
+                    None => {}
+                }
+            }
+            Token::Attribute { local, value, .. } => {
+                if let (true, Some(partial)) = (in_role_tag, role.as_mut()) {
+                    if local.as_str() == "name" {
+                        partial.name = Some(value.as_str().to_owned());
+                    }
+                }
+            }
+            Token::ElementEnd { end, span } => {
+                let is_role_tag = in_role_tag;
+                in_role_tag = false;
+
+                let closes_element = !matches!(end, ElementEnd::Open);
+                match role.take() {
+                    Some(partial) if closes_element && (is_role_tag || depth == 0) => {
+                        roles.push(partial.into_role(xml)?);
+                    }
+                    Some(mut partial) => {
+                        if closes_element {
+                            depth -= 1;
+                            if depth == 0 && in_media {
+                                partial.media_end = Some(span.end());
+                                in_media = false;
+                            }
+                        }
+                        role = Some(partial);
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if roles.is_empty() {
+        return Err(ParseError::NoRoles);
+    }
+
+    Ok(roles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role_xml(name: &str) -> String {
+        format!(
+            "<role name=\"{}\"><project name=\"{}\"><scripts></scripts></project><media name=\"{}\"><costume/></media></role>",
+            name, name, name
+        )
+    }
+
+    #[test]
+    fn test_single_role() {
+        let xml = format!("<room name=\"myProject\">{}</room>", role_xml("myRole"));
+        let roles = roles_from_project_xml(&xml).unwrap();
+
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0].name, "myRole");
+        assert_eq!(
+            roles[0].code,
+            "<project name=\"myRole\"><scripts></scripts></project>"
+        );
+        assert_eq!(roles[0].media, "<media name=\"myRole\"><costume/></media>");
+    }
+
+    #[test]
+    fn test_multiple_roles() {
+        let xml = format!(
+            "<room name=\"myProject\">{}{}</room>",
+            role_xml("r1"),
+            role_xml("r2")
+        );
+        let roles = roles_from_project_xml(&xml).unwrap();
+
+        let names: Vec<_> = roles.iter().map(|role| role.name.as_str()).collect();
+        assert_eq!(names, vec!["r1", "r2"]);
+        assert_eq!(roles[1].media, "<media name=\"r2\"><costume/></media>");
+        assert_eq!(roles[1].to_xml(), role_xml("r2"));
+    }
+
+    #[test]
+    fn test_empty_media() {
+        let xml = "<room><role name=\"r1\"><project></project><media/></role></room>";
+        let roles = roles_from_project_xml(xml).unwrap();
+
+        assert_eq!(roles[0].code, "<project></project>");
+        assert_eq!(roles[0].media, "<media/>");
+    }
+
+    #[test]
+    fn test_code_containing_media() {
+        let code = concat!(
+            "<project name=\"media\">",
+            "<!-- the </media> element follows -->",
+            "<notes>media</notes>",
+            "<scripts><block s=\"media\"/><media><costume/></media></scripts>",
+            "</project>"
+        );
+        let media = "<media name=\"r1\"><costume name=\"media\"/></media>";
+        let xml = format!("<room><role name=\"r1\">{}{}</role></room>", code, media);
+        let roles = roles_from_project_xml(&xml).unwrap();
+
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0].code, code);
+        assert_eq!(roles[0].media, media);
+    }
+
+    #[test]
+    fn test_invalid_xml() {
+        let result = roles_from_project_xml("<room><role name=");
+        assert!(matches!(result, Err(ParseError::InvalidXml { .. })));
+    }
+
+    #[test]
+    fn test_no_roles() {
+        let result = roles_from_project_xml("<notAProject/>");
+        assert_eq!(result.unwrap_err(), ParseError::NoRoles);
+    }
+
+    #[test]
+    fn test_media_before_project() {
+        let xml = "<room><role name=\"r1\"><media></media><project></project></role></room>";
+        let result = roles_from_project_xml(xml);
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::MissingMedia { name: "r1".into() }
+        );
+    }
+
+    #[test]
+    fn test_missing_media() {
+        let xml = "<room><role name=\"r1\"><project></project></role></room>";
+        let result = roles_from_project_xml(xml);
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::MissingMedia { name: "r1".into() }
+        );
+    }
+
+    #[test]
+    fn test_missing_role_name() {
+        let xml = "<room><role><project></project><media></media></role></room>";
+        let result = roles_from_project_xml(xml);
+        assert_eq!(result.unwrap_err(), ParseError::MissingRoleName);
+    }
+
+    #[test]
+    fn test_nested_role() {
+        let xml = "<room><role name=\"r1\"><role name=\"r2\"></role></role></room>";
+        let result = roles_from_project_xml(xml);
+        assert_eq!(result.unwrap_err(), ParseError::NestedRole);
+    }
+}
@@ -1,17 +1,115 @@
+use std::fmt;
+
 use derive_more::Display;
+use netsblox_api_common::ErrorBody;
+
+/// Error reported by the server. Older servers (and proxies) respond with a
+/// plain text message so the error code may not be available.
+#[derive(Debug, Clone, Default)]
+pub struct ServerError {
+    /// Machine-readable error code (eg, "ProjectNotFoundError")
+    pub code: Option<String>,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match &self.request_id {
+            Some(id) if !self.message.contains(id.as_str()) => write!(f, " (Request ID: {})", id),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<ErrorBody> for ServerError {
+    fn from(body: ErrorBody) -> Self {
+        Self {
+            code: Some(body.code),
+            message: body.message,
+            request_id: body.request_id,
+        }
+    }
+}
+
+impl From<String> for ServerError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for ServerError {
+    fn from(message: &str) -> Self {
+        message.to_owned().into()
+    }
+}
 
 #[derive(Debug, Display)]
 pub enum Error {
     #[display(fmt = "{}", _0)]
-    BadRequestError(String),
+    BadRequestError(ServerError),
     #[display(fmt = "Login required.")]
     LoginRequiredError,
     #[display(fmt = "Unauthorized: {}", _0)]
-    PermissionsError(String),
+    PermissionsError(ServerError),
+    #[display(fmt = "{}", _0)]
+    NotFoundError(ServerError),
     #[display(fmt = "{}", _0)]
-    NotFoundError(String),
-    #[display(fmt = "Internal server error occurred")]
-    InternalServerError,
+    ConflictError(ServerError),
+    #[display(fmt = "{}", _0)]
+    TooManyRequestsError(ServerError),
+    #[display(fmt = "{}", _0)]
+    InternalServerError(ServerError),
+    #[display(fmt = "Unexpected response ({}): {}", _0, _1)]
+    UnexpectedStatusError(u16, ServerError),
+    #[display(fmt = "Invalid cloud URL: {}", _0)]
+    InvalidUrlError(String),
+    #[display(fmt = "An app ID must be configured to connect.")]
+    MissingAppIdError,
+    #[display(fmt = "Magic link has expired or has already been used.")]
+    MagicLinkExpiredError,
+    #[display(fmt = "Invalid OAuth redirect: {}", _0)]
+    InvalidRedirectError(String),
+    #[display(fmt = "OAuth state does not match the authorization request.")]
+    OAuthStateMismatchError,
+    #[display(fmt = "Message too large ({} bytes). Max size is {} bytes.", _0, _1)]
+    MessageTooLargeError(usize, usize),
+    #[display(fmt = "Request failed: {}", _0)]
     RequestError(reqwest::Error),
+    #[display(fmt = "Unable to parse response: {}", _0)]
+    ParseResponseFailedError(reqwest::Error),
+    #[display(fmt = "Unable to parse response: {}", _0)]
+    InvalidResponseError(serde_json::Error),
+    #[display(fmt = "No session cookie received.")]
+    MissingCookieError,
+    #[display(fmt = "Response too large. Max size is {} bytes.", _0)]
+    ResponseTooLargeError(usize),
+    #[display(fmt = "Unable to connect: {}", _0)]
+    WebSocketConnectError(tokio_tungstenite::tungstenite::Error),
+    #[display(fmt = "Unable to send message: {}", _0)]
     WebSocketSendError(tokio_tungstenite::tungstenite::Error),
+    #[display(fmt = "Unable to receive message: {}", _0)]
+    WebSocketReceiveError(tokio_tungstenite::tungstenite::Error),
+    #[display(fmt = "Invalid message: {}", _0)]
+    InvalidMessageError(serde_json::Error),
+}
+
+impl Error {
+    /// Machine-readable code of the error reported by the server (if any)
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::BadRequestError(err)
+            | Error::PermissionsError(err)
+            | Error::NotFoundError(err)
+            | Error::ConflictError(err)
+            | Error::TooManyRequestsError(err)
+            | Error::InternalServerError(err)
+            | Error::UnexpectedStatusError(_, err) => err.code.as_deref(),
+            _ => None,
+        }
+    }
 }
@@ -1,19 +1,31 @@
 pub mod common;
 pub mod error;
+mod request;
 
 use crate::common::*;
-use futures_util::SinkExt;
+use futures_util::{future, stream, SinkExt, Stream, StreamExt, TryStreamExt};
 use netsblox_api_common::{
     CreateGroupData, CreateMagicLinkData, ServiceHostScope, UpdateGroupData, UpdateUserData,
 };
-use reqwest::{self, Method, RequestBuilder, Response};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use request::Request;
+use reqwest::{self, Method, Response};
 use serde::{Deserialize, Serialize};
 pub use serde_json;
 use serde_json::{json, Value};
+use std::time::Duration;
 use tokio::net::TcpStream;
-use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{self, Message};
 use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 
+/// Number of users requested at a time when streaming users
+const USERS_PAGE_SIZE: i64 = 100;
+
+/// Default max size (in bytes) of a response body read into memory, such as
+/// project or library XML
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 50 * 1024 * 1024;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub app_id: Option<AppId>,
@@ -33,30 +45,153 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+/// Builder for a validated client configuration
+#[derive(Default)]
+pub struct ConfigBuilder {
+    app_id: Option<AppId>,
+    url: Option<String>,
+    token: Option<String>,
+    username: Option<String>,
+}
+
+impl ConfigBuilder {
+    /// Set the app ID used when connecting to the network. This is required
+    /// by `Client::connect`.
+    pub fn app_id(mut self, app_id: AppId) -> Self {
+        self.app_id = Some(app_id);
+        self
+    }
+
+    /// Set the URL of the cloud (defaults to the public NetsBlox cloud).
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_owned());
+        self
+    }
+
+    /// Build the configuration, checking that the URL is a valid http(s) URL.
+    /// Trailing slashes are removed since request paths start with one.
+    #[allow(clippy::result_large_err)] // consistent with the rest of the client
+    pub fn build(self) -> Result<Config, error::Error> {
+        let url = self.url.unwrap_or_else(|| Config::default().url);
+        let parsed = reqwest::Url::parse(&url)
+            .map_err(|err| error::Error::InvalidUrlError(format!("{} ({})", url, err)))?;
+
+        if !matches!(parsed.scheme(), "http" | "https") {
+            let msg = format!("{} (expected http or https)", url);
+            return Err(error::Error::InvalidUrlError(msg));
+        }
+
+        Ok(Config {
+            app_id: self.app_id,
+            url: url.trim_end_matches('/').to_owned(),
+            token: self.token,
+            username: self.username,
+        })
+    }
+}
+
 async fn check_response(response: Response) -> Result<Response, error::Error> {
     let status_code = response.status().as_u16();
     let is_error = status_code > 399;
     if is_error {
-        let msg = response.text().await.map_err(error::Error::RequestError)?;
+        let request_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|id| id.to_owned());
+        let text = response.text().await.map_err(error::Error::RequestError)?;
+
+        // fall back to the plain text message for older servers
+        let mut err: error::ServerError = match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) => body.into(),
+            Err(_) if text.is_empty() && status_code == 500 => {
+                "Internal server error occurred.".into()
+            }
+            Err(_) => text.into(),
+        };
+        err.request_id = err.request_id.or(request_id);
 
         match status_code {
-            400 => Err(error::Error::BadRequestError(msg)),
+            400 => Err(error::Error::BadRequestError(err)),
             401 => Err(error::Error::LoginRequiredError),
-            403 => Err(error::Error::PermissionsError(msg)),
-            404 => Err(error::Error::NotFoundError(msg)),
-            500 => Err(error::Error::InternalServerError),
-            _ => panic!("Unknown status code: {:?}", status_code), // FIXME: Use error instead?
+            403 => Err(error::Error::PermissionsError(err)),
+            404 => Err(error::Error::NotFoundError(err)),
+            409 => Err(error::Error::ConflictError(err)),
+            429 => Err(error::Error::TooManyRequestsError(err)),
+            500 => Err(error::Error::InternalServerError(err)),
+            _ => Err(error::Error::UnexpectedStatusError(status_code, err)),
         }
     } else {
         Ok(response)
     }
 }
 
+/// Read the response body, failing if it is larger than `max_size` bytes. The
+/// Content-Length is checked first (when present) but the body is also capped
+/// as it is received since the header may be missing or incorrect.
+async fn read_body(mut response: Response, max_size: usize) -> Result<Vec<u8>, error::Error> {
+    if let Some(size) = response.content_length() {
+        if size > max_size as u64 {
+            return Err(error::Error::ResponseTooLargeError(max_size));
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(error::Error::ParseResponseFailedError)?
+    {
+        if body.len() + chunk.len() > max_size {
+            return Err(error::Error::ResponseTooLargeError(max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+fn normalize_hosts(hosts: Vec<ServiceHost>) -> Vec<ServiceHost> {
+    hosts
+        .into_iter()
+        .map(|host| ServiceHost {
+            url: normalize_host_url(&host.url),
+            categories: host.categories,
+        })
+        .collect()
+}
+
+/// Normalize a service host URL so equivalent URLs can be compared. The
+/// host is lowercased and default ports and trailing slashes are removed.
+pub fn normalize_host_url(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_err| url.to_owned())
+        .trim_end_matches('/')
+        .to_owned()
+}
+
 pub type Token = String;
 pub async fn login(mut cfg: Config, credentials: &LoginRequest) -> Result<Config, error::Error> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/users/login", cfg.url))
+    let path = "/users/login";
+    let builder = reqwest::Client::new().post(format!("{}{}", cfg.url, path));
+    let response = Request::new(builder, Method::POST, path)
         .json(&credentials)
         .send()
         .await
@@ -66,17 +201,56 @@ pub async fn login(mut cfg: Config, credentials: &LoginRequest) -> Result<Config
     let cookie = response
         .cookies()
         .find(|cookie| cookie.name() == "netsblox")
-        .ok_or("No cookie received.")
-        .unwrap();
+        .ok_or(error::Error::MissingCookieError)?;
 
     let token = cookie.value().to_owned();
 
-    let user = response.json::<User>().await.unwrap();
+    let user = response
+        .json::<User>()
+        .await
+        .map_err(error::Error::ParseResponseFailedError)?;
     cfg.username = Some(user.username);
     cfg.token = Some(token);
     Ok(cfg)
 }
 
+/// Login as a user with two-factor authentication enabled using the given
+/// TOTP (or backup) code.
+pub async fn login_with_2fa(
+    cfg: Config,
+    credentials: &LoginRequest,
+    code: &str,
+) -> Result<Config, error::Error> {
+    let request = LoginRequest {
+        credentials: credentials.credentials.clone(),
+        client_id: credentials.client_id.clone(),
+        two_factor_code: Some(code.to_owned()),
+        cancel_deletion: credentials.cancel_deletion,
+    };
+    login(cfg, &request).await
+}
+
+/// Characters which must be encoded in a URL path segment
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Percent-encode a value (eg, a project tag) for use as a segment of a URL path
+fn encode_path_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+/// Result of a conditional request (using an ETag from a previous response)
+#[derive(Debug)]
+pub enum Conditional<T> {
+    /// The resource has changed. The ETag (if any) can be used in the next request.
+    Modified(T, Option<String>),
+    /// The resource has not changed since the ETag was received.
+    NotModified,
+}
+
 #[derive(Serialize)]
 struct UserData<'a> {
     username: &'a str,
@@ -89,20 +263,57 @@ struct UserData<'a> {
 #[derive(Clone)]
 pub struct Client {
     cfg: Config,
+    on_behalf_of: Option<String>,
+    max_response_size: usize,
+    timeout: Option<Duration>,
 }
 
 impl Client {
     pub fn new(cfg: Config) -> Self {
-        Client { cfg }
+        Client {
+            cfg,
+            on_behalf_of: None,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            timeout: None,
+        }
+    }
+
+    /// Set the max size (in bytes) of project and library responses. Larger
+    /// responses fail with `ResponseTooLargeError` rather than being read into memory.
+    pub fn set_max_response_size(&mut self, max_size: usize) {
+        self.max_response_size = max_size;
     }
 
-    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+    /// Set the timeout for each request (including reading the response).
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Make subsequent requests on behalf of the given user. Requests made on
+    /// behalf of a user other than the one logged in are recorded in the audit log.
+    pub fn set_on_behalf_of(&mut self, username: Option<String>) {
+        self.on_behalf_of = username;
+    }
+
+    fn request(&self, method: Method, path: &str) -> Request {
         let client = reqwest::Client::new();
         let empty = "".to_owned();
         let token = self.cfg.token.as_ref().unwrap_or(&empty);
-        client
-            .request(method, format!("{}{}", self.cfg.url, path))
-            .header("Cookie", format!("netsblox={}", token))
+        let mut builder = client
+            .request(method.clone(), format!("{}{}", self.cfg.url, path))
+            .header("Cookie", format!("netsblox={}", token));
+
+        if let Some(username) = &self.on_behalf_of {
+            if self.cfg.username.as_ref() != Some(username) {
+                builder = builder.header(ON_BEHALF_OF_HEADER, username);
+            }
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        Request::new(builder, method, path)
     }
 
     // User management
@@ -122,21 +333,51 @@ impl Client {
             password: password.map(|pwd| pwd.to_owned()),
         };
 
+        self.post_new_user(&user_data).await
+    }
+
+    /// Create several users, sending at most `concurrency` requests at a time.
+    /// Results are returned in the same order as `users` so a failure does not
+    /// prevent the remaining users from being created.
+    pub async fn create_users(
+        &self,
+        users: &[NewUser],
+        concurrency: usize,
+    ) -> Vec<Result<(), error::Error>> {
+        futures_util::stream::iter(users)
+            .map(|user_data| self.post_new_user(user_data))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    async fn post_new_user(&self, user_data: &NewUser) -> Result<(), error::Error> {
         let response = self
             .request(Method::POST, "/users/create")
-            .json(&user_data)
+            .json(user_data)
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
-        println!(
-            "status {} {}",
-            response.status(),
-            response.text().await.unwrap()
-        );
+        check_response(response).await?;
         Ok(())
     }
 
+    /// Check that the current session is still valid and return the
+    /// authenticated user.
+    pub async fn verify_session(&self) -> Result<User, error::Error> {
+        let response = self
+            .request(Method::GET, "/users/whoami")
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        let username = response.text().await.map_err(error::Error::RequestError)?;
+
+        self.view_user(&username).await
+    }
+
     pub async fn list_users(&self) -> Result<Vec<User>, error::Error> {
         let response = self
             .request(Method::GET, "/users/")
@@ -145,7 +386,61 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<User>>().await.unwrap())
+        response
+            .json::<Vec<User>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// List a single page of users ordered by username.
+    pub async fn list_users_page(&self, query: &ListUsersQuery) -> Result<Vec<User>, error::Error> {
+        let response = self
+            .request(Method::GET, "/users/")
+            .query(query)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<User>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Stream all users ordered by username. Users are fetched a page at a
+    /// time so only a single page is held in memory.
+    pub fn stream_users(&self) -> impl Stream<Item = Result<User, error::Error>> + '_ {
+        self.stream_users_by_page(USERS_PAGE_SIZE)
+    }
+
+    fn stream_users_by_page(
+        &self,
+        page_size: i64,
+    ) -> impl Stream<Item = Result<User, error::Error>> + '_ {
+        let pages = stream::try_unfold(Some(None), move |cursor| async move {
+            let after = match cursor {
+                Some(after) => after,
+                None => return Ok(None),
+            };
+
+            let query = ListUsersQuery {
+                after,
+                limit: Some(page_size),
+            };
+            let users = self.list_users_page(&query).await?;
+            let next = if users.len() < page_size as usize {
+                None
+            } else {
+                users.last().map(|user| Some(user.username.clone()))
+            };
+
+            Ok(Some((users, next)))
+        });
+
+        pages
+            .map_ok(|users| stream::iter(users.into_iter().map(Ok)))
+            .try_flatten()
     }
 
     /// Send an email containing all usernames associated with the given
@@ -173,6 +468,131 @@ impl Client {
         Ok(())
     }
 
+    pub async fn cancel_user_deletion(&self, username: &str) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/users/{}/delete/cancel", username))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn send_email_verification(&self, username: &str) -> Result<(), error::Error> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/users/{}/send-verification", username),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    /// List privileged (moderator or admin) actions. Only available to admins.
+    pub async fn list_audit_log(
+        &self,
+        query: &AuditLogQuery,
+    ) -> Result<Vec<AuditLogEntry>, error::Error> {
+        let response = self
+            .request(Method::GET, "/admin/audit")
+            .query(query)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<AuditLogEntry>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn list_sessions(&self, username: &str) -> Result<Vec<SessionInfo>, error::Error> {
+        let response = self
+            .request(Method::GET, &format!("/users/{}/sessions", username))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<SessionInfo>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Revoke the given session (or all sessions if `None`). Returns the IDs
+    /// of the revoked sessions.
+    pub async fn revoke_sessions(
+        &self,
+        username: &str,
+        session_id: Option<&str>,
+    ) -> Result<Vec<String>, error::Error> {
+        let data = RevokeSessionsData {
+            session_id: session_id.map(|id| id.to_owned()),
+        };
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/users/{}/sessions/revoke", username),
+            )
+            .json(&data)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Start enrolling in two-factor authentication. It is not enabled until
+    /// a code from the authenticator app is verified with `verify_2fa`.
+    pub async fn enroll_2fa(&self, username: &str) -> Result<TwoFactorEnrollment, error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/users/{}/2fa/enroll", username))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<TwoFactorEnrollment>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn verify_2fa(&self, username: &str, code: &str) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/users/{}/2fa/verify", username))
+            .json(&code)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn disable_2fa(&self, username: &str, code: &str) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/users/{}/2fa/disable", username))
+            .json(&code)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
     pub async fn view_user(&self, username: &str) -> Result<User, error::Error> {
         let response = self
             .request(Method::GET, &format!("/users/{}", username))
@@ -181,7 +601,25 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<User>().await.unwrap())
+        response
+            .json::<User>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Export everything stored about the given user (for data requests)
+    pub async fn export_user_data(&self, username: &str) -> Result<UserExport, error::Error> {
+        let response = self
+            .request(Method::GET, &format!("/users/{}/export", username))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<UserExport>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn update_user(
@@ -198,7 +636,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<User>().await.unwrap())
+        response
+            .json::<User>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn set_password(&self, username: &str, password: &str) -> Result<(), error::Error> {
@@ -254,7 +695,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<BannedAccount>().await.unwrap())
+        response
+            .json::<BannedAccount>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn unban_user(&self, username: &str) -> Result<BannedAccount, error::Error> {
@@ -265,7 +709,45 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<BannedAccount>().await.unwrap())
+        response
+            .json::<BannedAccount>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Get the requirements for new passwords
+    pub async fn get_password_rules(&self) -> Result<PasswordRules, error::Error> {
+        let response = self
+            .request(Method::GET, "/users/password-policy")
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<PasswordRules>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// List a single page of banned accounts ordered by username. Accounts can
+    /// be filtered by (partial) username or email.
+    pub async fn list_banned_accounts(
+        &self,
+        query: &ListBannedAccountsQuery,
+    ) -> Result<Vec<BannedAccount>, error::Error> {
+        let response = self
+            .request(Method::GET, "/users/banned")
+            .query(query)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<BannedAccount>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     /// Send a magic link to the given email address. Usable for any user associated with the
@@ -282,6 +764,47 @@ impl Client {
         Ok(())
     }
 
+    /// Complete a login using a magic link. Returns the configuration for the
+    /// new session (like `login`).
+    pub async fn login_with_magic_link(
+        &self,
+        data: &MagicLinkLoginData,
+    ) -> Result<Config, error::Error> {
+        let path = "/magic-links/login";
+        let builder = reqwest::Client::new().post(format!("{}{}", self.cfg.url, path));
+        let response = Request::new(builder, Method::POST, path)
+            .json(data)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        // Used (or expired) links are removed so they are reported as missing.
+        // Password reset links are rejected since they must be opened in a browser.
+        let response = check_response(response).await.map_err(|err| match err {
+            err if err.code() == Some("PasswordResetLinkError") => err,
+            error::Error::BadRequestError(_) | error::Error::NotFoundError(_) => {
+                error::Error::MagicLinkExpiredError
+            }
+            err => err,
+        })?;
+
+        let token = response
+            .cookies()
+            .find(|cookie| cookie.name() == "netsblox")
+            .map(|cookie| cookie.value().to_owned())
+            .ok_or(error::Error::LoginRequiredError)?;
+
+        let user = response
+            .json::<User>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)?;
+
+        let mut cfg = self.cfg.clone();
+        cfg.username = Some(user.username);
+        cfg.token = Some(token);
+        Ok(cfg)
+    }
+
     // Project management
     pub async fn create_project(
         &self,
@@ -296,82 +819,265 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<ProjectMetadata>().await.unwrap())
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn list_projects(&self, owner: &str) -> Result<Vec<ProjectMetadata>, error::Error> {
+    /// List the projects owned by the given user. The projects starred by the
+    /// current user are listed first if `starred_first` is set.
+    pub async fn list_projects(
+        &self,
+        owner: &str,
+        starred_first: bool,
+    ) -> Result<Vec<ProjectMetadata>, error::Error> {
         let response = self
             .request(Method::GET, &format!("/projects/user/{}", &owner))
+            .query(&[("starredFirst", starred_first)])
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ProjectMetadata>>().await.unwrap())
+        response
+            .json::<Vec<ProjectMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
+    /// List the projects shared with the given user. The projects starred by the
+    /// current user are listed first if `starred_first` is set.
     pub async fn list_shared_projects(
         &self,
         owner: &str,
+        starred_first: bool,
     ) -> Result<Vec<ProjectMetadata>, error::Error> {
         let response = self
             .request(Method::GET, &format!("/projects/shared/{}", &owner))
+            .query(&[("starredFirst", starred_first)])
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ProjectMetadata>>().await.unwrap())
+        response
+            .json::<Vec<ProjectMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn get_project_metadata(
+    pub async fn list_projects_with_tag(
         &self,
         owner: &str,
-        name: &str,
-    ) -> Result<ProjectMetadata, error::Error> {
+        tag: &str,
+    ) -> Result<Vec<ProjectMetadata>, error::Error> {
         let response = self
-            .request(
-                Method::GET,
-                &format!("/projects/user/{}/{}/metadata", &owner, name),
-            )
+            .request(Method::GET, &format!("/projects/user/{}", &owner))
+            .query(&[("tag", tag)])
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<ProjectMetadata>().await.unwrap())
+        response
+            .json::<Vec<ProjectMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn rename_project(&self, id: &ProjectId, name: &str) -> Result<(), error::Error> {
+    pub async fn add_project_tag(
+        &self,
+        id: &ProjectId,
+        tag: &str,
+    ) -> Result<ProjectMetadata, error::Error> {
         let response = self
-            .request(Method::PATCH, &format!("/projects/id/{}", &id))
-            .json(&UpdateProjectData {
-                name: name.to_owned(),
-                client_id: None,
-            })
+            .request(
+                Method::POST,
+                &format!("/projects/id/{}/tags/{}", id, encode_path_segment(tag)),
+            )
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
-        check_response(response).await?;
+        let response = check_response(response).await?;
 
-        Ok(())
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn rename_role(
+    pub async fn remove_project_tag(
         &self,
         id: &ProjectId,
-        role_id: &RoleId,
-        name: &str,
-    ) -> Result<(), error::Error> {
+        tag: &str,
+    ) -> Result<ProjectMetadata, error::Error> {
         let response = self
-            .request(Method::PATCH, &format!("/projects/id/{}/{}", &id, &role_id))
-            .json(&UpdateRoleData {
+            .request(
+                Method::DELETE,
+                &format!("/projects/id/{}/tags/{}", id, encode_path_segment(tag)),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn set_project_tags(
+        &self,
+        id: &ProjectId,
+        tags: &[String],
+    ) -> Result<ProjectMetadata, error::Error> {
+        let response = self
+            .request(Method::PUT, &format!("/projects/id/{}/tags", id))
+            .json(&tags)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn star_project(&self, id: &ProjectId) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/projects/id/{}/star", id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn unstar_project(&self, id: &ProjectId) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::DELETE, &format!("/projects/id/{}/star", id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn list_starred_projects(
+        &self,
+        username: &str,
+    ) -> Result<Vec<ProjectMetadata>, error::Error> {
+        let response = self
+            .request(Method::GET, &format!("/projects/starred/{}", &username))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<Vec<ProjectMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn get_project_metadata(
+        &self,
+        owner: &str,
+        name: &str,
+    ) -> Result<ProjectMetadata, error::Error> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/projects/user/{}/{}/metadata", &owner, name),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Get the project metadata unless it has not changed since the given ETag
+    /// was received. Useful for clients polling for changes.
+    pub async fn get_project_metadata_if_modified(
+        &self,
+        owner: &str,
+        name: &str,
+        etag: Option<&str>,
+    ) -> Result<Conditional<ProjectMetadata>, error::Error> {
+        let path = format!("/projects/user/{}/{}/metadata", &owner, name);
+        let mut request = self.request(Method::GET, &path);
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await.map_err(error::Error::RequestError)?;
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+
+        let response = check_response(response).await?;
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(|etag| etag.to_owned());
+
+        let metadata = response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)?;
+
+        Ok(Conditional::Modified(metadata, etag))
+    }
+
+    pub async fn rename_project(&self, id: &ProjectId, name: &str) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::PATCH, &format!("/projects/id/{}", &id))
+            .json(&UpdateProjectData {
+                name: name.to_owned(),
+                client_id: None,
+                expected_version: None,
+            })
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+
+        Ok(())
+    }
+
+    pub async fn rename_role(
+        &self,
+        id: &ProjectId,
+        role_id: &RoleId,
+        name: &str,
+    ) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::PATCH, &format!("/projects/id/{}/{}", &id, &role_id))
+            .json(&UpdateRoleData {
                 name: name.to_owned(),
                 client_id: None,
+                expected_version: None,
             })
             .send()
             .await
@@ -394,6 +1100,55 @@ impl Client {
         Ok(())
     }
 
+    /// Create a private copy of a project owned by the current user. The copy
+    /// uses the name of the original project unless a new name is given.
+    pub async fn duplicate_project(
+        &self,
+        id: &ProjectId,
+        new_name: Option<&str>,
+    ) -> Result<ProjectMetadata, error::Error> {
+        let data = DuplicateProjectData {
+            name: new_name.map(|name| name.to_owned()),
+        };
+        let response = self
+            .request(Method::POST, &format!("/projects/id/{}/duplicate", id))
+            .json(&data)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Transfer a project to another user. The project will be renamed if the
+    /// new owner already has a project with the same name.
+    pub async fn transfer_project(
+        &self,
+        id: &ProjectId,
+        new_owner: &str,
+    ) -> Result<ProjectMetadata, error::Error> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/projects/id/{}/transfer/{}", id, new_owner),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
     pub async fn delete_role(&self, id: &ProjectId, role_id: &RoleId) -> Result<(), error::Error> {
         let response = self
             .request(Method::DELETE, &format!("/projects/id/{}/{}", id, role_id))
@@ -415,7 +1170,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<PublishState>().await.unwrap())
+        response
+            .json::<PublishState>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn unpublish_project(&self, id: &ProjectId) -> Result<(), error::Error> {
@@ -447,8 +1205,9 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
+        let body = read_body(response, self.max_response_size).await?;
 
-        Ok(response.json::<Project>().await.unwrap())
+        serde_json::from_slice(&body).map_err(error::Error::InvalidResponseError)
     }
 
     pub async fn get_role(
@@ -470,7 +1229,55 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<RoleData>().await.unwrap())
+        response
+            .json::<RoleData>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn list_role_versions(
+        &self,
+        id: &ProjectId,
+        role_id: &RoleId,
+    ) -> Result<Vec<RoleVersion>, error::Error> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/projects/id/{}/{}/versions", id, role_id),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<Vec<RoleVersion>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn restore_role_version(
+        &self,
+        id: &ProjectId,
+        role_id: &RoleId,
+        version: &str,
+    ) -> Result<ProjectMetadata, error::Error> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/projects/id/{}/{}/restore/{}", id, role_id, version),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     // Project collaborators
@@ -483,7 +1290,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<String>>().await.unwrap())
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn remove_collaborator(
@@ -505,6 +1315,34 @@ impl Client {
         Ok(())
     }
 
+    /// Set the role of a collaborator (eg, to make them a read-only viewer)
+    pub async fn set_collaborator_role(
+        &self,
+        project_id: &ProjectId,
+        username: &str,
+        role: CollabRole,
+    ) -> Result<ProjectMetadata, error::Error> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!(
+                    "/projects/id/{}/collaborators/{}/role",
+                    project_id, username
+                ),
+            )
+            .json(&role)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<ProjectMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
     pub async fn list_collaboration_invites(
         &self,
         username: &str,
@@ -520,14 +1358,19 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<CollaborationInvite>>().await.unwrap())
+        response
+            .json::<Vec<CollaborationInvite>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
+    /// Invite the user to collaborate on the project. If an invitation is
+    /// already pending, it is returned instead of sending another.
     pub async fn invite_collaborator(
         &self,
         id: &ProjectId,
         username: &str,
-    ) -> Result<(), error::Error> {
+    ) -> Result<CollaborationInvite, error::Error> {
         let response = self
             .request(
                 Method::POST,
@@ -537,8 +1380,11 @@ impl Client {
             .await
             .map_err(error::Error::RequestError)?;
 
-        check_response(response).await?;
-        Ok(())
+        let response = check_response(response).await?;
+        response
+            .json::<CollaborationInvite>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn respond_to_collaboration_invite(
@@ -557,6 +1403,21 @@ impl Client {
         Ok(())
     }
 
+    /// Hide a collaboration invite from the receiver's list of invitations.
+    pub async fn dismiss_collaboration_invite(
+        &self,
+        id: &InvitationId,
+    ) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::DELETE, &format!("/collaboration-invites/id/{}", id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
     // Friend capabilities
     pub async fn list_friends(&self, username: &str) -> Result<Vec<String>, error::Error> {
         let path = &format!("/friends/{}/", username);
@@ -567,7 +1428,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<String>>().await.unwrap())
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_online_friends(&self, username: &str) -> Result<Vec<String>, error::Error> {
@@ -579,7 +1443,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<String>>().await.unwrap())
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_friend_invites(
@@ -594,7 +1461,47 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<FriendInvite>>().await.unwrap())
+        response
+            .json::<Vec<FriendInvite>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Get the number of friends, online friends, and pending friend invites
+    /// for a user without fetching the full lists.
+    pub async fn get_friend_summary(&self, username: &str) -> Result<FriendSummary, error::Error> {
+        let path = &format!("/friends/{}/summary", username);
+        let response = self
+            .request(Method::GET, path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<FriendSummary>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Get the friendship status of `username` with respect to `other`.
+    pub async fn friendship_status(
+        &self,
+        username: &str,
+        other: &str,
+    ) -> Result<FriendshipStatus, error::Error> {
+        let path = &format!("/friends/{}/status/{}", username, other);
+        let response = self
+            .request(Method::GET, path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<FriendshipStatus>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn send_friend_invite(
@@ -681,7 +1588,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<LibraryMetadata>>().await.unwrap())
+        response
+            .json::<Vec<LibraryMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_submitted_libraries(&self) -> Result<Vec<LibraryMetadata>, error::Error> {
@@ -693,7 +1603,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<LibraryMetadata>>().await.unwrap())
+        response
+            .json::<Vec<LibraryMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_public_libraries(&self) -> Result<Vec<LibraryMetadata>, error::Error> {
@@ -705,7 +1618,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<LibraryMetadata>>().await.unwrap())
+        response
+            .json::<Vec<LibraryMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_library(&self, username: &str, name: &str) -> Result<String, error::Error> {
@@ -717,8 +1633,9 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
+        let body = read_body(response, self.max_response_size).await?;
 
-        Ok(response.text().await.unwrap())
+        Ok(String::from_utf8_lossy(&body).into_owned())
     }
 
     pub async fn save_library(
@@ -744,6 +1661,28 @@ impl Client {
         Ok(())
     }
 
+    /// Rename a library or update its notes without resending the blocks.
+    pub async fn update_library(
+        &self,
+        username: &str,
+        library: &str,
+        data: &UpdateLibraryData,
+    ) -> Result<LibraryMetadata, error::Error> {
+        let path = format!("/libraries/user/{}/{}", username, library);
+        let response = self
+            .request(Method::PATCH, &path)
+            .json(data)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<LibraryMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
     pub async fn delete_library(&self, username: &str, library: &str) -> Result<(), error::Error> {
         let path = format!("/libraries/user/{}/{}", username, library);
         let response = self
@@ -802,6 +1741,26 @@ impl Client {
         Ok(())
     }
 
+    /// Approve or reject several pending libraries at once. Each library is
+    /// processed independently so the results should be checked individually.
+    pub async fn approve_libraries(
+        &self,
+        approvals: &[LibraryApproval],
+    ) -> Result<Vec<LibraryApprovalResult>, error::Error> {
+        let response = self
+            .request(Method::POST, "/libraries/mod/approve-bulk")
+            .json(&approvals)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<LibraryApprovalResult>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
     // Group management
     pub async fn list_groups(&self, username: &str) -> Result<Vec<Group>, error::Error> {
         let path = format!("/groups/user/{}/", username);
@@ -813,7 +1772,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<Group>>().await.unwrap())
+        response
+            .json::<Vec<Group>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn create_group(&self, owner: &str, name: &str) -> Result<(), error::Error> {
@@ -854,7 +1816,134 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<User>>().await.unwrap())
+        response
+            .json::<Vec<User>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Archive a group including its members and the contents of their projects.
+    pub async fn export_group(&self, id: &GroupId) -> Result<GroupArchive, error::Error> {
+        let path = format!("/groups/id/{}/export", id);
+        let response = self
+            .request(Method::GET, &path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<GroupArchive>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Recreate an archived group for the given owner (eg, to move a class
+    /// between instances). Nothing is created if the import fails.
+    pub async fn import_group(
+        &self,
+        owner: &str,
+        archive: &GroupArchive,
+        options: &ImportGroupOptions,
+    ) -> Result<ImportGroupResult, error::Error> {
+        let path = format!("/groups/user/{}/import", owner);
+        let data = ImportGroupData {
+            archive: archive.clone(),
+            options: options.clone(),
+        };
+        let response = self
+            .request(Method::POST, &path)
+            .json(&data)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<ImportGroupResult>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Add a user to the group. Users belong to at most one group so this
+    /// will remove them from any other group.
+    pub async fn add_group_member(
+        &self,
+        id: &GroupId,
+        username: &str,
+    ) -> Result<User, error::Error> {
+        let path = format!("/groups/id/{}/members/{}", id, username);
+        let response = self
+            .request(Method::POST, &path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<User>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn remove_group_member(
+        &self,
+        id: &GroupId,
+        username: &str,
+    ) -> Result<User, error::Error> {
+        let path = format!("/groups/id/{}/members/{}", id, username);
+        let response = self
+            .request(Method::DELETE, &path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<User>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Make another user the owner of the given group.
+    pub async fn transfer_group(
+        &self,
+        id: &GroupId,
+        new_owner: &str,
+    ) -> Result<Group, error::Error> {
+        let path = format!("/groups/id/{}/transfer/{}", id, new_owner);
+        let response = self
+            .request(Method::POST, &path)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Group>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// List the projects owned by the members of the group, optionally
+    /// filtered by publish state.
+    pub async fn list_group_projects(
+        &self,
+        id: &GroupId,
+        state: Option<PublishState>,
+    ) -> Result<Vec<ProjectMetadata>, error::Error> {
+        let path = format!("/groups/id/{}/projects", id);
+        let mut request = self.request(Method::GET, &path);
+        if let Some(state) = state {
+            request = request.query(&[("state", state)]);
+        }
+        let response = request.send().await.map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<ProjectMetadata>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn rename_group(&self, id: &GroupId, name: &str) -> Result<(), error::Error> {
@@ -882,7 +1971,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Group>().await.unwrap())
+        response
+            .json::<Group>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     // Service host management
@@ -895,7 +1987,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ServiceHost>>().await.unwrap())
+        response
+            .json::<Vec<ServiceHost>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_group_hosts(
@@ -910,7 +2005,10 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ServiceHost>>().await.unwrap())
+        response
+            .json::<Vec<ServiceHost>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_hosts(&self, username: &str) -> Result<Vec<ServiceHost>, error::Error> {
@@ -922,17 +2020,44 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ServiceHost>>().await.unwrap())
+        response
+            .json::<Vec<ServiceHost>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// List all service hosts available to the user (including those registered
+    /// by their groups and the default hosts), merged by URL.
+    pub async fn get_effective_hosts(
+        &self,
+        username: &str,
+    ) -> Result<Vec<ServiceHost>, error::Error> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/services/hosts/effective/{}", username),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<ServiceHost>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn set_user_hosts(
         &self,
         username: &str,
         hosts: Vec<ServiceHost>,
+        validate: bool,
     ) -> Result<(), error::Error> {
         let response = self
             .request(Method::POST, &format!("/services/hosts/user/{}", username))
-            .json(&hosts)
+            .query(&[("validate", validate)])
+            .json(&normalize_hosts(hosts))
             .send()
             .await
             .map_err(error::Error::RequestError)?;
@@ -945,10 +2070,12 @@ impl Client {
         &self,
         group_id: &GroupId,
         hosts: Vec<ServiceHost>,
+        validate: bool,
     ) -> Result<(), error::Error> {
         let response = self
             .request(Method::POST, &format!("/services/hosts/group/{}", group_id))
-            .json(&hosts)
+            .query(&[("validate", validate)])
+            .json(&normalize_hosts(hosts))
             .send()
             .await
             .map_err(error::Error::RequestError)?;
@@ -957,6 +2084,24 @@ impl Client {
         Ok(())
     }
 
+    /// List the users and groups that have registered the given authorized host.
+    pub async fn get_host_usage(&self, id: &str) -> Result<ServiceHostUsage, error::Error> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/services/hosts/authorized/{}/usage", id),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<ServiceHostUsage>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
     pub async fn authorize_host(
         &self,
         url: &str,
@@ -976,7 +2121,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<String>().await.unwrap())
+        response
+            .json::<String>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn unauthorize_host(&self, id: &str) -> Result<(), error::Error> {
@@ -1001,7 +2149,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<AuthorizedServiceHost>>().await.unwrap())
+        response
+            .json::<Vec<AuthorizedServiceHost>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     // Service settings management
@@ -1019,7 +2170,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<String>>().await.unwrap())
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_user_settings(&self, username: &str) -> Result<Vec<String>, error::Error> {
@@ -1033,7 +2187,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<Vec<String>>().await.unwrap())
+        response
+            .json::<Vec<String>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_all_settings(
@@ -1051,7 +2208,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.json::<ServiceSettings>().await.unwrap())
+        response
+            .json::<ServiceSettings>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_group_settings(
@@ -1069,7 +2229,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn get_user_settings(
@@ -1087,7 +2250,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn set_user_settings(
@@ -1107,7 +2273,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn set_group_settings(
@@ -1127,7 +2296,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn delete_user_settings(
@@ -1145,7 +2317,10 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn delete_group_settings(
@@ -1163,19 +2338,45 @@ impl Client {
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-        Ok(response.text().await.unwrap())
+        response
+            .text()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
     // NetsBlox network capabilities
     pub async fn list_external_clients(&self) -> Result<Vec<ExternalClient>, error::Error> {
+        self.get_external_clients(&ExternalClientsQuery::default())
+            .await
+    }
+
+    /// List the external clients connected using the given app
+    pub async fn list_external_clients_for_app(
+        &self,
+        app_id: &AppId,
+    ) -> Result<Vec<ExternalClient>, error::Error> {
+        let query = ExternalClientsQuery {
+            app_id: Some(app_id.to_owned()),
+        };
+        self.get_external_clients(&query).await
+    }
+
+    async fn get_external_clients(
+        &self,
+        query: &ExternalClientsQuery,
+    ) -> Result<Vec<ExternalClient>, error::Error> {
         let response = self
             .request(Method::GET, "/network/external")
+            .query(query)
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ExternalClient>>().await.unwrap())
+        response
+            .json::<Vec<ExternalClient>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
     pub async fn list_networks(&self) -> Result<Vec<ProjectId>, error::Error> {
@@ -1187,113 +2388,265 @@ impl Client {
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<Vec<ProjectId>>().await.unwrap())
+        response
+            .json::<Vec<ProjectId>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn get_room_state(&self, id: &ProjectId) -> Result<RoomState, error::Error> {
+    /// List the active rooms with the number of occupants in each
+    pub async fn list_network_summaries(&self) -> Result<Vec<RoomSummary>, error::Error> {
         let response = self
-            .request(Method::GET, &format!("/network/id/{}", id))
+            .request(Method::GET, "/network/summary")
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<RoomState>().await.unwrap())
+        response
+            .json::<Vec<RoomSummary>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn get_client_state(&self, client_id: &ClientId) -> Result<ClientInfo, error::Error> {
+    /// List the messages waiting to be delivered to offline external clients
+    pub async fn list_queued_messages(&self) -> Result<Vec<QueuedMessage>, error::Error> {
         let response = self
-            .request(
-                Method::GET,
-                &format!("/network/{}/state", client_id.as_str()),
-            )
+            .request(Method::GET, "/network/messages/queued")
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        Ok(response.json::<ClientInfo>().await.unwrap())
+        response
+            .json::<Vec<QueuedMessage>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn evict_occupant(&self, client_id: &ClientId) -> Result<(), error::Error> {
+    /// Drop all messages waiting for offline external clients. Returns the number
+    /// of messages dropped.
+    pub async fn clear_queued_messages(&self) -> Result<u64, error::Error> {
+        let response = self
+            .request(Method::DELETE, "/network/messages/queued")
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<u64>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Start recording the network messages sent within a project
+    pub async fn start_network_trace(
+        &self,
+        project_id: &ProjectId,
+    ) -> Result<NetworkTraceMetadata, error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/network/id/{}/trace/", project_id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<NetworkTraceMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn stop_network_trace(
+        &self,
+        project_id: &ProjectId,
+        trace_id: &str,
+    ) -> Result<NetworkTraceMetadata, error::Error> {
         let response = self
             .request(
                 Method::POST,
-                &format!("/network/clients/{}/evict", client_id.as_str()),
+                &format!("/network/id/{}/trace/{}/stop", project_id, trace_id),
             )
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
-        check_response(response).await?;
-        Ok(())
+        let response = check_response(response).await?;
+        response
+            .json::<NetworkTraceMetadata>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    pub async fn connect(&self, address: &str) -> Result<MessageChannel, error::Error> {
+    /// Get the messages recorded in a network trace (oldest first). If the
+    /// result is truncated, the remaining messages can be fetched using `skip`.
+    pub async fn get_trace_messages(
+        &self,
+        project_id: &ProjectId,
+        trace_id: &str,
+        query: &TraceMessagesQuery,
+    ) -> Result<TraceMessages, error::Error> {
         let response = self
-            .request(Method::GET, "/configuration")
+            .request(
+                Method::GET,
+                &format!("/network/id/{}/trace/{}/messages", project_id, trace_id),
+            )
+            .query(query)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<TraceMessages>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn get_room_state(&self, id: &ProjectId) -> Result<RoomState, error::Error> {
+        let response = self
+            .request(Method::GET, &format!("/network/id/{}", id))
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
 
-        let config = response.json::<ClientConfig>().await.unwrap();
+        response
+            .json::<RoomState>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
 
+    /// Subscribe to changes to the state of a room. The stream yields the
+    /// current state (if the room is active) followed by each update. The
+    /// `version` of consecutive states is incremented by one so missed
+    /// updates can be detected.
+    pub async fn subscribe_room_state(
+        &self,
+        id: &ProjectId,
+    ) -> Result<impl Stream<Item = Result<RoomState, error::Error>>, error::Error> {
         let url = format!(
-            "{}/network/{}/connect",
+            "{}/network/id/{}/subscribe",
             self.cfg.url.replace("http", "ws"),
-            config.client_id
+            id
         );
-        let (ws_stream, _) = connect_async(&url).await.unwrap();
-
-        let state = ClientStateData {
-            state: ClientState::External(ExternalClientState {
-                address: address.to_owned(),
-                app_id: self.cfg.app_id.as_ref().unwrap().clone(),
-            }),
-        };
+        let mut request = url
+            .into_client_request()
+            .map_err(error::Error::WebSocketConnectError)?;
+
+        let token = self.cfg.token.as_deref().unwrap_or_default();
+        let cookie = format!("netsblox={}", token).parse().map_err(|err| {
+            error::Error::WebSocketConnectError(tungstenite::Error::HttpFormat(
+                tungstenite::http::Error::from(err),
+            ))
+        })?;
+        request.headers_mut().insert("Cookie", cookie);
+
+        let (ws_stream, _) = connect_async(request)
+            .await
+            .map_err(error::Error::WebSocketConnectError)?;
+
+        let states = ws_stream
+            .map_err(error::Error::WebSocketReceiveError)
+            .try_take_while(|frame| future::ready(Ok(!frame.is_close())))
+            .try_filter_map(|frame| async move {
+                match frame {
+                    Message::Text(text) => serde_json::from_str(&text)
+                        .map(Some)
+                        .map_err(error::Error::InvalidMessageError),
+                    _ => Ok(None),
+                }
+            });
+
+        Ok(states)
+    }
 
+    pub async fn get_client_state(&self, client_id: &ClientId) -> Result<ClientInfo, error::Error> {
         let response = self
             .request(
-                Method::POST,
-                &format!("/network/{}/state", config.client_id),
+                Method::GET,
+                &format!("/network/{}/state", client_id.as_str()),
             )
-            .json(&state)
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
-        check_response(response).await?;
+        let response = check_response(response).await?;
 
-        Ok(MessageChannel {
-            id: config.client_id,
-            stream: ws_stream,
-        })
+        response
+            .json::<ClientInfo>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
 
-    // NetsBlox OAuth capabilities
-    pub async fn add_oauth_client(
+    /// Invite a user to occupy a role in the project. The sender defaults to the
+    /// current user if not set.
+    pub async fn invite_occupant(
         &self,
-        client: &oauth::CreateClientData,
-    ) -> Result<oauth::CreatedClientData, error::Error> {
+        project_id: &ProjectId,
+        data: &OccupantInviteData,
+    ) -> Result<OccupantInvite, error::Error> {
         let response = self
-            .request(Method::POST, "/oauth/clients/")
-            .json(&client)
+            .request(
+                Method::POST,
+                &format!("/network/id/{}/occupants/invite", project_id),
+            )
+            .json(data)
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
+        response
+            .json::<OccupantInvite>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Respond to an invitation to occupy a role. Accepting the invite moves
+    /// the given client into the role. Nothing is sent to the server when the
+    /// invite is declined.
+    pub async fn respond_to_occupant_invite(
+        &self,
+        client_id: &ClientId,
+        invite: &OccupantInvite,
+        accept: bool,
+    ) -> Result<(), error::Error> {
+        if !accept {
+            return Ok(());
+        }
+
+        let state = ClientStateData {
+            state: ClientState::Browser(BrowserClientState {
+                project_id: invite.project_id.to_owned(),
+                role_id: invite.role_id.to_owned(),
+            }),
+        };
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/network/{}/state", client_id.as_str()),
+            )
+            .json(&state)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
 
-        Ok(response.json::<oauth::CreatedClientData>().await.unwrap())
+        check_response(response).await?;
+        Ok(())
     }
 
-    pub async fn remove_oauth_client(&self, id: &oauth::ClientId) -> Result<(), error::Error> {
+    pub async fn evict_occupant(&self, client_id: &ClientId) -> Result<(), error::Error> {
         let response = self
-            .request(Method::DELETE, &format!("/oauth/clients/{}", id))
+            .request(
+                Method::POST,
+                &format!("/network/clients/{}/evict", client_id.as_str()),
+            )
             .send()
             .await
             .map_err(error::Error::RequestError)?;
@@ -1302,25 +2655,348 @@ impl Client {
         Ok(())
     }
 
-    pub async fn list_oauth_clients(&self) -> Result<Vec<oauth::Client>, error::Error> {
+    /// Evict all clients logged in as the given user. Returns the IDs of the
+    /// evicted clients.
+    pub async fn evict_user(&self, username: &str) -> Result<Vec<ClientId>, error::Error> {
         let response = self
-            .request(Method::GET, "/oauth/clients/")
+            .request(Method::POST, &format!("/network/users/{}/evict", username))
             .send()
             .await
             .map_err(error::Error::RequestError)?;
 
         let response = check_response(response).await?;
-
-        Ok(response.json::<Vec<oauth::Client>>().await.unwrap())
+        response
+            .json::<Vec<ClientId>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
     }
-}
 
-pub struct MessageChannel {
-    pub id: String,
+    /// Evict all occupants of a room. Returns the IDs of the evicted clients.
+    pub async fn evict_room(&self, id: &ProjectId) -> Result<Vec<ClientId>, error::Error> {
+        let response = self
+            .request(Method::POST, &format!("/network/id/{}/evict", id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<ClientId>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Send a message to a target on the network (eg, an entire room or role)
+    /// without opening a websocket. The message is sent on behalf of the
+    /// logged in user.
+    pub async fn send_message(
+        &self,
+        target: SendMessageTarget,
+        msg_type: &str,
+        content: &Value,
+    ) -> Result<(), error::Error> {
+        let msg = SendMessage {
+            sender: self.cfg.username.clone().map(SendMessageSender::Username),
+            target,
+            content: json!({
+                "type": "message",
+                "msgType": msg_type,
+                "content": content
+            }),
+        };
+        let response = self
+            .request(Method::POST, "/network/messages/")
+            .json(&msg)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn connect(&self, address: &str) -> Result<MessageChannel, error::Error> {
+        let app_id = self
+            .cfg
+            .app_id
+            .clone()
+            .ok_or(error::Error::MissingAppIdError)?;
+
+        let response = self
+            .request(Method::GET, "/configuration")
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        let config = response
+            .json::<ClientConfig>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)?;
+
+        let stream = self.open_socket(&config.client_id).await?;
+        let channel = MessageChannel {
+            id: config.client_id,
+            stream,
+            max_message_size: config.max_message_size,
+            client: self.clone(),
+            state: ExternalClientState {
+                address: address.to_owned(),
+                app_id,
+            },
+        };
+        channel.register_state().await?;
+
+        Ok(channel)
+    }
+
+    async fn open_socket(
+        &self,
+        client_id: &str,
+    ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, error::Error> {
+        let url = format!(
+            "{}/network/{}/connect",
+            self.cfg.url.replace("http", "ws"),
+            client_id
+        );
+        let (ws_stream, _) = connect_async(&url)
+            .await
+            .map_err(error::Error::WebSocketConnectError)?;
+
+        Ok(ws_stream)
+    }
+
+    // NetsBlox OAuth capabilities
+    pub async fn add_oauth_client(
+        &self,
+        client: &oauth::CreateClientData,
+    ) -> Result<oauth::CreatedClientData, error::Error> {
+        let response = self
+            .request(Method::POST, "/oauth/clients/")
+            .json(&client)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<oauth::CreatedClientData>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn remove_oauth_client(&self, id: &oauth::ClientId) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::DELETE, &format!("/oauth/clients/{}", id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
+    /// Generate a new secret for the OAuth client. The previous secret will no
+    /// longer be accepted.
+    pub async fn rotate_oauth_secret(
+        &self,
+        id: &oauth::ClientId,
+    ) -> Result<oauth::CreatedClientData, error::Error> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/oauth/clients/{}/rotate-secret", id),
+            )
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<oauth::CreatedClientData>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    pub async fn list_oauth_clients(&self) -> Result<Vec<oauth::Client>, error::Error> {
+        let response = self
+            .request(Method::GET, "/oauth/clients/")
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+
+        response
+            .json::<Vec<oauth::Client>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Authorize the client on behalf of the current user and return the
+    /// authorization code. The redirect (to the client's redirect URI) is
+    /// validated, including the state, rather than followed.
+    pub async fn oauth_authorize(
+        &self,
+        auth_request: &oauth::AuthorizationRequest,
+    ) -> Result<oauth::CodeId, error::Error> {
+        let username = self
+            .cfg
+            .username
+            .as_ref()
+            .ok_or(error::Error::LoginRequiredError)?;
+        let path = format!("/oauth/{}/code", username);
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(error::Error::RequestError)?;
+        let token = self.cfg.token.as_deref().unwrap_or_default();
+        let builder = client
+            .post(format!("{}{}", self.cfg.url, path))
+            .header("Cookie", format!("netsblox={}", token));
+
+        let response = Request::new(builder, Method::POST, &path)
+            .query(auth_request)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| error::Error::InvalidRedirectError("Missing location".into()))?;
+
+        if !location.starts_with(&auth_request.redirect_uri) {
+            return Err(error::Error::InvalidRedirectError(location.to_owned()));
+        }
+
+        let url = reqwest::Url::parse(location)
+            .map_err(|_err| error::Error::InvalidRedirectError(location.to_owned()))?;
+        let params: std::collections::HashMap<_, _> = url.query_pairs().collect();
+
+        if let Some(error) = params.get("error") {
+            return Err(error::Error::BadRequestError(error.to_string().into()));
+        }
+
+        if params.get("state").map(|state| state.as_ref()) != Some(auth_request.state.as_str()) {
+            return Err(error::Error::OAuthStateMismatchError);
+        }
+
+        params
+            .get("code")
+            .map(|code| oauth::CodeId::new(code.to_string()))
+            .ok_or_else(|| error::Error::InvalidRedirectError(location.to_owned()))
+    }
+
+    /// Exchange an authorization code for an access token. The redirect URI
+    /// must match the one used when requesting the code.
+    pub async fn oauth_token(
+        &self,
+        code: &oauth::CodeId,
+        redirect_uri: &str,
+    ) -> Result<oauth::Token, error::Error> {
+        let params = oauth::CreateTokenParams {
+            code: Some(code.as_str().to_owned()),
+            redirect_uri: Some(redirect_uri.to_owned()),
+            grant_type: Some("authorization_code".into()),
+        };
+        let response = self
+            .request(Method::POST, "/oauth/token/")
+            .json(&params)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        let token_id = response
+            .json::<oauth::TokenId>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)?;
+
+        let response = self
+            .request(Method::GET, &format!("/oauth/token/{}", token_id))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<oauth::Token>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// List the OAuth tokens issued to the given user.
+    pub async fn list_oauth_tokens(
+        &self,
+        username: &str,
+    ) -> Result<Vec<oauth::Token>, error::Error> {
+        let response = self
+            .request(Method::GET, "/oauth/tokens")
+            .query(&[("username", username)])
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        let response = check_response(response).await?;
+        response
+            .json::<Vec<oauth::Token>>()
+            .await
+            .map_err(error::Error::ParseResponseFailedError)
+    }
+
+    /// Revoke an OAuth token so it can no longer be used.
+    pub async fn revoke_oauth_token(&self, id: &oauth::TokenId) -> Result<(), error::Error> {
+        let response = self
+            .request(Method::DELETE, &format!("/oauth/tokens/{}", id.as_str()))
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+}
+
+pub struct MessageChannel {
+    pub id: String,
     pub stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Max message size accepted by the server. Larger messages close the connection.
+    pub max_message_size: Option<usize>,
+    client: Client,
+    /// State registered for the client (used when reconnecting)
+    state: ExternalClientState,
 }
 
 impl MessageChannel {
+    /// Re-establish the connection (eg, after it was closed by the server) and
+    /// register the original address and app ID again.
+    pub async fn reconnect(&mut self) -> Result<(), error::Error> {
+        self.stream = self.client.open_socket(&self.id).await?;
+        self.register_state().await
+    }
+
+    async fn register_state(&self) -> Result<(), error::Error> {
+        let state = ClientStateData {
+            state: ClientState::External(self.state.clone()),
+        };
+
+        let response = self
+            .client
+            .request(Method::POST, &format!("/network/{}/state", self.id))
+            .json(&state)
+            .send()
+            .await
+            .map_err(error::Error::RequestError)?;
+
+        check_response(response).await?;
+        Ok(())
+    }
+
     // TODO: do we need a method for sending other types?
     // TODO: sending a generic struct (implementing Deserialize)
     pub async fn send_json(
@@ -1336,6 +3012,12 @@ impl MessageChannel {
             "content": data
         });
         let msg_text = serde_json::to_string(&msg).unwrap();
+        if let Some(max_size) = self.max_message_size {
+            if msg_text.len() > max_size {
+                return Err(error::Error::MessageTooLargeError(msg_text.len(), max_size));
+            }
+        }
+
         self.stream
             .send(Message::Text(msg_text))
             .await
@@ -1343,14 +3025,1115 @@ impl MessageChannel {
 
         Ok(())
     }
+
+    /// Wait for the next message. Returns `None` when the connection has been closed.
+    pub async fn recv_message(&mut self) -> Result<Option<NetworkMessage>, error::Error> {
+        while let Some(frame) = self.stream.next().await {
+            let frame = frame.map_err(error::Error::WebSocketReceiveError)?;
+            match frame {
+                Message::Text(text) => {
+                    let msg =
+                        serde_json::from_str(&text).map_err(error::Error::InvalidMessageError)?;
+                    return Ok(Some(msg));
+                }
+                Message::Close(_) => return Ok(None),
+                _ => (),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Message received over the NetsBlox network
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkMessage {
+    /// Type of the envelope (eg, "message" for NetsBlox messages)
+    pub r#type: String,
+    /// NetsBlox message type (for "message" envelopes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_type: Option<String>,
+    /// Address of the sender (eg, "role@project@owner")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_id: Option<String>,
+    /// Any other fields in the message (eg, "content")
+    #[serde(flatten)]
+    pub data: serde_json::Map<String, Value>,
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+
+    /// A request received by a mock server
+    #[derive(Clone, Debug)]
+    pub(crate) struct MockRequest {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    impl MockRequest {
+        /// The path of the request without the query string
+        fn route(&self) -> &str {
+            self.path.split('?').next().unwrap()
+        }
+
+        /// Get the value of the given header (if received)
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .get(&name.to_lowercase())
+                .map(|value| value.as_str())
+        }
+
+        /// Get the value of the given query parameter (if received)
+        fn param(&self, name: &str) -> Option<String> {
+            let url = reqwest::Url::parse(&format!("http://localhost{}", self.path)).unwrap();
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        }
+
+        /// The JSON body of the request (null if the body isn't JSON)
+        fn json(&self) -> Value {
+            serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+        }
+    }
+
+    /// A response sent by a mock server
+    pub(crate) struct MockResponse {
+        status: &'static str,
+        headers: Vec<(&'static str, String)>,
+        body: String,
+        content_length: bool,
+        close_socket: bool,
+    }
+
+    impl MockResponse {
+        /// Respond with the given body (and a 200 status)
+        fn text(body: impl Into<String>) -> Self {
+            MockResponse {
+                status: "200 OK",
+                headers: Vec::new(),
+                body: body.into(),
+                content_length: true,
+                close_socket: false,
+            }
+        }
+
+        /// Respond with the given JSON (and a 200 status)
+        pub(crate) fn json(body: impl Into<String>) -> Self {
+            MockResponse::text(body).header("Content-Type", "application/json")
+        }
+
+        fn status(mut self, status: &'static str) -> Self {
+            self.status = status;
+            self
+        }
+
+        fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+            self.headers.push((name, value.into()));
+            self
+        }
+
+        /// Omit the Content-Length header so the body ends when the connection closes
+        fn without_content_length(mut self) -> Self {
+            self.content_length = false;
+            self
+        }
+
+        /// Close the websocket as soon as it is opened (websocket requests only)
+        fn close_socket(mut self) -> Self {
+            self.close_socket = true;
+            self
+        }
+    }
+
+    /// Serve requests from a local server, responding to each with the result of
+    /// the given handler. Every request is recorded including websocket connections
+    /// which are kept open unless the response closes them.
+    pub(crate) fn serve_json_requests<F>(mut handler: F) -> (String, Arc<Mutex<Vec<MockRequest>>>)
+    where
+        F: FnMut(&MockRequest) -> MockResponse + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            let mut sockets = Vec::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut head = [0; 1024];
+                let len = stream.peek(&mut head).unwrap();
+                let head = String::from_utf8_lossy(&head[..len]).to_string();
+                let is_websocket = head.to_lowercase().contains("upgrade: websocket");
+
+                if is_websocket {
+                    let request = websocket_request(&head);
+                    let mut socket = tungstenite::accept(stream).unwrap();
+                    received.lock().unwrap().push(request.clone());
+                    if handler(&request).close_socket {
+                        socket.close(None).unwrap();
+                        socket.flush().unwrap();
+                    }
+                    sockets.push(socket);
+                    continue;
+                }
+
+                let request = read_request(&stream);
+                received.lock().unwrap().push(request.clone());
+                let response = handler(&request);
+
+                let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+                for (name, value) in response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                if response.content_length {
+                    head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+                }
+                // the client may close the connection before reading everything
+                let _ = write!(stream, "{}Connection: close\r\n\r\n{}", head, response.body);
+            }
+        });
+
+        (url, requests)
+    }
+
+    /// Serve empty responses from a local server, recording each request
+    fn serve_requests() -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        serve_json_requests(|_| MockResponse::json(""))
+    }
+
+    fn read_request(stream: &TcpStream) -> MockRequest {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut headers = HashMap::new();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.to_lowercase(), value.trim().to_owned());
+            }
+            line.clear();
+        }
+        let content_length = headers
+            .get("content-length")
+            .map(|length| length.parse().unwrap())
+            .unwrap_or(0);
+        let mut body = vec![0; content_length];
+        std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+
+        let mut request_line = request_line.split_whitespace();
+        MockRequest {
+            method: request_line.next().unwrap().to_owned(),
+            path: request_line.next().unwrap().to_owned(),
+            headers,
+            body,
+        }
+    }
+
+    /// Parse a websocket request from its head (websocket requests have no body)
+    fn websocket_request(head: &str) -> MockRequest {
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap().split_whitespace();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.to_lowercase(), value.trim().to_owned()))
+            .collect();
+
+        MockRequest {
+            method: request_line.next().unwrap().to_owned(),
+            path: request_line.next().unwrap().to_owned(),
+            headers,
+            body: Vec::new(),
+        }
+    }
+
+    /// Serve the given users from a local server which pages them like the
+    /// cloud.
+    pub(crate) fn serve_users(usernames: &[&str]) -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        let users: Vec<User> = usernames
+            .iter()
+            .map(|name| User {
+                username: name.to_string(),
+                email: format!("{}@netsblox.org", name),
+                group_id: None,
+                role: UserRole::User,
+                created_at: SystemTime::now(),
+                linked_accounts: Vec::new(),
+                services_hosts: None,
+                delete_at: None,
+            })
+            .collect();
+
+        serve_json_requests(move |request| {
+            let after = request.param("after");
+            let limit: usize = request.param("limit").unwrap().parse().unwrap();
+            let page: Vec<_> = users
+                .iter()
+                .skip_while(|user| after.as_ref().is_some_and(|after| &user.username <= after))
+                .take(limit)
+                .collect();
+            MockResponse::json(serde_json::to_string(&page).unwrap())
+        })
+    }
+
+    #[test]
+    fn build_config_with_app_id() {
+        let cfg = Config::builder()
+            .url("http://localhost:7777/")
+            .app_id(AppId::new("TestApp"))
+            .username("user")
+            .token("token")
+            .build()
+            .unwrap();
+
+        assert_eq!(cfg.url, "http://localhost:7777");
+        assert_eq!(cfg.app_id, Some(AppId::new("TestApp")));
+        assert_eq!(cfg.username.as_deref(), Some("user"));
+        assert_eq!(cfg.token.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn build_config_without_app_id() {
+        let cfg = Config::builder().build().unwrap();
+
+        assert_eq!(cfg.url, Config::default().url);
+        assert!(cfg.app_id.is_none());
+    }
+
+    #[test]
+    fn build_config_invalid_url() {
+        let result = Config::builder().url("localhost:7777").build();
+        assert!(matches!(result, Err(error::Error::InvalidUrlError(..))));
+
+        let result = Config::builder().url("not a url").build();
+        assert!(matches!(result, Err(error::Error::InvalidUrlError(..))));
+    }
+
+    #[tokio::test]
+    async fn connect_without_app_id() {
+        let cfg = Config::builder().url("http://127.0.0.1:1").build().unwrap();
+        let client = Client::new(cfg);
+
+        let result = client.connect("myAddress").await;
+        assert!(matches!(result, Err(error::Error::MissingAppIdError)));
+    }
+
+    #[tokio::test]
+    async fn connect_with_app_id() {
+        let cfg = Config::builder()
+            .url("http://127.0.0.1:1")
+            .app_id(AppId::new("TestApp"))
+            .build()
+            .unwrap();
+        let client = Client::new(cfg);
+
+        // nothing is listening so it should fail to reach the server rather
+        // than complaining about the configuration
+        let result = client.connect("myAddress").await;
+        assert!(matches!(result, Err(error::Error::RequestError(..))));
+    }
+
+    #[tokio::test]
+    async fn list_projects_starred_first() {
+        let (url, requests) = serve_json_requests(|_| MockResponse::json("[]"));
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        client.list_projects("brian", true).await.unwrap();
+        client.list_shared_projects("brian", false).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].route(), "/projects/user/brian");
+        assert_eq!(requests[0].param("starredFirst").as_deref(), Some("true"));
+        assert_eq!(requests[1].route(), "/projects/shared/brian");
+        assert_eq!(requests[1].param("starredFirst").as_deref(), Some("false"));
+    }
+
+    #[tokio::test]
+    async fn project_tags_are_encoded() {
+        let (url, requests) = serve_requests();
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        let id = ProjectId::new("someProject".into());
+
+        let _ = client.add_project_tag(&id, "unit-3/robots?").await;
+        let _ = client.remove_project_tag(&id, "a#b").await;
+
+        let routes: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.path.clone())
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                "/projects/id/someProject/tags/unit-3%2Frobots%3F",
+                "/projects/id/someProject/tags/a%23b",
+            ]
+        );
+    }
+
+    /// Serve the configuration, websocket and state endpoints used when
+    /// connecting to the network. The first websocket is closed by the server
+    /// immediately to simulate a dropped connection.
+    fn serve_network() -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        let mut sockets = 0;
+        serve_json_requests(move |request| {
+            if request.route().ends_with("/connect") {
+                sockets += 1;
+                let response = MockResponse::text("");
+                return if sockets == 1 {
+                    response.close_socket()
+                } else {
+                    response
+                };
+            }
+
+            if request.route() == "/configuration" {
+                let config = ClientConfig {
+                    client_id: "_netsblox_test".into(),
+                    username: None,
+                    services_hosts: Vec::new(),
+                    cloud_url: "".into(),
+                    max_message_size: None,
+                };
+                MockResponse::json(serde_json::to_string(&config).unwrap())
+            } else {
+                MockResponse::json("")
+            }
+        })
+    }
+
+    /// Get the states set on the mock network server
+    fn client_states(requests: &[MockRequest]) -> Vec<ClientStateData> {
+        requests
+            .iter()
+            .filter(|request| request.route().ends_with("/state"))
+            .map(|request| serde_json::from_value(request.json()).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reconnect_registers_state() {
+        let (url, requests) = serve_network();
+        let cfg = Config::builder()
+            .url(&url)
+            .app_id(AppId::new("TestApp"))
+            .build()
+            .unwrap();
+        let client = Client::new(cfg);
+
+        let mut channel = client.connect("myAddress").await.unwrap();
+        assert_eq!(client_states(&requests.lock().unwrap()).len(), 1);
+
+        // the server drops the first connection
+        let msg = channel.recv_message().await.unwrap();
+        assert!(msg.is_none());
+
+        channel.reconnect().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let sockets = requests
+            .iter()
+            .filter(|request| request.route().ends_with("/connect"))
+            .count();
+        let states = client_states(&requests);
+        assert_eq!(sockets, 2);
+        assert_eq!(states.len(), 2);
+
+        let expected = ClientState::External(ExternalClientState {
+            address: "myAddress".into(),
+            app_id: AppId::new("TestApp"),
+        });
+        assert!(states.iter().all(|data| data.state == expected));
+    }
+
+    #[tokio::test]
+    async fn list_queued_messages() {
+        let msgs = json!([{
+            "appId": "testapp",
+            "address": "device@brian",
+            "content": {"type": "message"},
+            "createdAt": {"secs_since_epoch": 0, "nanos_since_epoch": 0},
+            "expiresAt": {"secs_since_epoch": 60, "nanos_since_epoch": 0},
+        }]);
+        let (url, requests) = serve_json_requests(move |_| MockResponse::json(msgs.to_string()));
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let msgs = client.list_queued_messages().await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].address, "device@brian");
+        assert_eq!(msgs[0].app_id, AppId::new("TestApp"));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].path, "/network/messages/queued");
+    }
+
+    #[tokio::test]
+    async fn invite_occupant() {
+        let invite = json!({
+            "username": "hamid",
+            "projectId": "someProject",
+            "roleId": "someRole",
+            "createdAt": SystemTime::now(),
+        });
+        let (url, requests) = serve_json_requests(move |_| MockResponse::json(invite.to_string()));
+        let client = Client::new(Config {
+            url,
+            username: Some("brian".into()),
+            ..Default::default()
+        });
+
+        let project_id = ProjectId::new("someProject".into());
+        let data = OccupantInviteData {
+            username: "hamid".into(),
+            role_id: RoleId::new("someRole".into()),
+            sender: Some("brian".into()),
+        };
+        let invite = client.invite_occupant(&project_id, &data).await.unwrap();
+
+        assert_eq!(invite.username, "hamid");
+        assert_eq!(invite.role_id, data.role_id);
+        let requests = requests.lock().unwrap();
+        let (path, body) = (&requests[0].path, requests[0].json());
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(path, "/network/id/someProject/occupants/invite");
+        assert_eq!(body["username"], "hamid");
+        assert_eq!(body["roleId"], "someRole");
+        assert_eq!(body["sender"], "brian");
+    }
+
+    #[tokio::test]
+    async fn invite_collaborator_existing() {
+        let invite = json!({
+            "id": "existingInvite",
+            "sender": "brian",
+            "receiver": "hamid",
+            "projectId": "someProject",
+            "state": "Pending",
+            "createdAt": SystemTime::now(),
+        });
+        let (url, requests) = serve_json_requests(move |_| MockResponse::json(invite.to_string()));
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let project_id = ProjectId::new("someProject".into());
+        for _ in 0..2 {
+            let invite = client
+                .invite_collaborator(&project_id, "hamid")
+                .await
+                .unwrap();
+            assert_eq!(invite.id, "existingInvite");
+        }
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].path,
+            "/collaboration-invites/someProject/invite/hamid"
+        );
+    }
+
+    #[tokio::test]
+    async fn respond_to_occupant_invite() {
+        let (url, requests) = serve_requests();
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        let invite = OccupantInvite {
+            username: "hamid".into(),
+            project_id: ProjectId::new("someProject".into()),
+            role_id: RoleId::new("someRole".into()),
+            created_at: SystemTime::now(),
+        };
+        let client_id = ClientId::new("_someClient".into());
+
+        client
+            .respond_to_occupant_invite(&client_id, &invite, false)
+            .await
+            .unwrap();
+        assert!(requests.lock().unwrap().is_empty());
+
+        client
+            .respond_to_occupant_invite(&client_id, &invite, true)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let (path, body) = (&requests[0].path, requests[0].json());
+        assert_eq!(path, "/network/_someClient/state");
+        assert_eq!(body["state"]["browser"]["projectId"], "someProject");
+        assert_eq!(body["state"]["browser"]["roleId"], "someRole");
+    }
+
+    #[tokio::test]
+    async fn on_behalf_of_header() {
+        let (url, requests) = serve_requests();
+        let mut client = Client::new(Config {
+            url,
+            username: Some("admin".into()),
+            ..Default::default()
+        });
+
+        client.delete_user("admin").await.unwrap();
+        client.set_on_behalf_of(Some("admin".into()));
+        client.delete_user("admin").await.unwrap();
+        client.set_on_behalf_of(Some("brian".into()));
+        client.delete_user("brian").await.unwrap();
+
+        let on_behalf_of: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                request
+                    .header(ON_BEHALF_OF_HEADER)
+                    .map(|name| name.to_owned())
+            })
+            .collect();
+        assert_eq!(on_behalf_of, vec![None, None, Some("brian".to_owned())]);
+    }
+
+    /// Respond with project metadata (and an ETag) unless the If-None-Match
+    /// header matches the given ETag.
+    fn serve_metadata(etag: &'static str) -> String {
+        let metadata = ProjectMetadata {
+            id: ProjectId::new("someProject".into()),
+            owner: "brian".into(),
+            name: "myProject".into(),
+            updated: SystemTime::now(),
+            state: PublishState::Private,
+            collaborators: Vec::new(),
+            collaborator_roles: HashMap::new(),
+            network_traces: Vec::new(),
+            origin_time: SystemTime::now(),
+            save_state: SaveState::Saved,
+            roles: HashMap::new(),
+            tags: Vec::new(),
+            version: 0,
+        };
+        let body = serde_json::to_string(&metadata).unwrap();
+
+        let (url, _requests) = serve_json_requests(move |request| {
+            if request.header("if-none-match") == Some(etag) {
+                MockResponse::text("")
+                    .status("304 Not Modified")
+                    .header("ETag", etag)
+            } else {
+                MockResponse::json(body.clone()).header("ETag", etag)
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn get_project_metadata_not_modified() {
+        let url = serve_metadata("\"abc\"");
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let result = client
+            .get_project_metadata_if_modified("brian", "myProject", Some("\"abc\""))
+            .await
+            .unwrap();
+
+        assert!(matches!(result, Conditional::NotModified));
+    }
+
+    #[tokio::test]
+    async fn get_project_metadata_modified() {
+        let url = serve_metadata("\"abc\"");
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let result = client
+            .get_project_metadata_if_modified("brian", "myProject", Some("\"old\""))
+            .await
+            .unwrap();
+
+        match result {
+            Conditional::Modified(metadata, etag) => {
+                assert_eq!(metadata.name, "myProject");
+                assert_eq!(etag.as_deref(), Some("\"abc\""));
+            }
+            Conditional::NotModified => panic!("Expected metadata"),
+        }
+    }
+
+    /// Respond to any request with the given body. The Content-Length header is
+    /// omitted (and the connection closed) unless `content_length` is set.
+    fn serve_body(body: String, content_length: bool) -> String {
+        let (url, _requests) = serve_json_requests(move |_| {
+            let response = MockResponse::text(body.clone());
+            if content_length {
+                response
+            } else {
+                response.without_content_length()
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn get_library_within_limit() {
+        let url = serve_body("<blocks/>".into(), true);
+        let mut client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        client.set_max_response_size(100);
+
+        let library = client.get_library("brian", "myLibrary").await.unwrap();
+
+        assert_eq!(library, "<blocks/>");
+    }
+
+    #[tokio::test]
+    async fn get_library_too_large() {
+        let url = serve_body("x".repeat(1000), true);
+        let mut client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        client.set_max_response_size(100);
+
+        let err = client.get_library("brian", "myLibrary").await.unwrap_err();
+
+        assert!(matches!(err, error::Error::ResponseTooLargeError(100)));
+    }
+
+    #[tokio::test]
+    async fn get_library_too_large_without_content_length() {
+        let url = serve_body("x".repeat(1000), false);
+        let mut client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        client.set_max_response_size(100);
+
+        let err = client.get_library("brian", "myLibrary").await.unwrap_err();
+
+        assert!(matches!(err, error::Error::ResponseTooLargeError(100)));
+    }
+
+    #[tokio::test]
+    async fn get_project_too_large() {
+        let url = serve_body(format!("{{\"name\": \"{}\"}}", "x".repeat(1000)), false);
+        let mut client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        client.set_max_response_size(100);
+
+        let id = ProjectId::new("someProject".into());
+        let err = client.get_project(&id, &false).await.unwrap_err();
+
+        assert!(matches!(err, error::Error::ResponseTooLargeError(100)));
+    }
+
+    /// Respond to any request with the given error status and body (including
+    /// the request ID header)
+    fn serve_error(status: &'static str, body: &'static str) -> String {
+        let (url, _requests) = serve_json_requests(move |_| {
+            MockResponse::text(body)
+                .status(status)
+                .header(REQUEST_ID_HEADER, "someRequestId")
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn error_request_id() {
+        let url = serve_error("404 Not Found", "User not found.");
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let err = client.delete_user("brian").await.unwrap_err();
+
+        assert!(matches!(err, error::Error::NotFoundError(_)));
+        assert!(err.code().is_none());
+        assert_eq!(
+            err.to_string(),
+            "User not found. (Request ID: someRequestId)"
+        );
+    }
+
+    #[tokio::test]
+    async fn error_json_body() {
+        let url = serve_error(
+            "404 Not Found",
+            r#"{"code":"UserNotFoundError","message":"User not found.","requestId":"someRequestId"}"#,
+        );
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let err = client.delete_user("brian").await.unwrap_err();
+
+        assert!(matches!(err, error::Error::NotFoundError(_)));
+        assert_eq!(err.code(), Some("UserNotFoundError"));
+        assert_eq!(
+            err.to_string(),
+            "User not found. (Request ID: someRequestId)"
+        );
+    }
+
+    #[tokio::test]
+    async fn error_request_id_in_body() {
+        let url = serve_error(
+            "500 Internal Server Error",
+            "An internal error occurred. (Request ID: someRequestId)",
+        );
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let err = client.delete_user("brian").await.unwrap_err();
+
+        assert!(matches!(err, error::Error::InternalServerError(_)));
+        assert_eq!(
+            err.to_string(),
+            "An internal error occurred. (Request ID: someRequestId)"
+        );
+    }
+
+    /// Serve the magic link login endpoint. The given link can only be used once;
+    /// using it again (or using any other link) is a bad request.
+    fn serve_magic_link_login(link_id: &str) -> String {
+        let mut valid_link = Some(link_id.to_owned());
+
+        let (url, _requests) = serve_json_requests(move |request| {
+            let data: MagicLinkLoginData = serde_json::from_value(request.json()).unwrap();
+            if valid_link.as_deref() == Some(data.link_id.as_str()) {
+                valid_link = None;
+                let user = User {
+                    username: data.username,
+                    email: "user@netsblox.org".into(),
+                    group_id: None,
+                    role: UserRole::User,
+                    created_at: SystemTime::now(),
+                    linked_accounts: Vec::new(),
+                    services_hosts: None,
+                    delete_at: None,
+                };
+                MockResponse::json(serde_json::to_string(&user).unwrap())
+                    .header("Set-Cookie", "netsblox=sessionToken; Path=/")
+            } else {
+                MockResponse::text("Magic link not found.").status("400 Bad Request")
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn login_with_magic_link() {
+        let url = serve_magic_link_login("someLink");
+        let client = Client::new(Config {
+            url: url.clone(),
+            ..Default::default()
+        });
+        let data = MagicLinkLoginData {
+            link_id: MagicLinkId::new("someLink".into()),
+            username: "brian".into(),
+            client_id: None,
+            redirect_uri: None,
+        };
+
+        let cfg = client.login_with_magic_link(&data).await.unwrap();
+        assert_eq!(cfg.url, url);
+        assert_eq!(cfg.username.as_deref(), Some("brian"));
+        assert_eq!(cfg.token.as_deref(), Some("sessionToken"));
+
+        let result = client.login_with_magic_link(&data).await;
+        assert!(matches!(result, Err(error::Error::MagicLinkExpiredError)));
+    }
+
+    #[tokio::test]
+    async fn login_with_password_reset_link() {
+        let url = serve_error(
+            "400 Bad Request",
+            r#"{"code":"PasswordResetLinkError","message":"Password reset links must be opened in a browser."}"#,
+        );
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+        let data = MagicLinkLoginData {
+            link_id: MagicLinkId::new("someLink".into()),
+            username: "brian".into(),
+            client_id: None,
+            redirect_uri: None,
+        };
+
+        let err = client.login_with_magic_link(&data).await.unwrap_err();
+        assert!(matches!(err, error::Error::BadRequestError(_)));
+        assert_eq!(err.code(), Some("PasswordResetLinkError"));
+    }
+
+    /// Serve the OAuth authorization code flow, issuing the given code (which
+    /// can be exchanged for a token once).
+    fn serve_oauth(code: &str) -> String {
+        let code = code.to_owned();
+        let mut valid_code = Some(code.clone());
+
+        let (url, _requests) = serve_json_requests(move |request| match request.route() {
+            "/oauth/brian/code" => {
+                let location = format!(
+                    "{}?code={}&state={}",
+                    request.param("redirect_uri").unwrap(),
+                    code,
+                    request.param("state").unwrap()
+                );
+                MockResponse::json("")
+                    .status("302 Found")
+                    .header("Location", location)
+            }
+            "/oauth/token/" => {
+                let params: oauth::CreateTokenParams =
+                    serde_json::from_value(request.json()).unwrap();
+                if params.code.is_some() && params.code == valid_code {
+                    valid_code = None;
+                    MockResponse::json("\"someToken\"")
+                } else {
+                    MockResponse::json("Invalid authorization code").status("400 Bad Request")
+                }
+            }
+            "/oauth/token/someToken" => {
+                let token = oauth::Token {
+                    id: oauth::TokenId::new("someToken".into()),
+                    client_id: oauth::ClientId::new("someClient".into()),
+                    username: "brian".into(),
+                    created_at: SystemTime::now(),
+                };
+                MockResponse::json(serde_json::to_string(&token).unwrap())
+            }
+            _ => MockResponse::json("").status("404 Not Found"),
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn oauth_code_token_exchange() {
+        let url = serve_oauth("someCode");
+        let client = Client::new(Config {
+            url,
+            username: Some("brian".into()),
+            token: Some("sessionToken".into()),
+            ..Default::default()
+        });
+        let auth_request = oauth::AuthorizationRequest {
+            client_id: oauth::ClientId::new("someClient".into()),
+            client_secret: "secret".into(),
+            redirect_uri: "http://localhost:9000/callback".into(),
+            state: "someState".into(),
+        };
+
+        let code = client.oauth_authorize(&auth_request).await.unwrap();
+        assert_eq!(code.as_str(), "someCode");
+
+        let token = client
+            .oauth_token(&code, &auth_request.redirect_uri)
+            .await
+            .unwrap();
+        assert_eq!(token.id.as_str(), "someToken");
+        assert_eq!(token.client_id, auth_request.client_id);
+        assert_eq!(token.username, "brian");
+    }
+
+    #[tokio::test]
+    async fn oauth_token_invalid_code() {
+        let url = serve_oauth("someCode");
+        let client = Client::new(Config {
+            url,
+            username: Some("brian".into()),
+            token: Some("sessionToken".into()),
+            ..Default::default()
+        });
+
+        let code = oauth::CodeId::new("otherCode".into());
+        let result = client
+            .oauth_token(&code, "http://localhost:9000/callback")
+            .await;
+        assert!(matches!(result, Err(error::Error::BadRequestError(_))));
+    }
+
+    /// Send a message to the given target and return the request body received
+    async fn send_message_body(target: SendMessageTarget) -> Value {
+        let (url, requests) = serve_requests();
+        let client = Client::new(Config {
+            url,
+            username: Some("brian".into()),
+            ..Default::default()
+        });
+
+        client
+            .send_message(target, "greeting", &json!({"msg": "hello"}))
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let (path, body) = (&requests[0].path, requests[0].json());
+        assert_eq!(path, "/network/messages/");
+        assert_eq!(body["sender"], json!({"username": "brian"}));
+        assert_eq!(
+            body["content"],
+            json!({
+                "type": "message",
+                "msgType": "greeting",
+                "content": {"msg": "hello"}
+            })
+        );
+        body
+    }
+
+    #[tokio::test]
+    async fn send_message_address() {
+        let target = SendMessageTarget::Address {
+            address: "someAddress".into(),
+        };
+        let body = send_message_body(target).await;
+
+        let expected = json!({"address": {"address": "someAddress"}});
+        assert_eq!(body["target"], expected);
+    }
+
+    #[tokio::test]
+    async fn send_message_room() {
+        let target = SendMessageTarget::Room {
+            project_id: ProjectId::new("someProject".into()),
+        };
+        let body = send_message_body(target).await;
+
+        let expected = json!({"room": {"projectId": "someProject"}});
+        assert_eq!(body["target"], expected);
+    }
+
+    #[tokio::test]
+    async fn send_message_role() {
+        let target = SendMessageTarget::Role {
+            project_id: ProjectId::new("someProject".into()),
+            role_id: RoleId::new("someRole".into()),
+        };
+        let body = send_message_body(target).await;
+
+        let expected = json!({"role": {"projectId": "someProject", "roleId": "someRole"}});
+        assert_eq!(body["target"], expected);
+    }
+
+    #[tokio::test]
+    async fn send_message_client() {
+        let target = SendMessageTarget::Client {
+            state: None,
+            client_id: ClientId::new("_someClient".into()),
+        };
+        let body = send_message_body(target).await;
+
+        let expected = json!({"client": {"state": null, "clientId": "_someClient"}});
+        assert_eq!(body["target"], expected);
+    }
+
+    #[tokio::test]
+    async fn stream_users_all_pages() {
+        let usernames = ["alice", "bob", "carol", "dave", "eve"];
+        let (url, requests) = serve_users(&usernames);
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let users: Vec<_> = client
+            .stream_users_by_page(2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let streamed: Vec<_> = users.iter().map(|user| user.username.as_str()).collect();
+        assert_eq!(streamed, usernames);
+
+        let cursors: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.param("after"))
+            .collect();
+        let expected = [None, Some("bob".to_string()), Some("dave".to_string())];
+        assert_eq!(cursors, expected);
+    }
+
+    #[tokio::test]
+    async fn stream_users_exact_page() {
+        let usernames = ["alice", "bob"];
+        let (url, requests) = serve_users(&usernames);
+        let client = Client::new(Config {
+            url,
+            ..Default::default()
+        });
+
+        let users: Vec<_> = client
+            .stream_users_by_page(2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let streamed: Vec<_> = users.iter().map(|user| user.username.as_str()).collect();
+        assert_eq!(streamed, usernames);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
 
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn normalize_host_url_equivalent() {
+        let expected = normalize_host_url("http://localhost:8000");
+        assert_eq!(normalize_host_url("http://localhost:8000/"), expected);
+        assert_eq!(normalize_host_url("http://LocalHost:8000"), expected);
+    }
+
+    #[test]
+    fn normalize_host_url_default_port() {
+        assert_eq!(
+            normalize_host_url("https://services.netsblox.org:443/"),
+            "https://services.netsblox.org"
+        );
+        assert_eq!(
+            normalize_host_url("http://services.netsblox.org:80/api/"),
+            "http://services.netsblox.org/api"
+        );
+    }
 }
@@ -0,0 +1,194 @@
+use reqwest::{header::HeaderName, Body, Method, RequestBuilder, Response};
+use serde::Serialize;
+
+/// A request to the cloud. This wraps the underlying request builder so each
+/// request can be traced (when the "tracing" feature is enabled) without any
+/// overhead otherwise.
+pub(crate) struct Request {
+    builder: RequestBuilder,
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    method: Method,
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    path: String,
+}
+
+impl Request {
+    pub(crate) fn new(builder: RequestBuilder, method: Method, path: &str) -> Self {
+        Self {
+            builder,
+            method,
+            path: path.to_owned(),
+        }
+    }
+
+    pub(crate) fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+
+    pub(crate) fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+
+    pub(crate) fn header(mut self, name: HeaderName, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    pub(crate) fn body<T: Into<Body>>(mut self, body: T) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) async fn send(self) -> Result<Response, reqwest::Error> {
+        self.builder.send().await
+    }
+
+    /// Send the request within a span recording the method, path, status and
+    /// elapsed time. Headers (including the session cookie), bodies and query
+    /// strings are never recorded.
+    #[cfg(feature = "tracing")]
+    pub(crate) async fn send(self) -> Result<Response, reqwest::Error> {
+        use tracing::{field, Instrument};
+
+        let span = tracing::debug_span!(
+            "request",
+            method = %self.method,
+            path = redact_path(&self.path),
+            status = field::Empty,
+            elapsed_ms = field::Empty,
+        );
+
+        let start = std::time::Instant::now();
+        let result = self.builder.send().instrument(span.clone()).await;
+        span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+
+        match &result {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+            }
+            Err(err) => {
+                tracing::debug!(parent: &span, error = %err, "request failed");
+            }
+        }
+
+        result
+    }
+}
+
+/// Remove anything from the path which could contain secrets (like tokens in
+/// the query string).
+#[cfg(feature = "tracing")]
+fn redact_path(path: &str) -> &str {
+    path.split(['?', '#']).next().unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::common::ListUsersQuery;
+    use crate::{Client, Config};
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    type Fields = HashMap<String, String>;
+    /// Name, target and fields of a span
+    type SpanRecord = (&'static str, &'static str, Fields);
+
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0
+                .insert(field.name().to_owned(), format!("{:?}", value));
+        }
+    }
+
+    /// Subscriber which records each span created
+    #[derive(Clone, Default)]
+    struct SpanCapture {
+        spans: Arc<Mutex<Vec<SpanRecord>>>,
+    }
+
+    impl Subscriber for SpanCapture {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields::new();
+            span.record(&mut FieldVisitor(&mut fields));
+
+            let metadata = span.metadata();
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((metadata.name(), metadata.target(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let (_, _, fields) = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut FieldVisitor(fields));
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn redact_path_query() {
+        assert_eq!(redact_path("/users/?token=secret"), "/users/");
+        assert_eq!(redact_path("/users/#token"), "/users/");
+        assert_eq!(redact_path("/users/brian"), "/users/brian");
+    }
+
+    #[tokio::test]
+    async fn send_records_request_span() {
+        let (url, _requests) = crate::tests::serve_users(&["alice"]);
+        let client = Client::new(Config {
+            url,
+            token: Some("secretToken".into()),
+            ..Default::default()
+        });
+
+        let capture = SpanCapture::default();
+        let _guard = tracing::subscriber::set_default(capture.clone());
+        let query = ListUsersQuery {
+            after: Some("secretCursor".into()),
+            limit: Some(10),
+        };
+        client.list_users_page(&query).await.unwrap();
+
+        let spans = capture.spans.lock().unwrap();
+        let requests: Vec<_> = spans
+            .iter()
+            .filter(|(name, target, _)| *name == "request" && target.starts_with("netsblox_api"))
+            .map(|(_, _, fields)| fields)
+            .collect();
+        assert_eq!(requests.len(), 1);
+
+        let fields = requests[0];
+        assert_eq!(fields.get("method").unwrap(), "GET");
+        assert_eq!(fields.get("path").unwrap(), "/users/");
+        assert_eq!(fields.get("status").unwrap(), "200");
+        assert!(fields.contains_key("elapsed_ms"));
+
+        let values: Vec<_> = fields.values().collect();
+        assert!(values.iter().all(|value| !value.contains("secret")));
+    }
+}
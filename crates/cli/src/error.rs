@@ -1,4 +1,5 @@
 use derive_more::Display;
+use netsblox_api::error::Error as APIError;
 
 #[derive(Debug, Display)]
 pub enum Error {
@@ -8,10 +9,167 @@ pub enum Error {
     HostNotFoundError,
     #[display(fmt = "Service host not found.")]
     ServiceHostNotFoundError,
+    #[display(fmt = "Not logged in.")]
+    NotLoggedInError,
+    #[display(fmt = "Session expired. Please login again.")]
+    SessionExpiredError,
+    #[display(fmt = "Unable to read password from stdin.")]
+    PasswordReadError,
+    #[display(fmt = "Group not found.")]
+    GroupNotFoundError,
+    #[display(fmt = "Invalid roster: {}", _0)]
+    RosterParseError(String),
+    #[display(fmt = "Failed to create {} of {} users.", _0, _1)]
+    BulkCreateError(usize, usize),
+    #[display(fmt = "Invalid project: {}", _0)]
+    ProjectParseError(String),
+    #[display(fmt = "Failed to import {} of {} projects.", _0, _1)]
+    BatchImportError(usize, usize),
+    #[display(fmt = "{} already exists. Use --force to overwrite it.", _0)]
+    FileExistsError(String),
+    #[display(fmt = "Unable to write {}: {}", _0, _1)]
+    WriteFileError(String, std::io::Error),
 }
 
-impl From<netsblox_api::error::Error> for Error {
-    fn from(api_err: netsblox_api::error::Error) -> Error {
+impl Error {
+    /// Exit code to use when the command fails with this error
+    pub fn exit_code(&self) -> exitcode::ExitCode {
+        match self {
+            Error::APIError(err) => match err {
+                APIError::RequestError(..) | APIError::WebSocketConnectError(..) => {
+                    exitcode::NOHOST
+                }
+                APIError::WebSocketSendError(..)
+                | APIError::WebSocketReceiveError(..)
+                | APIError::InternalServerError(_) => exitcode::UNAVAILABLE,
+                APIError::LoginRequiredError
+                | APIError::PermissionsError(..)
+                | APIError::MagicLinkExpiredError => exitcode::NOPERM,
+                APIError::NotFoundError(..) => exitcode::NOINPUT,
+                APIError::TooManyRequestsError(..) => exitcode::TEMPFAIL,
+                APIError::BadRequestError(..)
+                | APIError::ConflictError(..)
+                | APIError::MessageTooLargeError(..)
+                | APIError::ResponseTooLargeError(..) => exitcode::DATAERR,
+                APIError::InvalidUrlError(..) | APIError::MissingAppIdError => exitcode::CONFIG,
+                APIError::ParseResponseFailedError(..)
+                | APIError::InvalidResponseError(..)
+                | APIError::MissingCookieError
+                | APIError::InvalidMessageError(..)
+                | APIError::InvalidRedirectError(..)
+                | APIError::OAuthStateMismatchError
+                | APIError::UnexpectedStatusError(..) => exitcode::PROTOCOL,
+            },
+            Error::NotLoggedInError | Error::SessionExpiredError => exitcode::USAGE,
+            Error::HostNotFoundError
+            | Error::ServiceHostNotFoundError
+            | Error::GroupNotFoundError => exitcode::NOINPUT,
+            Error::PasswordReadError | Error::WriteFileError(..) => exitcode::IOERR,
+            Error::FileExistsError(..) => exitcode::CANTCREAT,
+            Error::RosterParseError(..)
+            | Error::BulkCreateError(..)
+            | Error::ProjectParseError(..)
+            | Error::BatchImportError(..) => exitcode::DATAERR,
+        }
+    }
+}
+
+impl From<APIError> for Error {
+    fn from(api_err: APIError) -> Error {
         Error::APIError(api_err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netsblox_api::serde_json;
+    use tungstenite::Error as WsError;
+
+    fn request_error() -> reqwest::Error {
+        reqwest::Client::new().get("not a url").build().unwrap_err()
+    }
+
+    fn json_error() -> serde_json::Error {
+        serde_json::from_str::<serde_json::Value>("{").unwrap_err()
+    }
+
+    #[test]
+    fn api_error_exit_codes() {
+        let cases = [
+            (APIError::RequestError(request_error()), exitcode::NOHOST),
+            (
+                APIError::WebSocketConnectError(WsError::ConnectionClosed),
+                exitcode::NOHOST,
+            ),
+            (
+                APIError::WebSocketSendError(WsError::ConnectionClosed),
+                exitcode::UNAVAILABLE,
+            ),
+            (
+                APIError::WebSocketReceiveError(WsError::ConnectionClosed),
+                exitcode::UNAVAILABLE,
+            ),
+            (
+                APIError::InternalServerError("error".into()),
+                exitcode::UNAVAILABLE,
+            ),
+            (APIError::LoginRequiredError, exitcode::NOPERM),
+            (APIError::PermissionsError("".into()), exitcode::NOPERM),
+            (APIError::NotFoundError("".into()), exitcode::NOINPUT),
+            (APIError::ConflictError("".into()), exitcode::DATAERR),
+            (
+                APIError::TooManyRequestsError("".into()),
+                exitcode::TEMPFAIL,
+            ),
+            (APIError::BadRequestError("".into()), exitcode::DATAERR),
+            (APIError::MessageTooLargeError(2, 1), exitcode::DATAERR),
+            (APIError::InvalidUrlError("".into()), exitcode::CONFIG),
+            (APIError::MissingAppIdError, exitcode::CONFIG),
+            (
+                APIError::ParseResponseFailedError(request_error()),
+                exitcode::PROTOCOL,
+            ),
+            (
+                APIError::InvalidMessageError(json_error()),
+                exitcode::PROTOCOL,
+            ),
+            (APIError::MissingCookieError, exitcode::PROTOCOL),
+            (
+                APIError::UnexpectedStatusError(418, "".into()),
+                exitcode::PROTOCOL,
+            ),
+        ];
+
+        for (err, code) in cases {
+            let msg = err.to_string();
+            assert_eq!(Error::from(err).exit_code(), code, "{}", msg);
+        }
+    }
+
+    #[test]
+    fn cli_error_exit_codes() {
+        let cases = [
+            (Error::NotLoggedInError, exitcode::USAGE),
+            (Error::SessionExpiredError, exitcode::USAGE),
+            (Error::HostNotFoundError, exitcode::NOINPUT),
+            (Error::ServiceHostNotFoundError, exitcode::NOINPUT),
+            (Error::GroupNotFoundError, exitcode::NOINPUT),
+            (Error::PasswordReadError, exitcode::IOERR),
+            (Error::RosterParseError("".into()), exitcode::DATAERR),
+            (Error::BulkCreateError(1, 2), exitcode::DATAERR),
+            (Error::ProjectParseError("".into()), exitcode::DATAERR),
+            (Error::BatchImportError(1, 2), exitcode::DATAERR),
+            (Error::FileExistsError("".into()), exitcode::CANTCREAT),
+            (
+                Error::WriteFileError("".into(), std::io::ErrorKind::Other.into()),
+                exitcode::IOERR,
+            ),
+        ];
+
+        for (err, code) in cases {
+            let msg = err.to_string();
+            assert_eq!(err.exit_code(), code, "{}", msg);
+        }
+    }
+}
@@ -1,21 +1,28 @@
 static APP_NAME: &str = "netsblox";
+static PASSWORD_ENV_VAR: &str = "NETSBLOX_PASSWORD";
 mod config;
 mod error;
 
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
+use std::io::{self, BufRead, Write};
 
 use crate::config::{Config, HostConfig};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use futures_util::StreamExt;
 use inquire::{Confirm, Password, PasswordDisplayMode};
 use netsblox_api::common::{
-    oauth, ClientId, CreateMagicLinkData, CreateProjectData, Credentials, FriendLinkState, GroupId,
-    InvitationState, LinkedAccount, ProjectId, PublishState, RoleData, SaveState, ServiceHost,
-    ServiceHostScope, UpdateUserData, UserRole,
+    oauth, xml, AppId, ClientId, CollaborationInvite, CreateMagicLinkData, CreateProjectData,
+    Credentials, FriendInvite, FriendLinkState, GroupId, InvitationState, LibraryMetadata,
+    LinkedAccount, LoginRequest, MagicLinkIntent, NewUser, PasswordRules, ProjectId, PublishState,
+    RoleData, SaveState, ServiceHost, ServiceHostScope, UpdateUserData, UserRole,
 };
-use netsblox_api::{self, serde_json, Client};
-use std::path::Path;
-use xmlparser::{Token, Tokenizer};
+use netsblox_api::{self, normalize_host_url, serde_json, Client, NetworkMessage};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
 
 #[derive(Parser, Debug)]
 #[group(required = true, multiple = true)]
@@ -51,6 +58,9 @@ enum Users {
         /// Password for new user. If unset, user will need to manually reset password before logging in
         #[clap(short, long)]
         password: Option<String>,
+        /// Read the password for the new user from stdin
+        #[clap(long, conflicts_with = "password")]
+        password_stdin: bool,
         /// Make the new user a member of the given group
         #[clap(short, long)]
         group: Option<String>,
@@ -61,6 +71,23 @@ enum Users {
         #[clap(short, long, default_value = "user")]
         role: UserRole,
     },
+    /// Create NetsBlox users from a CSV or JSON roster (username,email[,password])
+    CreateBulk {
+        /// Path to the roster. Files ending in .json are read as JSON, otherwise CSV
+        path: String,
+        /// Make the new users members of the given group
+        #[clap(short, long)]
+        group: Option<String>,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+        /// Set the user role (eg, admin, moderator)
+        #[clap(short, long, default_value = "user")]
+        role: UserRole,
+        /// Maximum number of users to create at once
+        #[clap(long, default_value = "4")]
+        concurrency: usize,
+    },
     /// Delete an existing NetsBlox account
     Delete {
         username: String,
@@ -68,6 +95,8 @@ enum Users {
         #[clap(short, long)]
         no_confirm: bool,
     },
+    /// Cancel the pending deletion of a NetsBlox account
+    CancelDelete { username: String },
     /// View the current user
     View {
         /// Perform this action on behalf of this user
@@ -82,15 +111,47 @@ enum Users {
         #[clap(short, long)]
         user: Option<String>,
     },
+    /// Promote or demote a user to the given role
+    SetRole {
+        username: String,
+        /// New role for the user (eg, teacher, moderator, admin)
+        role: UserRole,
+        /// Skip confirmation prompts when granting moderator or admin privileges
+        #[clap(short, long)]
+        no_confirm: bool,
+    },
     /// Change the current user's password
     SetPassword {
-        password: String,
+        /// New password. If unset, it is read from NETSBLOX_PASSWORD or prompted for
+        password: Option<String>,
+        /// Read the new password from stdin
+        #[clap(long, conflicts_with = "password")]
+        password_stdin: bool,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
+    /// List the active login sessions for the current user
+    Sessions {
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
+    /// Revoke a login session for the current user
+    RevokeSessions {
+        /// Session to revoke. If unset, all sessions are revoked
+        session_id: Option<String>,
         /// Perform this action on behalf of this user
         #[clap(short, long)]
         user: Option<String>,
     },
     /// List NetsBlox users
     List, // TODO: add verbose option?
+    /// Email a link for setting a new password to the given address
+    ResetPassword {
+        /// Email address associated with the account
+        email: String,
+    },
     /// Email all associated usernames to a given address
     ForgotUsername {
         /// Email address associated with the username(s)
@@ -109,8 +170,11 @@ enum Users {
     Link {
         /// Snap! username to link to NetsBlox account
         username: String,
-        /// Snap! password
-        password: String,
+        /// Snap! password. If unset, it is read from NETSBLOX_PASSWORD or prompted for
+        password: Option<String>,
+        /// Read the Snap! password from stdin
+        #[clap(long, conflicts_with = "password")]
+        password_stdin: bool,
         // #[clap(short, long, default_value = "Snap")]
         // strategy: String,
         /// Perform this action on behalf of this user
@@ -142,6 +206,31 @@ enum MagicLinks {
     },
 }
 
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum ExportFormat {
+    /// NetsBlox project XML
+    Xml,
+    /// Structured project (or role) data as JSON
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Xml => "xml",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum InboxFormat {
+    /// One tab-separated line per pending item
+    Text,
+    /// A JSON object with a list for each type of pending item
+    Json,
+}
+
 /// Manage projects (or roles)
 #[derive(Subcommand, Debug)]
 enum Projects {
@@ -156,6 +245,14 @@ enum Projects {
         #[clap(short, long)]
         user: Option<String>,
     },
+    /// Import each project in a directory or .zip file into NetsBlox
+    ImportBatch {
+        /// The path to a directory or .zip file containing project files (.xml)
+        path: String,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
     /// Export a project from NetsBlox
     Export {
         /// Name of project to export
@@ -166,6 +263,15 @@ enum Projects {
         /// Include unsaved changes (from opened projects)
         #[clap(short, long)]
         latest: bool,
+        /// Write the project to this file (or directory) instead of stdout
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Overwrite the output file if it already exists
+        #[clap(short, long, requires = "output")]
+        force: bool,
+        /// Format of the exported project
+        #[clap(long, value_enum, default_value_t = ExportFormat::Xml)]
+        format: ExportFormat,
         /// Perform this action on behalf of this user
         #[clap(short, long)]
         user: Option<String>,
@@ -175,6 +281,9 @@ enum Projects {
         /// List the projects shared with the current user
         #[clap(short, long)]
         shared: bool,
+        /// List the projects starred by the current user first
+        #[clap(long)]
+        starred_first: bool,
         /// Perform this action on behalf of this user
         #[clap(short, long)]
         user: Option<String>,
@@ -287,6 +396,9 @@ enum ServiceHosts {
         /// Perform this action on behalf of this user
         #[clap(short, long)]
         user: Option<String>,
+        /// Skip checking that the host is reachable (eg, for offline setups)
+        #[clap(long)]
+        skip_check: bool,
     },
     /// Remove a registered services host from a given user or group
     Unregister {
@@ -452,6 +564,8 @@ enum Oauth {
     AddClient { name: String },
     /// Remove registered OAuth client from NetsBlox
     RemoveClient { id: oauth::ClientId },
+    /// Generate a new secret for a registered OAuth client
+    RotateSecret { id: oauth::ClientId },
 }
 
 /// Connect to the NetsBlox network
@@ -461,6 +575,9 @@ enum Network {
     List {
         #[clap(short, long)]
         external: bool,
+        /// Only list external clients for this app
+        #[clap(long)]
+        app_id: Option<String>,
     },
     /// View the network state of a given project
     View {
@@ -474,10 +591,40 @@ enum Network {
     },
     /// View the state of a given connected client
     ViewClient { client_id: ClientId },
+    /// Start recording the messages sent within a project
+    StartTrace {
+        project: String,
+        /// Interpret <project> argument as a project ID rather than name
+        #[clap(short, long)]
+        as_id: bool,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
+    /// Stop recording the messages sent within a project
+    StopTrace {
+        project: String,
+        trace_id: String,
+        /// Interpret <project> argument as a project ID rather than name
+        #[clap(short, long)]
+        as_id: bool,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
     /// Connect to NetsBlox and listen for messages
     Connect {
         #[clap(short, long, default_value = "project")]
         address: String,
+        /// Only show messages of this message type
+        #[clap(short, long)]
+        r#type: Option<String>,
+        /// Only show messages from this address (eg, "role@project@owner" or "project@owner")
+        #[clap(short, long)]
+        from: Option<String>,
+        /// Exit after receiving this many (matching) messages
+        #[clap(short, long)]
+        count: Option<usize>,
     },
     /// Evict a client from their current role
     Evict { client_id: ClientId },
@@ -492,6 +639,12 @@ enum Network {
         #[clap(short, long, default_value = "message")]
         r#type: String,
     },
+    /// List the messages waiting for offline external clients
+    Queued {
+        /// Drop all queued messages instead
+        #[clap(long)]
+        clear: bool,
+    },
 }
 
 /// Manage sandboxed groups for classes or camps
@@ -685,6 +838,8 @@ enum Command {
     Login,
     /// Logout of current cloud account
     Logout,
+    /// Print the active host and user and whether the login is still valid
+    Whoami,
     #[clap(alias = "user")]
     Users(UserCommand),
     #[clap(alias = "magic-link")]
@@ -703,15 +858,33 @@ enum Command {
     Oauth(OauthCommand),
     #[clap(alias = "hosts")]
     Host(HostCommand),
+    /// List pending friend invites, collaboration invites, and (for moderators)
+    /// libraries awaiting approval
+    Inbox {
+        /// Output format
+        #[clap(long, value_enum, default_value_t = InboxFormat::Text)]
+        format: InboxFormat,
+        /// Perform this action on behalf of this user
+        #[clap(short, long)]
+        user: Option<String>,
+    },
+    /// Generate shell completions (no login required)
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
 }
 
 #[derive(Parser, Debug)]
 struct Cli {
     #[clap(subcommand)]
     cmd: Command,
+    /// Print what would be deleted without deleting anything
+    #[clap(long, global = true)]
+    dry_run: bool,
 }
 
-fn prompt_credentials() -> (String, String, bool) {
+fn prompt_credentials() -> (String, Zeroizing<String>, bool) {
     let use_snap = inquire::Confirm::new("Would you like to login using Snap?")
         .with_default(false)
         .prompt()
@@ -721,6 +894,12 @@ fn prompt_credentials() -> (String, String, bool) {
         .prompt()
         .expect("Unable to prompt username");
 
+    let password = prompt_password();
+
+    (username, password, use_snap)
+}
+
+fn prompt_password() -> Zeroizing<String> {
     let password = Password::new("Password:")
         .with_display_toggle_enabled()
         .with_display_mode(PasswordDisplayMode::Masked)
@@ -728,13 +907,322 @@ fn prompt_credentials() -> (String, String, bool) {
         .prompt()
         .expect("Unable to prompt password");
 
-    (username, password, use_snap)
+    Zeroizing::new(password)
+}
+
+/// Prompt for a password until one satisfies the given rules
+fn prompt_new_password(rules: &PasswordRules) -> Zeroizing<String> {
+    loop {
+        let password = prompt_password();
+        match rules.check(&password) {
+            Ok(_) => return password,
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+}
+
+/// Resolve a password given (in order of precedence) as an argument, on stdin,
+/// or in the NETSBLOX_PASSWORD environment variable.
+fn read_password(
+    arg: Option<&str>,
+    from_stdin: bool,
+    env_password: Option<String>,
+    stdin: &mut impl BufRead,
+) -> io::Result<Option<Zeroizing<String>>> {
+    if let Some(password) = arg {
+        return Ok(Some(Zeroizing::new(password.to_owned())));
+    }
+
+    if from_stdin {
+        let mut line = Zeroizing::new(String::new());
+        stdin.read_line(&mut line)?;
+        let len = line.trim_end_matches(&['\r', '\n'][..]).len();
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.truncate(len);
+        return Ok(Some(line));
+    }
+
+    Ok(env_password.map(Zeroizing::new))
+}
+
+/// Anything containing a password which should be cleared once it has been used
+trait WithPassword {
+    fn password_mut(&mut self) -> &mut String;
+}
+
+impl WithPassword for Credentials {
+    fn password_mut(&mut self) -> &mut String {
+        match self {
+            Credentials::Snap { password, .. } | Credentials::NetsBlox { password, .. } => password,
+        }
+    }
+}
+
+impl WithPassword for LoginRequest {
+    fn password_mut(&mut self) -> &mut String {
+        self.credentials.password_mut()
+    }
+}
+
+/// Wrapper which zeroes the contained password when dropped (like `Zeroizing`)
+struct ZeroizingPassword<T: WithPassword>(T);
+
+impl<T: WithPassword> Deref for ZeroizingPassword<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: WithPassword> DerefMut for ZeroizingPassword<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: WithPassword> Drop for ZeroizingPassword<T> {
+    fn drop(&mut self) {
+        self.0.password_mut().zeroize();
+    }
+}
+
+/// Create credentials using the given password. The password is moved rather
+/// than copied so the result should be wrapped in a `ZeroizingPassword`.
+fn credentials(username: String, mut password: Zeroizing<String>, use_snap: bool) -> Credentials {
+    let password = std::mem::take(&mut *password);
+    if use_snap {
+        Credentials::Snap { username, password }
+    } else {
+        Credentials::NetsBlox { username, password }
+    }
+}
+
+fn snap_credentials(username: &str, password: Zeroizing<String>) -> ZeroizingPassword<Credentials> {
+    ZeroizingPassword(credentials(username.to_owned(), password, true))
+}
+
+fn password_from_env() -> Option<String> {
+    std::env::var(PASSWORD_ENV_VAR).ok()
+}
+
+fn write_completions(shell: Shell, buf: &mut impl Write) {
+    clap_complete::generate(shell, &mut Cli::command(), APP_NAME, buf);
+}
+
+async fn find_group_id(
+    client: &Client,
+    owner: &str,
+    group_name: &str,
+) -> Result<Option<GroupId>, error::Error> {
+    let groups = client.list_groups(owner).await?;
+    Ok(groups
+        .into_iter()
+        .find(|g| g.name == group_name)
+        .map(|group| group.id))
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct RosterEntry {
+    username: String,
+    email: String,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+impl RosterEntry {
+    fn to_new_user(&self, group_id: Option<&GroupId>, role: &UserRole) -> NewUser {
+        NewUser {
+            username: self.username.clone(),
+            email: self.email.clone(),
+            password: self.password.clone(),
+            group_id: group_id.cloned(),
+            role: Some(role.to_owned()),
+        }
+    }
+}
+
+/// Parse a roster of users to create. CSV rosters have one user per line as
+/// `username,email[,password]` with an optional header row.
+fn parse_roster(content: &str, is_json: bool) -> Result<Vec<RosterEntry>, String> {
+    if is_json {
+        return serde_json::from_str(content).map_err(|err| err.to_string());
+    }
+
+    let mut entries = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<_> = line.split(',').map(|field| field.trim()).collect();
+        let is_header = idx == 0 && fields[0].eq_ignore_ascii_case("username");
+        if is_header {
+            continue;
+        }
+
+        let entry = match fields[..] {
+            [username, email] | [username, email, ""] => RosterEntry {
+                username: username.to_owned(),
+                email: email.to_owned(),
+                password: None,
+            },
+            [username, email, password] => RosterEntry {
+                username: username.to_owned(),
+                email: email.to_owned(),
+                password: Some(password.to_owned()),
+            },
+            _ => {
+                return Err(format!(
+                    "Line {}: expected username,email[,password]",
+                    idx + 1
+                ));
+            }
+        };
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Separate out any entries reusing a username from earlier in the roster.
+fn split_duplicates(entries: Vec<RosterEntry>) -> (Vec<RosterEntry>, Vec<RosterEntry>) {
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .partition(|entry| seen.insert(entry.username.clone()))
+}
+
+/// Check if changing a user's role grants moderator (or admin) privileges
+/// that the user did not already have.
+fn is_role_elevation(before: &UserRole, after: &UserRole) -> bool {
+    after > before && *after >= UserRole::Moderator
+}
+
+/// Find the index of the service host with the given (possibly unnormalized) URL
+fn find_host(hosts: &[ServiceHost], url: &str) -> Option<usize> {
+    let url = normalize_host_url(url);
+    hosts
+        .iter()
+        .position(|host| normalize_host_url(&host.url) == url)
 }
 
 fn get_current_user(cfg: &HostConfig) -> String {
     cfg.username.as_ref().unwrap().clone()
 }
 
+/// Describe what a destructive command would have deleted (for --dry-run)
+fn dry_run_message(target: &str) -> String {
+    format!("Dry run: would delete {}", target)
+}
+
+/// Get the user to act as (defaulting to the current user). Requests made on behalf
+/// of another user are identified as such so they can be audited.
+fn act_as(client: &mut Client, user: &Option<String>, cfg: &HostConfig) -> String {
+    let username = user.clone().unwrap_or_else(|| get_current_user(cfg));
+    client.set_on_behalf_of(Some(username.clone()));
+    username
+}
+
+/// Pending items which need a response from the user
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Inbox {
+    friend_invites: Vec<FriendInvite>,
+    collaboration_invites: Vec<CollaborationInvite>,
+    /// Libraries awaiting approval. Always empty unless the user is a moderator
+    pending_libraries: Vec<LibraryMetadata>,
+}
+
+impl Inbox {
+    /// Fetch the pending items for the given user. `on_behalf_of` should be set
+    /// if the user is not the one logged in.
+    async fn fetch(
+        client: &Client,
+        username: &str,
+        on_behalf_of: bool,
+    ) -> Result<Self, error::Error> {
+        let (friend_invites, collaboration_invites, pending_libraries) = tokio::join!(
+            client.list_friend_invites(username),
+            client.list_collaboration_invites(username),
+            client.get_submitted_libraries(),
+        );
+
+        let mut pending_libraries = match pending_libraries {
+            Err(netsblox_api::error::Error::PermissionsError(..)) => Vec::new(),
+            result => result?,
+        };
+
+        // Libraries awaiting approval are listed with the privileges of the logged in
+        // user so they are only included if the given user can moderate them, too
+        if on_behalf_of && !pending_libraries.is_empty() {
+            let user = client.view_user(username).await?;
+            if user.role < UserRole::Moderator {
+                pending_libraries.clear();
+            }
+        }
+
+        Ok(Self {
+            friend_invites: friend_invites?,
+            collaboration_invites: collaboration_invites?,
+            pending_libraries,
+        })
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let friend_invites = self
+            .friend_invites
+            .iter()
+            .map(|invite| format!("friend\t{}", invite.sender));
+        let collaboration_invites = self
+            .collaboration_invites
+            .iter()
+            .map(|invite| format!("collaboration\t{}\t{}", invite.sender, invite.project_id));
+        let pending_libraries = self
+            .pending_libraries
+            .iter()
+            .map(|library| format!("library\t{}\t{}", library.owner, library.name));
+
+        friend_invites
+            .chain(collaboration_invites)
+            .chain(pending_libraries)
+            .collect()
+    }
+}
+
+/// Filter for messages received when connected to the network
+struct MessageFilter {
+    msg_type: Option<String>,
+    /// Sender address. Matches the full address or a suffix (eg, "project@owner")
+    from: Option<String>,
+}
+
+impl MessageFilter {
+    fn matches(&self, msg: &NetworkMessage) -> bool {
+        let type_matches = self
+            .msg_type
+            .as_ref()
+            .map(|msg_type| msg.msg_type.as_ref() == Some(msg_type))
+            .unwrap_or(true);
+
+        let sender_matches = self
+            .from
+            .as_ref()
+            .map(|from| {
+                msg.src_id
+                    .as_ref()
+                    .map(|src_id| src_id == from || src_id.ends_with(&format!("@{}", from)))
+                    .unwrap_or(false)
+            })
+            .unwrap_or(true);
+
+        type_matches && sender_matches
+    }
+}
+
 fn save_config(cfg: &Config) {
     confy::store(APP_NAME, cfg).expect("Unable to save configuration file.");
 }
@@ -745,12 +1233,7 @@ async fn main() {
 
     let args = Cli::parse();
     if let Err(err) = do_command(cfg, args).await {
-        let code = match err {
-            error::Error::APIError(netsblox_api::error::Error::RequestError(..)) => {
-                exitcode::NOHOST
-            }
-            _ => exitcode::USAGE,
-        };
+        let code = err.exit_code();
         eprintln!("{}", err);
         std::process::exit(code);
     }
@@ -761,32 +1244,45 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
     let login_required = match &args.cmd {
         Command::Login => true,
         Command::Logout => false,
+        Command::Whoami => false,
         Command::MagicLinks(cmd) => match &cmd.subcmd {
             MagicLinks::Send { .. } => false,
         },
         Command::Users(cmd) => match &cmd.subcmd {
-            Users::Create { .. } => false,
+            Users::Create { .. } | Users::ResetPassword { .. } => false,
             _ => !is_logged_in,
         },
         Command::Host(..) => false,
+        Command::Completions { .. } => false,
         _ => !is_logged_in,
     };
 
     let api_cfg: netsblox_api::Config = if login_required {
         let (username, password, use_snap) = prompt_credentials();
-        let credentials = if use_snap {
-            Credentials::Snap { username, password }
-        } else {
-            Credentials::NetsBlox { username, password }
-        };
-        let request = netsblox_api::common::LoginRequest {
-            credentials,
+        let mut request = ZeroizingPassword(LoginRequest {
+            credentials: credentials(username, password, use_snap),
             client_id: None,
-        };
+            two_factor_code: None,
+            cancel_deletion: false,
+        });
         let api_cfg: netsblox_api::Config = cfg.host().clone().into();
-        let api_cfg = netsblox_api::login(api_cfg, &request)
-            .await
-            .expect("Login failed");
+        let api_cfg = match netsblox_api::login(api_cfg.clone(), &request).await {
+            Err(netsblox_api::error::Error::PermissionsError(err))
+                if err.code.as_deref() == Some("TwoFactorCodeRequiredError")
+                    || err
+                        .message
+                        .starts_with("Two-factor authentication code required") =>
+            {
+                let code = inquire::Text::new("Two-factor authentication code:")
+                    .prompt()
+                    .expect("Unable to prompt two-factor authentication code");
+                // set the code directly rather than copying the credentials
+                request.two_factor_code = Some(code);
+                netsblox_api::login(api_cfg, &request).await
+            }
+            result => result,
+        }
+        .expect("Login failed");
 
         cfg.set_credentials(&api_cfg);
         save_config(&cfg);
@@ -794,7 +1290,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
     } else {
         cfg.host().clone().into()
     };
-    let client = Client::new(api_cfg.clone());
+    let mut client = Client::new(api_cfg.clone());
 
     match &args.cmd {
         Command::Login { .. } => {}
@@ -802,22 +1298,55 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
             cfg.clear_credentials();
             save_config(&cfg);
         }
+        Command::Whoami => {
+            if !is_logged_in {
+                return Err(error::Error::NotLoggedInError);
+            }
+
+            let user = match client.verify_session().await {
+                Ok(user) => user,
+                Err(
+                    netsblox_api::error::Error::LoginRequiredError
+                    | netsblox_api::error::Error::PermissionsError(..),
+                ) => {
+                    let username = cfg.host().username.clone().unwrap_or_default();
+                    println!(
+                        "{}\t{}\t{}\texpired",
+                        cfg.current_host,
+                        cfg.host().url,
+                        username
+                    );
+                    return Err(error::Error::SessionExpiredError);
+                }
+                Err(err) => return Err(err.into()),
+            };
+            println!(
+                "{}\t{}\t{}\tvalid",
+                cfg.current_host,
+                cfg.host().url,
+                user.username
+            );
+        }
         Command::Users(cmd) => match &cmd.subcmd {
             Users::Create {
                 username,
                 email,
                 password,
+                password_stdin,
                 role,
                 group,
                 user,
             } => {
+                let password = read_password(
+                    password.as_deref(),
+                    *password_stdin,
+                    password_from_env(),
+                    &mut io::stdin().lock(),
+                )
+                .map_err(|_err| error::Error::PasswordReadError)?;
                 let group_id = if let Some(group_name) = group {
-                    let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
-                    let groups = client.list_groups(&username).await?;
-                    groups
-                        .into_iter()
-                        .find(|g| g.name == *group_name)
-                        .map(|group| group.id)
+                    let username = act_as(&mut client, user, cfg.host());
+                    find_group_id(&client, &username, group_name).await?
                 } else {
                     None
                 };
@@ -826,25 +1355,140 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     .create_user(
                         username,
                         email,
-                        password.as_deref(),
+                        password.as_deref().map(|pwd| pwd.as_str()),
                         group_id.as_ref(),
                         role.to_owned(),
                     )
                     .await?;
             }
+            Users::CreateBulk {
+                path,
+                group,
+                user,
+                role,
+                concurrency,
+            } => {
+                let content = fs::read_to_string(path).expect("Unable to read roster file");
+                let is_json = Path::new(path)
+                    .extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("json"))
+                    .unwrap_or(false);
+                let entries =
+                    parse_roster(&content, is_json).map_err(error::Error::RosterParseError)?;
+
+                let group_id = if let Some(group_name) = group {
+                    let username = act_as(&mut client, user, cfg.host());
+                    let group_id = find_group_id(&client, &username, group_name).await?;
+                    Some(group_id.ok_or(error::Error::GroupNotFoundError)?)
+                } else {
+                    None
+                };
+
+                let (entries, duplicates) = split_duplicates(entries);
+                let new_users: Vec<_> = entries
+                    .iter()
+                    .map(|entry| entry.to_new_user(group_id.as_ref(), role))
+                    .collect();
+                let results = client.create_users(&new_users, *concurrency).await;
+
+                let mut report: Vec<_> = entries
+                    .into_iter()
+                    .zip(results)
+                    .map(|(entry, result)| (entry.username, result.map_err(|err| err.to_string())))
+                    .collect();
+                report.extend(duplicates.into_iter().map(|entry| {
+                    (
+                        entry.username,
+                        Err("Duplicate username in roster".to_owned()),
+                    )
+                }));
+
+                let failed = report.iter().filter(|(_, result)| result.is_err()).count();
+                for (username, result) in &report {
+                    match result {
+                        Ok(_) => println!("{}\tcreated", username),
+                        Err(msg) => println!("{}\tfailed: {}", username, msg),
+                    }
+                }
+                println!(
+                    "Created {} of {} users",
+                    report.len() - failed,
+                    report.len()
+                );
+
+                if failed > 0 {
+                    return Err(error::Error::BulkCreateError(failed, report.len()));
+                }
+            }
             Users::Update { data, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 client.update_user(&username, &data.into()).await?;
             }
-            Users::SetPassword { password, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
-                client.set_password(&username, password).await?;
+            Users::SetRole {
+                username,
+                role,
+                no_confirm,
+            } => {
+                let before = client.view_user(username).await?.role;
+                let confirmed = if *no_confirm || !is_role_elevation(&before, role) {
+                    true
+                } else {
+                    Confirm::new(&format!(
+                        "Are you sure you want to make {} a(n) {:?}?",
+                        username, role
+                    ))
+                    .prompt()
+                    .unwrap_or(false)
+                };
+                if confirmed {
+                    let data = UpdateUserData {
+                        email: None,
+                        group_id: None,
+                        role: Some(*role),
+                    };
+                    let user = client.update_user(username, &data).await?;
+                    println!("{}: {:?} -> {:?}", username, before, user.role);
+                }
+            }
+            Users::SetPassword {
+                password,
+                password_stdin,
+                user,
+            } => {
+                let username = act_as(&mut client, user, cfg.host());
+                let password = read_password(
+                    password.as_deref(),
+                    *password_stdin,
+                    password_from_env(),
+                    &mut io::stdin().lock(),
+                )
+                .map_err(|_err| error::Error::PasswordReadError)?;
+                let password = match password {
+                    Some(password) => password,
+                    None => {
+                        let rules = client.get_password_rules().await?;
+                        prompt_new_password(&rules)
+                    }
+                };
+                client.set_password(&username, &password).await?;
             }
             Users::List => {
-                for user in client.list_users().await? {
-                    println!("{}", serde_json::to_string(&user).unwrap());
+                let users = client.stream_users();
+                futures_util::pin_mut!(users);
+                while let Some(user) = users.next().await {
+                    println!("{}", serde_json::to_string(&user?).unwrap());
                 }
             }
+            Users::ResetPassword { email } => {
+                let data = CreateMagicLinkData {
+                    email: email.clone(),
+                    redirect_uri: None,
+                    intent: MagicLinkIntent::Reset,
+                };
+                client.send_magic_link(&data).await?;
+                println!("Password reset link sent to {}.", email);
+                println!("Open the link in a browser and select an account to set a new password.");
+            }
             Users::ForgotUsername { email } => {
                 client.forgot_username(&email).await?;
                 println!("Email sent to {}", email);
@@ -853,7 +1497,10 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 username,
                 no_confirm,
             } => {
-                let confirmed = if *no_confirm {
+                let confirmed = if args.dry_run {
+                    println!("{}", dry_run_message(&format!("user {}", username)));
+                    false
+                } else if *no_confirm {
                     true
                 } else {
                     Confirm::new(&format!("Are you sure you want to delete {}?", username))
@@ -862,28 +1509,51 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 };
                 if confirmed {
                     client.delete_user(username).await?;
-                    println!("deleted {}", username);
+                    println!("scheduled {} for deletion", username);
                 }
             }
+            Users::CancelDelete { username } => {
+                client.cancel_user_deletion(username).await?;
+                println!("cancelled deletion of {}", username);
+            }
             Users::View { user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let user = client.view_user(&username).await?;
                 println!("{:?}", user);
             }
+            Users::Sessions { user } => {
+                let username = act_as(&mut client, user, cfg.host());
+                for session in client.list_sessions(&username).await? {
+                    println!("{:?}", session);
+                }
+            }
+            Users::RevokeSessions { session_id, user } => {
+                let username = act_as(&mut client, user, cfg.host());
+                let revoked = client
+                    .revoke_sessions(&username, session_id.as_deref())
+                    .await?;
+                println!("revoked {} session(s)", revoked.len());
+            }
             Users::Link {
                 username,
                 password,
+                password_stdin,
                 user,
             } => {
-                let as_user = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
-                let creds = netsblox_api::common::Credentials::Snap {
-                    username: username.to_owned(),
-                    password: password.to_owned(),
-                };
+                let as_user = act_as(&mut client, user, cfg.host());
+                let password = read_password(
+                    password.as_deref(),
+                    *password_stdin,
+                    password_from_env(),
+                    &mut io::stdin().lock(),
+                )
+                .map_err(|_err| error::Error::PasswordReadError)?
+                .unwrap_or_else(prompt_password);
+                let creds = snap_credentials(username, password);
                 client.link_account(&as_user, &creds).await?;
             }
             Users::Unlink { username, user } => {
-                let as_user = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let as_user = act_as(&mut client, user, cfg.host());
                 let account = LinkedAccount {
                     username: username.to_owned(),
                     strategy: "snap".to_owned(), // FIXME: add to linked account impl?
@@ -902,6 +1572,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 let data = CreateMagicLinkData {
                     email: email.clone(),
                     redirect_uri: Some(url.to_owned()),
+                    intent: MagicLinkIntent::Login,
                 };
                 client.send_magic_link(&data).await?;
                 println!("Magic link sent to {}!", email);
@@ -913,56 +1584,11 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 name,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let project_xml = fs::read_to_string(filename).expect("Unable to read file");
 
-                let mut found_role = false;
-                let mut role_spans: Vec<RoleSpan> = Vec::new();
-                let mut role_start = None;
-                let mut media_start = None;
-                let mut role_name: Option<&str> = None;
-                for token in Tokenizer::from(project_xml.as_str()) {
-                    match token {
-                        Ok(Token::ElementStart { local, .. }) => {
-                            let is_role = local.as_str() == "role";
-                            if found_role {
-                                role_start = Some(local.start() - 1);
-                            }
-
-                            found_role = is_role;
-
-                            let is_media = local.as_str() == "media";
-                            if is_media {
-                                media_start = Some(local.start() - 1);
-                            }
-                        }
-                        Ok(Token::ElementEnd { span, .. }) => {
-                            if span.as_str().contains("media") {
-                                let media_end = span.end();
-                                if let (Some(name), Some(start), Some(media_start), end) =
-                                    (role_name, role_start, media_start, media_end)
-                                {
-                                    role_spans.push(RoleSpan::new(
-                                        name.to_owned(),
-                                        start,
-                                        media_start,
-                                        end,
-                                    ));
-                                }
-                            }
-                        }
-                        Ok(Token::Attribute { local, value, .. }) => {
-                            if found_role && local.as_str() == "name" {
-                                role_name = Some(value.as_str());
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                let roles: Vec<_> = role_spans
-                    .into_iter()
-                    .map(|rspan| rspan.into_role(&project_xml))
-                    .collect();
+                let roles = xml::roles_from_project_xml(&project_xml)
+                    .map_err(|err| error::Error::ProjectParseError(err.to_string()))?;
 
                 let project_data = CreateProjectData {
                     owner: Some(username),
@@ -980,16 +1606,71 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 };
                 client.create_project(&project_data).await?;
             }
+            Projects::ImportBatch { path, user } => {
+                let username = act_as(&mut client, user, cfg.host());
+                let path = Path::new(path);
+                let files = if path.is_dir() {
+                    read_project_dir(path)
+                } else {
+                    fs::File::open(path).and_then(read_project_zip)
+                }
+                .map_err(|err| error::Error::ProjectParseError(err.to_string()))?;
+
+                let mut report = Vec::new();
+                for (name, roles) in plan_project_imports(files) {
+                    let result = match roles {
+                        Ok(roles) => {
+                            let project_data = CreateProjectData {
+                                owner: Some(username.clone()),
+                                name: name.clone(),
+                                roles: Some(roles),
+                                save_state: Some(SaveState::Saved),
+                                client_id: None,
+                            };
+                            client
+                                .create_project(&project_data)
+                                .await
+                                .map(|metadata| metadata.name)
+                                .map_err(|err| err.to_string())
+                        }
+                        Err(msg) => Err(msg),
+                    };
+                    report.push((name, result));
+                }
+
+                let failed = report.iter().filter(|(_, result)| result.is_err()).count();
+                for (name, result) in &report {
+                    match result {
+                        Ok(created) if created != name => {
+                            println!("{}\timported as {}", name, created)
+                        }
+                        Ok(_) => println!("{}\timported", name),
+                        Err(msg) => println!("{}\tfailed: {}", name, msg),
+                    }
+                }
+                println!(
+                    "Imported {} of {} projects",
+                    report.len() - failed,
+                    report.len()
+                );
+
+                if failed > 0 {
+                    return Err(error::Error::BatchImportError(failed, report.len()));
+                }
+            }
             Projects::Export {
                 project,
                 role,
                 latest,
+                output,
+                force,
+                format,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&username, project).await?;
                 let project_id = metadata.id;
-                let xml = if let Some(role) = role {
+                let (name, content) = if let Some(role) = role {
                     let role_id = metadata
                         .roles
                         .into_iter()
@@ -997,21 +1678,48 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                         .map(|(id, _role_md)| id)
                         .expect("Role not found");
 
-                    client
-                        .get_role(&project_id, &role_id, latest)
-                        .await?
-                        .to_xml()
+                    let role_data = client.get_role(&project_id, &role_id, latest).await?;
+                    let content = match format {
+                        ExportFormat::Xml => role_data.to_xml(),
+                        ExportFormat::Json => serde_json::to_string_pretty(&role_data).unwrap(),
+                    };
+                    (role, content)
                 } else {
-                    client.get_project(&project_id, latest).await?.to_xml()
+                    let project_data = client.get_project(&project_id, latest).await?;
+                    let content = match format {
+                        ExportFormat::Xml => project_data.to_xml(),
+                        ExportFormat::Json => serde_json::to_string_pretty(&project_data).unwrap(),
+                    };
+                    (project, content)
                 };
-                println!("{}", xml);
+
+                if let Some(output) = output {
+                    let path = export_path(Path::new(output), name, format);
+                    write_export(&path, &content, *force).map_err(|err| {
+                        let path = path.display().to_string();
+                        if err.kind() == io::ErrorKind::AlreadyExists {
+                            error::Error::FileExistsError(path)
+                        } else {
+                            error::Error::WriteFileError(path, err)
+                        }
+                    })?;
+                    println!("Exported to {}", path.display());
+                } else {
+                    println!("{}", content);
+                }
             }
-            Projects::List { user, shared } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+            Projects::List {
+                user,
+                shared,
+                starred_first,
+            } => {
+                let username = act_as(&mut client, user, cfg.host());
                 let projects = if *shared {
-                    client.list_shared_projects(&username).await?
+                    client
+                        .list_shared_projects(&username, *starred_first)
+                        .await?
                 } else {
-                    client.list_projects(&username).await?
+                    client.list_projects(&username, *starred_first).await?
                 };
 
                 for project in projects {
@@ -1019,7 +1727,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 }
             }
             Projects::Publish { project, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&username, project).await?;
                 let project_id = metadata.id;
 
@@ -1031,7 +1739,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 }
             }
             Projects::Unpublish { project, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&username, project).await?;
                 let project_id = metadata.id;
 
@@ -1042,13 +1750,13 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 username,
                 user,
             } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&owner, project).await?;
                 let project_id = metadata.id;
                 client.invite_collaborator(&project_id, username).await?;
             }
             Projects::ListInvites { user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let invites = client.list_collaboration_invites(&username).await?;
                 for invite in invites {
                     println!("{}", serde_json::to_string(&invite).unwrap());
@@ -1060,7 +1768,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 reject,
                 user,
             } => {
-                let receiver = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let receiver = act_as(&mut client, user, cfg.host());
                 let invites = client.list_collaboration_invites(&receiver).await?;
                 let project_id = client.get_project_metadata(username, project).await?.id;
                 let invite = invites
@@ -1078,7 +1786,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     .await?;
             }
             Projects::ListCollaborators { project, user } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&owner, project).await?;
                 for user in metadata.collaborators {
                     println!("{}", user);
@@ -1089,7 +1797,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 username,
                 user,
             } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&owner, project).await?;
                 client.remove_collaborator(&metadata.id, username).await?;
             }
@@ -1098,7 +1806,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 role,
                 user,
             } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&owner, project).await?;
                 if let Some(role_name) = role {
                     let role_id = metadata
@@ -1108,7 +1816,18 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                         .map(|(id, _role)| id)
                         .expect("Role not found.");
 
-                    client.delete_role(&metadata.id, &role_id).await?;
+                    if args.dry_run {
+                        let target = format!(
+                            "role {} ({}) of {} ({})",
+                            role_name, role_id, project, metadata.id
+                        );
+                        println!("{}", dry_run_message(&target));
+                    } else {
+                        client.delete_role(&metadata.id, &role_id).await?;
+                    }
+                } else if args.dry_run {
+                    let target = format!("project {} ({})", project, metadata.id);
+                    println!("{}", dry_run_message(&target));
                 } else {
                     client.delete_project(&metadata.id).await?;
                 }
@@ -1119,7 +1838,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 role,
                 user,
             } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 let metadata = client.get_project_metadata(&owner, project).await?;
                 if let Some(role_name) = role {
                     let role_id = metadata
@@ -1136,9 +1855,16 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
             }
         },
         Command::Network(cmd) => match &cmd.subcmd {
-            Network::List { external } => {
+            Network::List { external, app_id } => {
                 if *external {
-                    for client in client.list_external_clients().await? {
+                    let clients = match app_id {
+                        Some(app_id) => {
+                            let app_id = AppId::new(app_id);
+                            client.list_external_clients_for_app(&app_id).await?
+                        }
+                        None => client.list_external_clients().await?,
+                    };
+                    for client in clients {
                         println!("{}", serde_json::to_string(&client).unwrap());
                     }
                 } else {
@@ -1155,7 +1881,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 let project_id = if *as_id {
                     ProjectId::new(project.to_owned())
                 } else {
-                    let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                    let owner = act_as(&mut client, user, cfg.host());
                     client.get_project_metadata(&owner, project).await?.id
                 };
                 let state = client.get_room_state(&project_id).await?;
@@ -1165,25 +1891,82 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 let state = client.get_client_state(client_id).await?;
                 println!("{}", serde_json::to_string(&state).unwrap());
             }
-            Network::Connect { address } => {
-                let channel = client.connect(address).await?;
+            Network::StartTrace {
+                project,
+                as_id,
+                user,
+            } => {
+                let project_id = if *as_id {
+                    ProjectId::new(project.to_owned())
+                } else {
+                    let owner = act_as(&mut client, user, cfg.host());
+                    client.get_project_metadata(&owner, project).await?.id
+                };
+                let trace = client.start_network_trace(&project_id).await?;
+                println!("{}", trace.id);
+            }
+            Network::StopTrace {
+                project,
+                trace_id,
+                as_id,
+                user,
+            } => {
+                let project_id = if *as_id {
+                    ProjectId::new(project.to_owned())
+                } else {
+                    let owner = act_as(&mut client, user, cfg.host());
+                    client.get_project_metadata(&owner, project).await?.id
+                };
+                client.stop_network_trace(&project_id, trace_id).await?;
+            }
+            Network::Connect {
+                address,
+                r#type,
+                from,
+                count,
+            } => {
+                let mut channel = client.connect(address).await?;
                 println!(
                     "Listening for messages at {}@{}#NetsBloxCLI",
                     address,
-                    cfg.host().username.clone().unwrap_or(channel.id)
+                    cfg.host().username.clone().unwrap_or(channel.id.clone())
                 );
-                channel
-                    .stream
-                    .for_each(|msg| async {
-                        let data = msg.unwrap().into_data();
-                        let message = std::str::from_utf8(&data).unwrap();
-                        println!("{}", &message);
-                    })
-                    .await;
+
+                let filter = MessageFilter {
+                    msg_type: r#type.clone(),
+                    from: from.clone(),
+                };
+                let mut remaining = *count;
+                while remaining != Some(0) {
+                    let msg = match channel.recv_message().await {
+                        Ok(Some(msg)) => msg,
+                        Ok(None) => break,
+                        Err(netsblox_api::error::Error::InvalidMessageError(err)) => {
+                            eprintln!("Skipping invalid message: {}", err);
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+
+                    if filter.matches(&msg) {
+                        println!("{}", serde_json::to_string(&msg).unwrap());
+                        remaining = remaining.map(|count| count - 1);
+                    }
+                }
             }
             Network::Evict { client_id } => {
                 client.evict_occupant(client_id).await?;
             }
+            Network::Queued { clear } => {
+                if *clear {
+                    let count = client.clear_queued_messages().await?;
+                    println!("Dropped {} queued message(s)", count);
+                } else {
+                    for msg in client.list_queued_messages().await? {
+                        println!("{}", serde_json::to_string(&msg).unwrap());
+                    }
+                }
+            }
             Network::Send {
                 address,
                 r#type,
@@ -1200,7 +1983,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
         },
         Command::Friends(cmd) => match &cmd.subcmd {
             Friends::List { online, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let friends = if *online {
                     client.list_online_friends(&username).await?
                 } else {
@@ -1213,25 +1996,25 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
             }
 
             Friends::ListInvites { user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 for invite in client.list_friend_invites(&username).await? {
                     println!("{}", serde_json::to_string(&invite).unwrap());
                 }
             }
             Friends::Block { username, user } => {
-                let requestor = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let requestor = act_as(&mut client, user, cfg.host());
                 client.block_user(&requestor, username).await?;
             }
             Friends::Unblock { username, user } => {
-                let requestor = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let requestor = act_as(&mut client, user, cfg.host());
                 client.unblock_user(&requestor, username).await?;
             }
             Friends::Remove { username, user } => {
-                let owner = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let owner = act_as(&mut client, user, cfg.host());
                 client.unfriend(&owner, username).await?;
             }
             Friends::SendInvite { username, user } => {
-                let sender = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let sender = act_as(&mut client, user, cfg.host());
                 client.send_friend_invite(&sender, username).await?;
             }
             Friends::AcceptInvite {
@@ -1239,7 +2022,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 reject,
                 user,
             } => {
-                let recipient = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let recipient = act_as(&mut client, user, cfg.host());
                 let state = if *reject {
                     FriendLinkState::Rejected
                 } else {
@@ -1262,7 +2045,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                         println!("{:?}", host);
                     }
                 } else {
-                    let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                    let username = act_as(&mut client, user, cfg.host());
                     let service_hosts = if *user_only {
                         client.list_user_hosts(&username).await?
                     } else if let Some(group_name) = group {
@@ -1287,8 +2070,9 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 categories,
                 group,
                 user,
+                skip_check,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let group_id = if let Some(group_name) = group {
                     let groups = client.list_groups(&username).await?;
                     groups
@@ -1304,19 +2088,29 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     client.list_user_hosts(&username).await?
                 };
 
-                service_hosts.push(ServiceHost {
+                let host = ServiceHost {
                     url: url.to_owned(),
                     categories: categories.split(',').map(|s| s.to_owned()).collect(),
-                });
+                };
+                // replace the existing entry if the host is already registered
+                match find_host(&service_hosts, url) {
+                    Some(index) => service_hosts[index] = host,
+                    None => service_hosts.push(host),
+                }
 
+                let validate = !*skip_check;
                 if let Some(group_id) = group_id {
-                    client.set_group_hosts(&group_id, service_hosts).await?;
+                    client
+                        .set_group_hosts(&group_id, service_hosts, validate)
+                        .await?;
                 } else {
-                    client.set_user_hosts(&username, service_hosts).await?;
+                    client
+                        .set_user_hosts(&username, service_hosts, validate)
+                        .await?;
                 }
             }
             ServiceHosts::Unregister { url, group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let group_id = if let Some(group_name) = group {
                     let groups = client.list_groups(&username).await?;
                     groups
@@ -1332,17 +2126,19 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     client.list_user_hosts(&username).await?
                 };
 
-                let index = service_hosts
-                    .iter()
-                    .position(|host| host.url == *url)
-                    .ok_or(error::Error::ServiceHostNotFoundError)?;
+                let index =
+                    find_host(&service_hosts, url).ok_or(error::Error::ServiceHostNotFoundError)?;
 
                 service_hosts.swap_remove(index);
 
                 if let Some(group_id) = group_id {
-                    client.set_group_hosts(&group_id, service_hosts).await?;
+                    client
+                        .set_group_hosts(&group_id, service_hosts, false)
+                        .await?;
                 } else {
-                    client.set_user_hosts(&username, service_hosts).await?;
+                    client
+                        .set_user_hosts(&username, service_hosts, false)
+                        .await?;
                 }
             }
             ServiceHosts::Authorize {
@@ -1367,10 +2163,10 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     .list_authorized_hosts()
                     .await?
                     .into_iter()
-                    .find(|host| &host.url == url)
+                    .find(|host| normalize_host_url(&host.url) == normalize_host_url(url))
                     .ok_or_else(|| {
                         netsblox_api::error::Error::NotFoundError(
-                            "Authorized host not found.".to_string(),
+                            "Authorized host not found.".into(),
                         )
                     })?;
                 client.unauthorize_host(&host.id).await?;
@@ -1378,7 +2174,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
         },
         Command::ServiceSettings(cmd) => match &cmd.subcmd {
             ServiceSettings::List { group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let service_hosts = if let Some(group_name) = group {
                     let groups = client.list_groups(&username).await?;
                     let group_id = groups
@@ -1401,7 +2197,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 all,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
 
                 if *all {
                     let all_settings = client.get_all_settings(&username, host).await?;
@@ -1430,7 +2226,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 group,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
 
                 let group_id = if let Some(group_name) = group {
                     let groups = client.list_groups(&username).await?;
@@ -1453,7 +2249,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 println!("{}", settings);
             }
             ServiceSettings::Delete { host, group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
 
                 let group_id = if let Some(group_name) = group {
                     let groups = client.list_groups(&username).await?;
@@ -1477,7 +2273,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 user,
                 approval_needed,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let libraries = if *community {
                     client.get_public_libraries().await?
                 } else if *approval_needed {
@@ -1496,7 +2292,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 name,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let blocks = fs::read_to_string(filename).expect("Unable to read file");
                 let name = name.clone().unwrap_or_else(|| {
                     Path::new(filename)
@@ -1511,20 +2307,25 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     .await?;
             }
             Libraries::Export { library, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let xml = client.get_library(&username, library).await?;
                 println!("{}", xml);
             }
             Libraries::Delete { library, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
-                client.delete_library(&username, library).await?;
+                let username = act_as(&mut client, user, cfg.host());
+                if args.dry_run {
+                    let target = format!("library {} owned by {}", library, username);
+                    println!("{}", dry_run_message(&target));
+                } else {
+                    client.delete_library(&username, library).await?;
+                }
             }
             Libraries::Publish { library, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 client.publish_library(&username, library).await?;
             }
             Libraries::Unpublish { library, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 client.unpublish_library(&username, library).await?;
             }
             Libraries::Approve {
@@ -1532,7 +2333,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 user,
                 reject,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let state = if *reject {
                     PublishState::ApprovalDenied
                 } else {
@@ -1543,18 +2344,18 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
         },
         Command::Groups(cmd) => match &cmd.subcmd {
             Groups::List { user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let groups = client.list_groups(&username).await?;
                 for group in groups {
                     println!("{}", group.name);
                 }
             }
             Groups::Create { name, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 client.create_group(&username, name).await?;
             }
             Groups::Delete { group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let groups = client.list_groups(&username).await?;
                 let group_id = groups
                     .into_iter()
@@ -1562,10 +2363,15 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                     .map(|group| group.id)
                     .unwrap();
 
-                client.delete_group(&group_id).await?;
+                if args.dry_run {
+                    let target = format!("group {} ({})", group, group_id);
+                    println!("{}", dry_run_message(&target));
+                } else {
+                    client.delete_group(&group_id).await?;
+                }
             }
             Groups::Members { group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let groups = client.list_groups(&username).await?;
                 let group_id = groups
                     .into_iter()
@@ -1582,7 +2388,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 new_name,
                 user,
             } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let groups = client.list_groups(&username).await?;
                 let group_id = groups
                     .into_iter()
@@ -1593,7 +2399,7 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 client.rename_group(&group_id, new_name).await?;
             }
             Groups::View { group, user } => {
-                let username = user.clone().unwrap_or_else(|| get_current_user(cfg.host()));
+                let username = act_as(&mut client, user, cfg.host());
                 let groups = client.list_groups(&username).await?;
                 let group_id = groups
                     .into_iter()
@@ -1622,6 +2428,10 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
             Oauth::RemoveClient { id } => {
                 client.remove_oauth_client(id).await?;
             }
+            Oauth::RotateSecret { id } => {
+                let client_data = client.rotate_oauth_secret(id).await?;
+                println!("{:?}", client_data);
+            }
         },
         Command::Host(cmd) => match &cmd.subcmd {
             Host::View => {
@@ -1670,37 +2480,695 @@ async fn do_command(mut cfg: Config, args: Cli) -> Result<(), error::Error> {
                 save_config(&cfg);
             }
         },
+        Command::Inbox { format, user } => {
+            let username = act_as(&mut client, user, cfg.host());
+            let on_behalf_of = cfg.host().username.as_ref() != Some(&username);
+            let inbox = Inbox::fetch(&client, &username, on_behalf_of).await?;
+            match format {
+                InboxFormat::Text => {
+                    for line in inbox.lines() {
+                        println!("{}", line);
+                    }
+                }
+                InboxFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&inbox).unwrap());
+                }
+            }
+        }
+        Command::Completions { shell } => {
+            write_completions(*shell, &mut std::io::stdout());
+        }
     }
 
     Ok(())
 }
 
-#[derive(Debug)]
-struct RoleSpan {
-    name: String,
-    start: usize,
-    media_start: usize,
-    end: usize,
+/// Get the path to export to. Exporting to a directory uses the project (or
+/// role) name as the filename.
+fn export_path(output: &Path, name: &str, format: &ExportFormat) -> PathBuf {
+    if output.is_dir() {
+        output.join(format!("{}.{}", name, format.extension()))
+    } else {
+        output.to_owned()
+    }
 }
 
-impl RoleSpan {
-    pub(crate) fn new(name: String, start: usize, media_start: usize, end: usize) -> Self {
-        Self {
-            name,
-            start,
-            media_start,
-            end,
+/// Write the exported project to the given path, creating any missing parent
+/// directories. Existing files are only replaced if `force` is set.
+fn write_export(path: &Path, content: &str, force: bool) -> io::Result<()> {
+    if path.exists() && !force {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+/// Check if the file (or zip entry) is a project file
+fn is_project_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("xml"))
+        .unwrap_or(false)
+}
+
+/// Get the default project name for the given project file
+fn project_name(path: &Path) -> Option<String> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.to_owned())
+}
+
+/// Read the project files in the given directory (sorted by name)
+fn read_project_dir(dir: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter(|path| path.is_file() && is_project_file(path))
+        .filter_map(|path| project_name(&path).map(|name| (name, path)))
+        .map(|(name, path)| fs::read(path).map(|content| (name, content)))
+        .collect()
+}
+
+/// Read the project files in the given zip archive (sorted by name)
+fn read_project_zip<R: io::Read + io::Seek>(reader: R) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let path = Path::new(file.name()).to_owned();
+        let is_metadata = path.starts_with("__MACOSX");
+        if !file.is_file() || is_metadata || !is_project_file(&path) {
+            continue;
+        }
+
+        if let Some(name) = project_name(&path) {
+            let mut content = Vec::new();
+            io::Read::read_to_end(&mut file, &mut content)?;
+            files.push((name, content));
         }
     }
+    files.sort_by(|(name1, _), (name2, _)| name1.cmp(name2));
+
+    Ok(files)
+}
+
+/// Parse each project file to import. Projects reusing a name from earlier in the
+/// batch are given a unique name so they do not overwrite one another.
+fn plan_project_imports(
+    files: Vec<(String, Vec<u8>)>,
+) -> Vec<(String, Result<Vec<RoleData>, String>)> {
+    let mut names = HashSet::new();
+    files
+        .into_iter()
+        .map(|(name, content)| {
+            let mut unique_name = name.clone();
+            let mut count = 1;
+            while !names.insert(unique_name.clone()) {
+                count += 1;
+                unique_name = format!("{} ({})", name, count);
+            }
+
+            let roles = String::from_utf8(content)
+                .map_err(|_err| "Invalid UTF-8".to_owned())
+                .and_then(|xml| xml::roles_from_project_xml(&xml).map_err(|err| err.to_string()));
+
+            (unique_name, roles)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netsblox_api::common::{Group, User};
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    fn network_msg(value: serde_json::Value) -> NetworkMessage {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_message_filter_type() {
+        let filter = MessageFilter {
+            msg_type: Some("rpcResult".into()),
+            from: None,
+        };
+        let msg = network_msg(serde_json::json!({
+            "type": "message",
+            "msgType": "rpcResult",
+            "srcId": "role@project@owner",
+            "content": {"result": 1}
+        }));
+        assert!(filter.matches(&msg));
+
+        let msg = network_msg(serde_json::json!({
+            "type": "message",
+            "msgType": "message",
+            "content": {}
+        }));
+        assert!(!filter.matches(&msg));
+
+        let msg = network_msg(serde_json::json!({"type": "room-roles"}));
+        assert!(!filter.matches(&msg));
+    }
+
+    #[test]
+    fn test_message_filter_from() {
+        let filter = MessageFilter {
+            msg_type: None,
+            from: Some("project@owner".into()),
+        };
+        let msg = network_msg(serde_json::json!({
+            "type": "message",
+            "msgType": "message",
+            "srcId": "role@project@owner",
+        }));
+        assert!(filter.matches(&msg));
+
+        let msg = network_msg(serde_json::json!({
+            "type": "message",
+            "msgType": "message",
+            "srcId": "role@otherproject@owner",
+        }));
+        assert!(!filter.matches(&msg));
+
+        let msg = network_msg(serde_json::json!({"type": "message", "msgType": "message"}));
+        assert!(!filter.matches(&msg));
+    }
+
+    #[test]
+    fn test_message_filter_none() {
+        let filter = MessageFilter {
+            msg_type: None,
+            from: None,
+        };
+        let msg = network_msg(serde_json::json!({"type": "eviction-notice"}));
+        assert!(filter.matches(&msg));
+    }
+
+    #[test]
+    fn test_role_elevation_to_admin() {
+        assert!(is_role_elevation(&UserRole::User, &UserRole::Admin));
+        assert!(is_role_elevation(&UserRole::Teacher, &UserRole::Moderator));
+        assert!(is_role_elevation(&UserRole::Moderator, &UserRole::Admin));
+    }
+
+    #[test]
+    fn test_role_elevation_to_teacher() {
+        assert!(!is_role_elevation(&UserRole::User, &UserRole::Teacher));
+    }
+
+    #[test]
+    fn test_role_elevation_demotion() {
+        assert!(!is_role_elevation(&UserRole::Admin, &UserRole::Moderator));
+        assert!(!is_role_elevation(&UserRole::Admin, &UserRole::User));
+        assert!(!is_role_elevation(&UserRole::Admin, &UserRole::Admin));
+    }
+
+    #[test]
+    fn test_find_host_equivalent_url() {
+        let hosts = vec![ServiceHost {
+            url: "http://localhost:8000".into(),
+            categories: vec!["custom".into()],
+        }];
+
+        assert_eq!(find_host(&hosts, "http://LOCALHOST:8000/"), Some(0));
+        assert_eq!(find_host(&hosts, "http://localhost:8001"), None);
+    }
+
+    #[test]
+    fn test_bash_completions_include_subcommands() {
+        let mut buf = Vec::new();
+        write_completions(Shell::Bash, &mut buf);
+        let script = String::from_utf8(buf).unwrap();
+
+        assert!(!script.is_empty());
+        for subcmd in ["login", "users", "projects", "network", "completions"] {
+            assert!(script.contains(subcmd), "missing subcommand: {}", subcmd);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_whoami_logged_out() {
+        let cfg = Config::default();
+        let args = Cli {
+            cmd: Command::Whoami,
+            dry_run: false,
+        };
+
+        let result = do_command(cfg, args).await;
+        assert!(matches!(result, Err(error::Error::NotLoggedInError)));
+    }
+
+    /// A request received by a mock server
+    #[derive(Clone, Debug)]
+    struct MockRequest {
+        method: String,
+        path: String,
+    }
+
+    /// Serve requests from a local server, responding to each with the status and
+    /// JSON body returned by the given handler. Every request is recorded.
+    fn serve_json_requests<F>(handler: F) -> (String, Arc<Mutex<Vec<MockRequest>>>)
+    where
+        F: Fn(&MockRequest) -> (&'static str, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let mut request_line = request_line.split_whitespace();
+                let request = MockRequest {
+                    method: request_line.next().unwrap().to_owned(),
+                    path: request_line.next().unwrap().to_owned(),
+                };
+                received.lock().unwrap().push(request.clone());
+                let (status, body) = handler(&request);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    /// Serve the given (status, JSON body) for each request path
+    fn serve_routes(routes: HashMap<&'static str, (&'static str, String)>) -> String {
+        let (url, _requests) = serve_json_requests(move |request| {
+            routes
+                .get(request.path.as_str())
+                .cloned()
+                .unwrap_or(("404 Not Found", String::new()))
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_delete_group_dry_run() {
+        let group = Group {
+            id: GroupId::new("someGroupId".into()),
+            owner: "brian".into(),
+            name: "myGroup".into(),
+            services_hosts: None,
+        };
+        let body = serde_json::to_string(&vec![group]).unwrap();
+        let (url, requests) = serve_json_requests(move |_| ("200 OK", body.clone()));
+        let host = HostConfig {
+            url,
+            username: Some("brian".into()),
+            token: Some("someToken".into()),
+        };
+        let cfg = Config {
+            current_host: "test".into(),
+            hosts: HashMap::from([("test".into(), host)]),
+        };
+        let args = Cli::parse_from(["netsblox", "groups", "delete", "myGroup", "--dry-run"]);
+
+        do_command(cfg, args).await.unwrap();
+        let methods: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.method.clone())
+            .collect();
+        assert_eq!(methods, vec!["GET"]);
+    }
+
+    fn inbox_routes(
+        libraries: (&'static str, String),
+    ) -> HashMap<&'static str, (&'static str, String)> {
+        let friend_invite = FriendInvite {
+            id: "someInviteId".into(),
+            sender: "hamid".into(),
+            recipient: "brian".into(),
+            created_at: std::time::SystemTime::now(),
+        };
+        let collab_invite = CollaborationInvite::new(
+            "will".into(),
+            "brian".into(),
+            ProjectId::new("someProjectId".into()),
+        );
+
+        HashMap::from([
+            (
+                "/friends/brian/invites/",
+                (
+                    "200 OK",
+                    serde_json::to_string(&vec![friend_invite]).unwrap(),
+                ),
+            ),
+            (
+                "/collaboration-invites/user/brian/",
+                (
+                    "200 OK",
+                    serde_json::to_string(&vec![collab_invite]).unwrap(),
+                ),
+            ),
+            ("/libraries/mod/pending", libraries),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_inbox_fetch() {
+        let library = LibraryMetadata::new(
+            "steve".into(),
+            "myLibrary".into(),
+            PublishState::PendingApproval,
+            None,
+        );
+        let libraries = ("200 OK", serde_json::to_string(&vec![library]).unwrap());
+        let url = serve_routes(inbox_routes(libraries));
+        let client = Client::new(netsblox_api::Config {
+            url,
+            ..Default::default()
+        });
+
+        let inbox = Inbox::fetch(&client, "brian", false).await.unwrap();
+        assert_eq!(
+            inbox.lines(),
+            vec![
+                "friend\thamid",
+                "collaboration\twill\tsomeProjectId",
+                "library\tsteve\tmyLibrary",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inbox_fetch_not_moderator() {
+        let libraries = ("403 Forbidden", "Unauthorized".to_owned());
+        let url = serve_routes(inbox_routes(libraries));
+        let client = Client::new(netsblox_api::Config {
+            url,
+            ..Default::default()
+        });
+
+        let inbox = Inbox::fetch(&client, "brian", false).await.unwrap();
+        assert!(inbox.pending_libraries.is_empty());
+        assert_eq!(inbox.friend_invites.len(), 1);
+        assert_eq!(inbox.collaboration_invites.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_inbox_fetch_on_behalf_of_user() {
+        let library = LibraryMetadata::new(
+            "steve".into(),
+            "myLibrary".into(),
+            PublishState::PendingApproval,
+            None,
+        );
+        let libraries = ("200 OK", serde_json::to_string(&vec![library]).unwrap());
+        let user = User {
+            username: "brian".into(),
+            email: "brian@netsblox.org".into(),
+            group_id: None,
+            role: UserRole::User,
+            created_at: std::time::SystemTime::now(),
+            linked_accounts: Vec::new(),
+            services_hosts: None,
+            delete_at: None,
+        };
+        let mut routes = inbox_routes(libraries);
+        routes.insert(
+            "/users/brian",
+            ("200 OK", serde_json::to_string(&user).unwrap()),
+        );
+        let url = serve_routes(routes);
+        let client = Client::new(netsblox_api::Config {
+            url,
+            ..Default::default()
+        });
+
+        // the logged in moderator can see the libraries but brian cannot
+        let inbox = Inbox::fetch(&client, "brian", true).await.unwrap();
+        assert!(inbox.pending_libraries.is_empty());
+        assert_eq!(inbox.friend_invites.len(), 1);
+        assert_eq!(inbox.collaboration_invites.len(), 1);
+    }
+
+    #[test]
+    fn test_dry_run_message() {
+        let message = dry_run_message("group myGroup (someGroupId)");
+        assert_eq!(message, "Dry run: would delete group myGroup (someGroupId)");
+    }
+
+    fn project_xml(role: &str) -> String {
+        format!(
+            "<room name=\"myProject\"><role name=\"{}\"><project name=\"{}\"></project><media></media></role></room>",
+            role, role
+        )
+    }
+
+    /// Create a new (empty) temporary directory
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("netsblox-cli-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
 
-    pub(crate) fn into_role(self, xml: &str) -> RoleData {
-        let code = &xml[self.start..self.media_start];
-        let media = &xml[self.media_start..self.end];
+    #[test]
+    fn test_write_export_creates_dirs() {
+        let dir = temp_dir();
+        let path = dir.join("exports").join("project.xml");
 
-        RoleData {
-            name: self.name,
-            code: code.to_owned(),
-            media: media.to_owned(),
+        write_export(&path, "<room/>", false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<room/>");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_export_no_overwrite() {
+        let dir = temp_dir();
+        let path = dir.join("project.xml");
+        fs::write(&path, "original").unwrap();
+
+        let err = write_export(&path, "<room/>", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+
+        write_export(&path, "<room/>", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<room/>");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_path_dir() {
+        let dir = temp_dir();
+
+        let path = export_path(&dir, "myRole", &ExportFormat::Xml);
+        assert_eq!(path, dir.join("myRole.xml"));
+        let path = export_path(&dir, "myRole", &ExportFormat::Json);
+        assert_eq!(path, dir.join("myRole.json"));
+
+        let file = dir.join("out.xml");
+        assert_eq!(export_path(&file, "myRole", &ExportFormat::Xml), file);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_plan_project_imports() {
+        let files = vec![
+            ("proj".to_owned(), project_xml("r1").into_bytes()),
+            ("broken".to_owned(), b"<room><role".to_vec()),
+            ("proj".to_owned(), project_xml("r2").into_bytes()),
+        ];
+
+        let plan = plan_project_imports(files);
+        let names: Vec<_> = plan.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["proj", "broken", "proj (2)"]);
+        assert_eq!(plan[0].1.as_ref().unwrap()[0].name, "r1");
+        assert!(plan[1].1.is_err());
+        assert_eq!(plan[2].1.as_ref().unwrap()[0].name, "r2");
+    }
+
+    #[test]
+    fn test_read_project_zip() {
+        let mut buf = io::Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut buf);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let entries = [
+            ("submissions/second.xml", project_xml("r2")),
+            ("first.XML", project_xml("r1")),
+            ("notes.txt", "not a project".to_owned()),
+            ("__MACOSX/first.xml", "metadata".to_owned()),
+        ];
+        for (name, content) in entries {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
         }
+        writer.finish().unwrap();
+        drop(writer);
+
+        buf.set_position(0);
+        let files = read_project_zip(buf).unwrap();
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert_eq!(files[0].1, project_xml("r1").into_bytes());
+    }
+
+    #[test]
+    fn test_read_password_stdin() {
+        let mut stdin = io::Cursor::new("s3cret\n");
+        let password = read_password(None, true, None, &mut stdin)
+            .unwrap()
+            .unwrap();
+        let creds = snap_credentials("brian", password);
+
+        assert!(matches!(
+            &*creds,
+            Credentials::Snap { username, password } if username == "brian" && password == "s3cret"
+        ));
+    }
+
+    #[test]
+    fn test_read_password_stdin_crlf() {
+        let mut stdin = io::Cursor::new("s3cret\r\n");
+        let password = read_password(None, true, None, &mut stdin)
+            .unwrap()
+            .unwrap();
+        assert_eq!(password.as_str(), "s3cret");
+    }
+
+    #[test]
+    fn test_read_password_stdin_empty() {
+        let mut stdin = io::Cursor::new("");
+        let result = read_password(None, true, None, &mut stdin);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_password_prefers_stdin_over_env() {
+        let mut stdin = io::Cursor::new("fromstdin\n");
+        let env = Some("fromenv".to_owned());
+        let password = read_password(None, true, env, &mut stdin).unwrap().unwrap();
+        assert_eq!(password.as_str(), "fromstdin");
+    }
+
+    #[test]
+    fn test_read_password_env_fallback() {
+        let mut stdin = io::Cursor::new("ignored\n");
+        let env = Some("fromenv".to_owned());
+        let password = read_password(None, false, env, &mut stdin)
+            .unwrap()
+            .unwrap();
+        assert_eq!(password.as_str(), "fromenv");
+    }
+
+    #[test]
+    fn test_read_password_none() {
+        let mut stdin = io::Cursor::new("");
+        let password = read_password(None, false, None, &mut stdin).unwrap();
+        assert!(password.is_none());
+    }
+
+    #[test]
+    fn test_set_password_stdin_conflicts_with_arg() {
+        let result =
+            Cli::try_parse_from([APP_NAME, "users", "set-password", "pwd", "--password-stdin"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_roster_csv() {
+        let roster =
+            "username,email,password\nalice,alice@example.com,pwd\n\nbob, bob@example.com\n";
+        let entries = parse_roster(roster, false).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                RosterEntry {
+                    username: "alice".to_owned(),
+                    email: "alice@example.com".to_owned(),
+                    password: Some("pwd".to_owned()),
+                },
+                RosterEntry {
+                    username: "bob".to_owned(),
+                    email: "bob@example.com".to_owned(),
+                    password: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_roster_csv_malformed_row() {
+        let roster = "alice,alice@example.com\nbob\n";
+        let result = parse_roster(roster, false);
+        assert!(matches!(result, Err(msg) if msg.starts_with("Line 2")));
+    }
+
+    #[test]
+    fn test_parse_roster_json() {
+        let roster = r#"[
+            {"username": "alice", "email": "alice@example.com", "password": "pwd"},
+            {"username": "bob", "email": "not-an-email"}
+        ]"#;
+        let entries = parse_roster(roster, true).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].password.as_deref(), Some("pwd"));
+        // email validation is left to the server so it can be reported per row
+        assert_eq!(entries[1].email, "not-an-email");
+        assert_eq!(entries[1].password, None);
+    }
+
+    #[test]
+    fn test_split_duplicates() {
+        let roster = "alice,a1@example.com\nbob,b@example.com\nalice,a2@example.com\n";
+        let entries = parse_roster(roster, false).unwrap();
+        let (entries, duplicates) = split_duplicates(entries);
+
+        let names: Vec<_> = entries.iter().map(|e| e.username.as_str()).collect();
+        assert_eq!(names, vec!["alice", "bob"]);
+        assert_eq!(entries[0].email, "a1@example.com");
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].email, "a2@example.com");
+    }
+
+    #[test]
+    fn test_roster_entry_to_new_user() {
+        let entry = RosterEntry {
+            username: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+            password: None,
+        };
+        let group_id = GroupId::new("someGroup".to_owned());
+        let user = entry.to_new_user(Some(&group_id), &UserRole::User);
+
+        assert_eq!(user.username, "alice");
+        assert_eq!(user.group_id, Some(group_id));
+        assert!(matches!(user.role, Some(UserRole::User)));
     }
 }
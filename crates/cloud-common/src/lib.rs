@@ -1,7 +1,7 @@
 use mongodb::bson::{self, doc, document::Document, Bson, DateTime};
 pub use netsblox_api_common as api;
 use netsblox_api_common::{
-    oauth, ClientState, LibraryMetadata, NewUser, PublishState, RoleId, UserRole,
+    oauth, ClientState, CollabRole, LibraryMetadata, NewUser, PublishState, RoleId, UserRole,
 };
 use netsblox_api_common::{
     FriendInvite, FriendLinkState, GroupId, InvitationState, LinkedAccount, ProjectId, RoleData,
@@ -28,6 +28,13 @@ pub struct User {
     pub linked_accounts: Vec<LinkedAccount>,
     pub services_hosts: Option<Vec<ServiceHost>>,
     pub service_settings: HashMap<String, String>,
+    /// Accounts scheduled for deletion are removed once this time has passed
+    pub delete_at: Option<DateTime>,
+    /// Whether the user has confirmed ownership of their email address
+    #[serde(default)]
+    pub verified: bool,
+    /// Optional TOTP-based two-factor authentication
+    pub two_factor: Option<TwoFactorAuth>,
 }
 
 impl User {
@@ -49,6 +56,30 @@ impl From<User> for Bson {
             "linkedAccounts": user.linked_accounts,
             "servicesHosts": user.services_hosts,
             "serviceSettings": bson::to_bson(&user.service_settings).unwrap(),
+            "deleteAt": user.delete_at,
+            "verified": user.verified,
+            "twoFactor": user.two_factor,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorAuth {
+    /// The TOTP secret, encrypted by the server
+    pub secret: String,
+    /// Two-factor auth is only enabled once the user has verified a code
+    pub enabled: bool,
+    /// Hashes of the remaining (unused) backup codes
+    pub backup_codes: Vec<String>,
+}
+
+impl From<TwoFactorAuth> for Bson {
+    fn from(two_factor: TwoFactorAuth) -> Bson {
+        Bson::Document(doc! {
+            "secret": two_factor.secret,
+            "enabled": two_factor.enabled,
+            "backupCodes": two_factor.backup_codes,
         })
     }
 }
@@ -63,6 +94,7 @@ impl From<User> for netsblox_api_common::User {
             created_at: user.created_at.to_system_time(),
             linked_accounts: user.linked_accounts,
             services_hosts: user.services_hosts,
+            delete_at: user.delete_at.map(|time| time.to_system_time()),
         }
     }
 }
@@ -94,6 +126,9 @@ impl From<NewUser> for User {
             role: user_data.role.unwrap_or(UserRole::User),
             services_hosts: None,
             service_settings: HashMap::new(),
+            delete_at: None,
+            verified: false,
+            two_factor: None,
         }
     }
 }
@@ -206,6 +241,9 @@ pub struct CollaborationInvite {
     pub project_id: ProjectId,
     pub state: InvitationState,
     pub created_at: DateTime,
+    /// Dismissed invites are hidden from the receiver but kept for the sender
+    #[serde(default)]
+    pub dismissed: bool,
 }
 
 impl CollaborationInvite {
@@ -217,6 +255,7 @@ impl CollaborationInvite {
             project_id,
             state: InvitationState::Pending,
             created_at: DateTime::from_system_time(SystemTime::now()),
+            dismissed: false,
         }
     }
 }
@@ -230,6 +269,7 @@ impl From<CollaborationInvite> for Bson {
             "projectId": invite.project_id,
             "state": invite.state,
             "createdAt": invite.created_at,
+            "dismissed": invite.dismissed,
         })
     }
 }
@@ -356,11 +396,22 @@ pub struct ProjectMetadata {
     pub updated: DateTime,
     pub state: PublishState,
     pub collaborators: std::vec::Vec<String>,
+    /// Roles of the collaborators. Collaborators without a role are editors.
+    #[serde(default)]
+    pub collaborator_roles: HashMap<String, CollabRole>,
     pub origin_time: DateTime,
     pub save_state: SaveState,
     pub delete_at: Option<DateTime>,
     pub network_traces: Vec<NetworkTraceMetadata>,
     pub roles: HashMap<RoleId, RoleMetadata>,
+    /// Users who have starred (favorited) the project
+    #[serde(default)]
+    pub starred_by: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Incremented on each edit so concurrent updates can be detected
+    #[serde(default)]
+    pub version: u64,
 }
 
 impl ProjectMetadata {
@@ -390,12 +441,29 @@ impl ProjectMetadata {
             origin_time,
             state: PublishState::Private,
             collaborators: vec![],
+            collaborator_roles: HashMap::new(),
             save_state,
             delete_at,
             network_traces: Vec::new(),
             roles,
+            starred_by: Vec::new(),
+            tags: Vec::new(),
+            version: 0,
         }
     }
+
+    /// Get the role of the given user if they are a collaborator on the project
+    pub fn collaborator_role(&self, username: &str) -> Option<CollabRole> {
+        self.collaborators
+            .iter()
+            .any(|name| name == username)
+            .then(|| {
+                self.collaborator_roles
+                    .get(username)
+                    .copied()
+                    .unwrap_or_default()
+            })
+    }
 }
 
 impl From<ProjectMetadata> for Bson {
@@ -404,6 +472,13 @@ impl From<ProjectMetadata> for Bson {
         metadata.roles.into_iter().for_each(|(id, md)| {
             roles.insert(id.as_str(), md);
         });
+        let mut collaborator_roles = Document::new();
+        metadata
+            .collaborator_roles
+            .into_iter()
+            .for_each(|(name, role)| {
+                collaborator_roles.insert(name, role);
+            });
 
         Bson::Document(doc! {
             "id": metadata.id,
@@ -413,10 +488,14 @@ impl From<ProjectMetadata> for Bson {
             "originTime": metadata.origin_time,
             "state": metadata.state,
             "collaborators": metadata.collaborators,
+            "collaboratorRoles": collaborator_roles,
             "saveState": metadata.save_state,
             "roles": roles,
             "deleteAt": metadata.delete_at,
             "networkTraces": metadata.network_traces,
+            "starredBy": metadata.starred_by,
+            "tags": metadata.tags,
+            "version": metadata.version as i64,
         })
     }
 }
@@ -431,7 +510,10 @@ impl From<ProjectMetadata> for netsblox_api_common::ProjectMetadata {
             updated: metadata.updated.to_system_time(),
             state: metadata.state,
             collaborators: metadata.collaborators,
+            collaborator_roles: metadata.collaborator_roles,
             save_state: metadata.save_state,
+            tags: metadata.tags,
+            version: metadata.version,
             network_traces: metadata
                 .network_traces
                 .into_iter()
@@ -580,6 +662,68 @@ impl From<LogMessage> for api::LogMessage {
     }
 }
 
+/// Message to an offline external client which is delivered when the client
+/// reconnects (unless it expires first)
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedMessage {
+    pub app_id: api::AppId,
+    pub address: String,
+    pub content: serde_json::Value,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+}
+
+impl QueuedMessage {
+    pub fn new(
+        app_id: api::AppId,
+        address: String,
+        content: serde_json::Value,
+        ttl: Duration,
+    ) -> Self {
+        let now = SystemTime::now();
+        QueuedMessage {
+            app_id,
+            address,
+            content,
+            created_at: DateTime::from_system_time(now),
+            expires_at: DateTime::from_system_time(now + ttl),
+        }
+    }
+}
+
+/// Address at which an external client has registered. Messages are only queued
+/// for addresses which have been registered before.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalRegistration {
+    pub app_id: api::AppId,
+    pub address: String,
+    pub last_seen: DateTime,
+}
+
+impl ExternalRegistration {
+    pub fn new(app_id: api::AppId, address: String) -> Self {
+        ExternalRegistration {
+            app_id,
+            address,
+            last_seen: DateTime::now(),
+        }
+    }
+}
+
+impl From<QueuedMessage> for api::QueuedMessage {
+    fn from(value: QueuedMessage) -> Self {
+        api::QueuedMessage {
+            app_id: value.app_id,
+            address: value.address,
+            content: value.content,
+            created_at: value.created_at.to_system_time(),
+            expires_at: value.expires_at.to_system_time(),
+        }
+    }
+}
+
 impl SentMessage {
     pub fn new(
         project_id: ProjectId,
@@ -610,6 +754,134 @@ impl From<SentMessage> for api::SentMessage {
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailVerificationToken {
+    pub username: String,
+    /// The email address being verified (in case it changes before verification)
+    pub email: String,
+    pub secret: String,
+    pub created_at: DateTime,
+}
+
+impl EmailVerificationToken {
+    pub fn new(username: String, email: String) -> Self {
+        let secret = Uuid::new_v4().to_string();
+        let created_at = DateTime::from_system_time(SystemTime::now());
+
+        EmailVerificationToken {
+            username,
+            email,
+            secret,
+            created_at,
+        }
+    }
+}
+
+impl From<EmailVerificationToken> for Bson {
+    fn from(token: EmailVerificationToken) -> Bson {
+        Bson::Document(doc! {
+            "username": token.username,
+            "email": token.email,
+            "secret": token.secret,
+            "createdAt": token.created_at,
+        })
+    }
+}
+
+/// An entry in the (append-only) audit log of privileged actions
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub actor: String,
+    pub action: api::AuditAction,
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+    pub created_at: DateTime,
+}
+
+impl AuditLogEntry {
+    pub fn new(actor: String, action: api::AuditAction, target: String) -> Self {
+        AuditLogEntry {
+            actor,
+            action,
+            target,
+            request: None,
+            created_at: DateTime::now(),
+        }
+    }
+
+    pub fn impersonation(actor: String, target: String, request: String) -> Self {
+        AuditLogEntry {
+            request: Some(request),
+            ..AuditLogEntry::new(actor, api::AuditAction::Impersonate, target)
+        }
+    }
+}
+
+impl From<AuditLogEntry> for Bson {
+    fn from(entry: AuditLogEntry) -> Bson {
+        Bson::Document(doc! {
+            "actor": entry.actor,
+            "action": entry.action,
+            "target": entry.target,
+            "request": entry.request,
+            "createdAt": entry.created_at,
+        })
+    }
+}
+
+impl From<AuditLogEntry> for api::AuditLogEntry {
+    fn from(entry: AuditLogEntry) -> api::AuditLogEntry {
+        api::AuditLogEntry {
+            actor: entry.actor,
+            action: entry.action,
+            target: entry.target,
+            request: entry.request,
+            created_at: entry.created_at.to_system_time(),
+        }
+    }
+}
+
+/// A login session. Sessions are recorded so users can view and revoke them.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSession {
+    pub id: String,
+    pub username: String,
+    pub created_at: DateTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub revoked: bool,
+}
+
+impl UserSession {
+    pub fn new(username: String, user_agent: Option<String>, ip_address: Option<String>) -> Self {
+        UserSession {
+            id: Uuid::new_v4().to_string(),
+            username,
+            created_at: DateTime::from_system_time(SystemTime::now()),
+            user_agent,
+            ip_address,
+            revoked: false,
+        }
+    }
+}
+
+impl From<UserSession> for Bson {
+    fn from(session: UserSession) -> Bson {
+        Bson::Document(doc! {
+            "id": session.id,
+            "username": session.username,
+            "createdAt": session.created_at,
+            "userAgent": session.user_agent,
+            "ipAddress": session.ip_address,
+            "revoked": session.revoked,
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SetPasswordToken {
@@ -716,16 +988,21 @@ pub struct Library {
     pub notes: String,
     pub blocks: String,
     pub state: PublishState,
+    #[serde(default)]
+    pub downloads: u32,
 }
 
 impl From<Library> for LibraryMetadata {
     fn from(library: Library) -> LibraryMetadata {
-        LibraryMetadata::new(
-            library.owner.clone(),
-            library.name.clone(),
-            library.state,
-            Some(library.notes),
-        )
+        LibraryMetadata {
+            downloads: library.downloads,
+            ..LibraryMetadata::new(
+                library.owner.clone(),
+                library.name.clone(),
+                library.state,
+                Some(library.notes),
+            )
+        }
     }
 }
 
@@ -737,6 +1014,7 @@ impl From<Library> for Bson {
             "notes": library.notes,
             "blocks": library.blocks,
             "state": library.state,
+            "downloads": library.downloads,
         })
     }
 }
@@ -753,15 +1031,7 @@ pub struct OAuthClient {
 
 impl OAuthClient {
     pub fn new(name: String, password: String) -> Self {
-        let salt = passwords::PasswordGenerator::new()
-            .length(8)
-            .exclude_similar_characters(true)
-            .numbers(true)
-            .spaces(false)
-            .generate_one()
-            .unwrap_or_else(|_err| "salt".to_owned());
-
-        let hash = sha512(&(password + &salt));
+        let (hash, salt) = hash_client_secret(password);
         Self {
             id: oauth::ClientId::new(Uuid::new_v4().to_string()),
             name,
@@ -770,6 +1040,24 @@ impl OAuthClient {
             salt,
         }
     }
+
+    pub fn is_valid_secret(&self, secret: &str) -> bool {
+        sha512(&(secret.to_owned() + &self.salt)) == self.hash
+    }
+}
+
+/// Hash the client secret with a newly generated salt. Returns the hash and salt.
+pub fn hash_client_secret(secret: String) -> (String, String) {
+    let salt = passwords::PasswordGenerator::new()
+        .length(8)
+        .exclude_similar_characters(true)
+        .numbers(true)
+        .spaces(false)
+        .generate_one()
+        .unwrap_or_else(|_err| "salt".to_owned());
+
+    let hash = sha512(&(secret + &salt));
+    (hash, salt)
 }
 
 impl From<OAuthClient> for Bson {
@@ -854,6 +1142,8 @@ pub struct MagicLink {
     pub id: api::MagicLinkId,
     pub email: String,
     pub created_at: DateTime,
+    #[serde(default)]
+    pub intent: api::MagicLinkIntent,
 }
 
 impl MagicLink {
@@ -862,6 +1152,7 @@ impl MagicLink {
             id: api::MagicLinkId::new(Uuid::new_v4().to_string()),
             email,
             created_at: DateTime::now(),
+            intent: api::MagicLinkIntent::Login,
         }
     }
 }
@@ -872,6 +1163,7 @@ impl From<MagicLink> for Bson {
             "id": link.id,
             "email": link.email,
             "createdAt": link.created_at,
+            "intent": link.intent,
         })
     }
 }
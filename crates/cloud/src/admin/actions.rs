@@ -0,0 +1,73 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, DateTime},
+    options::FindOptions,
+    Collection,
+};
+use netsblox_cloud_common::{api, AuditLogEntry};
+
+use crate::auth;
+use crate::errors::{InternalError, UserError};
+
+/// Max number of entries returned when listing the audit log
+const MAX_AUDIT_LOG_ENTRIES: i64 = 1000;
+
+pub(crate) struct AdminActions<'a> {
+    audit_log: &'a Collection<AuditLogEntry>,
+}
+
+impl<'a> AdminActions<'a> {
+    pub(crate) fn new(audit_log: &'a Collection<AuditLogEntry>) -> Self {
+        Self { audit_log }
+    }
+
+    /// List the matching audit log entries (newest first)
+    pub(crate) async fn list_audit_log(
+        &self,
+        _val: &auth::ViewAuditLog,
+        filter: &api::AuditLogQuery,
+    ) -> Result<Vec<api::AuditLogEntry>, UserError> {
+        let mut query = doc! {};
+        if let Some(actor) = &filter.actor {
+            query.insert("actor", actor);
+        }
+        if let Some(target) = &filter.target {
+            query.insert("target", target);
+        }
+
+        let mut created_at = doc! {};
+        if let Some(after) = filter.after {
+            created_at.insert("$gte", DateTime::from_millis(after));
+        }
+        if let Some(before) = filter.before {
+            created_at.insert("$lt", DateTime::from_millis(before));
+        }
+        if !created_at.is_empty() {
+            query.insert("createdAt", created_at);
+        }
+
+        let limit = filter
+            .limit
+            .map(|limit| limit.clamp(1, MAX_AUDIT_LOG_ENTRIES))
+            .unwrap_or(MAX_AUDIT_LOG_ENTRIES);
+        // entries recorded in the same millisecond are ordered by insertion
+        let options = FindOptions::builder()
+            .sort(doc! {"createdAt": -1, "_id": -1})
+            .skip(filter.skip)
+            .limit(limit)
+            .build();
+        let entries = self
+            .audit_log
+            .find(query, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|entry| entry.into())
+            .collect();
+
+        Ok(entries)
+    }
+}
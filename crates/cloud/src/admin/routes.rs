@@ -0,0 +1,136 @@
+use crate::admin::actions::AdminActions;
+use crate::app_data::AppData;
+use crate::auth;
+use crate::common::api;
+use crate::errors::UserError;
+use actix_web::{get, HttpRequest};
+use actix_web::{web, HttpResponse};
+
+#[get("/audit")]
+async fn list_audit_log(
+    app: web::Data<AppData>,
+    params: web::Query<api::AuditLogQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_val = auth::try_view_audit_log(&app, &req).await?;
+
+    let actions: AdminActions = app.as_admin_actions();
+    let entries = actions.list_audit_log(&auth_val, &params).await?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_audit_log);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use actix_web::{http, test, App};
+    use netsblox_cloud_common::{api::UserRole, User};
+
+    #[actix_web::test]
+    async fn test_list_audit_log() {
+        let admin: User = api::NewUser {
+            username: "admin".into(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .run(|app_data| async move {
+                app_data
+                    .record_audit_entry("mod1", api::AuditAction::BanUser, "user1")
+                    .await;
+                app_data
+                    .record_audit_entry("mod2", api::AuditAction::BanUser, "user2")
+                    .await;
+                app_data
+                    .record_audit_entry("mod1", api::AuditAction::UnbanUser, "user2")
+                    .await;
+
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/audit")
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let entries: Vec<api::AuditLogEntry> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(entries.len(), 3);
+                assert_eq!(entries[0].action, api::AuditAction::UnbanUser);
+
+                let req = test::TestRequest::get()
+                    .uri("/audit?skip=1&limit=1")
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let entries: Vec<api::AuditLogEntry> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].actor, "mod2");
+
+                let req = test::TestRequest::get()
+                    .uri("/audit?actor=mod1&target=user2")
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let entries: Vec<api::AuditLogEntry> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].actor, "mod1");
+                assert_eq!(entries[0].target, "user2");
+
+                let req = test::TestRequest::get()
+                    .uri("/audit?before=0")
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let entries: Vec<api::AuditLogEntry> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(entries.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_audit_log_403() {
+        let moderator: User = api::NewUser {
+            username: "moderator".into(),
+            email: "moderator@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Moderator),
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[moderator.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/audit")
+                    .cookie(test_utils::cookie::new(&moderator.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+}
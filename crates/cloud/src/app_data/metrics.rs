@@ -1,34 +1,47 @@
 use actix_web_prom::{PrometheusMetrics, PrometheusMetricsBuilder};
-use prometheus::{IntCounter, IntGauge};
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, Opts, TextEncoder};
+
+const LOGIN_SUCCESS: &str = "success";
+const LOGIN_FAILURE: &str = "failure";
 
 /// This is used to record various server metrics for use with prometheus. Metrics include:
-///  - logins (username, program?)
+///  - logins (result)
 ///  - signups (username)
 ///  - active users (program)
+///  - active rooms
+///  - projects created
 ///  - messages sent (sender, receiver)
 ///  - general API endpoint usage?
 
 #[derive(Clone)]
 pub(crate) struct Metrics {
     prometheus: PrometheusMetrics,
-    logins: IntCounter,
+    logins: IntCounterVec,
     signups: IntCounter,
     clients: IntGauge,
+    rooms: IntGauge,
+    projects_created: IntCounter,
     sent_messages: IntCounter,
 }
 
 impl Metrics {
     pub(crate) fn new() -> Self {
-        let prometheus = PrometheusMetricsBuilder::new("metrics")
-            .endpoint("/metrics")
-            .build()
-            .unwrap();
+        // The endpoint is served by the metrics routes (rather than the middleware)
+        // so it can be protected by a bearer token
+        let prometheus = PrometheusMetricsBuilder::new("metrics").build().unwrap();
 
-        let logins = IntCounter::new("netsblox_logins", "NetsBlox logins").unwrap();
+        let logins = IntCounterVec::new(
+            Opts::new("netsblox_logins_total", "NetsBlox login attempts"),
+            &["result"],
+        )
+        .unwrap();
         prometheus
             .registry
             .register(Box::new(logins.clone()))
             .unwrap();
+        // report both results (even before any logins) so rates can be computed
+        logins.with_label_values(&[LOGIN_SUCCESS]);
+        logins.with_label_values(&[LOGIN_FAILURE]);
 
         let signups = IntCounter::new("netsblox_signups", "New account creation count").unwrap();
         prometheus
@@ -42,6 +55,19 @@ impl Metrics {
             .register(Box::new(clients.clone()))
             .unwrap();
 
+        let rooms = IntGauge::new("netsblox_rooms", "Active rooms").unwrap();
+        prometheus
+            .registry
+            .register(Box::new(rooms.clone()))
+            .unwrap();
+
+        let projects_created =
+            IntCounter::new("netsblox_projects_created", "Projects created").unwrap();
+        prometheus
+            .registry
+            .register(Box::new(projects_created.clone()))
+            .unwrap();
+
         let sent_messages =
             IntCounter::new("netsblox_sent_messages", "NetsBlox messages sent").unwrap();
         prometheus
@@ -56,6 +82,8 @@ impl Metrics {
             signups,
 
             clients,
+            rooms,
+            projects_created,
             sent_messages,
         }
     }
@@ -64,8 +92,22 @@ impl Metrics {
         self.prometheus.clone()
     }
 
+    /// Get the current metrics in the prometheus text format
+    pub(crate) fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.prometheus.registry.gather(), &mut buffer)
+            .unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+
     pub(crate) fn record_login(&self) {
-        self.logins.inc();
+        self.logins.with_label_values(&[LOGIN_SUCCESS]).inc();
+    }
+
+    pub(crate) fn record_failed_login(&self) {
+        self.logins.with_label_values(&[LOGIN_FAILURE]).inc();
     }
 
     pub(crate) fn record_signup(&self) {
@@ -76,6 +118,14 @@ impl Metrics {
         self.clients.set(count as i64);
     }
 
+    pub(crate) fn record_active_rooms(&self, count: usize) {
+        self.rooms.set(count as i64);
+    }
+
+    pub(crate) fn record_project_created(&self) {
+        self.projects_created.inc();
+    }
+
     pub(crate) fn record_msg_sent(&self) {
         self.sent_messages.inc();
     }
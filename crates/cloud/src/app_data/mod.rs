@@ -1,20 +1,24 @@
 pub(crate) mod metrics;
 
+use crate::admin::actions::AdminActions;
 use crate::collaboration_invites::actions::CollaborationInviteActions;
 use crate::common::api::{oauth, NewUser, ProjectId, UserRole};
 use crate::friends::actions::FriendActions;
 use crate::groups::actions::GroupActions;
 use crate::libraries::actions::LibraryActions;
-use crate::login_helper::LoginHelper;
+use crate::login_helper::{LoginHelper, LoginHelperData};
 use crate::magic_links::actions::MagicLinkActions;
-use crate::network::actions::NetworkActions;
+use crate::network::actions::{NetworkActionData, NetworkActions};
 use crate::oauth::actions::OAuthActions;
 use crate::projects::ProjectActions;
+use crate::rate_limit::RateLimiter;
 use crate::services::hosts::actions::HostActions;
 use crate::services::settings::actions::SettingsActions;
-use crate::users::actions::{UserActionData, UserActions};
-use actix::dev::OneshotSender;
+use crate::two_factor::EncryptionKey;
+use crate::users::actions::{UserActionData, UserActions, EMAIL_VERIFICATION_TOKEN_TTL};
+use actix_session::Session;
 use actix_web::rt::time;
+use actix_web::HttpRequest;
 use lettre::message::Mailbox;
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::SmtpTransport;
@@ -28,27 +32,36 @@ use std::collections::HashSet;
 use std::net::IpAddr;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock as AsyncRwLock;
 
 use crate::common::api::SaveState;
 use crate::common::{
-    AuthorizedServiceHost, BannedAccount, CollaborationInvite, FriendLink, Group, Library,
-    OAuthClient, OAuthToken, ProjectMetadata, SetPasswordToken, User,
+    AuditLogEntry, AuthorizedServiceHost, BannedAccount, CollaborationInvite,
+    EmailVerificationToken, FriendLink, Group, Library, OAuthClient, OAuthToken, ProjectMetadata,
+    SetPasswordToken, User, UserSession,
 };
-use crate::common::{LogMessage, OccupantInvite, SentMessage};
+use crate::common::{ExternalRegistration, LogMessage, OccupantInvite, QueuedMessage, SentMessage};
 use crate::config::Settings;
 use crate::errors::{InternalError, UserError};
-use crate::network::topology::{SetStorage, TopologyActor, TopologyPanic};
+use crate::network::topology::{SetStorage, TopologyActor, TopologyFailure};
+use crate::utils;
 use actix::{Actor, Addr};
 use aws_config::SdkConfig;
 use aws_credential_types::{provider::SharedCredentialsProvider, Credentials as S3Credentials};
 use aws_sdk_s3::{self as s3, config::Region};
 use futures::TryStreamExt;
-use mongodb::{Client, Collection, IndexModel};
+use mongodb::{Client, Collection, Database, IndexModel};
+
+/// How long an external client address is remembered after it was last registered
+const EXTERNAL_REGISTRATION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Lifetime of the session cookie (and the corresponding session record)
+pub(crate) const SESSION_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
 #[derive(Clone)]
 pub struct AppData {
     bucket: String,
+    db: Database,
     tor_exit_nodes: Collection<TorNode>,
     s3: s3::Client,
     pub(crate) settings: Settings,
@@ -56,15 +69,20 @@ pub struct AppData {
     pub(crate) groups: Collection<Group>,
     pub(crate) users: Collection<User>,
     pub(crate) banned_accounts: Collection<BannedAccount>,
-    friends: Collection<FriendLink>,
+    pub(crate) friends: Collection<FriendLink>,
     magic_links: Collection<MagicLink>,
     pub(crate) project_metadata: Collection<ProjectMetadata>,
     pub(crate) libraries: Collection<Library>,
     pub(crate) authorized_services: Collection<AuthorizedServiceHost>,
 
     pub(crate) password_tokens: Collection<SetPasswordToken>,
+    pub(crate) email_verification_tokens: Collection<EmailVerificationToken>,
+    pub(crate) sessions: Collection<UserSession>,
+    pub(crate) audit_log: Collection<AuditLogEntry>,
     pub(crate) recorded_messages: Collection<SentMessage>,
     pub(crate) logged_messages: Collection<LogMessage>,
+    pub(crate) queued_messages: Collection<QueuedMessage>,
+    pub(crate) external_registrations: Collection<ExternalRegistration>,
     pub(crate) collab_invites: Collection<CollaborationInvite>,
     pub(crate) occupant_invites: Collection<OccupantInvite>,
 
@@ -73,6 +91,12 @@ pub struct AppData {
     pub(crate) oauth_codes: Collection<oauth::Code>,
 
     pub(crate) metrics: metrics::Metrics,
+    pub(crate) email_rate_limiter: Arc<RateLimiter>,
+    pub(crate) ip_rate_limiter: Arc<RateLimiter>,
+    /// Failed two-factor codes (per user)
+    two_factor_rate_limiter: Arc<RateLimiter>,
+    pub(crate) two_factor_key: EncryptionKey,
+    pub(crate) breached_passwords: Arc<HashSet<String>>,
     mailer: SmtpTransport,
     sender: Mailbox,
 
@@ -89,7 +113,7 @@ impl AppData {
         settings: Settings,
         network: Option<Addr<TopologyActor>>,
         prefix: Option<&str>,
-        tx: Option<OneshotSender<TopologyPanic>>,
+        tx: Option<UnboundedSender<TopologyFailure>>,
     ) -> AppData {
         // Blob storage
         let access_key = settings.s3.credentials.access_key.clone();
@@ -131,6 +155,10 @@ impl AppData {
         let groups = db.collection::<Group>(&(prefix.to_owned() + "groups"));
         let password_tokens =
             db.collection::<SetPasswordToken>(&(prefix.to_owned() + "passwordTokens"));
+        let email_verification_tokens = db
+            .collection::<EmailVerificationToken>(&(prefix.to_owned() + "emailVerificationTokens"));
+        let sessions = db.collection::<UserSession>(&(prefix.to_owned() + "sessions"));
+        let audit_log = db.collection::<AuditLogEntry>(&(prefix.to_owned() + "auditLog"));
         let users = db.collection::<User>(&(prefix.to_owned() + "users"));
         let banned_accounts =
             db.collection::<BannedAccount>(&(prefix.to_owned() + "bannedAccounts"));
@@ -147,8 +175,17 @@ impl AppData {
         let recorded_messages =
             db.collection::<SentMessage>(&(prefix.to_owned() + "recordedMessages"));
         let logged_messages = db.collection::<LogMessage>(&(prefix.to_owned() + "loggedMessages"));
+        let queued_messages =
+            db.collection::<QueuedMessage>(&(prefix.to_owned() + "queuedMessages"));
+        let external_registrations =
+            db.collection::<ExternalRegistration>(&(prefix.to_owned() + "externalRegistrations"));
         let network = network.unwrap_or_else(|| {
-            TopologyActor::new(settings.cache_settings.num_addresses, tx).start()
+            TopologyActor::new(
+                settings.cache_settings.num_addresses,
+                settings.topology.clone(),
+                tx,
+            )
+            .start()
         });
         let oauth_clients = db.collection::<OAuthClient>(&(prefix.to_owned() + "oauthClients"));
         let oauth_tokens = db.collection::<OAuthToken>(&(prefix.to_owned() + "oauthToken"));
@@ -169,11 +206,43 @@ impl AppData {
             settings.cache_settings.num_users_friend_data,
         )));
 
+        let rate_limits = &settings.security.rate_limits;
+        let window = Duration::from_secs(rate_limits.window_secs);
+        let email_rate_limiter =
+            Arc::new(RateLimiter::new(rate_limits.max_requests_per_email, window));
+        let ip_rate_limiter = Arc::new(RateLimiter::new(rate_limits.max_requests_per_ip, window));
+        let two_factor_rate_limiter = Arc::new(RateLimiter::new(
+            rate_limits.max_two_factor_failures,
+            window,
+        ));
+        let two_factor_key = match settings.security.two_factor_key.as_ref() {
+            Some(key) => EncryptionKey::new(key),
+            None => {
+                warn!("No two-factor key configured (security.two_factor_key). Using the cookie key to encrypt two-factor secrets. Rotating the cookie key will disable two-factor login for all users.");
+                EncryptionKey::new(&settings.cookie.key)
+            }
+        };
+        let breached_passwords = settings
+            .security
+            .password_policy
+            .breached_passwords
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .expect("Unable to read breached passwords list.")
+                    .lines()
+                    .map(|line| line.trim().to_owned())
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
         AppData {
             settings,
             network,
             s3,
             bucket,
+            db,
             groups,
             users,
             banned_accounts,
@@ -184,6 +253,9 @@ impl AppData {
             collab_invites,
             occupant_invites,
             password_tokens,
+            email_verification_tokens,
+            sessions,
+            audit_log,
             friends,
             magic_links,
 
@@ -195,10 +267,17 @@ impl AppData {
             oauth_codes,
 
             metrics: metrics::Metrics::new(),
+            email_rate_limiter,
+            ip_rate_limiter,
+            two_factor_rate_limiter,
+            two_factor_key,
+            breached_passwords: Arc::new(breached_passwords),
 
             tor_exit_nodes,
             recorded_messages,
             logged_messages,
+            queued_messages,
+            external_registrations,
             project_cache,
             membership_cache,
             admin_cache,
@@ -206,6 +285,23 @@ impl AppData {
         }
     }
 
+    /// Check that the database and blob storage are reachable
+    pub(crate) async fn check_dependencies(&self) -> Result<(), InternalError> {
+        self.db
+            .run_command(doc! {"ping": 1}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        self.s3
+            .head_bucket()
+            .bucket(&self.bucket)
+            .send()
+            .await
+            .map_err(|_err| InternalError::S3Error)?;
+
+        Ok(())
+    }
+
     pub async fn initialize(&self) -> Result<(), InternalError> {
         // Create the s3 bucket
         let bucket = &self.settings.s3.bucket;
@@ -245,6 +341,28 @@ impl AppData {
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
+        let index_opts = IndexOptions::builder()
+            .expire_after(EMAIL_VERIFICATION_TOKEN_TTL)
+            .build();
+        let token_index = IndexModel::builder()
+            .keys(doc! {"createdAt": 1})
+            .options(index_opts)
+            .build();
+        self.email_verification_tokens
+            .create_index(token_index, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        // find accounts scheduled for deletion
+        let delete_at_index = IndexModel::builder()
+            .keys(doc! {"deleteAt": 1})
+            .options(IndexOptions::builder().sparse(true).build())
+            .build();
+        self.users
+            .create_index(delete_at_index, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
         let one_week = Duration::from_secs(60 * 60 * 24 * 7);
         self.project_metadata
             .create_indexes(
@@ -298,6 +416,30 @@ impl AppData {
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
+        // sessions expire at the same time as the session cookie
+        let session_indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"createdAt": 1})
+                .options(IndexOptions::builder().expire_after(SESSION_TTL).build())
+                .build(),
+            IndexModel::builder().keys(doc! {"username": 1}).build(),
+            IndexModel::builder().keys(doc! {"id": 1}).build(),
+        ];
+        self.sessions
+            .create_indexes(session_indexes, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let audit_log_indexes = vec![
+            IndexModel::builder().keys(doc! {"createdAt": -1}).build(),
+            IndexModel::builder().keys(doc! {"actor": 1}).build(),
+            IndexModel::builder().keys(doc! {"target": 1}).build(),
+        ];
+        self.audit_log
+            .create_indexes(audit_log_indexes, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
         self.network.do_send(SetStorage {
             app_data: self.clone(),
         });
@@ -305,6 +447,8 @@ impl AppData {
         if !self.settings.security.allow_tor_login {
             self.start_update_interval();
         }
+        self.start_user_deletion_interval();
+        self.start_invite_expiry_interval();
 
         if let Some(admin) = self.settings.admin.as_ref() {
             let user: User = NewUser {
@@ -355,6 +499,39 @@ impl AppData {
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
+        let queued_message_indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"expiresAt": 1})
+                .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"appId": 1, "address": 1})
+                .build(),
+        ];
+        self.queued_messages
+            .create_indexes(queued_message_indexes, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let registration_indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"lastSeen": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(EXTERNAL_REGISTRATION_TTL)
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"appId": 1, "address": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        ];
+        self.external_registrations
+            .create_indexes(registration_indexes, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
         Ok(())
     }
 
@@ -372,6 +549,45 @@ impl AppData {
         });
     }
 
+    /// Periodically remove accounts whose deletion grace period has expired
+    fn start_user_deletion_interval(&self) {
+        let app = self.clone();
+        actix_web::rt::spawn(async move {
+            let one_hour = Duration::from_secs(60 * 60);
+            let mut interval = time::interval(one_hour);
+            loop {
+                interval.tick().await;
+                let actions = app.as_user_actions();
+                if let Err(error) = actions.delete_expired_users().await {
+                    warn!("Unable to delete expired accounts: {:?}", error);
+                }
+            }
+        });
+    }
+
+    /// Periodically remove pending invites which are older than the configured TTL
+    fn start_invite_expiry_interval(&self) {
+        let app = self.clone();
+        actix_web::rt::spawn(async move {
+            let one_hour = Duration::from_secs(60 * 60);
+            let mut interval = time::interval(one_hour);
+            loop {
+                interval.tick().await;
+                let actions = app.as_collab_invite_actions();
+                if let Err(error) = actions.delete_expired_invites().await {
+                    warn!(
+                        "Unable to delete expired collaboration invites: {:?}",
+                        error
+                    );
+                }
+                let actions = app.as_friend_actions();
+                if let Err(error) = actions.delete_expired_invites().await {
+                    warn!("Unable to delete expired friend invites: {:?}", error);
+                }
+            }
+        });
+    }
+
     pub async fn get_project_metadatum(
         &self,
         id: &ProjectId,
@@ -524,6 +740,91 @@ impl AppData {
             .unwrap_or(false)
     }
 
+    /// Get the IP address of the client making the request (using the address
+    /// reported by trusted proxies, if any)
+    pub(crate) fn get_client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        utils::get_client_ip(req, &self.settings.security.trusted_proxies)
+    }
+
+    /// Throttle requests which send emails or accept secret tokens for the given
+    /// email address (or username) to prevent spam and brute-forcing.
+    pub(crate) fn ensure_within_rate_limit(
+        &self,
+        ip_addr: Option<&IpAddr>,
+        key: &str,
+    ) -> Result<(), UserError> {
+        if let Some(ip_addr) = ip_addr {
+            self.ip_rate_limiter.check(&ip_addr.to_string())?;
+        }
+        self.email_rate_limiter.check(&key.to_lowercase())
+    }
+
+    /// Get the username for the given session if it is still active. Sessions which
+    /// have been revoked or have expired are cleared as are (legacy) sessions without
+    /// an ID since they cannot be revoked.
+    pub(crate) async fn get_session_username(&self, session: &Session) -> Option<String> {
+        let username = session.get::<String>("username").unwrap_or(None)?;
+        let session_id = session.get::<String>("sessionId").unwrap_or(None);
+
+        if let Some(id) = session_id {
+            let query = doc! {"id": id, "username": &username, "revoked": false};
+            match self.sessions.find_one(query, None).await {
+                Ok(Some(_)) => return Some(username),
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Unable to check session for {}: {:?}", username, err);
+                    return None;
+                }
+            }
+        }
+
+        session.purge();
+        None
+    }
+
+    /// Record a privileged action in the audit log. This should be called in the same
+    /// request as the action itself. Failures are logged rather than returned since the
+    /// action has already been performed.
+    pub(crate) async fn record_audit_entry(
+        &self,
+        actor: &str,
+        action: api::AuditAction,
+        target: &str,
+    ) {
+        let entry = AuditLogEntry::new(actor.to_owned(), action, target.to_owned());
+        self.insert_audit_entry(entry).await;
+    }
+
+    /// Record a privileged action performed by the user who made the given request.
+    /// The action has already been authorized so a missing username is unexpected
+    /// and is logged instead of being skipped silently.
+    pub(crate) async fn audit_request(
+        &self,
+        req: &HttpRequest,
+        action: api::AuditAction,
+        target: &str,
+    ) {
+        match utils::get_username(req).await {
+            Some(actor) => self.record_audit_entry(&actor, action, target).await,
+            None => warn!(
+                "Unable to record {:?} of {} in the audit log: request has no username",
+                action, target
+            ),
+        }
+    }
+
+    /// Record a request made by the actor on behalf of another user.
+    pub(crate) async fn record_impersonation(&self, actor: &str, target: &str, request: String) {
+        let entry = AuditLogEntry::impersonation(actor.to_owned(), target.to_owned(), request);
+        self.insert_audit_entry(entry).await;
+    }
+
+    async fn insert_audit_entry(&self, entry: AuditLogEntry) {
+        if let Err(err) = self.audit_log.insert_one(&entry, None).await {
+            error!("Unable to record audit log entry {:?}: {:?}", entry, err);
+        }
+    }
+
     // Tor-related restrictions
     pub async fn ensure_not_tor_ip(&self, ip_addr: &IpAddr) -> Result<(), UserError> {
         let ip_addr = ip_addr.to_string();
@@ -591,13 +892,14 @@ impl AppData {
             &self.project_metadata,
             &self.project_cache,
             &self.network,
+            &self.metrics,
             &self.bucket,
             &self.s3,
         )
     }
 
     pub(crate) fn as_group_actions(&self) -> GroupActions {
-        GroupActions::new(&self.groups, &self.users)
+        GroupActions::new(&self.groups, &self.users, &self.banned_accounts)
     }
 
     pub(crate) fn as_friend_actions(&self) -> FriendActions {
@@ -607,13 +909,19 @@ impl AppData {
             &self.users,
             &self.groups,
             &self.network,
+            Duration::from_secs(self.settings.invites.ttl_secs),
         )
     }
 
+    pub(crate) fn as_admin_actions(&self) -> AdminActions {
+        AdminActions::new(&self.audit_log)
+    }
+
     pub(crate) fn as_magic_link_actions(&self) -> MagicLinkActions {
         MagicLinkActions::new(
             &self.magic_links,
             &self.users,
+            &self.password_tokens,
             &self.mailer,
             &self.sender,
             &self.settings.public_url,
@@ -623,21 +931,29 @@ impl AppData {
     pub(crate) fn as_collab_invite_actions(&self) -> CollaborationInviteActions {
         CollaborationInviteActions::new(
             &self.collab_invites,
+            &self.friends,
             &self.project_metadata,
             &self.project_cache,
             &self.network,
+            Duration::from_secs(self.settings.invites.ttl_secs),
         )
     }
 
     pub(crate) fn as_network_actions(&self) -> NetworkActions {
-        NetworkActions::new(
-            &self.project_metadata,
-            &self.project_cache,
-            &self.network,
-            &self.occupant_invites,
-            &self.recorded_messages,
-            &self.logged_messages,
-        )
+        let data = NetworkActionData {
+            project_metadata: &self.project_metadata,
+            project_cache: &self.project_cache,
+            network: &self.network,
+
+            occupant_invites: &self.occupant_invites,
+            recorded_messages: &self.recorded_messages,
+            logged_messages: &self.logged_messages,
+            queued_messages: &self.queued_messages,
+
+            max_active_traces: self.settings.network.max_active_traces,
+            max_room_occupants: self.settings.network.max_room_occupants,
+        };
+        NetworkActions::new(data)
     }
 
     pub(crate) fn as_settings_actions(&self) -> SettingsActions {
@@ -653,6 +969,8 @@ impl AppData {
             users: &self.users,
             banned_accounts: &self.banned_accounts,
             password_tokens: &self.password_tokens,
+            email_verification_tokens: &self.email_verification_tokens,
+            sessions: &self.sessions,
             metrics: &self.metrics,
 
             network: &self.network,
@@ -661,22 +979,32 @@ impl AppData {
             mailer: &self.mailer,
             sender: &self.sender,
             public_url: &self.settings.public_url,
+            two_factor_key: &self.two_factor_key,
+            password_policy: &self.settings.security.password_policy,
+            breached_passwords: &self.breached_passwords,
         };
         UserActions::new(data)
     }
 
     pub(crate) fn as_host_actions(&self) -> HostActions {
-        HostActions::new(&self.authorized_services)
+        HostActions::new(&self.authorized_services, &self.users, &self.groups)
     }
 
     pub(crate) fn as_login_helper(&self) -> LoginHelper {
-        LoginHelper::new(
-            &self.network,
-            &self.metrics,
-            &self.project_metadata,
-            &self.project_cache,
-            &self.banned_accounts,
-        )
+        let data = LoginHelperData {
+            network: &self.network,
+            metrics: &self.metrics,
+            project_metadata: &self.project_metadata,
+            project_cache: &self.project_cache,
+
+            banned_accounts: &self.banned_accounts,
+            users: &self.users,
+            sessions: &self.sessions,
+            two_factor_key: &self.two_factor_key,
+            two_factor_rate_limiter: &self.two_factor_rate_limiter,
+            trusted_proxies: &self.settings.security.trusted_proxies,
+        };
+        LoginHelper::new(data)
     }
 
     #[cfg(test)]
@@ -0,0 +1,46 @@
+use super::is_super_user;
+use crate::app_data::AppData;
+use crate::errors::UserError;
+use actix_web::{http::header, HttpRequest};
+
+/// Permissions to view the audit log of privileged actions
+pub(crate) struct ViewAuditLog {
+    _private: (),
+}
+
+pub(crate) async fn try_view_audit_log(
+    app: &AppData,
+    req: &HttpRequest,
+) -> Result<ViewAuditLog, UserError> {
+    if is_super_user(app, req).await? {
+        Ok(ViewAuditLog { _private: () })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
+
+/// Permissions to view the server metrics (used by prometheus)
+pub(crate) struct ViewMetrics {
+    _private: (),
+}
+
+/// Check the bearer token (if one is configured) for the metrics endpoint
+pub(crate) fn try_view_metrics(app: &AppData, req: &HttpRequest) -> Result<ViewMetrics, UserError> {
+    let token = match &app.settings.security.metrics_token {
+        Some(token) => token,
+        None => return Ok(ViewMetrics { _private: () }),
+    };
+
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(UserError::LoginRequiredError)?;
+
+    if bearer == token {
+        Ok(ViewMetrics { _private: () })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use actix_web::HttpRequest;
 use mongodb::bson::doc;
 use netsblox_cloud_common::{api, CollaborationInvite, ProjectMetadata};
@@ -5,6 +7,7 @@ use netsblox_cloud_common::{api, CollaborationInvite, ProjectMetadata};
 use crate::{
     app_data::AppData,
     errors::{InternalError, UserError},
+    utils,
 };
 
 use super::try_edit_user;
@@ -40,8 +43,11 @@ pub(crate) async fn try_respond_to_invite(
     req: &HttpRequest,
     invite_id: &str,
 ) -> Result<RespondToCollabInvite, UserError> {
-    // Only the owner for now
-    let query = doc! {"id": invite_id};
+    let ttl = Duration::from_secs(app.settings.invites.ttl_secs);
+    let query = doc! {
+        "id": invite_id,
+        "createdAt": {"$gt": utils::invite_expiry_cutoff(ttl)},
+    };
     let invite = app
         .collab_invites
         .find_one(query, None)
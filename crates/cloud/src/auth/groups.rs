@@ -21,6 +21,17 @@ pub(crate) struct DeleteGroup {
     _private: (),
 }
 
+pub(crate) struct TransferGroup {
+    pub(crate) id: api::GroupId,
+    _private: (),
+}
+
+pub(crate) struct EditGroupMembership {
+    pub(crate) id: api::GroupId,
+    pub(crate) username: String,
+    _private: (),
+}
+
 // functions to try to obtain the given permissions
 pub(crate) async fn try_view_group(
     app: &AppData,
@@ -87,6 +98,40 @@ pub(crate) async fn try_delete_group(
     })
 }
 
+/// Try to obtain permissions to transfer ownership of the given group. As with
+/// deleting a group, only the owner (or those who can edit the owner) are allowed.
+pub(crate) async fn try_transfer_group(
+    app: &AppData,
+    req: &HttpRequest,
+    group_id: &api::GroupId,
+) -> Result<TransferGroup, UserError> {
+    try_delete_group(app, req, group_id)
+        .await
+        .map(|dg| TransferGroup {
+            id: dg.id,
+            _private: (),
+        })
+}
+
+/// Try to obtain permissions to add (or remove) the given user to the group.
+/// This requires permissions to edit both the group and the user so users
+/// cannot be moved out of a group owned by someone else.
+pub(crate) async fn try_edit_group_membership(
+    app: &AppData,
+    req: &HttpRequest,
+    group_id: &api::GroupId,
+    username: &str,
+) -> Result<EditGroupMembership, UserError> {
+    try_edit_group(app, req, group_id).await?;
+    super::try_edit_user(app, req, None, username).await?;
+
+    Ok(EditGroupMembership {
+        id: group_id.to_owned(),
+        username: username.to_owned(),
+        _private: (),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
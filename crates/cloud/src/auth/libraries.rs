@@ -1,7 +1,6 @@
 use super::users::is_moderator;
 use crate::app_data::AppData;
 use crate::errors::{InternalError, UserError};
-use actix_session::SessionExt;
 use actix_web::HttpRequest;
 use mongodb::bson::doc;
 use netsblox_cloud_common::api::PublishState;
@@ -13,6 +12,17 @@ pub(crate) struct ListLibraries {
     _private: (),
 }
 
+impl ListLibraries {
+    /// Get permissions to list all libraries owned by a user from data export permissions.
+    pub(crate) fn from_export_user(witness: &super::ExportUser) -> Self {
+        Self {
+            username: witness.username.clone(),
+            visibility: PublishState::Private,
+            _private: (),
+        }
+    }
+}
+
 pub(crate) struct ViewLibrary {
     pub(crate) library: Library,
     _private: (),
@@ -95,8 +105,7 @@ pub(crate) async fn try_publish_library(
     req: &HttpRequest,
     owner: &str,
 ) -> Result<PublishLibrary, UserError> {
-    let session = req.get_session();
-    if is_moderator(app, &session).await? {
+    if is_moderator(app, req).await? {
         Ok(PublishLibrary {
             owner: owner.to_owned(),
             can_approve: true,
@@ -117,8 +126,7 @@ pub(crate) async fn try_moderate_libraries(
     app: &AppData,
     req: &HttpRequest,
 ) -> Result<ModerateLibraries, UserError> {
-    let session = req.get_session();
-    if is_moderator(app, &session).await? {
+    if is_moderator(app, req).await? {
         Ok(ModerateLibraries { _private: () })
     } else {
         Err(UserError::PermissionsError)
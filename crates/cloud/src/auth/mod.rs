@@ -1,3 +1,4 @@
+pub(crate) mod admin;
 pub(crate) mod collaboration;
 pub(crate) mod groups;
 pub(crate) mod hosts;
@@ -8,6 +9,7 @@ pub(crate) mod projects;
 pub(crate) mod system;
 pub(crate) mod users;
 
+pub(crate) use crate::auth::admin::*;
 pub(crate) use crate::auth::collaboration::*;
 pub(crate) use crate::auth::groups::*;
 pub(crate) use crate::auth::hosts::*;
@@ -26,6 +26,10 @@ pub(crate) struct ListClients {
     _private: (),
 }
 
+pub(crate) struct ManageMessageQueue {
+    _private: (),
+}
+
 pub(crate) struct SendMessage {
     _private: (),
     pub(crate) msg: api::SendMessage,
@@ -60,7 +64,7 @@ pub(crate) async fn try_view_client(
             id: client_id.to_owned(),
             _private: (),
         })
-    } else if utils::get_username(req).is_some() {
+    } else if utils::get_username(req).await.is_some() {
         Err(UserError::PermissionsError)
     } else {
         Err(UserError::LoginRequiredError)
@@ -78,7 +82,7 @@ pub(crate) async fn ensure_is_auth_host_or_admin(
 
     if is_auth_host || is_super_user(app, req).await? {
         Ok(())
-    } else if utils::get_username(req).is_some() {
+    } else if utils::get_username(req).await.is_some() {
         Err(UserError::PermissionsError)
     } else {
         Err(UserError::LoginRequiredError)
@@ -145,10 +149,21 @@ pub(crate) async fn try_list_clients(
     }
 }
 
+pub(crate) async fn try_manage_message_queue(
+    app: &AppData,
+    req: &HttpRequest,
+) -> Result<ManageMessageQueue, UserError> {
+    if is_super_user(app, req).await? {
+        Ok(ManageMessageQueue { _private: () })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
+
 pub(crate) async fn try_send_message(
     app: &AppData,
     req: &HttpRequest,
-    msg: api::SendMessage,
+    mut msg: api::SendMessage,
 ) -> Result<SendMessage, UserError> {
     // Allow extension messages where the inner msg type is prefixed with "unauth".
     // Check out the tests for an example.
@@ -170,40 +185,53 @@ pub(crate) async fn try_send_message(
         .map(|inner_type| inner_type.starts_with("unauth:"))
         .unwrap_or(false);
 
-    if is_unauth_ok {
-        return Ok(SendMessage { _private: (), msg });
-    }
-
     let host = utils::get_authorized_host(&app.authorized_services, req).await?;
 
     // Sending messages is allowed if you:
-    // - are an authorized host
+    // - are an authorized host (which can set any sender)
     if let Some(_host) = host {
         Ok(SendMessage { _private: (), msg })
     // - or can edit (ie, operate on behalf of) the sender
     } else if let Some(sender) = msg.sender.as_ref() {
-        // check if we have permissions to edit sender
-        let username = match sender {
-            api::SendMessageSender::Username(username) => Some(username.clone()),
-            api::SendMessageSender::Client(client_id) => {
-                let task = app
-                    .network
-                    .send(topology::GetClientUsername(client_id.clone()))
-                    .await
-                    .map_err(InternalError::ActixMessageError)?;
-                task.run().await
+        match try_send_as(app, req, sender).await {
+            Ok(_) => Ok(SendMessage { _private: (), msg }),
+            // unauth messages can still be sent but the sender cannot be spoofed
+            Err(_err) if is_unauth_ok => {
+                msg.sender = None;
+                Ok(SendMessage { _private: (), msg })
             }
+            Err(err) => Err(err),
         }
-        .ok_or(UserError::PermissionsError)?; // must be an authorized host
-
-        super::try_edit_user(app, req, None, &username)
-            .await
-            .map(|_eu| SendMessage { _private: (), msg })
+    } else if is_unauth_ok {
+        Ok(SendMessage { _private: (), msg })
     } else {
         Err(UserError::PermissionsError)
     }
 }
 
+/// Check if the request has permissions to send messages on behalf of the given sender
+async fn try_send_as(
+    app: &AppData,
+    req: &HttpRequest,
+    sender: &api::SendMessageSender,
+) -> Result<(), UserError> {
+    let username = match sender {
+        api::SendMessageSender::Username(username) => Some(username.clone()),
+        api::SendMessageSender::Client(client_id) => {
+            let task = app
+                .network
+                .send(topology::GetClientUsername(client_id.clone()))
+                .await
+                .map_err(InternalError::ActixMessageError)?;
+            task.run().await
+        }
+    }
+    .ok_or(UserError::PermissionsError)?; // must be an authorized host
+
+    try_edit_user(app, req, None, &username).await?;
+    Ok(())
+}
+
 pub(crate) async fn try_log_message(
     app: &AppData,
     req: &HttpRequest,
@@ -609,13 +637,182 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_try_send_msg_auth_host_sender() {
+        let msg = api::SendMessage {
+            sender: Some(api::SendMessageSender::Username("user".into())),
+            target: api::SendMessageTarget::Client {
+                client_id: ClientId::new("_test_client_id".into()),
+                state: None,
+            },
+            content: json!({"test": "hello!"}),
+        };
+        let visibility = api::ServiceHostScope::Public(Vec::new());
+        let host = AuthorizedServiceHost::new(
+            "http://localhost:5656".into(),
+            "TestServices".into(),
+            visibility,
+        );
+
+        test_utils::setup()
+            .with_authorized_services(&[host.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(send_msg_test),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .append_header(host.auth_header())
+                    .uri("/send")
+                    .set_json(msg)
+                    .to_request();
+
+                let sender: Option<api::SendMessageSender> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(
+                    matches!(sender, Some(api::SendMessageSender::Username(name)) if name == "user")
+                );
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_try_send_msg_unauth_spoofed_sender() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let msg = api::SendMessage {
+            sender: Some(api::SendMessageSender::Username(user.username.clone())),
+            target: api::SendMessageTarget::Client {
+                client_id: ClientId::new("_test_client_id".into()),
+                state: None,
+            },
+            content: json!({
+                "type": "extension",
+                "data": {
+                    "type": "unauth:test",
+                }
+            }),
+        };
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(send_msg_test),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/send")
+                    .set_json(msg)
+                    .to_request();
+
+                let sender: Option<api::SendMessageSender> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(sender.is_none());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_try_send_msg_unauth_host_header_invalid() {
+        let msg = api::SendMessage {
+            sender: Some(api::SendMessageSender::Username("user".into())),
+            target: api::SendMessageTarget::Client {
+                client_id: ClientId::new("_test_client_id".into()),
+                state: None,
+            },
+            content: json!({"test": "hello!"}),
+        };
+        let visibility = api::ServiceHostScope::Public(Vec::new());
+        let host = AuthorizedServiceHost::new(
+            "http://localhost:5656".into(),
+            "TestServices".into(),
+            visibility,
+        );
+
+        // the host is not authorized so the header should not be honored
+        test_utils::setup()
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(send_msg_test),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .append_header(host.auth_header())
+                    .uri("/send")
+                    .set_json(msg)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_try_send_msg_no_host_header() {
+        let msg = api::SendMessage {
+            sender: Some(api::SendMessageSender::Username("user".into())),
+            target: api::SendMessageTarget::Client {
+                client_id: ClientId::new("_test_client_id".into()),
+                state: None,
+            },
+            content: json!({"test": "hello!"}),
+        };
+        let visibility = api::ServiceHostScope::Public(Vec::new());
+        let host = AuthorizedServiceHost::new(
+            "http://localhost:5656".into(),
+            "TestServices".into(),
+            visibility,
+        );
+
+        test_utils::setup()
+            .with_authorized_services(&[host])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(send_msg_test),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/send")
+                    .set_json(msg)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+            })
+            .await;
+    }
+
     #[post("/send")]
     async fn send_msg_test(
         app: web::Data<AppData>,
         req: HttpRequest,
         data: web::Json<api::SendMessage>,
     ) -> Result<HttpResponse, UserError> {
-        try_send_message(&app, &req, data.into_inner()).await?;
-        Ok(HttpResponse::Ok().finish())
+        let auth_sm = try_send_message(&app, &req, data.into_inner()).await?;
+        Ok(HttpResponse::Ok().json(auth_sm.msg.sender))
     }
 }
@@ -1,8 +1,14 @@
 use actix_web::HttpRequest;
+use mongodb::bson::doc;
+use netsblox_cloud_common::api::oauth;
 
-use crate::{app_data::AppData, errors::UserError};
+use crate::{
+    app_data::AppData,
+    errors::{InternalError, UserError},
+    utils,
+};
 
-use super::ensure_is_auth_host_or_admin;
+use super::{ensure_is_auth_host_or_admin, is_super_user};
 
 pub(crate) struct ManageClient {
     _private: (),
@@ -16,3 +22,64 @@ pub(crate) async fn try_manage_client(
         .await
         .map(|_| ManageClient { _private: () })
 }
+
+pub(crate) struct ListTokens {
+    pub(crate) username: String,
+    _private: (),
+}
+
+pub(crate) struct RevokeToken {
+    pub(crate) id: oauth::TokenId,
+    _private: (),
+}
+
+/// Tokens can be listed by the user to whom they were issued or an admin.
+pub(crate) async fn try_list_tokens(
+    app: &AppData,
+    req: &HttpRequest,
+    username: &str,
+) -> Result<ListTokens, UserError> {
+    ensure_is_owner_or_admin(app, req, username)
+        .await
+        .map(|_| ListTokens {
+            username: username.to_owned(),
+            _private: (),
+        })
+}
+
+/// Tokens can be revoked by the user to whom they were issued or an admin.
+pub(crate) async fn try_revoke_token(
+    app: &AppData,
+    req: &HttpRequest,
+    token_id: &oauth::TokenId,
+) -> Result<RevokeToken, UserError> {
+    let query = doc! {"id": token_id};
+    let token = app
+        .oauth_tokens
+        .find_one(query, None)
+        .await
+        .map_err(InternalError::DatabaseConnectionError)?
+        .ok_or(UserError::OAuthTokenNotFoundError)?;
+
+    ensure_is_owner_or_admin(app, req, &token.username)
+        .await
+        .map(|_| RevokeToken {
+            id: token.id,
+            _private: (),
+        })
+}
+
+async fn ensure_is_owner_or_admin(
+    app: &AppData,
+    req: &HttpRequest,
+    username: &str,
+) -> Result<(), UserError> {
+    let requestor = utils::get_username(req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
+    if requestor == username || is_super_user(app, req).await? {
+        Ok(())
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
@@ -1,13 +1,12 @@
-use actix_session::SessionExt;
 use actix_web::HttpRequest;
 use mongodb::bson::doc;
 use netsblox_cloud_common::{api, ProjectMetadata};
 
 use crate::app_data::AppData;
-use crate::errors::UserError;
+use crate::errors::{InternalError, UserError};
 use crate::utils;
 
-use super::{is_moderator, ManageSystem};
+use super::{is_moderator, ExportUser, ManageSystem};
 
 /// Permissions to view a specific project
 pub(crate) struct ViewProject {
@@ -22,11 +21,34 @@ pub(crate) struct ListProjects {
     _private: (),
 }
 
+/// Permissions to star (or unstar) a project as the current user
+pub(crate) struct StarProject {
+    pub(crate) username: String,
+    pub(crate) metadata: ProjectMetadata,
+    _private: (),
+}
+
+/// Permissions to edit a project (owner, editor collaborators, or those who can edit the owner)
 pub(crate) struct EditProject {
     pub(crate) metadata: ProjectMetadata,
     _private: (),
 }
 
+/// Permissions to change the visibility of a project. Unlike editing, this is
+/// not available to collaborators.
+pub(crate) struct PublishProject {
+    pub(crate) metadata: ProjectMetadata,
+    _private: (),
+}
+
+/// Permissions to transfer a project to another user. Like publishing, this is
+/// not available to collaborators.
+pub(crate) struct TransferProject {
+    pub(crate) metadata: ProjectMetadata,
+    pub(crate) new_owner: String,
+    _private: (),
+}
+
 pub(crate) struct DeleteProject {
     pub(crate) id: api::ProjectId,
     _private: (),
@@ -39,6 +61,17 @@ impl DeleteProject {
     }
 }
 
+impl ListProjects {
+    /// Get permissions to list all projects owned by a user from data export permissions.
+    pub(crate) fn from_export_user(witness: &ExportUser) -> Self {
+        Self {
+            username: witness.username.clone(),
+            visibility: api::PublishState::Private,
+            _private: (),
+        }
+    }
+}
+
 /// Permissions to approve projects that require manual approval
 pub(crate) struct ModerateProjects {
     _private: (),
@@ -63,16 +96,23 @@ pub(crate) async fn try_view_project(
         });
     }
 
+    let username = utils::get_username(req).await;
     let can_view = match metadata.state {
         api::PublishState::Private => {
             // Allow viewing if:
             // we can edit the project or...
             let auth_ep = can_edit_project(app, req, client_id, &metadata).await;
-            if auth_ep.is_ok() {
+            // we are a collaborator (including viewers) or...
+            let is_collaborator = username
+                .as_ref()
+                .and_then(|name| metadata.collaborator_role(name))
+                .is_some();
+
+            if auth_ep.is_ok() || is_collaborator {
                 true
             } else {
                 // the user has been invited to the project
-                if let Some(username) = utils::get_username(req) {
+                if let Some(username) = &username {
                     let query = doc! {"username": username};
                     let invite = flatten(app.occupant_invites.find_one(query, None).await.ok());
                     invite.is_some()
@@ -87,7 +127,7 @@ pub(crate) async fn try_view_project(
         _ => true,
     };
 
-    let is_logged_in = utils::get_username(req).is_some();
+    let is_logged_in = username.is_some();
     if can_view {
         Ok(ViewProject {
             metadata,
@@ -100,6 +140,24 @@ pub(crate) async fn try_view_project(
     }
 }
 
+pub(crate) async fn try_star_project(
+    app: &AppData,
+    req: &HttpRequest,
+    project_id: &api::ProjectId,
+) -> Result<StarProject, UserError> {
+    let username = utils::get_username(req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
+    // Any project the user can view can be starred
+    let vp = try_view_project(app, req, None, project_id).await?;
+
+    Ok(StarProject {
+        username,
+        metadata: vp.metadata,
+        _private: (),
+    })
+}
+
 pub(crate) async fn try_edit_project(
     app: &AppData,
     req: &HttpRequest,
@@ -111,6 +169,87 @@ pub(crate) async fn try_edit_project(
     can_edit_project(app, req, client_id.as_ref(), &metadata).await
 }
 
+/// Try to get permissions to publish the project. If email verification is
+/// required, the owner must also have verified their email address.
+pub(crate) async fn try_publish_project(
+    app: &AppData,
+    req: &HttpRequest,
+    project_id: &api::ProjectId,
+) -> Result<PublishProject, UserError> {
+    let pp = try_change_visibility(app, req, project_id).await?;
+
+    if app.settings.security.require_email_verification {
+        let query = doc! {"username": &pp.metadata.owner, "verified": true};
+        app.users
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::EmailVerificationRequiredError)?;
+    }
+
+    Ok(pp)
+}
+
+/// Try to get permissions to unpublish the project.
+pub(crate) async fn try_unpublish_project(
+    app: &AppData,
+    req: &HttpRequest,
+    project_id: &api::ProjectId,
+) -> Result<PublishProject, UserError> {
+    try_change_visibility(app, req, project_id).await
+}
+
+/// Only the owner (or someone who can edit the owner) can change the visibility
+/// of a project.
+async fn try_change_visibility(
+    app: &AppData,
+    req: &HttpRequest,
+    project_id: &api::ProjectId,
+) -> Result<PublishProject, UserError> {
+    let metadata = app.get_project_metadatum(project_id).await?;
+    super::try_edit_user(app, req, None, &metadata.owner).await?;
+
+    Ok(PublishProject {
+        metadata,
+        _private: (),
+    })
+}
+
+/// Try to get permissions to transfer the project to a new owner. Only the
+/// owner (or someone who can edit the owner) can transfer a project and the
+/// new owner must be an existing user who has not been banned.
+pub(crate) async fn try_transfer_project(
+    app: &AppData,
+    req: &HttpRequest,
+    project_id: &api::ProjectId,
+    new_owner: &str,
+) -> Result<TransferProject, UserError> {
+    let metadata = app.get_project_metadatum(project_id).await?;
+    super::try_edit_user(app, req, None, &metadata.owner).await?;
+
+    let query = doc! {"username": new_owner};
+    app.users
+        .find_one(query.clone(), None)
+        .await
+        .map_err(InternalError::DatabaseConnectionError)?
+        .ok_or(UserError::UserNotFoundError)?;
+
+    let banned = app
+        .banned_accounts
+        .find_one(query, None)
+        .await
+        .map_err(InternalError::DatabaseConnectionError)?;
+    if banned.is_some() {
+        return Err(UserError::BannedUserError);
+    }
+
+    Ok(TransferProject {
+        metadata,
+        new_owner: new_owner.to_owned(),
+        _private: (),
+    })
+}
+
 pub(crate) async fn try_delete_project(
     app: &AppData,
     req: &HttpRequest,
@@ -158,9 +297,12 @@ pub(crate) async fn can_edit_project(
         .unwrap_or(false);
 
     if !is_owner {
-        let username = utils::get_username(req).ok_or(UserError::LoginRequiredError)?;
-        if !project.collaborators.contains(&username) {
-            // if we are not a collaborator, then we must be able to edit the owner
+        let username = utils::get_username(req)
+            .await
+            .ok_or(UserError::LoginRequiredError)?;
+        let is_editor = project.collaborator_role(&username) == Some(api::CollabRole::Editor);
+        if !is_editor {
+            // if we are not an editor, then we must be able to edit the owner
             super::try_edit_user(app, req, client_id, &project.owner).await?;
         }
     }
@@ -175,8 +317,7 @@ pub(crate) async fn try_moderate_projects(
     app: &AppData,
     req: &HttpRequest,
 ) -> Result<ModerateProjects, UserError> {
-    let session = req.get_session();
-    if is_moderator(app, &session).await? {
+    if is_moderator(app, req).await? {
         Ok(ModerateProjects { _private: () })
     } else {
         Err(UserError::PermissionsError)
@@ -220,10 +361,172 @@ mod test_utils {
             }
         }
     }
+
+    impl PublishProject {
+        pub(crate) fn test(metadata: ProjectMetadata) -> Self {
+            Self {
+                metadata,
+                _private: (),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use actix_web::{http, post, test, web, App, HttpResponse};
+    use netsblox_cloud_common::{api::UserRole, User};
+
+    use crate::test_utils;
+
+    #[actix_web::test]
+    async fn test_try_edit_project_owner() {
+        let status = call_witness_test("edit", Some("owner")).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_try_edit_project_collaborator() {
+        let status = call_witness_test("edit", Some("collaborator")).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_try_edit_project_admin() {
+        let status = call_witness_test("edit", Some("admin")).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_try_edit_project_403() {
+        let status = call_witness_test("edit", Some("other")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_try_edit_project_logged_out() {
+        let status = call_witness_test("edit", None).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_try_publish_project_owner() {
+        let status = call_witness_test("publish", Some("owner")).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_try_publish_project_collaborator() {
+        let status = call_witness_test("publish", Some("collaborator")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_try_publish_project_admin() {
+        let status = call_witness_test("publish", Some("admin")).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_try_publish_project_403() {
+        let status = call_witness_test("publish", Some("other")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_try_publish_project_logged_out() {
+        let status = call_witness_test("publish", None).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_try_unpublish_project_collaborator() {
+        let status = call_witness_test("unpublish", Some("collaborator")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+    }
+
+    /// Try to get the given witness ("edit", "publish" or "unpublish") for a project
+    /// owned by "owner" (with "collaborator" as a collaborator) as the given user.
+    async fn call_witness_test(
+        witness: &'static str,
+        caller: Option<&'static str>,
+    ) -> http::StatusCode {
+        let users: Vec<User> = [
+            ("owner", UserRole::User),
+            ("collaborator", UserRole::User),
+            ("other", UserRole::User),
+            ("admin", UserRole::Admin),
+        ]
+        .into_iter()
+        .map(|(name, role)| {
+            api::NewUser {
+                username: name.into(),
+                email: format!("{}@netsblox.org", name),
+                password: None,
+                group_id: None,
+                role: Some(role),
+            }
+            .into()
+        })
+        .collect();
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_collaborators(&["collaborator"])
+            .build();
+
+        let status = Arc::new(Mutex::new(None));
+        let status_c = status.clone();
+        test_utils::setup()
+            .with_users(&users)
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(witness_test),
+                )
+                .await;
+
+                let mut req =
+                    test::TestRequest::post().uri(&format!("/{}/{}", witness, &project.id));
+                if let Some(username) = caller {
+                    req = req.cookie(test_utils::cookie::new(username));
+                }
+
+                let response = test::call_service(&app, req.to_request()).await;
+                *status_c.lock().unwrap() = Some(response.status());
+            })
+            .await;
+
+        let status = status.lock().unwrap().take();
+        status.unwrap()
+    }
+
+    #[post("/{witness}/{id}")]
+    async fn witness_test(
+        app: web::Data<AppData>,
+        req: HttpRequest,
+        path: web::Path<(String, api::ProjectId)>,
+    ) -> Result<HttpResponse, UserError> {
+        let (witness, project_id) = path.into_inner();
+        match witness.as_str() {
+            "edit" => try_edit_project(&app, &req, None, &project_id)
+                .await
+                .map(|_ep| ())?,
+            "publish" => try_publish_project(&app, &req, &project_id)
+                .await
+                .map(|_pp| ())?,
+            _ => try_unpublish_project(&app, &req, &project_id)
+                .await
+                .map(|_pp| ())?,
+        };
+        Ok(HttpResponse::Ok().finish())
+    }
+
     #[actix_web::test]
     #[ignore]
     async fn test_try_view_project_owner() {
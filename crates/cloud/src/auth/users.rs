@@ -1,8 +1,8 @@
 use std::collections::HashSet;
 
-use actix_session::{Session, SessionExt};
 use actix_web::HttpRequest;
 use futures::TryStreamExt;
+use log::warn;
 use mongodb::bson::doc;
 use netsblox_cloud_common::api::{self, ClientId, UpdateUserData, UserRole};
 
@@ -31,6 +31,11 @@ pub(crate) struct ListUsers {
     _private: (),
 }
 
+/// Authorization to list the banned accounts
+pub(crate) struct ListBannedAccounts {
+    _private: (),
+}
+
 /// Authorization to edit the user with the given username
 pub(crate) struct EditUser {
     pub(crate) username: String,
@@ -65,6 +70,28 @@ impl UpdateUser {
     }
 }
 
+/// Authorization to export all data stored about a given user
+pub(crate) struct ExportUser {
+    pub(crate) username: String,
+    _private: (),
+}
+
+impl ViewUser {
+    /// Get view permissions from data export permissions.
+    pub(crate) fn from_export_user(witness: &ExportUser) -> Self {
+        Self {
+            username: witness.username.clone(),
+            _private: (),
+        }
+    }
+}
+
+/// Authorization to manage two-factor authentication for the given user
+pub(crate) struct ManageTwoFactor {
+    pub(crate) username: String,
+    _private: (),
+}
+
 pub(crate) struct SetPassword {
     pub(crate) username: String,
     _private: (),
@@ -141,7 +168,9 @@ async fn try_assign_role(
 
     let is_authorized = if is_privileged {
         // only moderators, admins can make privileged users (up to their role)
-        let username = utils::get_username(req).ok_or(UserError::LoginRequiredError)?;
+        let username = utils::get_username(req)
+            .await
+            .ok_or(UserError::LoginRequiredError)?;
         let req_role = get_user_role(app, &username).await?;
         dbg!(&req_role, &role);
         req_role >= UserRole::Moderator && req_role >= *role
@@ -190,12 +219,15 @@ pub(crate) async fn try_view_user(
     // - self
     // - moderator/admin
     // - group owner
-    let viewer = utils::get_username(req).ok_or(UserError::LoginRequiredError)?;
+    let viewer = utils::get_username(req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
     let authorized = viewer == username
         || get_user_role(app, &viewer).await? >= UserRole::Moderator
         || has_group_containing(app, &viewer, username).await?;
 
     if authorized {
+        record_impersonation(app, req, &viewer, username).await;
         Ok(ViewUser {
             username: username.to_owned(),
             _private: (),
@@ -222,12 +254,13 @@ pub(crate) async fn try_edit_user(
     client_id: Option<&api::ClientId>,
     username: &str,
 ) -> Result<EditUser, UserError> {
-    if let Some(requestor) = utils::get_username(req) {
+    if let Some(requestor) = utils::get_username(req).await {
         let can_edit = requestor == username
             || get_user_role(app, &requestor).await? >= UserRole::Moderator
             || has_group_containing(app, &requestor, username).await?;
 
         if can_edit {
+            record_impersonation(app, req, &requestor, username).await;
             Ok(EditUser {
                 username: username.to_owned(),
                 _private: (),
@@ -252,6 +285,34 @@ pub(crate) async fn try_edit_user(
     }
 }
 
+/// Record access to another user's account (eg, by an admin or group owner) in
+/// the audit log along with the request that was made. Only requests which may
+/// modify the account or which were explicitly made on behalf of the user (using
+/// the `X-On-Behalf-Of` header) are recorded so browsing other accounts doesn't
+/// flood the audit log.
+async fn record_impersonation(app: &AppData, req: &HttpRequest, actor: &str, username: &str) {
+    if actor == username {
+        return;
+    }
+
+    let on_behalf_of = req
+        .headers()
+        .get(api::ON_BEHALF_OF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if let Some(name) = on_behalf_of.filter(|name| *name != username) {
+        warn!(
+            "{} made a request on behalf of {} but accessed {}",
+            actor, name, username
+        );
+    }
+
+    let is_explicit = on_behalf_of == Some(username);
+    if is_explicit || !req.method().is_safe() {
+        let request = format!("{} {}", req.method(), req.path());
+        app.record_impersonation(actor, username, request).await;
+    }
+}
+
 /// Try to get privileges to apply the given updates to the specified user.
 pub(crate) async fn try_update_user(
     app: &AppData,
@@ -278,6 +339,46 @@ pub(crate) async fn try_update_user(
         })
 }
 
+/// Try to get privileges to export the data for the given user. Unlike
+/// viewing a user, this is restricted to the user themselves or an admin.
+pub(crate) async fn try_export_user(
+    app: &AppData,
+    req: &HttpRequest,
+    username: &str,
+) -> Result<ExportUser, UserError> {
+    let requestor = utils::get_username(req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
+    if requestor == username || is_super_user(app, req).await? {
+        Ok(ExportUser {
+            username: username.to_owned(),
+            _private: (),
+        })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
+
+/// Two-factor authentication is available for teachers, moderators, and admins
+/// and can only be managed by the users themselves.
+pub(crate) async fn try_manage_two_factor(
+    app: &AppData,
+    req: &HttpRequest,
+    username: &str,
+) -> Result<ManageTwoFactor, UserError> {
+    let requestor = utils::get_username(req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
+    if requestor == username && get_user_role(app, username).await? >= UserRole::Teacher {
+        Ok(ManageTwoFactor {
+            username: username.to_owned(),
+            _private: (),
+        })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
+
 pub(crate) async fn try_set_password(
     app: &AppData,
     req: &HttpRequest,
@@ -317,8 +418,7 @@ pub(crate) async fn try_ban_user(
     req: &HttpRequest,
     username: &str,
 ) -> Result<BanUser, UserError> {
-    let session = req.get_session();
-    if is_moderator(app, &session).await? {
+    if is_moderator(app, req).await? {
         Ok(BanUser {
             username: username.to_owned(),
             _private: (),
@@ -328,19 +428,28 @@ pub(crate) async fn try_ban_user(
     }
 }
 
+pub(crate) async fn try_list_banned_accounts(
+    app: &AppData,
+    req: &HttpRequest,
+) -> Result<ListBannedAccounts, UserError> {
+    if is_moderator(app, req).await? {
+        Ok(ListBannedAccounts { _private: () })
+    } else {
+        Err(UserError::PermissionsError)
+    }
+}
+
 pub(super) async fn is_super_user(app: &AppData, req: &HttpRequest) -> Result<bool, UserError> {
-    let session = req.get_session();
-    match get_session_role(app, &session).await? {
+    match get_session_role(app, req).await? {
         UserRole::Admin => Ok(true),
         _ => Ok(false),
     }
 }
 
-async fn get_session_role(app: &AppData, session: &Session) -> Result<UserRole, UserError> {
-    if let Some(username) = session.get::<String>("username").unwrap_or(None) {
+async fn get_session_role(app: &AppData, req: &HttpRequest) -> Result<UserRole, UserError> {
+    if let Some(username) = utils::get_username(req).await {
         get_user_role(app, &username).await
     } else {
-        session.purge();
         Err(UserError::LoginRequiredError)
     }
 }
@@ -356,8 +465,8 @@ async fn get_user_role(app: &AppData, username: &str) -> Result<UserRole, UserEr
         .unwrap_or(UserRole::User))
 }
 
-pub(super) async fn is_moderator(app: &AppData, session: &Session) -> Result<bool, UserError> {
-    let role = get_session_role(app, session).await?;
+pub(super) async fn is_moderator(app: &AppData, req: &HttpRequest) -> Result<bool, UserError> {
+    let role = get_session_role(app, req).await?;
     Ok(role >= UserRole::Moderator)
 }
 
@@ -396,7 +505,7 @@ async fn has_group_containing(app: &AppData, owner: &str, member: &str) -> Resul
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::{get, http, test, web, App, HttpResponse};
+    use actix_web::{get, http, route, test, web, App, HttpResponse};
     use netsblox_cloud_common::{api, Group, User};
 
     use crate::test_utils;
@@ -723,6 +832,13 @@ mod tests {
 
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
+
+                let count = app_data
+                    .audit_log
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
             })
             .await;
     }
@@ -769,6 +885,33 @@ mod tests {
 
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
+
+                // viewing other users is only recorded if made on their behalf
+                let count = app_data
+                    .audit_log
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&viewer.username))
+                    .insert_header((api::ON_BEHALF_OF_HEADER, "user"))
+                    .uri("/test")
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let entry = app_data
+                    .audit_log
+                    .find_one(doc! {"target": "user"}, None)
+                    .await
+                    .unwrap()
+                    .expect("Audit log entry not found");
+                assert_eq!(entry.actor, viewer.username);
+                assert_eq!(entry.action, api::AuditAction::Impersonate);
+                assert_eq!(entry.request.as_deref(), Some("GET /test"));
             })
             .await;
     }
@@ -844,6 +987,13 @@ mod tests {
 
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
+
+                let count = app_data
+                    .audit_log
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
             })
             .await;
     }
@@ -884,6 +1034,32 @@ mod tests {
 
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
+
+                let count = app_data
+                    .audit_log
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+
+                // requests which may modify the user are always recorded
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&viewer.username))
+                    .uri("/test")
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let entry = app_data
+                    .audit_log
+                    .find_one(doc! {"target": "user"}, None)
+                    .await
+                    .unwrap()
+                    .expect("Audit log entry not found");
+                assert_eq!(entry.actor, viewer.username);
+                assert_eq!(entry.action, api::AuditAction::Impersonate);
+                assert_eq!(entry.request.as_deref(), Some("POST /test"));
             })
             .await;
     }
@@ -968,6 +1144,35 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_try_edit_user_logged_out() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(edit_test),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/test").to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_try_edit_user_other_owner() {
         let user: User = api::NewUser {
@@ -1225,7 +1430,7 @@ mod tests {
         Ok(HttpResponse::Ok().finish())
     }
 
-    #[get("/test")]
+    #[route("/test", method = "GET", method = "POST")]
     async fn edit_test(
         app: web::Data<AppData>,
         req: HttpRequest,
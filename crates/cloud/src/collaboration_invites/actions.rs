@@ -1,4 +1,5 @@
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use actix::Addr;
 use futures::TryStreamExt;
@@ -9,8 +10,8 @@ use mongodb::{
     Collection,
 };
 use netsblox_cloud_common::{
-    api::{self, InvitationState},
-    CollaborationInvite, ProjectMetadata,
+    api::{self, FriendLinkState, InvitationState},
+    CollaborationInvite, FriendLink, ProjectMetadata,
 };
 
 use crate::{
@@ -22,32 +23,48 @@ use crate::{
 
 pub(crate) struct CollaborationInviteActions<'a> {
     collab_invites: &'a Collection<CollaborationInvite>,
+    friends: &'a Collection<FriendLink>,
 
     project_metadata: &'a Collection<ProjectMetadata>,
     project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
     network: &'a Addr<TopologyActor>,
+    invite_ttl: Duration,
 }
 
 impl<'a> CollaborationInviteActions<'a> {
     pub(crate) fn new(
         collab_invites: &'a Collection<CollaborationInvite>,
+        friends: &'a Collection<FriendLink>,
 
         project_metadata: &'a Collection<ProjectMetadata>,
         project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
         network: &'a Addr<TopologyActor>,
+        invite_ttl: Duration,
     ) -> Self {
         Self {
             collab_invites,
+            friends,
             project_metadata,
             project_cache,
             network,
+            invite_ttl,
         }
     }
+
+    /// Invites created before this time have expired
+    fn expiry_cutoff(&self) -> DateTime {
+        utils::invite_expiry_cutoff(self.invite_ttl)
+    }
+
     pub(crate) async fn list_invites(
         &self,
         eu: &auth::ViewUser,
     ) -> Result<Vec<api::CollaborationInvite>, UserError> {
-        let query = doc! {"receiver": &eu.username};
+        let query = doc! {
+            "receiver": &eu.username,
+            "dismissed": {"$ne": true},
+            "createdAt": {"$gt": self.expiry_cutoff()},
+        };
         let cursor = self
             .collab_invites
             .find(query, None)
@@ -64,6 +81,32 @@ impl<'a> CollaborationInviteActions<'a> {
         Ok(invites)
     }
 
+    /// List all invitations sent or received by the user (including dismissed ones).
+    pub(crate) async fn export_invites(
+        &self,
+        ex: &auth::ExportUser,
+    ) -> Result<Vec<api::CollaborationInvite>, UserError> {
+        let query = doc! {
+            "$or": [
+                {"sender": &ex.username},
+                {"receiver": &ex.username},
+            ]
+        };
+        let invites = self
+            .collab_invites
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|invite| invite.into())
+            .collect();
+
+        Ok(invites)
+    }
+
     // TODO: should I restrict sending the invite based on target?
     // TODO: only allow the owner to send collaboration invites?
     pub(crate) async fn send_invite(
@@ -72,28 +115,36 @@ impl<'a> CollaborationInviteActions<'a> {
         target: &str,
     ) -> Result<api::CollaborationInvite, UserError> {
         let sender = ep.project.owner.to_owned();
+        self.ensure_not_blocked(&sender, target).await?;
+
         let invitation =
             CollaborationInvite::new(sender.clone(), target.to_owned(), ep.project.id.clone());
 
+        // expired or dismissed invites are ignored so the receiver is invited again
         let query = doc! {
+            "sender": &sender,
             "receiver": &target,
-            "projectId": &invitation.project_id
+            "projectId": &invitation.project_id,
+            "createdAt": {"$gt": self.expiry_cutoff()},
+            "dismissed": {"$ne": true},
         };
         let update = doc! {
             "$setOnInsert": &invitation
         };
-        let options = mongodb::options::UpdateOptions::builder()
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::Before)
             .upsert(true)
             .build();
 
-        let result = self
+        let existing = self
             .collab_invites
-            .update_one(query, update, Some(options))
+            .find_one_and_update(query, update, options)
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
-        if result.matched_count == 1 {
-            Err(UserError::InviteAlreadyExistsError)
+        if let Some(existing) = existing {
+            // the invite is already pending so there is nothing to send
+            Ok(existing.into())
         } else {
             // notify the recipient of the new invitation
             let invitation: api::CollaborationInvite = invitation.into();
@@ -109,6 +160,26 @@ impl<'a> CollaborationInviteActions<'a> {
         }
     }
 
+    /// Ensure the receiver has not blocked the sender
+    async fn ensure_not_blocked(&self, sender: &str, receiver: &str) -> Result<(), UserError> {
+        let query = doc! {
+            "sender": receiver,
+            "recipient": sender,
+            "state": FriendLinkState::Blocked,
+        };
+        let block = self
+            .friends
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        if block.is_some() {
+            Err(UserError::InviteBlockedError)
+        } else {
+            Ok(())
+        }
+    }
+
     pub(crate) async fn respond(
         &self,
         ri: &auth::RespondToCollabInvite,
@@ -129,6 +200,7 @@ impl<'a> CollaborationInviteActions<'a> {
                 "$addToSet": {
                     "collaborators": &ri.invite.receiver,
                 },
+                "$inc": {"version": 1},
                 "$set": {
                     "updated": DateTime::now()
                 }
@@ -158,4 +230,39 @@ impl<'a> CollaborationInviteActions<'a> {
 
         Ok(state)
     }
+
+    /// Hide the invitation from the receiver. The invitation is only marked as
+    /// dismissed so the sender's record is unaffected.
+    pub(crate) async fn dismiss(&self, ri: &auth::RespondToCollabInvite) -> Result<(), UserError> {
+        let query = doc! {"id": &ri.invite.id};
+        let update = doc! {"$set": {"dismissed": true}};
+        let invitation = self
+            .collab_invites
+            .find_one_and_update(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::InviteNotFoundError)?;
+
+        let invitation: api::CollaborationInvite = invitation.into();
+        self.network
+            .send(network::topology::CollabInviteChangeMsg::new(
+                network::topology::ChangeType::Remove,
+                invitation,
+            ))
+            .await
+            .map_err(InternalError::ActixMessageError)?;
+
+        Ok(())
+    }
+
+    /// Remove all invitations which are older than the configured TTL.
+    pub(crate) async fn delete_expired_invites(&self) -> Result<(), UserError> {
+        let query = doc! {"createdAt": {"$lte": self.expiry_cutoff()}};
+        self.collab_invites
+            .delete_many(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(())
+    }
 }
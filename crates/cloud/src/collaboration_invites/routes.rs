@@ -1,4 +1,4 @@
-use actix_web::{get, post, HttpRequest};
+use actix_web::{delete, get, post, HttpRequest};
 use actix_web::{web, HttpResponse};
 
 use crate::app_data::AppData;
@@ -54,18 +54,39 @@ async fn respond_to_invite(
     Ok(HttpResponse::Ok().json(state))
 }
 
+#[delete("/id/{id}")]
+async fn dismiss_invite(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id,) = path.into_inner();
+    let auth_ri = auth::collaboration::try_respond_to_invite(&app, &req, &id).await?;
+
+    let actions: CollaborationInviteActions = app.as_collab_invite_actions();
+    actions.dismiss(&auth_ri).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(list_invites)
         .service(send_invite)
-        .service(respond_to_invite);
+        .service(respond_to_invite)
+        .service(dismiss_invite);
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, SystemTime};
+
     use super::*;
     use actix_web::{http, test, web, App};
-    use netsblox_cloud_common::{api, CollaborationInvite, User};
+    use futures::TryStreamExt;
+    use mongodb::bson::{doc, DateTime};
+    use netsblox_cloud_common::{api, CollaborationInvite, FriendLink, User};
 
+    use crate::config::InviteSettings;
     use crate::test_utils;
 
     #[actix_web::test]
@@ -116,6 +137,147 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_list_invites_expired() {
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let invite = CollaborationInvite::new(
+            "sender".into(),
+            rcvr.username.clone(),
+            ProjectId::new("project".into()),
+        );
+        let mut old_invite = CollaborationInvite::new(
+            "old_sender".into(),
+            rcvr.username.clone(),
+            ProjectId::new("old_project".into()),
+        );
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        old_invite.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_users(&[rcvr.clone()])
+            .with_collab_invites(&[invite.clone(), old_invite])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/user/{}/", &rcvr.username))
+                    .to_request();
+
+                let invites: Vec<api::CollaborationInvite> =
+                    test::call_and_read_body_json(&app, req).await;
+
+                assert_eq!(invites.len(), 1);
+                assert_eq!(invites[0].id, invite.id);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_respond_to_invite_expired() {
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner("sender".to_string())
+            .build();
+        let mut invite =
+            CollaborationInvite::new("sender".into(), rcvr.username.clone(), project.id.clone());
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        invite.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_users(&[rcvr.clone()])
+            .with_projects(&[project.clone()])
+            .with_collab_invites(&[invite.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/id/{}", &invite.id))
+                    .set_json(api::InvitationState::Accepted)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+                let query = doc! {"id": &project.id};
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(metadata.collaborators.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_expired_invites() {
+        let invite = CollaborationInvite::new(
+            "sender".into(),
+            "rcvr".into(),
+            ProjectId::new("project".into()),
+        );
+        let mut old_invite = CollaborationInvite::new(
+            "old_sender".into(),
+            "rcvr".into(),
+            ProjectId::new("old_project".into()),
+        );
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        old_invite.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_collab_invites(&[invite.clone(), old_invite])
+            .run(|app_data| async move {
+                let actions = app_data.as_collab_invite_actions();
+                actions.delete_expired_invites().await.unwrap();
+
+                let ids: Vec<_> = app_data
+                    .collab_invites
+                    .find(doc! {}, None)
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|invite| invite.id)
+                    .collect();
+                assert_eq!(ids, vec![invite.id]);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_list_invites_403() {
         let rcvr: User = api::NewUser {
@@ -340,14 +502,70 @@ mod tests {
             .with_owner("sender".to_string())
             .build();
         let invite = CollaborationInvite::new(
-            "anySender".into(),
+            sender.username.clone(),
             rcvr.username.clone(),
             project.id.clone(),
         );
 
         test_utils::setup()
             .with_users(&[sender.clone(), rcvr.clone()])
-            .with_collab_invites(&[invite])
+            .with_collab_invites(&[invite.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                for _ in 0..2 {
+                    let req = test::TestRequest::post()
+                        .cookie(test_utils::cookie::new(&sender.username))
+                        .uri(&format!("/{}/invite/{}", &project.id, &rcvr.username))
+                        .to_request();
+
+                    let existing: api::CollaborationInvite =
+                        test::call_and_read_body_json(&app, req).await;
+                    assert_eq!(existing.id, invite.id);
+                }
+
+                let query = doc! {"receiver": &rcvr.username, "projectId": &project.id};
+                let count = app_data
+                    .collab_invites
+                    .count_documents(query, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_send_invite_blocked() {
+        let sender: User = api::NewUser {
+            username: "sender".to_string(),
+            email: "sender@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner("sender".to_string())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[sender.clone(), rcvr.clone()])
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -358,13 +576,207 @@ mod tests {
                 )
                 .await;
 
+                let auth_eu = auth::users::EditUser::test(rcvr.username.clone());
+                let actions = app_data.as_friend_actions();
+                actions.block(&auth_eu, &sender.username).await.unwrap();
+
                 let req = test::TestRequest::post()
                     .cookie(test_utils::cookie::new(&sender.username))
                     .uri(&format!("/{}/invite/{}", &project.id, &rcvr.username))
                     .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+                // the invite can be sent once the sender has been unblocked
+                actions.unblock(&auth_eu, &sender.username).await.unwrap();
 
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&sender.username))
+                    .uri(&format!("/{}/invite/{}", &project.id, &rcvr.username))
+                    .to_request();
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::CONFLICT);
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_send_invite_blocked_by_sender() {
+        let sender: User = api::NewUser {
+            username: "sender".to_string(),
+            email: "sender@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner("sender".to_string())
+            .build();
+        // the receiver invited the sender to be friends before being blocked
+        let link = FriendLink::new(
+            rcvr.username.clone(),
+            sender.username.clone(),
+            Some(api::FriendLinkState::Approved),
+        );
+
+        test_utils::setup()
+            .with_users(&[sender.clone(), rcvr.clone()])
+            .with_projects(&[project.clone()])
+            .with_friend_links(&[link])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // only blocks by the receiver prevent the invite
+                let auth_eu = auth::users::EditUser::test(sender.username.clone());
+                let actions = app_data.as_friend_actions();
+                actions.block(&auth_eu, &rcvr.username).await.unwrap();
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&sender.username))
+                    .uri(&format!("/{}/invite/{}", &project.id, &rcvr.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_dismiss_invite() {
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let invite = CollaborationInvite::new(
+            "sender".into(),
+            rcvr.username.clone(),
+            ProjectId::new("project".into()),
+        );
+
+        test_utils::setup()
+            .with_users(&[rcvr.clone()])
+            .with_collab_invites(&[invite.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/id/{}", &invite.id))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/user/{}/", &rcvr.username))
+                    .to_request();
+
+                let invites: Vec<api::CollaborationInvite> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(invites.is_empty());
+
+                // the sender's record is kept
+                let query = doc! {"id": &invite.id};
+                let invite = app_data
+                    .collab_invites
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(invite.dismissed);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_send_invite_after_dismissed() {
+        let sender: User = api::NewUser {
+            username: "sender".to_string(),
+            email: "sender@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let rcvr: User = api::NewUser {
+            username: "rcvr".to_string(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner("sender".to_string())
+            .build();
+        let invite = CollaborationInvite::new(
+            sender.username.clone(),
+            rcvr.username.clone(),
+            project.id.clone(),
+        );
+
+        test_utils::setup()
+            .with_users(&[sender.clone(), rcvr.clone()])
+            .with_collab_invites(&[invite.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/id/{}", &invite.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&sender.username))
+                    .uri(&format!("/{}/invite/{}", &project.id, &rcvr.username))
+                    .to_request();
+                let new_invite: api::CollaborationInvite =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_ne!(new_invite.id, invite.id);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&rcvr.username))
+                    .uri(&format!("/user/{}/", &rcvr.username))
+                    .to_request();
+                let invites: Vec<api::CollaborationInvite> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(invites.len(), 1);
+                assert_eq!(invites[0].id, new_invite.id);
             })
             .await;
     }
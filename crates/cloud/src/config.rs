@@ -1,12 +1,48 @@
-use std::{env, num::NonZeroUsize};
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+};
 
+use derive_more::{Display, Error};
 use figment::{
     providers::{Format, Toml},
     Figment,
 };
-use netsblox_cloud_common::api::ServiceHostScope;
+use netsblox_cloud_common::api::{PasswordRules, ServiceHostScope};
+use reqwest::Url;
 use serde::Deserialize;
 
+/// Minimum length of the cookie key (required by actix)
+const MIN_COOKIE_KEY_LEN: usize = 64;
+
+#[derive(Debug, Display, Error)]
+pub enum ConfigError {
+    #[display(
+        fmt = "cookie.key must be at least {} bytes long (found {} bytes).",
+        MIN_COOKIE_KEY_LEN,
+        _0
+    )]
+    CookieKeyTooShort(#[error(not(source))] usize),
+    #[display(
+        fmt = "address must be a host and port such as \"0.0.0.0:7777\" (found \"{}\").",
+        _0
+    )]
+    InvalidAddress(#[error(not(source))] String),
+    #[display(fmt = "public_url must be set to the public URL of the server.")]
+    MissingPublicUrl,
+    #[display(
+        fmt = "database.url must be a MongoDB connection string such as \"mongodb://127.0.0.1:27017/\" (found \"{}\").",
+        _0
+    )]
+    InvalidDatabaseUrl(#[error(not(source))] String),
+    #[display(
+        fmt = "s3.endpoint must be an http(s) URL such as \"http://127.0.0.1:9000\" (found \"{}\").",
+        _0
+    )]
+    InvalidS3Endpoint(#[error(not(source))] String),
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct Database {
     pub url: String,
@@ -50,6 +86,187 @@ pub struct SMTPSettings {
 #[derive(Clone, Deserialize, Debug)]
 pub struct SecuritySettings {
     pub allow_tor_login: bool,
+    /// Require a verified email address for actions like publishing projects
+    #[serde(default)]
+    pub require_email_verification: bool,
+    #[serde(default)]
+    pub rate_limits: RateLimitSettings,
+    /// Secret used to encrypt two-factor auth secrets. Defaults to the cookie key (with
+    /// a warning on startup) but should be set separately in production
+    pub two_factor_key: Option<String>,
+    /// Bearer token required to access the metrics endpoint. Metrics are public if unset
+    pub metrics_token: Option<String>,
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
+    /// Request sources (from the `x-source` header) which can only make GET requests
+    #[serde(default = "default_restricted_sources")]
+    pub restricted_sources: Vec<String>,
+    /// Allow validating service hosts on loopback or private networks (eg, for
+    /// local development)
+    #[serde(default)]
+    pub allow_private_service_hosts: bool,
+    /// Addresses of reverse proxies trusted to report the client IP address (using
+    /// the Forwarded or X-Forwarded-For headers)
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+fn default_restricted_sources() -> Vec<String> {
+    vec!["NetsBlox".to_owned()]
+}
+
+/// Requirements for new passwords. No requirements are enforced by default.
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Path to a list of breached passwords (one per line) which cannot be used
+    pub breached_passwords: Option<String>,
+}
+
+impl From<&PasswordPolicy> for PasswordRules {
+    fn from(policy: &PasswordPolicy) -> Self {
+        Self {
+            min_length: policy.min_length,
+            require_lowercase: policy.require_lowercase,
+            require_uppercase: policy.require_uppercase,
+            require_digit: policy.require_digit,
+            require_symbol: policy.require_symbol,
+        }
+    }
+}
+
+/// Limits for endpoints which send emails or accept reset tokens (eg, magic links)
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Max number of requests for a given email address (or username) per window
+    pub max_requests_per_email: u32,
+    /// Max number of requests from a given IP address per window
+    pub max_requests_per_ip: u32,
+    /// Length of the window in seconds
+    pub window_secs: u64,
+    /// Max number of failed two-factor codes for a given user per window. Logging
+    /// in with two-factor auth is locked until the window ends.
+    pub max_two_factor_failures: u32,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            max_requests_per_email: 5,
+            max_requests_per_ip: 20,
+            window_secs: 60 * 60,
+            max_two_factor_failures: 5,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Max size (in bytes) of a message sent over the network websocket
+    pub max_message_size: usize,
+    /// Max number of network traces which can be recording at once (per project)
+    pub max_active_traces: usize,
+    /// Max number of clients which can occupy a single room (project) at once
+    pub max_room_occupants: usize,
+    /// Store messages sent to offline external clients and deliver them on reconnect
+    pub queue_offline_messages: bool,
+    /// Number of seconds a queued message is kept before it is dropped
+    pub offline_message_ttl_secs: u64,
+    /// Max number of messages queued for a single address. The oldest are dropped first.
+    pub max_queued_messages: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 * 1024,
+            max_active_traces: 3,
+            max_room_occupants: 50,
+            queue_offline_messages: false,
+            offline_message_ttl_secs: 24 * 60 * 60,
+            max_queued_messages: 100,
+        }
+    }
+}
+
+/// Limits for restarting the network topology after a failure. If the topology fails
+/// more than `max_restarts` times within the window, the server is shut down.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct TopologySettings {
+    pub max_restarts: u32,
+    /// Length of the window in seconds
+    pub restart_window_secs: u64,
+}
+
+impl Default for TopologySettings {
+    fn default() -> Self {
+        Self {
+            max_restarts: 3,
+            restart_window_secs: 10 * 60,
+        }
+    }
+}
+
+/// Pending collaboration and friend invites expire after `ttl_secs`
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct InviteSettings {
+    pub ttl_secs: u64,
+}
+
+impl Default for InviteSettings {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Origins allowed to make (credentialed) cross-origin requests
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CorsSettings {
+    /// Allow requests from any origin. This should only be used for development.
+    pub dev: bool,
+    /// Allowed origins such as "https://editor.netsblox.org". A wildcard for the
+    /// subdomain (eg, "https://*.netsblox.org") allows any subdomain.
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsSettings {
+    pub fn is_allowed(&self, origin: &str) -> bool {
+        self.dev
+            || self
+                .allowed_origins
+                .iter()
+                .any(|pattern| origin_matches(pattern, origin))
+    }
+}
+
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    match pattern.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|origin| origin.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .and_then(|subdomain| subdomain.strip_suffix('.'))
+            .map(|subdomain| {
+                !subdomain.is_empty()
+                    && subdomain
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            })
+            .unwrap_or(false),
+        None => pattern == origin,
+    }
 }
 
 #[derive(Clone, Deserialize, Debug)]
@@ -101,6 +318,14 @@ pub struct Settings {
     pub admin: Option<UserCreds>,
     pub authorized_host: Option<AuthorizedServiceHost>,
     pub cache_settings: CacheSettings,
+    #[serde(default)]
+    pub network: NetworkSettings,
+    #[serde(default)]
+    pub topology: TopologySettings,
+    #[serde(default)]
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub invites: InviteSettings,
 }
 
 impl Settings {
@@ -113,4 +338,165 @@ impl Settings {
 
         Ok(c)
     }
+
+    /// Check for settings which would otherwise cause opaque errors (or panics)
+    /// after startup.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.cookie.key.len() < MIN_COOKIE_KEY_LEN {
+            return Err(ConfigError::CookieKeyTooShort(self.cookie.key.len()));
+        }
+
+        if !is_valid_address(&self.address) {
+            return Err(ConfigError::InvalidAddress(self.address.clone()));
+        }
+
+        if self.public_url.trim().is_empty() {
+            return Err(ConfigError::MissingPublicUrl);
+        }
+
+        let db_url = &self.database.url;
+        let is_mongo_url = ["mongodb://", "mongodb+srv://"]
+            .iter()
+            .any(|scheme| db_url.len() > scheme.len() && db_url.starts_with(scheme));
+        if !is_mongo_url {
+            return Err(ConfigError::InvalidDatabaseUrl(db_url.clone()));
+        }
+
+        let is_http_url = Url::parse(&self.s3.endpoint)
+            .map(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+            .unwrap_or(false);
+        if !is_http_url {
+            return Err(ConfigError::InvalidS3Endpoint(self.s3.endpoint.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that the address is a host and port (without resolving the host)
+fn is_valid_address(address: &str) -> bool {
+    if address.parse::<SocketAddr>().is_ok() {
+        return true;
+    }
+
+    address
+        .rsplit_once(':')
+        .map(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_default() {
+        let settings = Settings::new().unwrap();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_partial_settings_use_defaults() {
+        let limits: RateLimitSettings =
+            serde_json::from_str(r#"{"max_requests_per_ip": 10}"#).unwrap();
+        assert_eq!(limits.max_requests_per_ip, 10);
+        assert_eq!(limits.max_two_factor_failures, 5);
+
+        let topology: TopologySettings = serde_json::from_str(r#"{"max_restarts": 1}"#).unwrap();
+        assert_eq!(topology.restart_window_secs, 10 * 60);
+
+        let invites: InviteSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(invites.ttl_secs, 30 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn test_cors_allowed_origins() {
+        let cors = CorsSettings {
+            dev: false,
+            allowed_origins: vec![
+                "https://netsblox.org".into(),
+                "https://*.netsblox.org".into(),
+                "http://localhost:8080".into(),
+            ],
+        };
+
+        assert!(cors.is_allowed("https://netsblox.org"));
+        assert!(cors.is_allowed("https://editor.netsblox.org"));
+        assert!(cors.is_allowed("https://dev.editor.netsblox.org"));
+        assert!(cors.is_allowed("http://localhost:8080"));
+
+        assert!(!cors.is_allowed("http://editor.netsblox.org"));
+        assert!(!cors.is_allowed("https://evilnetsblox.org"));
+        assert!(!cors.is_allowed("https://netsblox.org.evil.com"));
+        assert!(!cors.is_allowed("https://evil.com/.netsblox.org"));
+        assert!(!cors.is_allowed("https://.netsblox.org"));
+        assert!(!cors.is_allowed("http://localhost:8081"));
+    }
+
+    #[test]
+    fn test_cors_dev() {
+        let cors = CorsSettings {
+            dev: true,
+            allowed_origins: Vec::new(),
+        };
+
+        assert!(cors.is_allowed("https://example.com"));
+    }
+
+    #[test]
+    fn test_validate_short_cookie_key() {
+        let mut settings = Settings::new().unwrap();
+        settings.cookie.key = "tooShort".into();
+
+        let result = settings.validate();
+        assert!(matches!(result, Err(ConfigError::CookieKeyTooShort(8))));
+    }
+
+    #[test]
+    fn test_validate_invalid_address() {
+        let mut settings = Settings::new().unwrap();
+        for address in ["0.0.0.0", "localhost:port", ":7777", ""] {
+            settings.address = address.into();
+            let result = settings.validate();
+            assert!(
+                matches!(result, Err(ConfigError::InvalidAddress(_))),
+                "{} should be invalid",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_hostname_address() {
+        let mut settings = Settings::new().unwrap();
+        settings.address = "localhost:7777".into();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_missing_public_url() {
+        let mut settings = Settings::new().unwrap();
+        settings.public_url = " ".into();
+
+        let result = settings.validate();
+        assert!(matches!(result, Err(ConfigError::MissingPublicUrl)));
+    }
+
+    #[test]
+    fn test_validate_invalid_db_url() {
+        let mut settings = Settings::new().unwrap();
+        settings.database.url = "127.0.0.1:27017".into();
+
+        let result = settings.validate();
+        assert!(matches!(result, Err(ConfigError::InvalidDatabaseUrl(_))));
+    }
+
+    #[test]
+    fn test_validate_invalid_s3_endpoint() {
+        let mut settings = Settings::new().unwrap();
+        settings.s3.endpoint = "127.0.0.1:9000".into();
+
+        let result = settings.validate();
+        assert!(matches!(result, Err(ConfigError::InvalidS3Endpoint(_))));
+    }
 }
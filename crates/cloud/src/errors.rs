@@ -3,6 +3,8 @@ use derive_more::{Display, Error};
 use log::warn;
 use serde::Serialize;
 
+use crate::common::api;
+
 #[derive(Debug, Display, Error)]
 pub enum InternalError {
     DatabaseConnectionError(mongodb::error::Error),
@@ -17,6 +19,7 @@ pub enum InternalError {
     ThumbnailDecodeError(image::ImageError),
     ThumbnailEncodeError(image::ImageError),
     PasswordGenerationError,
+    TwoFactorDecryptError,
 }
 
 #[derive(Debug, Display, Error)]
@@ -29,6 +32,8 @@ pub enum UserError {
     ProjectNotFoundError,
     #[display(fmt = "Client not found.")]
     ClientNotFoundError,
+    #[display(fmt = "Collaborator not found.")]
+    CollaboratorNotFoundError,
     #[display(fmt = "Thumbnail not available.")]
     ThumbnailNotFoundError,
     #[display(fmt = "Unable to retrieve project.")]
@@ -43,12 +48,22 @@ pub enum UserError {
     MagicLinkSentError,
     #[display(fmt = "Magic link not found or no longer active.")]
     MagicLinkNotFoundError,
+    #[display(fmt = "Password reset links must be opened in a browser.")]
+    PasswordResetLinkError,
     #[display(fmt = "Network trace not found.")]
     NetworkTraceNotFoundError,
+    #[display(fmt = "Too many active network traces. Please stop one and try again.")]
+    TooManyNetworkTracesError,
+    #[display(fmt = "Room is full. Please try again later.")]
+    RoomFullError,
     #[display(fmt = "Library not found.")]
     LibraryNotFoundError,
     #[display(fmt = "Role not found.")]
     RoleNotFoundError,
+    #[display(fmt = "Role version not found.")]
+    RoleVersionNotFoundError,
+    #[display(fmt = "Session not found.")]
+    SessionNotFoundError,
     #[display(fmt = "Group not found.")]
     GroupNotFoundError,
     #[display(fmt = "User not found.")]
@@ -61,8 +76,8 @@ pub enum UserError {
     InviteNotFoundError,
     #[display(fmt = "Invitation not allowed between members.")]
     InviteNotAllowedError,
-    #[display(fmt = "Invitation already exists.")]
-    InviteAlreadyExistsError,
+    #[display(fmt = "User is not accepting invitations from this sender.")]
+    InviteBlockedError,
     #[display(fmt = "Service host not found.")]
     ServiceHostNotFoundError,
     #[display(fmt = "Project not active.")]
@@ -75,6 +90,30 @@ pub enum UserError {
     IncorrectUsernameOrPasswordError,
     #[display(fmt = "User has been banned.")]
     BannedUserError,
+    #[display(fmt = "Email address has been banned.")]
+    BannedEmailError,
+    #[display(fmt = "{}", _0)]
+    WeakPasswordError(api::PasswordRuleError),
+    #[display(fmt = "Password has appeared in a data breach. Please choose another.")]
+    BreachedPasswordError,
+    #[display(fmt = "Account is scheduled for deletion.")]
+    AccountPendingDeletionError,
+    #[display(fmt = "Too many requests. Please try again later.")]
+    TooManyRequestsError,
+    #[display(fmt = "Email address must be verified.")]
+    EmailVerificationRequiredError,
+    #[display(fmt = "Email address is already verified.")]
+    EmailAlreadyVerifiedError,
+    #[display(fmt = "Verification link is invalid or has expired.")]
+    EmailVerificationTokenNotFoundError,
+    #[display(fmt = "Two-factor authentication code required.")]
+    TwoFactorCodeRequiredError,
+    #[display(fmt = "Invalid two-factor authentication code.")]
+    InvalidTwoFactorCodeError,
+    #[display(fmt = "Two-factor authentication is already enabled.")]
+    TwoFactorAlreadyEnabledError,
+    #[display(fmt = "Two-factor authentication is not enabled.")]
+    TwoFactorNotEnabledError,
     #[display(fmt = "User already exists.")]
     UserExistsError,
     // FIXME: use a different status code or something so the client can
@@ -83,6 +122,12 @@ pub enum UserError {
     UsernameExists,
     #[display(fmt = "Group already exists.")]
     GroupExistsError,
+    #[display(fmt = "Project has been modified since it was last loaded.")]
+    ProjectVersionConflictError,
+    #[display(fmt = "Library already exists.")]
+    LibraryExistsError,
+    #[display(fmt = "Library is not pending approval.")]
+    LibraryNotPendingApprovalError,
     #[display(fmt = "Invalid username.")]
     InvalidUsername,
     #[display(fmt = "Invalid name.")]
@@ -91,6 +136,10 @@ pub enum UserError {
     RoleOrProjectNameExists,
     #[display(fmt = "Invalid library name.")]
     InvalidLibraryName,
+    #[display(fmt = "Invalid project tag.")]
+    InvalidProjectTag,
+    #[display(fmt = "Too many project tags.")]
+    TooManyProjectTags,
     #[display(fmt = "Invalid email address.")]
     InvalidEmailAddress,
     #[display(fmt = "Invalid client ID.")]
@@ -113,6 +162,10 @@ pub enum UserError {
     InternalError,
     #[display(fmt = "Services endpoint already authorized.")]
     ServiceHostAlreadyAuthorizedError,
+    #[display(fmt = "Unable to reach service host at {}.", _0)]
+    ServiceHostUnreachableError(#[error(not(source))] String),
+    #[display(fmt = "Service host at {} is not allowed.", _0)]
+    DisallowedServiceHostError(#[error(not(source))] String),
     #[display(fmt = "OAuth client with the given name already exists.")]
     OAuthClientAlreadyExistsError,
     #[display(fmt = "OAuth client not found.")]
@@ -163,15 +216,105 @@ impl From<&OAuthFlowError> for OAuthErrorBody {
     }
 }
 
+impl UserError {
+    /// Machine-readable code for the error. These are part of the public API (and
+    /// matched by clients) so they must not change when a variant is renamed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UserError::LoginRequiredError => "LoginRequiredError",
+            UserError::PermissionsError => "PermissionsError",
+            UserError::ProjectNotFoundError => "ProjectNotFoundError",
+            UserError::ClientNotFoundError => "ClientNotFoundError",
+            UserError::CollaboratorNotFoundError => "CollaboratorNotFoundError",
+            UserError::ThumbnailNotFoundError => "ThumbnailNotFoundError",
+            UserError::ProjectUnavailableError => "ProjectUnavailableError",
+            UserError::MissingUrlOrXmlError => "MissingUrlOrXmlError",
+            UserError::UserUpdateFieldRequiredError => "UserUpdateFieldRequiredError",
+            UserError::PasswordResetLinkSentError => "PasswordResetLinkSentError",
+            UserError::MagicLinkSentError => "MagicLinkSentError",
+            UserError::MagicLinkNotFoundError => "MagicLinkNotFoundError",
+            UserError::PasswordResetLinkError => "PasswordResetLinkError",
+            UserError::NetworkTraceNotFoundError => "NetworkTraceNotFoundError",
+            UserError::TooManyNetworkTracesError => "TooManyNetworkTracesError",
+            UserError::RoomFullError => "RoomFullError",
+            UserError::LibraryNotFoundError => "LibraryNotFoundError",
+            UserError::RoleNotFoundError => "RoleNotFoundError",
+            UserError::RoleVersionNotFoundError => "RoleVersionNotFoundError",
+            UserError::SessionNotFoundError => "SessionNotFoundError",
+            UserError::GroupNotFoundError => "GroupNotFoundError",
+            UserError::UserNotFoundError => "UserNotFoundError",
+            UserError::MessageNotFoundError => "MessageNotFoundError",
+            UserError::FriendNotFoundError => "FriendNotFoundError",
+            UserError::InviteNotFoundError => "InviteNotFoundError",
+            UserError::InviteNotAllowedError => "InviteNotAllowedError",
+            UserError::InviteBlockedError => "InviteBlockedError",
+            UserError::ServiceHostNotFoundError => "ServiceHostNotFoundError",
+            UserError::ProjectNotActiveError => "ProjectNotActiveError",
+            UserError::CannotDeleteLastRoleError => "CannotDeleteLastRoleError",
+            UserError::IncorrectPasswordError => "IncorrectPasswordError",
+            UserError::IncorrectUsernameOrPasswordError => "IncorrectUsernameOrPasswordError",
+            UserError::BannedUserError => "BannedUserError",
+            UserError::BannedEmailError => "BannedEmailError",
+            UserError::WeakPasswordError(..) => "WeakPasswordError",
+            UserError::BreachedPasswordError => "BreachedPasswordError",
+            UserError::AccountPendingDeletionError => "AccountPendingDeletionError",
+            UserError::TooManyRequestsError => "TooManyRequestsError",
+            UserError::EmailVerificationRequiredError => "EmailVerificationRequiredError",
+            UserError::EmailAlreadyVerifiedError => "EmailAlreadyVerifiedError",
+            UserError::EmailVerificationTokenNotFoundError => "EmailVerificationTokenNotFoundError",
+            UserError::TwoFactorCodeRequiredError => "TwoFactorCodeRequiredError",
+            UserError::InvalidTwoFactorCodeError => "InvalidTwoFactorCodeError",
+            UserError::TwoFactorAlreadyEnabledError => "TwoFactorAlreadyEnabledError",
+            UserError::TwoFactorNotEnabledError => "TwoFactorNotEnabledError",
+            UserError::UserExistsError => "UserExistsError",
+            UserError::UsernameExists => "UsernameExists",
+            UserError::GroupExistsError => "GroupExistsError",
+            UserError::ProjectVersionConflictError => "ProjectVersionConflictError",
+            UserError::LibraryExistsError => "LibraryExistsError",
+            UserError::LibraryNotPendingApprovalError => "LibraryNotPendingApprovalError",
+            UserError::InvalidUsername => "InvalidUsername",
+            UserError::InvalidRoleOrProjectName => "InvalidRoleOrProjectName",
+            UserError::RoleOrProjectNameExists => "RoleOrProjectNameExists",
+            UserError::InvalidLibraryName => "InvalidLibraryName",
+            UserError::InvalidProjectTag => "InvalidProjectTag",
+            UserError::TooManyProjectTags => "TooManyProjectTags",
+            UserError::InvalidEmailAddress => "InvalidEmailAddress",
+            UserError::InvalidClientIdError => "InvalidClientIdError",
+            UserError::InvalidAppIdError => "InvalidAppIdError",
+            UserError::InvalidServiceHostIDError => "InvalidServiceHostIDError",
+            UserError::SnapConnectionError => "SnapConnectionError",
+            UserError::AccountAlreadyLinkedError => "AccountAlreadyLinkedError",
+            UserError::InvalidAccountTypeError => "InvalidAccountTypeError",
+            UserError::TorAddressError => "TorAddressError",
+            UserError::OperaVPNError => "OperaVPNError",
+            UserError::InternalError => "InternalError",
+            UserError::ServiceHostAlreadyAuthorizedError => "ServiceHostAlreadyAuthorizedError",
+            UserError::ServiceHostUnreachableError(..) => "ServiceHostUnreachableError",
+            UserError::DisallowedServiceHostError(..) => "DisallowedServiceHostError",
+            UserError::OAuthClientAlreadyExistsError => "OAuthClientAlreadyExistsError",
+            UserError::OAuthClientNotFoundError => "OAuthClientNotFoundError",
+            UserError::OAuthTokenNotFoundError => "OAuthTokenNotFoundError",
+            UserError::OAuthFlowError(..) => "OAuthFlowError",
+        }
+    }
+
+    pub fn to_body(&self) -> api::ErrorBody {
+        api::ErrorBody {
+            code: self.code().to_owned(),
+            message: self.to_string(),
+            request_id: None,
+        }
+    }
+}
+
 impl error::ResponseError for UserError {
     fn error_response(&self) -> HttpResponse {
-        // TODO: make these JSON?
         match self {
             UserError::OAuthFlowError(err) => {
                 let body: OAuthErrorBody = err.into();
                 HttpResponse::BadRequest().json(body)
             }
-            _ => HttpResponseBuilder::new(self.status_code()).body(self.to_string()),
+            _ => HttpResponseBuilder::new(self.status_code()).json(self.to_body()),
         }
     }
 
@@ -181,6 +324,11 @@ impl error::ResponseError for UserError {
             Self::PermissionsError
             | Self::IncorrectUsernameOrPasswordError
             | Self::BannedUserError
+            | Self::BannedEmailError
+            | Self::AccountPendingDeletionError
+            | Self::EmailVerificationRequiredError
+            | Self::TwoFactorCodeRequiredError
+            | Self::InvalidTwoFactorCodeError
             | Self::IncorrectPasswordError => StatusCode::FORBIDDEN,
 
             Self::ProjectNotFoundError
@@ -190,13 +338,17 @@ impl error::ResponseError for UserError {
             | Self::LibraryNotFoundError
             | Self::ServiceHostNotFoundError
             | Self::RoleNotFoundError
+            | Self::RoleVersionNotFoundError
             | Self::InviteNotFoundError
             | Self::MagicLinkNotFoundError
+            | Self::EmailVerificationTokenNotFoundError
             | Self::UserNotFoundError
+            | Self::SessionNotFoundError
             | Self::MessageNotFoundError
             | Self::FriendNotFoundError
             | Self::OAuthClientNotFoundError
             | Self::OAuthTokenNotFoundError
+            | Self::CollaboratorNotFoundError
             | Self::GroupNotFoundError => StatusCode::NOT_FOUND,
             Self::InternalError | Self::SnapConnectionError => StatusCode::INTERNAL_SERVER_ERROR,
             Self::InvalidUsername
@@ -205,27 +357,42 @@ impl error::ResponseError for UserError {
             | Self::InvalidEmailAddress
             | Self::InvalidClientIdError
             | Self::InvalidLibraryName
+            | Self::InvalidProjectTag
+            | Self::TooManyProjectTags
             | Self::InvalidAppIdError
             | Self::InvalidServiceHostIDError
             | Self::AccountAlreadyLinkedError
             | Self::PasswordResetLinkSentError
             | Self::MagicLinkSentError
+            | Self::PasswordResetLinkError
             | Self::InvalidAccountTypeError
             | Self::TorAddressError
             | Self::OperaVPNError
             | Self::UserExistsError
+            | Self::EmailAlreadyVerifiedError
+            | Self::TwoFactorAlreadyEnabledError
+            | Self::TwoFactorNotEnabledError
             | Self::UsernameExists
             | Self::OAuthClientAlreadyExistsError
             | Self::GroupExistsError
+            | Self::LibraryExistsError
+            | Self::LibraryNotPendingApprovalError
             | Self::CannotDeleteLastRoleError
             | Self::ServiceHostAlreadyAuthorizedError
+            | Self::ServiceHostUnreachableError(..)
+            | Self::DisallowedServiceHostError(..)
             | Self::InviteNotAllowedError
+            | Self::InviteBlockedError
             | Self::OAuthFlowError(..)
             | Self::ProjectUnavailableError
             | Self::MissingUrlOrXmlError
             | Self::UserUpdateFieldRequiredError
+            | Self::TooManyNetworkTracesError
+            | Self::WeakPasswordError(..)
+            | Self::BreachedPasswordError
             | Self::ProjectNotActiveError => StatusCode::BAD_REQUEST,
-            Self::InviteAlreadyExistsError => StatusCode::CONFLICT,
+            Self::ProjectVersionConflictError | Self::RoomFullError => StatusCode::CONFLICT,
+            Self::TooManyRequestsError => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 }
@@ -237,8 +404,80 @@ impl From<InternalError> for UserError {
     }
 }
 
+impl From<api::s3::S3KeyError> for UserError {
+    fn from(err: api::s3::S3KeyError) -> UserError {
+        warn!("Invalid S3 key: {}", err);
+        UserError::InvalidRoleOrProjectName
+    }
+}
+
 impl From<OAuthFlowError> for UserError {
     fn from(err: OAuthFlowError) -> UserError {
         UserError::OAuthFlowError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::MessageBody, error::ResponseError};
+
+    use super::*;
+
+    #[test]
+    fn test_error_code() {
+        let cases = [
+            (UserError::LoginRequiredError, "LoginRequiredError"),
+            (UserError::PermissionsError, "PermissionsError"),
+            (UserError::ProjectNotFoundError, "ProjectNotFoundError"),
+            (UserError::UserNotFoundError, "UserNotFoundError"),
+            (
+                UserError::IncorrectUsernameOrPasswordError,
+                "IncorrectUsernameOrPasswordError",
+            ),
+            (
+                UserError::TwoFactorCodeRequiredError,
+                "TwoFactorCodeRequiredError",
+            ),
+            (
+                UserError::InvalidTwoFactorCodeError,
+                "InvalidTwoFactorCodeError",
+            ),
+            (
+                UserError::ProjectVersionConflictError,
+                "ProjectVersionConflictError",
+            ),
+            (UserError::TooManyRequestsError, "TooManyRequestsError"),
+            (UserError::PasswordResetLinkError, "PasswordResetLinkError"),
+            (UserError::UsernameExists, "UsernameExists"),
+            (UserError::InternalError, "InternalError"),
+            (
+                UserError::ServiceHostUnreachableError("http://localhost".into()),
+                "ServiceHostUnreachableError",
+            ),
+            (
+                UserError::DisallowedServiceHostError("file:///etc/passwd".into()),
+                "DisallowedServiceHostError",
+            ),
+            (
+                UserError::OAuthFlowError(OAuthFlowError::InvalidGrantTypeError),
+                "OAuthFlowError",
+            ),
+        ];
+
+        for (err, code) in cases {
+            assert_eq!(err.code(), code);
+        }
+    }
+
+    #[test]
+    fn test_json_error_body() {
+        let response = UserError::ProjectNotFoundError.error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let bytes = response.into_body().try_into_bytes().unwrap();
+        let body: api::ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body.code, "ProjectNotFoundError");
+        assert_eq!(body.message, "Project not found.");
+        assert!(body.request_id.is_none());
+    }
+}
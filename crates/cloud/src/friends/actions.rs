@@ -1,10 +1,11 @@
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use actix::Addr;
 use futures::TryStreamExt;
 use lru::LruCache;
 use mongodb::{
-    bson::doc,
+    bson::{doc, DateTime},
     options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
     Collection,
 };
@@ -30,6 +31,7 @@ pub(crate) struct FriendActions<'a> {
     users: &'a Collection<User>,
     groups: &'a Collection<Group>,
     network: &'a Addr<TopologyActor>,
+    invite_ttl: Duration,
 }
 
 impl<'a> FriendActions<'a> {
@@ -40,6 +42,7 @@ impl<'a> FriendActions<'a> {
         users: &'a Collection<User>,
         groups: &'a Collection<Group>,
         network: &'a Addr<TopologyActor>,
+        invite_ttl: Duration,
     ) -> Self {
         Self {
             friends,
@@ -48,9 +51,15 @@ impl<'a> FriendActions<'a> {
             users,
             groups,
             network,
+            invite_ttl,
         }
     }
 
+    /// Pending invites created before this time have expired
+    fn expiry_cutoff(&self) -> DateTime {
+        utils::invite_expiry_cutoff(self.invite_ttl)
+    }
+
     pub(crate) async fn list_friends(
         &self,
         vu: &auth::users::ViewUser,
@@ -125,10 +134,16 @@ impl<'a> FriendActions<'a> {
         eu: &auth::users::EditUser,
         other_user: &str,
     ) -> Result<api::FriendLink, UserError> {
+        // A block by the other user is left as is (rather than reversed) so the
+        // other user can't be unblocked by blocking them back
         let query = doc! {
             "$or": [
                 {"sender": &eu.username, "recipient": &other_user},
-                {"sender": &other_user, "recipient": &eu.username}
+                {
+                    "sender": &other_user,
+                    "recipient": &eu.username,
+                    "state": {"$ne": FriendLinkState::Blocked}
+                }
             ]
         };
         let link = FriendLink::new(
@@ -136,12 +151,16 @@ impl<'a> FriendActions<'a> {
             other_user.to_owned(),
             Some(FriendLinkState::Blocked),
         );
+        // The sender of a block is always the user who blocked the other
         let update = doc! {
             "$set": {
+                "sender": &link.sender,
+                "recipient": &link.recipient,
                 "state": &link.state,
                 "updatedAt": &link.updated_at,
             },
             "$setOnInsert": {
+                "id": &link.id,
                 "createdAt": &link.created_at,
             },
         };
@@ -162,6 +181,8 @@ impl<'a> FriendActions<'a> {
             cache.pop(&eu.username);
             cache.pop(other_user);
 
+            original.sender = link.sender;
+            original.recipient = link.recipient;
             original.state = link.state;
             original.updated_at = link.updated_at;
 
@@ -194,7 +215,11 @@ impl<'a> FriendActions<'a> {
         &self,
         vu: &auth::users::ViewUser,
     ) -> Result<Vec<api::FriendInvite>, UserError> {
-        let query = doc! {"recipient": &vu.username, "state": FriendLinkState::Pending}; // TODO: ensure they are still pending
+        let query = doc! {
+            "recipient": &vu.username,
+            "state": FriendLinkState::Pending,
+            "createdAt": {"$gt": self.expiry_cutoff()},
+        };
         let cursor = self
             .friends
             .find(query, None)
@@ -211,6 +236,79 @@ impl<'a> FriendActions<'a> {
         Ok(invites)
     }
 
+    pub(crate) async fn get_friend_summary(
+        &self,
+        vu: &auth::users::ViewUser,
+    ) -> Result<api::FriendSummary, UserError> {
+        let friends = self.list_friends(vu).await?.len();
+        let online = self.list_online_friends(vu).await?.len();
+
+        let query = doc! {
+            "recipient": &vu.username,
+            "state": FriendLinkState::Pending,
+            "createdAt": {"$gt": self.expiry_cutoff()},
+        };
+        let pending_invites =
+            self.friends
+                .count_documents(query, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)? as usize;
+
+        Ok(api::FriendSummary {
+            friends,
+            online,
+            pending_invites,
+        })
+    }
+
+    pub(crate) async fn get_friendship_status(
+        &self,
+        vu: &auth::users::ViewUser,
+        other: &str,
+    ) -> Result<api::FriendshipStatus, UserError> {
+        let query = doc! {
+            "$or": [
+                {"sender": &vu.username, "recipient": &other},
+                {"sender": &other, "recipient": &vu.username}
+            ]
+        };
+        let links: Vec<FriendLink> = self
+            .friends
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        // Both users may have blocked each other. Prefer the user's own block.
+        let link = links
+            .iter()
+            .find(|link| link.sender == vu.username && link.state == FriendLinkState::Blocked)
+            .or_else(|| links.first())
+            .cloned();
+
+        let is_sender = link
+            .as_ref()
+            .map(|link| link.sender == vu.username)
+            .unwrap_or_default();
+
+        let status = match link.map(|link| link.state) {
+            Some(FriendLinkState::Approved) => api::FriendshipStatus::Friends,
+            Some(FriendLinkState::Pending) if is_sender => {
+                api::FriendshipStatus::InvitePendingOutgoing
+            }
+            Some(FriendLinkState::Pending) => api::FriendshipStatus::InvitePendingIncoming,
+            // Only the user who blocked the other can see the block
+            Some(FriendLinkState::Blocked) if is_sender => api::FriendshipStatus::Blocked,
+            Some(FriendLinkState::Blocked) | Some(FriendLinkState::Rejected) | None => {
+                api::FriendshipStatus::None
+            }
+        };
+
+        Ok(status)
+    }
+
     pub async fn send_invite(
         &self,
         eu: &auth::users::EditUser,
@@ -250,10 +348,12 @@ impl<'a> FriendActions<'a> {
         eu: &auth::users::EditUser,
         recipient: &str,
     ) -> Result<api::FriendLinkState, UserError> {
+        let cutoff = self.expiry_cutoff();
         let query = doc! {
             "sender": &recipient,
             "recipient": &eu.username,
-            "state": FriendLinkState::Pending
+            "state": FriendLinkState::Pending,
+            "createdAt": {"$gt": cutoff},
         };
 
         let update = doc! {"$set": {"state": FriendLinkState::Approved}};
@@ -274,14 +374,25 @@ impl<'a> FriendActions<'a> {
 
             FriendLinkState::Approved
         } else {
-            // Don't add the link if one already exists
-            let query = doc! {
+            let between_users = doc! {
                 "$or": [
                     {"sender": &eu.username, "recipient": &recipient},
                     {"sender": &recipient, "recipient": &eu.username},
                 ]
             };
 
+            // Expired invites (which may not have been cleaned up yet) are replaced
+            // so the new invite is created with a fresh timestamp
+            let mut expired = between_users.clone();
+            expired.insert("state", FriendLinkState::Pending);
+            expired.insert("createdAt", doc! {"$lte": cutoff});
+            self.friends
+                .delete_many(expired, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?;
+
+            // Don't add the link if one already exists
+            let query = between_users;
             let link = FriendLink::new(eu.username.to_owned(), recipient.to_owned(), None);
             let update = doc! {"$setOnInsert": &link};
             let options = FindOneAndUpdateOptions::builder().upsert(true).build();
@@ -321,7 +432,8 @@ impl<'a> FriendActions<'a> {
         let query = doc! {
           "recipient": &eu.username,
           "sender": &sender,
-          "state": FriendLinkState::Pending
+          "state": FriendLinkState::Pending,
+          "createdAt": {"$gt": self.expiry_cutoff()},
         };
 
         let link = match resp {
@@ -363,14 +475,29 @@ impl<'a> FriendActions<'a> {
 
         Ok(link)
     }
+
+    /// Remove all pending invites which are older than the configured TTL.
+    pub(crate) async fn delete_expired_invites(&self) -> Result<(), UserError> {
+        let query = doc! {
+            "state": FriendLinkState::Pending,
+            "createdAt": {"$lte": self.expiry_cutoff()},
+        };
+        self.friends
+            .delete_many(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(())
+    }
 }
 
 // TODO: test that cache is invalidated on unfriend, block
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{errors::UserError, test_utils};
+    use crate::{config::InviteSettings, errors::UserError, test_utils};
     use netsblox_cloud_common::{api, User};
+    use std::time::SystemTime;
 
     #[actix_web::test]
     async fn test_respond_to_request() {
@@ -666,6 +793,157 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_friendship_status() {
+        let sender: User = api::NewUser {
+            username: "sender".into(),
+            email: "sender@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let rcvr: User = api::NewUser {
+            username: "rcvr".into(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[sender.clone(), rcvr.clone()])
+            .run(|app_data| async move {
+                let actions: FriendActions = app_data.as_friend_actions();
+                let sender_vu = auth::ViewUser::test(sender.username.clone());
+                let rcvr_vu = auth::ViewUser::test(rcvr.username.clone());
+
+                let status = actions
+                    .get_friendship_status(&sender_vu, &rcvr.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::None);
+
+                // Send an invite
+                let auth_eu = auth::EditUser::test(sender.username.clone());
+                actions.send_invite(&auth_eu, &rcvr.username).await.unwrap();
+
+                let status = actions
+                    .get_friendship_status(&sender_vu, &rcvr.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::InvitePendingOutgoing);
+                let status = actions
+                    .get_friendship_status(&rcvr_vu, &sender.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::InvitePendingIncoming);
+
+                // accept it
+                let rcvr_eu = auth::EditUser::test(rcvr.username.clone());
+                actions
+                    .respond_to_invite(&rcvr_eu, &sender.username, api::FriendLinkState::Approved)
+                    .await
+                    .unwrap();
+
+                let status = actions
+                    .get_friendship_status(&sender_vu, &rcvr.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Friends);
+                let status = actions
+                    .get_friendship_status(&rcvr_vu, &sender.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Friends);
+
+                // block the sender. Only the receiver should see the block
+                actions.block(&rcvr_eu, &sender.username).await.unwrap();
+
+                let status = actions
+                    .get_friendship_status(&rcvr_vu, &sender.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Blocked);
+                let status = actions
+                    .get_friendship_status(&sender_vu, &rcvr.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::None);
+
+                // unblock
+                actions.unblock(&rcvr_eu, &sender.username).await.unwrap();
+
+                let status = actions
+                    .get_friendship_status(&rcvr_vu, &sender.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::None);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_block_blocked_user() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone(), other.clone()])
+            .run(|app_data| async move {
+                let actions: FriendActions = app_data.as_friend_actions();
+                let user_eu = auth::EditUser::test(user.username.clone());
+                let other_eu = auth::EditUser::test(other.username.clone());
+                let user_vu = auth::ViewUser::test(user.username.clone());
+                let other_vu = auth::ViewUser::test(other.username.clone());
+
+                actions.block(&user_eu, &other.username).await.unwrap();
+                let link = actions.block(&other_eu, &user.username).await.unwrap();
+                assert_eq!(link.sender, other.username);
+
+                let status = actions
+                    .get_friendship_status(&user_vu, &other.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Blocked);
+                let status = actions
+                    .get_friendship_status(&other_vu, &user.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Blocked);
+
+                // unblocking only removes the user's own block
+                actions.unblock(&other_eu, &user.username).await.unwrap();
+
+                let status = actions
+                    .get_friendship_status(&user_vu, &other.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::Blocked);
+                let status = actions
+                    .get_friendship_status(&other_vu, &user.username)
+                    .await
+                    .unwrap();
+                assert_eq!(status, api::FriendshipStatus::None);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_send_invite_no_duplicates() {
         let user: User = api::NewUser {
@@ -707,4 +985,98 @@ mod tests {
             })
             .await;
     }
+
+    #[actix_web::test]
+    async fn test_respond_to_expired_request_404() {
+        let mut link = FriendLink::new("sender".into(), "rcvr".into(), None);
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        link.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_friend_links(&[link])
+            .run(|app_data| async move {
+                let actions: FriendActions = app_data.as_friend_actions();
+                let auth_eu = auth::EditUser::test("rcvr".into());
+                let result = actions
+                    .respond_to_invite(&auth_eu, "sender", api::FriendLinkState::Approved)
+                    .await;
+
+                assert!(matches!(result, Err(UserError::InviteNotFoundError)));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_send_invite_after_expired() {
+        let sender: User = api::NewUser {
+            username: "sender".into(),
+            email: "sender@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let rcvr: User = api::NewUser {
+            username: "rcvr".into(),
+            email: "rcvr@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let mut link = FriendLink::new(rcvr.username.clone(), sender.username.clone(), None);
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        link.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_users(&[sender.clone(), rcvr.clone()])
+            .with_friend_links(&[link])
+            .run(|app_data| async move {
+                let actions: FriendActions = app_data.as_friend_actions();
+
+                // the expired (reverse) invite is not approved by the new one
+                let auth_eu = auth::EditUser::test(sender.username.clone());
+                let state = actions.send_invite(&auth_eu, &rcvr.username).await.unwrap();
+                assert!(matches!(state, api::FriendLinkState::Pending));
+
+                let auth_vu = auth::ViewUser::test(rcvr.username.clone());
+                let invites = actions.list_invites(&auth_vu).await.unwrap();
+                assert_eq!(invites.len(), 1);
+                assert_eq!(invites[0].sender, sender.username);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_expired_invites() {
+        let mut invite =
+            FriendLink::new("s1".into(), "user".into(), Some(FriendLinkState::Pending));
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        invite.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+        // old friendships are unaffected
+        let mut link = FriendLink::new("f1".into(), "user".into(), Some(FriendLinkState::Approved));
+        link.created_at = invite.created_at;
+
+        test_utils::setup()
+            .with_friend_links(&[invite, link.clone()])
+            .run(|app_data| async move {
+                let actions = app_data.as_friend_actions();
+                actions.delete_expired_invites().await.unwrap();
+
+                let links: Vec<_> = app_data
+                    .friends
+                    .find(doc! {}, None)
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(links.len(), 1);
+                assert_eq!(links[0].id, link.id);
+            })
+            .await;
+    }
 }
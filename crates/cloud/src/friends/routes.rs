@@ -36,6 +36,36 @@ async fn list_online_friends(
     Ok(HttpResponse::Ok().json(online_friends))
 }
 
+#[get("/{owner}/summary")]
+async fn get_friend_summary(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (owner,) = path.into_inner();
+    let auth_vu = auth::try_view_user(&app, &req, None, &owner).await?;
+
+    let actions: FriendActions = app.as_friend_actions();
+    let summary = actions.get_friend_summary(&auth_vu).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[get("/{owner}/status/{other}")]
+async fn get_friendship_status(
+    app: web::Data<AppData>,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (owner, other) = path.into_inner();
+    let auth_vu = auth::try_view_user(&app, &req, None, &owner).await?;
+
+    let actions: FriendActions = app.as_friend_actions();
+    let status = actions.get_friendship_status(&auth_vu, &other).await?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
 #[post("/{owner}/unfriend/{friend}")]
 async fn unfriend(
     app: web::Data<AppData>,
@@ -142,6 +172,8 @@ async fn respond_to_invite(
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(list_friends)
         .service(list_online_friends)
+        .service(get_friend_summary)
+        .service(get_friendship_status)
         .service(block_user)
         .service(unblock_user)
         .service(unfriend)
@@ -164,6 +196,7 @@ mod tests {
     };
     use netsblox_cloud_common::{FriendLink, Group};
 
+    use crate::config::InviteSettings;
     use crate::test_utils;
 
     #[actix_web::test]
@@ -389,6 +422,228 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_get_friend_summary() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let f1: User = api::NewUser {
+            username: "f1".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let f2: User = api::NewUser {
+            username: "f2".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let s1: User = api::NewUser {
+            username: "s1".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let s2: User = api::NewUser {
+            username: "s2".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+
+        let l1 = FriendLink::new(
+            user.username.clone(),
+            f1.username.clone(),
+            Some(FriendLinkState::Approved),
+        );
+        let l2 = FriendLink::new(
+            f2.username.clone(),
+            user.username.clone(),
+            Some(FriendLinkState::Approved),
+        );
+        let i1 = FriendLink::new(
+            s1.username.clone(),
+            user.username.clone(),
+            Some(FriendLinkState::Pending),
+        );
+        let i2 = FriendLink::new(
+            s2.username.clone(),
+            user.username.clone(),
+            Some(FriendLinkState::Pending),
+        );
+        // sent by the user so it shouldn't count as a pending invite
+        let i3 = FriendLink::new(
+            user.username.clone(),
+            s1.username.clone(),
+            Some(FriendLinkState::Pending),
+        );
+
+        let c1 = test_utils::network::Client::new(Some(f1.username.clone()), None);
+        let c2 = test_utils::network::Client::new(Some(s1.username.clone()), None);
+        test_utils::setup()
+            .with_users(&[user.clone(), f1, f2, s1, s2])
+            .with_friend_links(&[l1, l2, i1, i2, i3])
+            .with_clients(&[c1, c2])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let cookie = test_utils::cookie::new(&user.username);
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/summary", &user.username))
+                    .cookie(cookie)
+                    .to_request();
+
+                let summary: api::FriendSummary = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(summary.friends, 2);
+                assert_eq!(summary.online, 1);
+                assert_eq!(summary.pending_invites, 2);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_invites_expired() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let s1: User = api::NewUser {
+            username: "s1".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let s2: User = api::NewUser {
+            username: "s2".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+
+        let i1 = FriendLink::new(
+            s1.username.clone(),
+            user.username.clone(),
+            Some(FriendLinkState::Pending),
+        );
+        let mut i2 = FriendLink::new(
+            s2.username.clone(),
+            user.username.clone(),
+            Some(FriendLinkState::Pending),
+        );
+        let ttl = Duration::from_secs(InviteSettings::default().ttl_secs);
+        i2.created_at =
+            DateTime::from_system_time(SystemTime::now() - ttl - Duration::from_secs(60));
+
+        test_utils::setup()
+            .with_users(&[user.clone(), s1.clone(), s2])
+            .with_friend_links(&[i1, i2])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/invites/", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let invites: Vec<api::FriendInvite> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(invites.len(), 1);
+                assert_eq!(invites[0].sender, s1.username);
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/summary", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let summary: api::FriendSummary = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(summary.pending_invites, 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_friendship_status_403() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let l1 = FriendLink::new(
+            user.username.clone(),
+            other.username.clone(),
+            Some(FriendLinkState::Blocked),
+        );
+
+        test_utils::setup()
+            .with_users(&[user.clone(), other.clone()])
+            .with_friend_links(&[l1])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // other user cannot check the status of the block from the user's side
+                let cookie = test_utils::cookie::new(&other.username);
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/status/{}", &user.username, &other.username))
+                    .cookie(cookie)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_unfriend() {
         let user: User = api::NewUser {
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use futures::TryStreamExt;
 use mongodb::{bson::doc, options::ReturnDocument, Collection};
-use netsblox_cloud_common::{api, Group, User};
+use netsblox_cloud_common::{api, BannedAccount, Group, User};
 
 use crate::auth;
 use crate::errors::{InternalError, UserError};
@@ -10,11 +10,20 @@ use crate::errors::{InternalError, UserError};
 pub(crate) struct GroupActions<'a> {
     groups: &'a Collection<Group>,
     users: &'a Collection<User>,
+    banned_accounts: &'a Collection<BannedAccount>,
 }
 
 impl<'a> GroupActions<'a> {
-    pub(crate) fn new(groups: &'a Collection<Group>, users: &'a Collection<User>) -> Self {
-        Self { groups, users }
+    pub(crate) fn new(
+        groups: &'a Collection<Group>,
+        users: &'a Collection<User>,
+        banned_accounts: &'a Collection<BannedAccount>,
+    ) -> Self {
+        Self {
+            groups,
+            users,
+            banned_accounts,
+        }
     }
 
     pub(crate) async fn create_group(
@@ -196,6 +205,70 @@ impl<'a> GroupActions<'a> {
         Ok(group.into())
     }
 
+    /// Make another (existing) user the owner of the group. The group name
+    /// must not conflict with any of the groups already owned by the user.
+    pub(crate) async fn transfer_group(
+        &self,
+        tg: &auth::groups::TransferGroup,
+        new_owner: &str,
+    ) -> Result<api::Group, UserError> {
+        let query = doc! {"username": &new_owner};
+        self.users
+            .find_one(query.clone(), None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        let is_banned = self
+            .banned_accounts
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .is_some();
+
+        if is_banned {
+            return Err(UserError::BannedUserError);
+        }
+
+        let query = doc! {"id": &tg.id};
+        let group = self
+            .groups
+            .find_one(query.clone(), None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::GroupNotFoundError)?;
+
+        if group.owner == new_owner {
+            return Ok(group.into());
+        }
+
+        let conflict_query = doc! {"owner": &new_owner, "name": &group.name};
+        let has_conflict = self
+            .groups
+            .find_one(conflict_query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .is_some();
+
+        if has_conflict {
+            return Err(UserError::GroupExistsError);
+        }
+
+        let update = doc! {"$set": {"owner": &new_owner}};
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let group = self
+            .groups
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::GroupNotFoundError)?;
+
+        Ok(group.into())
+    }
+
     // TODO: move this to the user actions??
     pub(crate) async fn list_members(
         &self,
@@ -218,6 +291,48 @@ impl<'a> GroupActions<'a> {
 
         Ok(members)
     }
+
+    /// Add the user to the group. Users can only belong to a single group so
+    /// this will move the user out of any group they are currently in.
+    pub(crate) async fn add_member(
+        &self,
+        em: &auth::groups::EditGroupMembership,
+    ) -> Result<api::User, UserError> {
+        let query = doc! {"username": &em.username};
+        let update = doc! {"$set": {"groupId": &em.id}};
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let user = self
+            .users
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        Ok(user.into())
+    }
+
+    pub(crate) async fn remove_member(
+        &self,
+        em: &auth::groups::EditGroupMembership,
+    ) -> Result<api::User, UserError> {
+        let query = doc! {"username": &em.username, "groupId": &em.id};
+        let update = doc! {"$unset": {"groupId": true}};
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let user = self
+            .users
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        Ok(user.into())
+    }
 }
 
 #[cfg(test)]
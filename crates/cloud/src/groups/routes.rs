@@ -1,9 +1,15 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::app_data::AppData;
 use crate::auth;
 use crate::errors::UserError;
 use crate::groups::actions::GroupActions;
+use crate::projects::actions::CreateProjectDataDict;
+use crate::projects::ProjectActions;
+use crate::users::actions::UserActions;
 use actix_web::{delete, get, patch, post, HttpRequest};
 use actix_web::{web, HttpResponse};
+use serde::Deserialize;
 
 use crate::common::api;
 
@@ -53,6 +59,331 @@ async fn list_members(
     Ok(HttpResponse::Ok().json(members))
 }
 
+#[post("/id/{id}/members/{username}")]
+async fn add_member(
+    app: web::Data<AppData>,
+    path: web::Path<(api::GroupId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id, username) = path.into_inner();
+
+    let auth_em = auth::try_edit_group_membership(&app, &req, &id, &username).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let user = actions.add_member(&auth_em).await?;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[delete("/id/{id}/members/{username}")]
+async fn remove_member(
+    app: web::Data<AppData>,
+    path: web::Path<(api::GroupId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id, username) = path.into_inner();
+
+    let auth_em = auth::try_edit_group_membership(&app, &req, &id, &username).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let user = actions.remove_member(&auth_em).await?;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[derive(Deserialize)]
+struct ListGroupProjectsParams {
+    /// Only list projects with the given publish state
+    state: Option<api::PublishState>,
+}
+
+/// List the projects owned by all the current members of the group.
+#[get("/id/{id}/projects")]
+async fn list_group_projects(
+    app: web::Data<AppData>,
+    path: web::Path<(api::GroupId,)>,
+    params: web::Query<ListGroupProjectsParams>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id,) = path.into_inner();
+
+    let auth_vg = auth::try_view_group(&app, &req, &id).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let members = actions.list_members(&auth_vg).await?;
+
+    let project_actions: ProjectActions = app.as_project_actions();
+    let projects = project_actions
+        .list_group_projects(&auth_vg, &members, params.state.as_ref())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(projects))
+}
+
+#[get("/id/{id}/export")]
+async fn export_group(
+    app: web::Data<AppData>,
+    path: web::Path<(api::GroupId,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id,) = path.into_inner();
+
+    let auth_vg = auth::try_view_group(&app, &req, &id).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let group = actions.view_group(&auth_vg).await?;
+    let members = actions.list_members(&auth_vg).await?;
+
+    let project_actions: ProjectActions = app.as_project_actions();
+    let mut member_archives = Vec::with_capacity(members.len());
+    for user in members {
+        let auth_lp = auth::try_list_projects(&app, &req, &user.username).await?;
+        let mut projects = Vec::new();
+        for metadata in project_actions.list_projects(&auth_lp).await? {
+            let auth_vp = auth::try_view_project(&app, &req, None, &metadata.id).await?;
+            projects.push(project_actions.get_latest_project(&auth_vp).await?);
+        }
+        member_archives.push(api::GroupMemberArchive { user, projects });
+    }
+
+    let archive = api::GroupArchive {
+        group,
+        members: member_archives,
+    };
+
+    Ok(HttpResponse::Ok().json(archive))
+}
+
+/// Archived group member to create when importing a group
+struct MemberImport {
+    /// Username in the archive
+    archived_username: String,
+    data: api::NewUser,
+    projects: Vec<api::Project>,
+}
+
+/// Accounts and projects created while importing a group (removed if the import fails)
+#[derive(Default)]
+struct ImportedMembers {
+    usernames: Vec<String>,
+    project_ids: Vec<api::ProjectId>,
+}
+
+/// Recreate an exported group (and, optionally, its members and their projects).
+/// Member accounts are created without passwords and with the default role.
+/// Everything is validated before the group is created and the group (along
+/// with any imported members and projects) is removed if the import fails.
+#[post("/user/{owner}/import")]
+async fn import_group(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+    body: web::Json<api::ImportGroupData>,
+) -> Result<HttpResponse, UserError> {
+    let (owner,) = path.into_inner();
+    let api::ImportGroupData { archive, options } = body.into_inner();
+    let auth_eu = auth::try_edit_user(&app, &req, None, &owner).await?;
+
+    let user_actions: UserActions = app.as_user_actions();
+    let (imports, skipped) = validate_import(&user_actions, archive.members, &options).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let data = api::CreateGroupData {
+        name: archive.group.name,
+        services_hosts: archive.group.services_hosts,
+    };
+    let group = actions.create_group(&auth_eu, data).await?;
+
+    let mut imported = ImportedMembers::default();
+    let mut members = HashMap::new();
+    for import in imports {
+        let result =
+            import_member(&app, &req, &user_actions, &group.id, import, &mut imported).await;
+        match result {
+            Ok((archived_username, username)) => {
+                members.insert(archived_username, username);
+            }
+            Err(err) => {
+                rollback_import(&app, &req, &group.id, imported).await;
+                return Err(err);
+            }
+        }
+    }
+
+    let result = api::ImportGroupResult {
+        group,
+        members,
+        skipped,
+    };
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Check that the archived members can be imported before
+/// anything is created. Returns the members to import and the usernames of the
+/// archived members which will be skipped.
+async fn validate_import(
+    actions: &UserActions<'_>,
+    members: Vec<api::GroupMemberArchive>,
+    options: &api::ImportGroupOptions,
+) -> Result<(Vec<MemberImport>, Vec<String>), UserError> {
+    if !options.members {
+        let skipped = members
+            .into_iter()
+            .map(|member| member.user.username)
+            .collect();
+        return Ok((Vec::new(), skipped));
+    }
+
+    let mut imports: Vec<MemberImport> = Vec::new();
+    let mut skipped = Vec::new();
+    for member in members {
+        let archived_username = member.user.username;
+        let taken: HashSet<_> = imports.iter().map(|import| &import.data.username).collect();
+        let username =
+            available_username(actions, &archived_username, options.on_collision, &taken).await?;
+        let username = match username {
+            Some(username) => username,
+            None => {
+                skipped.push(archived_username);
+                continue;
+            }
+        };
+
+        let data = api::NewUser {
+            username,
+            email: member.user.email,
+            password: None,
+            group_id: None,
+            role: None,
+        };
+        match actions.ensure_valid_new_user(&data).await {
+            Ok(_) => {}
+            // the suffix may make the username too long
+            Err(UserError::InvalidUsername) if data.username != archived_username => {
+                skipped.push(archived_username);
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+
+        let projects = if options.projects {
+            member.projects
+        } else {
+            Vec::new()
+        };
+
+        imports.push(MemberImport {
+            archived_username,
+            data,
+            projects,
+        });
+    }
+
+    Ok((imports, skipped))
+}
+
+/// Find an available username for an archived group member. Returns `None` if
+/// the member should be skipped due to a username collision.
+async fn available_username(
+    actions: &UserActions<'_>,
+    username: &str,
+    on_collision: api::UsernameCollision,
+    taken: &HashSet<&String>,
+) -> Result<Option<String>, UserError> {
+    let max_attempts = 100;
+    for attempt in 1..=max_attempts {
+        let candidate = if attempt == 1 {
+            username.to_owned()
+        } else {
+            format!("{}_{}", username, attempt)
+        };
+
+        if !taken.contains(&candidate) && !actions.user_exists(&candidate).await? {
+            return Ok(Some(candidate));
+        }
+
+        if on_collision == api::UsernameCollision::Skip {
+            return Ok(None);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Create the account (and projects) of an archived group member.
+/// Returns the archived and new username of the member.
+async fn import_member(
+    app: &AppData,
+    req: &HttpRequest,
+    actions: &UserActions<'_>,
+    group_id: &api::GroupId,
+    import: MemberImport,
+    imported: &mut ImportedMembers,
+) -> Result<(String, String), UserError> {
+    let data = api::NewUser {
+        group_id: Some(group_id.clone()),
+        ..import.data
+    };
+    let auth_cu = auth::try_create_user(app, req, data).await?;
+    let user = actions.create_user(auth_cu).await?;
+    imported.usernames.push(user.username.clone());
+
+    let project_actions: ProjectActions = app.as_project_actions();
+    let auth_eu = auth::try_edit_user(app, req, None, &user.username).await?;
+    for project in import.projects {
+        let data = CreateProjectDataDict {
+            name: project.name,
+            roles: project.roles,
+            save_state: Some(api::SaveState::Saved),
+            state: api::PublishState::Private,
+        };
+        let metadata = project_actions.create_project(&auth_eu, data).await?;
+        imported.project_ids.push(metadata.id);
+    }
+
+    Ok((import.archived_username, user.username))
+}
+
+/// Remove the group and everything imported into it after a failed import
+async fn rollback_import(
+    app: &AppData,
+    req: &HttpRequest,
+    group_id: &api::GroupId,
+    imported: ImportedMembers,
+) {
+    let project_actions: ProjectActions = app.as_project_actions();
+    for id in imported.project_ids {
+        let result = match auth::try_delete_project(app, req, None, &id).await {
+            Ok(auth_dp) => project_actions.delete_project(&auth_dp).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log::error!("Unable to remove imported project {}: {}", id, err);
+        }
+    }
+
+    let user_actions: UserActions = app.as_user_actions();
+    for username in imported.usernames {
+        let result = match auth::try_edit_user(app, req, None, &username).await {
+            Ok(auth_eu) => user_actions.remove_user(&auth_eu).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log::error!("Unable to remove imported user {}: {}", username, err);
+        }
+    }
+
+    let actions: GroupActions = app.as_group_actions();
+    let result = match auth::try_delete_group(app, req, group_id).await {
+        Ok(auth_dg) => actions.delete_group(&auth_dg).await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        log::error!("Unable to remove imported group {}: {}", group_id, err);
+    }
+}
+
 #[post("/user/{owner}/")]
 async fn create_group(
     app: web::Data<AppData>,
@@ -101,17 +432,41 @@ async fn delete_group(
     Ok(HttpResponse::Ok().json(group))
 }
 
+#[post("/id/{id}/transfer/{new_owner}")]
+async fn transfer_group(
+    app: web::Data<AppData>,
+    path: web::Path<(api::GroupId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (id, new_owner) = path.into_inner();
+
+    let auth_tg = auth::try_transfer_group(&app, &req, &id).await?;
+
+    let actions: GroupActions = app.as_group_actions();
+    let group = actions.transfer_group(&auth_tg, &new_owner).await?;
+
+    Ok(HttpResponse::Ok().json(group))
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(list_groups)
         .service(view_group)
         .service(list_members)
+        .service(list_group_projects)
+        .service(add_member)
+        .service(remove_member)
+        .service(export_group)
+        .service(import_group)
         .service(update_group)
         .service(delete_group)
+        .service(transfer_group)
         .service(create_group);
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
     use actix_web::{body::MessageBody, http, test, App};
     use mongodb::bson::doc;
     use netsblox_cloud_common::{Group, User};
@@ -388,4 +743,984 @@ mod tests {
             .await;
     }
     // TODO: How does it handle malformed IDs?
+
+    #[actix_web::test]
+    async fn test_add_member() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), user.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                // only admins (or the user) can add an existing user to a group
+                let req = test::TestRequest::post()
+                    .uri(&format!("/id/{}/members/{}", &group.id, &user.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let admin: User = api::NewUser {
+                    username: "admin".into(),
+                    email: "admin@netsblox.org".into(),
+                    password: None,
+                    group_id: None,
+                    role: Some(api::UserRole::Admin),
+                }
+                .into();
+                app_data.users.insert_one(&admin, None).await.unwrap();
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/id/{}/members/{}", &group.id, &user.username))
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+
+                let user: api::User = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(user.group_id, Some(group.id.clone()));
+
+                let query = doc! {"groupId": &group.id};
+                let count = app_data.users.count_documents(query, None).await.unwrap();
+                assert_eq!(count, 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_add_member_move_groups() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let other_group = Group::new(owner.username.clone(), "other_group".into());
+        let member: User = api::NewUser {
+            username: "member".into(),
+            email: "member@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), member.clone()])
+            .with_groups(&[group.clone(), other_group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "/id/{}/members/{}",
+                        &other_group.id, &member.username
+                    ))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let member: api::User = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(member.group_id, Some(other_group.id.clone()));
+
+                // the member should only belong to the new group
+                let query = doc! {"groupId": &group.id};
+                let count = app_data.users.count_documents(query, None).await.unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_add_member_403() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let other_group = Group::new(other.username.clone(), "other_group".into());
+        let member: User = api::NewUser {
+            username: "member".into(),
+            email: "member@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner, other.clone(), member.clone()])
+            .with_groups(&[group.clone(), other_group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                // cannot take a member from another user's group
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "/id/{}/members/{}",
+                        &other_group.id, &member.username
+                    ))
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_remove_member() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let member: User = api::NewUser {
+            username: "member".into(),
+            email: "member@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), member.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .uri(&format!("/id/{}/members/{}", &group.id, &member.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let member: api::User = test::call_and_read_body_json(&app, req).await;
+                assert!(member.group_id.is_none());
+
+                let query = doc! {"groupId": &group.id};
+                let count = app_data.users.count_documents(query, None).await.unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_remove_member_404() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let other_group = Group::new(owner.username.clone(), "other_group".into());
+        let member: User = api::NewUser {
+            username: "member".into(),
+            email: "member@netsblox.org".into(),
+            password: None,
+            group_id: Some(other_group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), member.clone()])
+            .with_groups(&[group.clone(), other_group])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .uri(&format!("/id/{}/members/{}", &group.id, &member.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_group() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let new_owner: User = api::NewUser {
+            username: "new_owner".into(),
+            email: "new_owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), new_owner.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "/id/{}/transfer/{}",
+                        &group.id, &new_owner.username
+                    ))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let group: api::Group = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(group.owner, new_owner.username);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_group_403() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let new_owner: User = api::NewUser {
+            username: "new_owner".into(),
+            email: "new_owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), new_owner.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "/id/{}/transfer/{}",
+                        &group.id, &new_owner.username
+                    ))
+                    .cookie(test_utils::cookie::new(&new_owner.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_group_404() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/id/{}/transfer/{}", &group.id, "nonExistentUser"))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+                let query = doc! {"id": &group.id};
+                let group = app_data
+                    .groups
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(group.owner, owner.username);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_group_name_conflict() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let new_owner: User = api::NewUser {
+            username: "new_owner".into(),
+            email: "new_owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let existing = Group::new(new_owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), new_owner.clone()])
+            .with_groups(&[group.clone(), existing])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "/id/{}/transfer/{}",
+                        &group.id, &new_owner.username
+                    ))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_group_projects() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let m1: User = api::NewUser {
+            username: "m1".into(),
+            email: "m1@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+        let m2: User = api::NewUser {
+            username: "m2".into(),
+            email: "m2@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        let p1 = test_utils::project::builder()
+            .with_name("p1")
+            .with_owner(m1.username.clone())
+            .build();
+        let p2 = test_utils::project::builder()
+            .with_name("p2")
+            .with_owner(m2.username.clone())
+            .with_state(api::PublishState::Public)
+            .build();
+        let p3 = test_utils::project::builder()
+            .with_name("p3")
+            .with_owner(m2.username.clone())
+            .build();
+        let other_project = test_utils::project::builder()
+            .with_name("other")
+            .with_owner(other.username.clone())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), other, m1, m2])
+            .with_groups(&[group.clone()])
+            .with_projects(&[p1, p2, p3, other_project])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/projects", &group.id))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                let mut names: Vec<_> = projects.into_iter().map(|p| p.name).collect();
+                names.sort();
+                assert_eq!(names, vec!["p1", "p2", "p3"]);
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/projects?state=Public", &group.id))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(projects.len(), 1);
+                assert_eq!(projects[0].name, "p2");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_group_projects_403() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[owner, other.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/projects", &group.id))
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_export_group() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let members: Vec<User> = ["m1", "m2"]
+            .into_iter()
+            .map(|name| {
+                api::NewUser {
+                    username: name.into(),
+                    email: format!("{}@netsblox.org", name),
+                    password: None,
+                    group_id: Some(group.id.clone()),
+                    role: None,
+                }
+                .into()
+            })
+            .collect();
+        let projects: Vec<_> = ["m1", "m1", "m2"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, owner)| {
+                test_utils::project::builder()
+                    .with_owner(owner.to_string())
+                    .with_name(&format!("project {}", i))
+                    .build()
+            })
+            .collect();
+        // projects from non-members should not be included
+        let owner_project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .build();
+
+        let mut users = members.clone();
+        users.push(owner.clone());
+        let mut all_projects = projects.clone();
+        all_projects.push(owner_project);
+
+        test_utils::setup()
+            .with_users(&users)
+            .with_groups(&[group.clone()])
+            .with_projects(&all_projects)
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/export", &group.id))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+
+                let archive: api::GroupArchive = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(archive.group.id, group.id);
+
+                let mut usernames: Vec<_> = archive
+                    .members
+                    .iter()
+                    .map(|member| member.user.username.clone())
+                    .collect();
+                usernames.sort();
+                assert_eq!(usernames, vec!["m1", "m2"]);
+
+                for member in archive.members {
+                    let mut project_ids: Vec<_> = member
+                        .projects
+                        .into_iter()
+                        .map(|p| p.id.to_string())
+                        .collect();
+                    let mut expected: Vec<_> = projects
+                        .iter()
+                        .filter(|p| p.owner == member.user.username)
+                        .map(|p| p.id.to_string())
+                        .collect();
+                    project_ids.sort();
+                    expected.sort();
+                    assert_eq!(project_ids, expected);
+                }
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_export_group_403() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new("other_user".into(), "some_group".into());
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_groups(&[group.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/export", &group.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_import_group_round_trip() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let members: Vec<User> = ["member1", "member2"]
+            .into_iter()
+            .map(|name| {
+                api::NewUser {
+                    username: name.into(),
+                    email: format!("{}@netsblox.org", name),
+                    password: None,
+                    group_id: Some(group.id.clone()),
+                    role: None,
+                }
+                .into()
+            })
+            .collect();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner("member1".into())
+            .with_name("project")
+            .with_roles([(role_id, role_data)].into_iter().collect())
+            .build();
+
+        let mut users = members.clone();
+        users.push(owner.clone());
+        let archive: Arc<Mutex<Option<api::GroupArchive>>> = Arc::new(Mutex::new(None));
+
+        // export from one instance...
+        let exported = archive.clone();
+        let username = owner.username.clone();
+        test_utils::setup()
+            .with_users(&users)
+            .with_groups(&[group.clone()])
+            .with_projects(&[project])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/export", &group.id))
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+
+                let archive: api::GroupArchive = test::call_and_read_body_json(&app, req).await;
+                *exported.lock().unwrap() = Some(archive);
+            })
+            .await;
+
+        // ...and import into another (where "member1" is already taken)
+        let archive = archive.lock().unwrap().take().unwrap();
+        let existing: User = api::NewUser {
+            username: "member1".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        test_utils::setup()
+            .with_users(&[owner.clone(), existing])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::ImportGroupData {
+                    archive,
+                    options: api::ImportGroupOptions {
+                        on_collision: api::UsernameCollision::Suffix,
+                        ..Default::default()
+                    },
+                };
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}/import", &owner.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let result: api::ImportGroupResult = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(result.group.name, "some_group");
+                assert_eq!(result.group.owner, owner.username);
+                assert_eq!(result.members.get("member1").unwrap(), "member1_2");
+                assert_eq!(result.members.get("member2").unwrap(), "member2");
+                assert!(result.skipped.is_empty());
+
+                let query = doc! {"groupId": &result.group.id};
+                let count = app_data.users.count_documents(query, None).await.unwrap();
+                assert_eq!(count, 2);
+
+                // the project contents are copied from the archive
+                let query = doc! {"owner": "member1_2"};
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .expect("Project not imported");
+                let actions: ProjectActions = app_data.as_project_actions();
+                let project = actions
+                    .get_project(&auth::ViewProject::test(metadata))
+                    .await
+                    .unwrap();
+                assert_eq!(project.name, "project");
+                let role = project.roles.values().next().unwrap();
+                assert_eq!(role.code, "<code/>");
+                assert_eq!(role.media, "<media/>");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_import_group_copy_projects() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner.username.clone(), "some_group".into());
+        let member: User = api::NewUser {
+            username: "member1".into(),
+            email: "member1@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(member.username.clone())
+            .with_name("project")
+            .with_roles([(role_id, role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), member])
+            .with_groups(&[group.clone()])
+            .with_projects(&[project])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/export", &group.id))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .to_request();
+                let archive: api::GroupArchive = test::call_and_read_body_json(&app, req).await;
+
+                let data = api::ImportGroupData {
+                    archive,
+                    options: api::ImportGroupOptions {
+                        on_collision: api::UsernameCollision::Suffix,
+                        ..Default::default()
+                    },
+                };
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}/import", &owner.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let result: api::ImportGroupResult = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(result.members.get("member1").unwrap(), "member1_2");
+
+                let query = doc! {"owner": "member1_2"};
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .expect("Project not imported");
+                assert_eq!(metadata.name, "project");
+                assert_eq!(metadata.roles.len(), 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_import_group_skip_collision() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let existing: User = api::NewUser {
+            username: "member1".into(),
+            email: "member1@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new("someone".into(), "some_group".into());
+        let archive = api::GroupArchive {
+            group: group.clone().into(),
+            members: vec![api::GroupMemberArchive {
+                user: existing.clone().into(),
+                projects: Vec::new(),
+            }],
+        };
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), existing])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::ImportGroupData {
+                    archive,
+                    options: api::ImportGroupOptions::default(),
+                };
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}/import", &owner.username))
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let result: api::ImportGroupResult = test::call_and_read_body_json(&app, req).await;
+                assert!(result.members.is_empty());
+                assert_eq!(result.skipped, vec!["member1".to_string()]);
+
+                // the existing account should be left untouched
+                let query = doc! {"username": "member1"};
+                let user = app_data.users.find_one(query, None).await.unwrap().unwrap();
+                assert!(user.group_id.is_none());
+            })
+            .await;
+    }
 }
@@ -0,0 +1,80 @@
+use crate::app_data::AppData;
+use actix_web::{get, web, HttpResponse};
+use log::warn;
+
+/// Check that the server is up. This does not access the database so it can be
+/// used as a lightweight liveness check by load balancers.
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().body("OK")
+}
+
+/// Check that the server is able to handle requests (ie, its dependencies are reachable)
+#[get("/readyz")]
+async fn readyz(app: web::Data<AppData>) -> HttpResponse {
+    match app.check_dependencies().await {
+        Ok(_) => HttpResponse::Ok().body("OK"),
+        Err(err) => {
+            warn!("Readiness check failed: {:?}", err);
+            HttpResponse::ServiceUnavailable().body("Service unavailable.")
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz).service(readyz);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::test_utils;
+    use actix_web::{http, test, App};
+    use mongodb::Client;
+
+    #[actix_web::test]
+    async fn test_healthz() {
+        let app = test::init_service(App::new().configure(config)).await;
+
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_readyz() {
+        test_utils::setup()
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/readyz").to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_readyz_db_unreachable() {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100")
+            .await
+            .unwrap();
+        let app_data = AppData::new(client, Settings::new().unwrap(), None, None, None);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_data))
+                .configure(config),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/readyz").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -29,8 +29,14 @@ impl<'a> LibraryActions<'a> {
 
     pub(crate) async fn list_community_libraries(
         &self,
+        by_popularity: bool,
     ) -> Result<Vec<api::LibraryMetadata>, UserError> {
-        let options = FindOptions::builder().sort(doc! {"name": 1}).build();
+        let sort = if by_popularity {
+            doc! {"downloads": -1, "name": 1}
+        } else {
+            doc! {"name": 1}
+        };
+        let options = FindOptions::builder().sort(sort).build();
         let public_filter = doc! {"state": PublishState::Public};
         let cursor = self
             .libraries
@@ -81,6 +87,28 @@ impl<'a> LibraryActions<'a> {
         vl.library.blocks.to_owned()
     }
 
+    /// Count a download of a public library. Fetches by the owner are not counted.
+    pub(crate) async fn record_download(
+        &self,
+        vl: &auth::ViewLibrary,
+        viewer: Option<&str>,
+    ) -> Result<(), UserError> {
+        let is_public = matches!(vl.library.state, PublishState::Public);
+        let is_owner = viewer == Some(vl.library.owner.as_str());
+        if !is_public || is_owner {
+            return Ok(());
+        }
+
+        let query = doc! {"owner": &vl.library.owner, "name": &vl.library.name};
+        let update = doc! {"$inc": {"downloads": 1}};
+        self.libraries
+            .update_one(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(())
+    }
+
     pub(crate) async fn save_library(
         &self,
         el: &auth::EditLibrary,
@@ -141,6 +169,53 @@ impl<'a> LibraryActions<'a> {
         }
     }
 
+    /// Rename a library or update its notes without changing the blocks.
+    pub(crate) async fn update_library(
+        &self,
+        el: &auth::EditLibrary,
+        name: &str,
+        data: &api::UpdateLibraryData,
+    ) -> Result<api::LibraryMetadata, UserError> {
+        let mut update = doc! {};
+        if let Some(new_name) = data.new_name.as_ref().filter(|new_name| *new_name != name) {
+            ensure_valid_name(new_name)?;
+
+            let query = doc! {"owner": &el.owner, "name": new_name};
+            let exists = self
+                .libraries
+                .find_one(query, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?
+                .is_some();
+
+            if exists {
+                return Err(UserError::LibraryExistsError);
+            }
+            update.insert("name", new_name);
+        }
+
+        if let Some(notes) = &data.notes {
+            update.insert("notes", notes);
+        }
+
+        let query = doc! {"owner": &el.owner, "name": name};
+        let library = if update.is_empty() {
+            self.libraries.find_one(query, None).await
+        } else {
+            let update = doc! {"$set": update};
+            let options = FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build();
+            self.libraries
+                .find_one_and_update(query, update, options)
+                .await
+        }
+        .map_err(InternalError::DatabaseConnectionError)?
+        .ok_or(UserError::LibraryNotFoundError)?;
+
+        Ok(library.into())
+    }
+
     pub(crate) async fn delete_library(
         &self,
         vl: &auth::EditLibrary,
@@ -257,6 +332,70 @@ impl<'a> LibraryActions<'a> {
 
         Ok(library.into())
     }
+
+    /// Apply several moderation decisions. Each library is handled independently
+    /// so a failure for one does not prevent the others from being updated.
+    pub(crate) async fn approve_libraries(
+        &self,
+        _ml: &auth::ModerateLibraries,
+        approvals: &[api::LibraryApproval],
+    ) -> Vec<api::LibraryApprovalResult> {
+        let mut results = Vec::with_capacity(approvals.len());
+        for approval in approvals {
+            let (library, error) = match self.approve_library(approval).await {
+                Ok(library) => (Some(library), None),
+                Err(err) => (None, Some(err.to_string())),
+            };
+
+            results.push(api::LibraryApprovalResult {
+                owner: approval.owner.clone(),
+                name: approval.name.clone(),
+                library,
+                error,
+            });
+        }
+
+        results
+    }
+
+    async fn approve_library(
+        &self,
+        approval: &api::LibraryApproval,
+    ) -> Result<api::LibraryMetadata, UserError> {
+        let query = doc! {
+            "owner": &approval.owner,
+            "name": &approval.name,
+            "state": PublishState::PendingApproval,
+        };
+        let update = doc! {"$set": {"state": &approval.state}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let library = self
+            .libraries
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        match library {
+            Some(library) => Ok(library.into()),
+            None => {
+                let query = doc! {"owner": &approval.owner, "name": &approval.name};
+                let exists = self
+                    .libraries
+                    .find_one(query, None)
+                    .await
+                    .map_err(InternalError::DatabaseConnectionError)?
+                    .is_some();
+
+                if exists {
+                    Err(UserError::LibraryNotPendingApprovalError)
+                } else {
+                    Err(UserError::LibraryNotFoundError)
+                }
+            }
+        }
+    }
 }
 
 fn ensure_valid_name(name: &str) -> Result<(), UserError> {
@@ -360,6 +499,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
         let pub2 = Library {
             owner: user.username.clone(),
@@ -367,6 +507,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
         let private = Library {
             owner: user.username.clone(),
@@ -374,6 +515,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Private,
+            downloads: 0,
         };
 
         test_utils::setup()
@@ -408,6 +550,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
         let pub2 = Library {
             owner: user.username.clone(),
@@ -415,6 +558,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
         let private = Library {
             owner: user.username.clone(),
@@ -422,6 +566,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Private,
+            downloads: 0,
         };
 
         test_utils::setup()
@@ -455,6 +600,7 @@ mod tests {
             notes: "".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::PendingApproval,
+            downloads: 0,
         };
 
         test_utils::setup()
@@ -1,16 +1,36 @@
 use crate::app_data::AppData;
 use crate::auth;
-use crate::common::api::{CreateLibraryData, PublishState};
+use crate::common::api::{self, CreateLibraryData, PublishState, UpdateLibraryData};
 use crate::errors::UserError;
 use crate::libraries::actions::LibraryActions;
-use actix_web::{delete, get, post, HttpRequest};
+use crate::utils;
+use actix_web::{delete, get, patch, post, HttpRequest};
 use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum LibrarySortOrder {
+    #[default]
+    Name,
+    Popular,
+}
+
+#[derive(Deserialize)]
+struct ListCommunityLibrariesParams {
+    #[serde(default)]
+    sort: LibrarySortOrder,
+}
 
 // TODO: add an endpoint for the official ones?
 #[get("/community/")]
-async fn list_community_libraries(app: web::Data<AppData>) -> Result<HttpResponse, UserError> {
+async fn list_community_libraries(
+    app: web::Data<AppData>,
+    params: web::Query<ListCommunityLibrariesParams>,
+) -> Result<HttpResponse, UserError> {
+    let by_popularity = params.sort == LibrarySortOrder::Popular;
     let actions: LibraryActions = app.as_library_actions();
-    let libraries = actions.list_community_libraries().await?;
+    let libraries = actions.list_community_libraries(by_popularity).await?;
 
     Ok(HttpResponse::Ok().json(libraries))
 }
@@ -40,6 +60,8 @@ async fn get_user_library(
     let auth_vl = auth::try_view_library(&app, &req, &owner, &name).await?;
 
     let actions: LibraryActions = app.as_library_actions();
+    let viewer = utils::get_username(&req).await;
+    actions.record_download(&auth_vl, viewer.as_deref()).await?;
     let blocks = actions.get_library_code(&auth_vl);
 
     Ok(HttpResponse::Ok().body(blocks))
@@ -62,6 +84,22 @@ async fn save_user_library(
     Ok(HttpResponse::Ok().json(library))
 }
 
+#[patch("/user/{owner}/{name}")]
+async fn update_user_library(
+    app: web::Data<AppData>,
+    path: web::Path<(String, String)>,
+    data: web::Json<UpdateLibraryData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (owner, name) = path.into_inner();
+    let auth_el = auth::try_edit_library(&app, &req, &owner).await?;
+
+    let actions: LibraryActions = app.as_library_actions();
+    let library = actions.update_library(&auth_el, &name, &data).await?;
+
+    Ok(HttpResponse::Ok().json(library))
+}
+
 #[delete("/user/{owner}/{name}")]
 async fn delete_user_library(
     app: web::Data<AppData>,
@@ -135,26 +173,55 @@ async fn set_library_state(
         .set_library_state(&auth_ml, &owner, &name, state.into_inner())
         .await?;
 
+    let target = format!("{}/{}", owner, name);
+    app.audit_request(&req, api::AuditAction::SetLibraryState, &target)
+        .await;
+
     Ok(HttpResponse::Ok().json(library))
 }
 
+#[post("/mod/approve-bulk")]
+async fn approve_libraries(
+    app: web::Data<AppData>,
+    approvals: web::Json<Vec<api::LibraryApproval>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_ml = auth::try_moderate_libraries(&app, &req).await?;
+
+    let actions: LibraryActions = app.as_library_actions();
+    let results = actions.approve_libraries(&auth_ml, &approvals).await;
+
+    let applied = results.iter().filter(|result| result.library.is_some());
+    for result in applied {
+        let target = format!("{}/{}", result.owner, result.name);
+        app.audit_request(&req, api::AuditAction::SetLibraryState, &target)
+            .await;
+    }
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(list_community_libraries)
         .service(list_user_libraries)
         .service(get_user_library)
         .service(save_user_library)
+        .service(update_user_library)
         .service(delete_user_library)
         .service(publish_user_library)
         .service(unpublish_user_library)
         .service(list_pending_libraries)
-        .service(set_library_state);
+        .service(set_library_state)
+        .service(approve_libraries);
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::errors::UserError;
     use crate::test_utils;
-    use actix_web::{test, web, App};
-    use netsblox_cloud_common::{api, Library, User};
+    use actix_web::{http, test, web, App};
+    use mongodb::bson::doc;
+    use netsblox_cloud_common::{api, api::UserRole, Library, User};
 
     #[actix_web::test]
     async fn test_list_user_libraries() {
@@ -172,6 +239,7 @@ mod tests {
             notes: "my notes".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Private,
+            downloads: 0,
         };
         let pub_lib = Library {
             owner: user.username.to_owned(),
@@ -179,6 +247,7 @@ mod tests {
             notes: "my notes".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
 
         test_utils::setup()
@@ -212,6 +281,184 @@ mod tests {
         todo!()
     }
 
+    #[actix_web::test]
+    async fn test_get_library_records_download() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone(), other.clone()])
+            .with_libraries(&[library.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let uri = format!("/user/{}/{}", &user.username, &library.name);
+                let req = test::TestRequest::get()
+                    .uri(&uri)
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get().uri(&uri).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"owner": &user.username, "name": &library.name};
+                let saved = app_data
+                    .libraries
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(saved.downloads, 2);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_library_ignores_owner_download() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[library.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/user/{}/{}", &user.username, &library.name))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"owner": &user.username, "name": &library.name};
+                let saved = app_data
+                    .libraries
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(saved.downloads, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_community_libraries_popular() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let lib_a = Library {
+            owner: user.username.to_owned(),
+            name: "a library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 1,
+        };
+        let lib_b = Library {
+            owner: user.username.to_owned(),
+            name: "b library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 7,
+        };
+        let lib_c = Library {
+            owner: user.username.to_owned(),
+            name: "c library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 3,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[lib_a.clone(), lib_b.clone(), lib_c.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/community/?sort=popular")
+                    .to_request();
+                let libraries: Vec<api::LibraryMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                let names: Vec<_> = libraries.iter().map(|lib| lib.name.as_str()).collect();
+                assert_eq!(names, vec!["b library", "c library", "a library"]);
+                assert_eq!(libraries[0].downloads, 7);
+
+                let req = test::TestRequest::get().uri("/community/").to_request();
+                let libraries: Vec<api::LibraryMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                let names: Vec<_> = libraries.iter().map(|lib| lib.name.as_str()).collect();
+                assert_eq!(names, vec!["a library", "b library", "c library"]);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_save_public_library_with_approval() {
         let user: User = api::NewUser {
@@ -228,6 +475,7 @@ mod tests {
             notes: "my notes".into(),
             blocks: "<blocks/>".into(),
             state: api::PublishState::Public,
+            downloads: 0,
         };
 
         test_utils::setup()
@@ -259,6 +507,383 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_update_user_library_notes() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "my notes".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[library.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let data = api::UpdateLibraryData {
+                    new_name: None,
+                    notes: Some("new notes".into()),
+                };
+                let req = test::TestRequest::patch()
+                    .uri(&format!("/user/{}/{}", &user.username, &library.name))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let metadata: api::LibraryMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.name, library.name);
+                assert_eq!(metadata.notes, "new notes");
+
+                // the blocks should be unchanged
+                let query = doc! {"owner": &user.username, "name": &library.name};
+                let saved = app_data
+                    .libraries
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(saved.blocks, library.blocks);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_update_user_library_rename() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "my notes".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[library.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let data = api::UpdateLibraryData {
+                    new_name: Some("renamed library".into()),
+                    notes: None,
+                };
+                let req = test::TestRequest::patch()
+                    .uri(&format!("/user/{}/{}", &user.username, &library.name))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let metadata: api::LibraryMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.name, "renamed library");
+                assert_eq!(metadata.notes, library.notes);
+
+                let query = doc! {"owner": &user.username};
+                let count = app_data
+                    .libraries
+                    .count_documents(query, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_update_user_library_rename_collision() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "my notes".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+        let other = Library {
+            owner: user.username.to_owned(),
+            name: "other library".into(),
+            notes: "my notes".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[library.clone(), other.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let data = api::UpdateLibraryData {
+                    new_name: Some(other.name.clone()),
+                    notes: None,
+                };
+                let req = test::TestRequest::patch()
+                    .uri(&format!("/user/{}/{}", &user.username, &library.name))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_update_user_library_invalid_name() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let library = Library {
+            owner: user.username.to_owned(),
+            name: "my library".into(),
+            notes: "my notes".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[library.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let data = api::UpdateLibraryData {
+                    new_name: Some("<invalid>".into()),
+                    notes: None,
+                };
+                let req = test::TestRequest::patch()
+                    .uri(&format!("/user/{}/{}", &user.username, &library.name))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_approve_libraries_partial() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let moderator: User = api::NewUser {
+            username: "moderator".into(),
+            email: "moderator@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Moderator),
+        }
+        .into();
+        let pending = Library {
+            owner: user.username.to_owned(),
+            name: "pending library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::PendingApproval,
+            downloads: 0,
+        };
+        let approved = Library {
+            owner: user.username.to_owned(),
+            name: "approved library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Public,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone(), moderator.clone()])
+            .with_libraries(&[pending.clone(), approved.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let approvals = vec![
+                    api::LibraryApproval {
+                        owner: user.username.clone(),
+                        name: pending.name.clone(),
+                        state: api::PublishState::Public,
+                    },
+                    api::LibraryApproval {
+                        owner: user.username.clone(),
+                        name: approved.name.clone(),
+                        state: api::PublishState::Private,
+                    },
+                    api::LibraryApproval {
+                        owner: user.username.clone(),
+                        name: "missing library".into(),
+                        state: api::PublishState::Public,
+                    },
+                ];
+                let req = test::TestRequest::post()
+                    .uri("/mod/approve-bulk")
+                    .cookie(test_utils::cookie::new(&moderator.username))
+                    .set_json(&approvals)
+                    .to_request();
+
+                let results: Vec<api::LibraryApprovalResult> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(results.len(), 3);
+
+                let library = results[0].library.as_ref().unwrap();
+                assert_eq!(library.name, pending.name);
+                assert!(matches!(library.state, api::PublishState::Public));
+                assert!(results[0].error.is_none());
+
+                assert!(results[1].library.is_none());
+                assert_eq!(
+                    results[1].error,
+                    Some(UserError::LibraryNotPendingApprovalError.to_string())
+                );
+
+                assert!(results[2].library.is_none());
+                assert_eq!(
+                    results[2].error,
+                    Some(UserError::LibraryNotFoundError.to_string())
+                );
+
+                // the already approved library should be unchanged
+                let query = doc! {"owner": &user.username, "name": &approved.name};
+                let saved = app_data
+                    .libraries
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(matches!(saved.state, api::PublishState::Public));
+
+                // the missing library should not be created
+                let query = doc! {"owner": &user.username};
+                let count = app_data
+                    .libraries
+                    .count_documents(query, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 2);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_approve_libraries_403() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let pending = Library {
+            owner: user.username.to_owned(),
+            name: "pending library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::PendingApproval,
+            downloads: 0,
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_libraries(&[pending.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(super::config),
+                )
+                .await;
+
+                let approvals = vec![api::LibraryApproval {
+                    owner: user.username.clone(),
+                    name: pending.name.clone(),
+                    state: api::PublishState::Public,
+                }];
+                let req = test::TestRequest::post()
+                    .uri("/mod/approve-bulk")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&approvals)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
     // #[actix_web::test]
     //#[ignore]
     // async fn test_list_user_libraries_403() {
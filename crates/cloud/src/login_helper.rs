@@ -1,7 +1,9 @@
+use std::net::IpAddr;
 use std::sync::{Arc, RwLock};
 
 use actix::Addr;
-use actix_session::Session;
+use actix_session::SessionExt;
+use actix_web::{http::header, HttpRequest};
 use lru::LruCache;
 use mongodb::{
     bson::{doc, DateTime},
@@ -10,14 +12,15 @@ use mongodb::{
 };
 use netsblox_cloud_common::{
     api::{self, ClientId},
-    BannedAccount, ProjectMetadata,
+    BannedAccount, ProjectMetadata, User, UserSession,
 };
 
 use crate::{
     app_data::metrics,
     errors::{InternalError, UserError},
     network::topology::{self, TopologyActor},
-    utils,
+    rate_limit::RateLimiter,
+    two_factor, utils,
 };
 
 /// This is a helper file containing logic reused across multiple resources.
@@ -31,31 +34,77 @@ pub(crate) struct LoginHelper<'a> {
     project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
 
     banned_accounts: &'a Collection<BannedAccount>,
+    users: &'a Collection<User>,
+    sessions: &'a Collection<UserSession>,
+    two_factor_key: &'a two_factor::EncryptionKey,
+    two_factor_rate_limiter: &'a RateLimiter,
+    trusted_proxies: &'a [IpAddr],
+}
+
+/// A struct for passing data to the constructor of `LoginHelper` w/o having
+/// too many arguments
+pub(crate) struct LoginHelperData<'a> {
+    pub(crate) network: &'a Addr<TopologyActor>,
+    pub(crate) metrics: &'a metrics::Metrics,
+    pub(crate) project_metadata: &'a Collection<ProjectMetadata>,
+    pub(crate) project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
+
+    pub(crate) banned_accounts: &'a Collection<BannedAccount>,
+    pub(crate) users: &'a Collection<User>,
+    pub(crate) sessions: &'a Collection<UserSession>,
+    pub(crate) two_factor_key: &'a two_factor::EncryptionKey,
+    pub(crate) two_factor_rate_limiter: &'a RateLimiter,
+    pub(crate) trusted_proxies: &'a [IpAddr],
 }
 
 impl<'a> LoginHelper<'a> {
-    pub(crate) fn new(
-        network: &'a Addr<TopologyActor>,
-        metrics: &'a metrics::Metrics,
-        project_metadata: &'a Collection<ProjectMetadata>,
-        project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
-        banned_accounts: &'a Collection<BannedAccount>,
-    ) -> Self {
+    pub(crate) fn new(data: LoginHelperData<'a>) -> Self {
         Self {
-            network,
-            metrics,
-            project_metadata,
-            project_cache,
-            banned_accounts,
+            network: data.network,
+            metrics: data.metrics,
+            project_metadata: data.project_metadata,
+            project_cache: data.project_cache,
+            banned_accounts: data.banned_accounts,
+            users: data.users,
+            sessions: data.sessions,
+            two_factor_key: data.two_factor_key,
+            two_factor_rate_limiter: data.two_factor_rate_limiter,
+            trusted_proxies: data.trusted_proxies,
         }
     }
 
-    /// Login as the given user for the current session
+    /// Login as the given user for the current session. A two-factor auth code
+    /// is required if the user has enabled two-factor authentication. Accounts
+    /// scheduled for deletion can only log in if `cancel_deletion` is set (which
+    /// cancels the deletion).
     pub(crate) async fn login(
         &self,
-        session: Session,
+        req: &HttpRequest,
         user: &api::User,
         client_id: Option<ClientId>,
+        two_factor_code: Option<&str>,
+        cancel_deletion: bool,
+    ) -> Result<(), UserError> {
+        let result = self
+            .start_session(req, user, client_id, two_factor_code, cancel_deletion)
+            .await;
+
+        if result.is_ok() {
+            self.metrics.record_login();
+        } else {
+            self.metrics.record_failed_login();
+        }
+
+        result
+    }
+
+    async fn start_session(
+        &self,
+        req: &HttpRequest,
+        user: &api::User,
+        client_id: Option<ClientId>,
+        two_factor_code: Option<&str>,
+        cancel_deletion: bool,
     ) -> Result<(), UserError> {
         // TODO: make sure the user isn't banned
         let query = doc! {"$or": [
@@ -73,6 +122,47 @@ impl<'a> LoginHelper<'a> {
             return Err(UserError::BannedUserError);
         }
 
+        let query = doc! {"username": &user.username};
+        let account = self
+            .users
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        if account.delete_at.is_some() && !cancel_deletion {
+            return Err(UserError::AccountPendingDeletionError);
+        }
+
+        if let Some(two_factor) = account.two_factor.filter(|two_factor| two_factor.enabled) {
+            let code = two_factor_code.ok_or(UserError::TwoFactorCodeRequiredError)?;
+            // lock out two-factor login after too many failed codes
+            self.two_factor_rate_limiter
+                .ensure_available(&user.username)?;
+            two_factor::verify_code(
+                self.users,
+                self.two_factor_key,
+                &user.username,
+                &two_factor,
+                code,
+            )
+            .await
+            .inspect_err(|err| {
+                if matches!(err, UserError::InvalidTwoFactorCodeError) {
+                    self.two_factor_rate_limiter.record(&user.username);
+                }
+            })?;
+        }
+
+        if account.delete_at.is_some() {
+            let query = doc! {"username": &user.username};
+            let update = doc! {"$unset": {"deleteAt": true}};
+            self.users
+                .update_one(query, update, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?;
+        }
+
         // update ownership, if applicable
         if let Some(client_id) = client_id {
             self.update_ownership(&client_id, &user.username).await?;
@@ -81,9 +171,24 @@ impl<'a> LoginHelper<'a> {
                 username: Some(user.username.clone()),
             });
         }
-        self.metrics.record_login();
 
+        // record the session so it can be listed (and revoked) later
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+        let ip_address =
+            utils::get_client_ip(req, self.trusted_proxies).map(|addr| addr.to_string());
+        let user_session = UserSession::new(user.username.clone(), user_agent, ip_address);
+        self.sessions
+            .insert_one(&user_session, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let session = req.get_session();
         session.insert("username", &user.username).unwrap();
+        session.insert("sessionId", &user_session.id).unwrap();
 
         Ok(())
     }
@@ -110,6 +215,7 @@ impl<'a> LoginHelper<'a> {
                 utils::get_valid_project_name(self.project_metadata, username, &metadata.name)
                     .await?;
             let update = doc! {
+                "$inc": {"version": 1},
                 "$set": {
                     "owner": username,
                     "name": name,
@@ -5,7 +5,7 @@ use lettre::{
 };
 use mongodb::{bson::doc, options::ReturnDocument, Collection};
 use netsblox_cloud_common::api;
-use netsblox_cloud_common::{MagicLink, User};
+use netsblox_cloud_common::{MagicLink, SetPasswordToken, User};
 use nonempty::NonEmpty;
 
 use crate::errors::{InternalError, UserError};
@@ -15,6 +15,7 @@ use super::email_template;
 pub(crate) struct MagicLinkActions<'a> {
     links: &'a Collection<MagicLink>,
     users: &'a Collection<User>,
+    password_tokens: &'a Collection<SetPasswordToken>,
 
     // email support
     mailer: &'a SmtpTransport,
@@ -26,6 +27,7 @@ impl<'a> MagicLinkActions<'a> {
     pub(crate) fn new(
         links: &'a Collection<MagicLink>,
         users: &'a Collection<User>,
+        password_tokens: &'a Collection<SetPasswordToken>,
         mailer: &'a SmtpTransport,
         sender: &'a Mailbox,
         public_url: &'a String,
@@ -33,6 +35,7 @@ impl<'a> MagicLinkActions<'a> {
         Self {
             links,
             users,
+            password_tokens,
             mailer,
             sender,
             public_url,
@@ -58,7 +61,10 @@ impl<'a> MagicLinkActions<'a> {
         let usernames: NonEmpty<String> = utils::find_usernames(self.users, &data.email).await?;
 
         let query = doc! {"email": &data.email};
-        let link = MagicLink::new(data.email.clone());
+        let link = MagicLink {
+            intent: data.intent,
+            ..MagicLink::new(data.email.clone())
+        };
         let update = doc! {"$setOnInsert": &link};
         let options = mongodb::options::FindOneAndUpdateOptions::builder()
             .return_document(ReturnDocument::Before)
@@ -83,30 +89,87 @@ impl<'a> MagicLinkActions<'a> {
         }
     }
 
-    pub(crate) async fn login(
+    /// Use the magic link (as the given user). Links for resetting a password
+    /// do not log the user in; instead, they provide a URL for setting a new password.
+    /// If `allow_reset` is not set, links for resetting a password are rejected
+    /// (without being used).
+    pub(crate) async fn use_link(
         &self,
         username: &str,
         link_id: &api::MagicLinkId,
-    ) -> Result<api::User, UserError> {
-        let query = doc! {"id": &link_id};
+        allow_reset: bool,
+    ) -> Result<UsedLink, UserError> {
+        let mut query = doc! {"id": &link_id};
+        if !allow_reset {
+            query.insert("intent", doc! {"$ne": api::MagicLinkIntent::Reset});
+        }
         let link = self
             .links
             .find_one_and_delete(query, None)
             .await
-            .map_err(InternalError::DatabaseConnectionError)?
-            .ok_or(UserError::MagicLinkNotFoundError)?;
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let link = match link {
+            Some(link) => link,
+            None => {
+                let is_reset_link = !allow_reset
+                    && self
+                        .links
+                        .find_one(doc! {"id": &link_id}, None)
+                        .await
+                        .map_err(InternalError::DatabaseConnectionError)?
+                        .is_some();
+
+                return Err(if is_reset_link {
+                    UserError::PasswordResetLinkError
+                } else {
+                    UserError::MagicLinkNotFoundError
+                });
+            }
+        };
 
         let query = doc! {"username": username, "email": &link.email};
 
-        self.users
+        let user: api::User = self
+            .users
             .find_one(query, None)
             .await
             .map_err(InternalError::DatabaseConnectionError)?
-            .ok_or(UserError::UserNotFoundError)
-            .map(|user| user.into())
+            .ok_or(UserError::UserNotFoundError)?
+            .into();
+
+        match link.intent {
+            api::MagicLinkIntent::Login => Ok(UsedLink::Login(user)),
+            api::MagicLinkIntent::Reset => {
+                // Replace any existing token since the user just proved they own the address
+                let token = SetPasswordToken::new(user.username.clone());
+                let query = doc! {"username": &user.username};
+                let options = mongodb::options::ReplaceOptions::builder()
+                    .upsert(true)
+                    .build();
+                self.password_tokens
+                    .replace_one(query, &token, options)
+                    .await
+                    .map_err(InternalError::DatabaseConnectionError)?;
+
+                let url = format!(
+                    "{}/users/{}/password?token={}",
+                    self.public_url, user.username, token.secret
+                );
+                Ok(UsedLink::SetPassword(url))
+            }
+        }
     }
 }
 
+/// Result of using a magic link
+pub(crate) enum UsedLink {
+    /// The user can be logged in
+    Login(api::User),
+    /// The user must set a new password at the given URL (and is not logged in)
+    SetPassword(String),
+}
+
 struct MagicLinkEmail {
     sender: Mailbox,
     link: MagicLink,
@@ -121,6 +184,7 @@ impl MagicLinkEmail {
             &self.public_url,
             &self.usernames,
             &self.link.id,
+            self.link.intent,
             self.redirect_uri.clone(),
         )
     }
@@ -130,7 +194,7 @@ impl TryFrom<MagicLinkEmail> for lettre::Message {
     type Error = UserError;
 
     fn try_from(data: MagicLinkEmail) -> Result<Self, UserError> {
-        let subject = "Magic sign-in link for NetsBlox";
+        let subject = email_template::subject(data.link.intent);
         let body = data.render();
         let to_email = data.link.email;
         let message = Message::builder()
@@ -176,6 +240,7 @@ mod tests {
                 let data = api::CreateMagicLinkData {
                     email: user.email.clone(),
                     redirect_uri: None,
+                    intent: api::MagicLinkIntent::Login,
                 };
                 actions.try_create_link(&data).await.unwrap();
                 let query = doc! {"email": &user.email};
@@ -208,6 +273,7 @@ mod tests {
                 let data = api::CreateMagicLinkData {
                     email: user.email.clone(),
                     redirect_uri: None,
+                    intent: api::MagicLinkIntent::Login,
                 };
                 let res = actions.create_link(&data).await;
                 assert!(matches!(res, Err(UserError::MagicLinkSentError)))
@@ -224,6 +290,7 @@ mod tests {
                 let data = api::CreateMagicLinkData {
                     email: "IDon'tExist!".into(),
                     redirect_uri: None,
+                    intent: api::MagicLinkIntent::Login,
                 };
                 let res = actions.create_link(&data).await;
                 assert!(matches!(res, Err(UserError::UserNotFoundError)));
@@ -250,7 +317,13 @@ mod tests {
             .run(|app_data| async move {
                 let actions = app_data.as_magic_link_actions();
 
-                let data = actions.login(&user.username, &l1.id).await.unwrap();
+                let used = actions
+                    .use_link(&user.username, &l1.id, true)
+                    .await
+                    .unwrap();
+                let UsedLink::Login(data) = used else {
+                    panic!("Expected login link");
+                };
                 assert_eq!(data.username, user.username);
             })
             .await;
@@ -275,10 +348,10 @@ mod tests {
             .run(|app_data| async move {
                 let actions = app_data.as_magic_link_actions();
 
-                let res1 = actions.login(&user.username, &l1.id).await;
+                let res1 = actions.use_link(&user.username, &l1.id, true).await;
                 assert!(res1.is_ok());
 
-                let res2 = actions.login(&user.username, &l1.id).await;
+                let res2 = actions.use_link(&user.username, &l1.id, true).await;
                 assert!(res2.is_err(), "Should not allow more than one use.");
             })
             .await;
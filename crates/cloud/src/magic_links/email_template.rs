@@ -1,13 +1,27 @@
 use lettre::message::MultiPart;
-use netsblox_cloud_common::api::MagicLinkId;
+use netsblox_cloud_common::api::{MagicLinkId, MagicLinkIntent};
 use nonempty::NonEmpty;
 
+pub(crate) fn subject(intent: MagicLinkIntent) -> &'static str {
+    match intent {
+        MagicLinkIntent::Login => "Magic sign-in link for NetsBlox",
+        MagicLinkIntent::Reset => "Password reset link for NetsBlox",
+    }
+}
+
 pub(crate) fn magic_link_email(
     cloud_url: &str,
     usernames: &NonEmpty<String>,
     link_id: &MagicLinkId,
+    intent: MagicLinkIntent,
     redirect_uri: Option<String>,
 ) -> MultiPart {
+    let title = subject(intent);
+    let action = match intent {
+        MagicLinkIntent::Login => "\"auto-magically\" sign-in",
+        MagicLinkIntent::Reset => "set a new password",
+    };
+
     let uri_param = redirect_uri
         .map(|uri| format!("&redirectUri={}", uri))
         .unwrap_or_default();
@@ -25,9 +39,9 @@ pub(crate) fn magic_link_email(
     let (txt, html) = if usernames.len() == 1 {
         let url = make_url(usernames.first());
         let html = format!(
-            "<h1>Magic sign-in link for NetsBlox</h1>
+            "<h1>{title}</h1>
         <p>
-            Please click <a href=\"{url}\">here</a> to \"auto-magically\" sign-in to NetsBlox as {name}. Link can only be used once.
+            Please click <a href=\"{url}\">here</a> to {action} for NetsBlox as {name}. Link can only be used once.
             <br/>
             <br/>
 
@@ -38,9 +52,9 @@ pub(crate) fn magic_link_email(
         );
 
         let txt = format!(
-            "Magic sign-in link for NetsBlox
+            "{title}
 
-        Please click the link below to \"auto-magically\" sign-in to NetsBlox as {name}. Link can only be used once.
+        Please click the link below to {action} for NetsBlox as {name}. Link can only be used once.
 
         {url}
             
@@ -61,9 +75,9 @@ pub(crate) fn magic_link_email(
             )
         });
         let html = format!(
-            "<h1>Magic sign-in link for NetsBlox</h1>
+            "<h1>{title}</h1>
         <p>
-            Please select an account below to \"auto-magically\" sign-in. These links can only be used once (combined).
+            Please select an account below to {action}. These links can only be used once (combined).
             <br/>
             <br/>
 
@@ -84,9 +98,9 @@ pub(crate) fn magic_link_email(
             )
         });
         let txt = format!(
-            "Magic sign-in link for NetsBlox
+            "{title}
 
-        Please select an account below to \"auto-magically\" sign-in. These links can only be used once (combined).
+        Please select an account below to {action}. These links can only be used once (combined).
 
         {urlText}
             
@@ -1,6 +1,6 @@
 use crate::app_data::AppData;
 use crate::errors::UserError;
-use actix_session::Session;
+use crate::magic_links::actions::UsedLink;
 use actix_web::{get, post, HttpRequest};
 use actix_web::{web, HttpResponse};
 
@@ -10,10 +10,13 @@ use crate::common::api;
 async fn create_link(
     app: web::Data<AppData>,
     body: web::Json<api::CreateMagicLinkData>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
-    let actions = app.as_magic_link_actions();
-
     let data = body.into_inner();
+    let req_addr = app.get_client_ip(&req);
+    app.ensure_within_rate_limit(req_addr.as_ref(), &data.email)?;
+
+    let actions = app.as_magic_link_actions();
     actions.create_link(&data).await?;
 
     Ok(HttpResponse::Ok().finish())
@@ -23,41 +26,84 @@ async fn create_link(
 async fn login(
     app: web::Data<AppData>,
     req: HttpRequest,
-    session: Session,
     params: web::Query<api::MagicLinkLoginData>,
 ) -> Result<HttpResponse, UserError> {
-    let req_addr = req.peer_addr().map(|addr| addr.ip());
+    let data = params.into_inner();
+    let user = match use_link(&app, &req, &data, true).await? {
+        UsedLink::Login(user) => user,
+        UsedLink::SetPassword(url) => return Ok(redirect(&url)),
+    };
+
+    if let Some(url) = data.redirect_uri {
+        Ok(redirect(&url))
+    } else {
+        Ok(HttpResponse::Ok().body(format!("Logged in as {}", user.username)))
+    }
+}
+
+/// Login using a magic link from an API client (rather than a browser)
+#[post("/login")]
+async fn login_json(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+    body: web::Json<api::MagicLinkLoginData>,
+) -> Result<HttpResponse, UserError> {
+    let data = body.into_inner();
+    // Password reset links need to be opened in a browser to set the new password
+    match use_link(&app, &req, &data, false).await? {
+        UsedLink::Login(user) => Ok(HttpResponse::Ok().json(user)),
+        UsedLink::SetPassword(_url) => Err(UserError::PasswordResetLinkError),
+    }
+}
+
+/// Use the magic link, logging in unless the link is for resetting the password
+async fn use_link(
+    app: &AppData,
+    req: &HttpRequest,
+    data: &api::MagicLinkLoginData,
+    allow_reset: bool,
+) -> Result<UsedLink, UserError> {
+    let req_addr = app.get_client_ip(req);
     if let Some(addr) = req_addr {
         app.ensure_not_tor_ip(&addr).await?;
     }
 
-    let data = params.into_inner();
     let actions = app.as_magic_link_actions();
-    let user = actions.login(&data.username, &data.link_id).await?;
+    let used = actions
+        .use_link(&data.username, &data.link_id, allow_reset)
+        .await?;
 
-    let helper = app.as_login_helper();
-    helper.login(session, &user, data.client_id).await?;
-
-    if let Some(url) = data.redirect_uri {
-        Ok(HttpResponse::Found()
-            .insert_header(("Location", url.as_str()))
-            .finish())
-    } else {
-        Ok(HttpResponse::Ok().body(format!("Logged in as {}", user.username)))
+    if let UsedLink::Login(user) = &used {
+        // Magic links can't be used for accounts with two-factor auth enabled
+        let helper = app.as_login_helper();
+        helper
+            .login(req, user, data.client_id.clone(), None, false)
+            .await?;
     }
+
+    Ok(used)
+}
+
+fn redirect(url: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header(("Location", url))
+        .finish()
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(create_link).service(login);
+    cfg.service(create_link).service(login).service(login_json);
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, time::Duration};
+
     use actix_web::{http, test, App};
+    use mongodb::bson::doc;
     use netsblox_cloud_common::{MagicLink, User};
 
     use super::*;
-    use crate::test_utils;
+    use crate::{rate_limit::RateLimiter, test_utils};
 
     #[actix_web::test]
     async fn test_login() {
@@ -99,6 +145,165 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_login_json() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let l1 = MagicLink::new(user.email.clone());
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_magic_links(&[l1.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::MagicLinkLoginData {
+                    link_id: l1.id.clone(),
+                    username: user.username.clone(),
+                    client_id: None,
+                    redirect_uri: None,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/login")
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_some());
+
+                let logged_in: api::User = test::read_body_json(response).await;
+                assert_eq!(logged_in.username, user.username);
+
+                // links can only be used once
+                let req = test::TestRequest::post()
+                    .uri("/login")
+                    .set_json(&data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_login_reset_link() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let l1 = MagicLink {
+            intent: api::MagicLinkIntent::Reset,
+            ..MagicLink::new(user.email.clone())
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_magic_links(&[l1.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/login?linkId={}&username=user", &l1.id.as_str()))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FOUND);
+
+                // The user should not be logged in
+                let cookie = response.headers().get(http::header::SET_COOKIE);
+                assert!(cookie.is_none());
+
+                // ...but should be sent to the password form (with a token)
+                let token = app_data
+                    .password_tokens
+                    .find_one(doc! {"username": "user"}, None)
+                    .await
+                    .unwrap()
+                    .expect("Password token not created");
+                let location = response.headers().get(http::header::LOCATION).unwrap();
+                let expected = format!("/users/user/password?token={}", token.secret);
+                assert!(location.to_str().unwrap().ends_with(&expected));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_login_json_reset_link() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let l1 = MagicLink {
+            intent: api::MagicLinkIntent::Reset,
+            ..MagicLink::new(user.email.clone())
+        };
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_magic_links(&[l1.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::MagicLinkLoginData {
+                    link_id: l1.id.clone(),
+                    username: user.username.clone(),
+                    client_id: None,
+                    redirect_uri: None,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/login")
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+                let body: api::ErrorBody = test::read_body_json(response).await;
+                assert_eq!(body.code, "PasswordResetLinkError");
+
+                // the link can still be used to reset the password (in a browser)
+                let req = test::TestRequest::get()
+                    .uri(&format!("/login?linkId={}&username=user", &l1.id.as_str()))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FOUND);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_login_banned() {
         let user: User = api::NewUser {
@@ -197,4 +402,55 @@ mod tests {
             })
             .await;
     }
+
+    #[actix_web::test]
+    async fn test_create_link_429() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|mut app_data| async move {
+                app_data.email_rate_limiter =
+                    Arc::new(RateLimiter::new(1, Duration::from_secs(60)));
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::CreateMagicLinkData {
+                    email: user.email.clone(),
+                    redirect_uri: None,
+                    intent: api::MagicLinkIntent::Login,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/")
+                    .set_json(&data)
+                    .to_request();
+                test::call_service(&app, req).await;
+
+                // the email address is not case sensitive
+                let data = api::CreateMagicLinkData {
+                    email: user.email.to_uppercase(),
+                    redirect_uri: None,
+                    intent: api::MagicLinkIntent::Login,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/")
+                    .set_json(&data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+            })
+            .await;
+    }
 }
@@ -1,3 +1,4 @@
+mod admin;
 mod app_data;
 mod auth;
 mod collaboration_invites;
@@ -6,78 +7,86 @@ mod config;
 mod errors;
 mod friends;
 mod groups;
+mod health;
 mod libraries;
 mod login_helper;
 mod magic_links;
+mod metrics;
 mod network;
 mod oauth;
 mod projects;
+mod rate_limit;
+mod request_id;
 mod services;
+mod source_guard;
 #[cfg(test)]
 mod test_utils;
+mod two_factor;
 mod users;
 mod utils;
 
+use crate::app_data::{AppData, SESSION_TTL};
 use crate::common::api;
-use crate::config::Settings;
+use crate::config::{CorsSettings, Settings};
 use crate::errors::UserError;
-use crate::{app_data::AppData, errors::InternalError};
+use crate::services::hosts::actions::HostActions;
+use crate::source_guard::SourceGuard;
 use actix_cors::Cors;
 use actix_session::{
-    config::CookieContentSecurity, config::PersistentSession, storage::CookieSessionStore, Session,
+    config::CookieContentSecurity, config::PersistentSession, storage::CookieSessionStore,
     SessionMiddleware,
 };
 use actix_web::cookie::time::Duration;
 use actix_web::{
-    cookie::Key, cookie::SameSite, dev::Service, error::ErrorForbidden, get, http::Method,
-    middleware, web, App, HttpResponse, HttpServer,
+    cookie::Key, cookie::SameSite, dev::Service, get, middleware, web, App, HttpRequest,
+    HttpResponse, HttpServer,
 };
-use futures::TryStreamExt;
-use log::error;
-use mongodb::bson::doc;
+use log::{error, warn};
 use mongodb::Client;
-use tokio::sync::oneshot;
+use network::topology::TopologyFailure;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 #[get("/configuration")]
 async fn get_client_config(
     app: web::Data<AppData>,
-    session: Session,
+    req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
-    let query = doc! {"visibility": {"$ne": "private"}};
-    let default_hosts: Vec<api::ServiceHost> = app
-        .authorized_services
-        .find(query, None)
-        .await
-        .map_err(InternalError::DatabaseConnectionError)?
-        .try_collect::<Vec<_>>()
-        .await
-        .map_err(InternalError::DatabaseConnectionError)?
-        .into_iter()
-        .map(|host| host.into())
-        .collect();
+    let actions: HostActions = app.as_host_actions();
+    let default_hosts = actions.get_default_hosts().await?;
 
     let config = api::ClientConfig {
         client_id: format!("_netsblox{}", Uuid::new_v4()),
-        username: session.get::<String>("username").unwrap_or(None),
+        username: utils::get_username(&req).await,
         services_hosts: default_hosts,
         cloud_url: app.settings.public_url.to_owned(),
+        max_message_size: Some(app.settings.network.max_message_size),
     };
 
     Ok(HttpResponse::Ok().json(config))
 }
 
+/// Default actix log format with the request ID appended
+const LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{request_id}xi"#;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = Settings::new().unwrap();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let config = Settings::new().unwrap_or_else(|err| {
+        error!("Unable to load configuration: {}", err);
+        std::process::exit(1);
+    });
+    if let Err(err) = config.validate() {
+        error!("Invalid configuration: {}", err);
+        std::process::exit(1);
+    }
 
     let client = Client::with_uri_str(&config.database.url)
         .await
         .expect("Could not connect to mongodb.");
 
-    let (tx, rx) = oneshot::channel();
-    let app_data = AppData::new(client, Settings::new().unwrap(), None, None, Some(tx));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let app_data = AppData::new(client, config.clone(), None, None, Some(tx));
     app_data
         .initialize()
         .await
@@ -89,37 +98,36 @@ async fn main() -> std::io::Result<()> {
 
     let address = config.address.clone();
     let server = HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_header()
-            .allow_any_method()
-            .supports_credentials();
-
         let size_32_mb = 1 << 25;
+        let source_guard = SourceGuard::new(&config.security.restricted_sources);
         App::new()
-            .wrap(cors)
+            .wrap(cors_middleware(&config.cors))
             .wrap(app_data.metrics.handler())
             .wrap(session_middleware(&config))
-            .wrap(middleware::Logger::default())
-            .wrap_fn(|req, srv| {
-                let source = req
-                    .headers()
-                    .get("x-source")
-                    .and_then(|v| v.to_str().ok())
-                    .unwrap_or("unknown");
-                let allow = *req.method() == Method::GET || source != "NetsBlox";
-
-                let fut = if allow { Some(srv.call(req)) } else { None };
+            .wrap(
+                middleware::Logger::new(LOG_FORMAT)
+                    .custom_request_replace("request_id", request_id::get),
+            )
+            .wrap_fn(move |req, srv| {
+                let result = source_guard.check(&req).map(|_| srv.call(req));
                 async {
-                    match fut {
-                        Some(x) => x.await,
-                        None => Err(ErrorForbidden("Operation is not allowed")),
+                    match result {
+                        Ok(fut) => fut.await,
+                        Err(err) => Err(err),
                     }
                 }
             })
+            .wrap_fn(|req, srv| {
+                let id = request_id::assign(&req);
+                let fut = srv.call(req);
+                async move { fut.await.map(|res| request_id::add_to_response(res, &id)) }
+            })
             .app_data(web::PayloadConfig::new(size_32_mb))
             .app_data(web::JsonConfig::default().limit(size_32_mb))
             .app_data(web::Data::new(app_data.clone()))
+            .configure(health::routes::config)
+            .configure(metrics::routes::config)
+            .service(web::scope("/admin").configure(admin::routes::config))
             .service(web::scope("/libraries").configure(libraries::routes::config))
             .service(web::scope("/users").configure(users::routes::config))
             .service(web::scope("/projects").configure(projects::routes::config))
@@ -139,10 +147,16 @@ async fn main() -> std::io::Result<()> {
     .bind(&address)?
     .run();
 
-    // If the network topology is dropped, the server should be stopped as it is unusable
+    // If the network topology fails and cannot be restarted (or is dropped), the server
+    // should be stopped as it is unusable
     let handle = server.handle();
     tokio::spawn(async move {
-        let _ = rx.await;
+        while let Some(failure) = rx.recv().await {
+            match failure {
+                TopologyFailure::Restarted => warn!("Network topology was restarted."),
+                TopologyFailure::Fatal => break,
+            }
+        }
         handle.stop(false).await;
     });
 
@@ -151,7 +165,7 @@ async fn main() -> std::io::Result<()> {
 
 fn session_middleware(config: &Settings) -> SessionMiddleware<CookieSessionStore> {
     let secret_key = Key::from(config.cookie.key.as_bytes());
-    let secs_in_week: i64 = 60 * 60 * 24 * 7;
+    let session_ttl = Duration::seconds(SESSION_TTL.as_secs() as i64);
 
     let mut builder = SessionMiddleware::builder(CookieSessionStore::default(), secret_key)
         .cookie_name(config.cookie.name.clone())
@@ -160,9 +174,7 @@ fn session_middleware(config: &Settings) -> SessionMiddleware<CookieSessionStore
         .cookie_http_only(true)
         .cookie_domain(Some(config.cookie.domain.clone()))
         .cookie_content_security(CookieContentSecurity::Private)
-        .session_lifecycle(
-            PersistentSession::default().session_ttl(Duration::seconds(secs_in_week)),
-        );
+        .session_lifecycle(PersistentSession::default().session_ttl(session_ttl));
 
     let domain = config.cookie.domain.clone();
     if domain.starts_with("localhost") {
@@ -171,3 +183,87 @@ fn session_middleware(config: &Settings) -> SessionMiddleware<CookieSessionStore
 
     builder.build()
 }
+
+fn cors_middleware(settings: &CorsSettings) -> Cors {
+    let cors = Cors::default()
+        .allow_any_header()
+        .allow_any_method()
+        .supports_credentials();
+
+    if settings.dev {
+        cors.allow_any_origin()
+    } else {
+        let settings = settings.clone();
+        cors.allowed_origin_fn(move |origin, _req| {
+            origin
+                .to_str()
+                .map(|origin| settings.is_allowed(origin))
+                .unwrap_or(false)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header, test};
+
+    /// Get the allowed origin (if any) for a request from the given origin
+    async fn allowed_origin(settings: &CorsSettings, origin: &str) -> Option<String> {
+        let app = test::init_service(
+            App::new()
+                .wrap(cors_middleware(settings))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, origin))
+            .to_request();
+
+        match test::try_call_service(&app, req).await {
+            Ok(response) => response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .map(|value| value.to_str().unwrap().to_owned()),
+            Err(_err) => None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cors_allowed_origin() {
+        let settings = CorsSettings {
+            dev: false,
+            allowed_origins: vec!["https://*.netsblox.org".into()],
+        };
+
+        let allowed = allowed_origin(&settings, "https://editor.netsblox.org").await;
+
+        assert_eq!(allowed.as_deref(), Some("https://editor.netsblox.org"));
+    }
+
+    #[actix_web::test]
+    async fn test_cors_disallowed_origin() {
+        let settings = CorsSettings {
+            dev: false,
+            allowed_origins: vec!["https://*.netsblox.org".into()],
+        };
+
+        let allowed = allowed_origin(&settings, "https://evil.com").await;
+
+        assert!(allowed.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_cors_dev() {
+        let settings = CorsSettings {
+            dev: true,
+            allowed_origins: Vec::new(),
+        };
+
+        let allowed = allowed_origin(&settings, "https://example.com").await;
+
+        assert_eq!(allowed.as_deref(), Some("https://example.com"));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::app_data::AppData;
+use crate::auth;
+use crate::errors::UserError;
+use actix_web::{get, HttpRequest};
+use actix_web::{web, HttpResponse};
+
+#[get("/metrics")]
+async fn get_metrics(app: web::Data<AppData>, req: HttpRequest) -> Result<HttpResponse, UserError> {
+    auth::try_view_metrics(&app, &req)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(app.metrics.render()))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_metrics);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::api;
+    use crate::test_utils;
+    use actix_web::{body::MessageBody, http, test, App};
+    use netsblox_cloud_common::User;
+
+    fn get_value(metrics: &str, name: &str) -> Option<i64> {
+        metrics
+            .lines()
+            .filter_map(|line| line.strip_prefix(name))
+            .find_map(|value| value.trim().parse().ok())
+    }
+
+    #[actix_web::test]
+    async fn test_projects_created() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(web::scope("/projects").configure(crate::projects::routes::config))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/metrics").to_request();
+                let body = test::call_and_read_body(&app, req).await;
+                let metrics = std::str::from_utf8(&body).unwrap();
+                assert_eq!(get_value(metrics, "netsblox_projects_created"), Some(0));
+
+                let data = api::CreateProjectData {
+                    owner: None,
+                    name: "project".into(),
+                    roles: None,
+                    client_id: None,
+                    save_state: None,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/projects/")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get().uri("/metrics").to_request();
+                let body = test::call_and_read_body(&app, req).await;
+                let metrics = std::str::from_utf8(&body).unwrap();
+                assert_eq!(get_value(metrics, "netsblox_projects_created"), Some(1));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_logins_total() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: Some("password".into()),
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(web::scope("/users").configure(crate::users::routes::config))
+                        .configure(config),
+                )
+                .await;
+
+                let login = |password: &str| {
+                    let data = api::LoginRequest {
+                        credentials: api::Credentials::NetsBlox {
+                            username: user.username.clone(),
+                            password: password.into(),
+                        },
+                        client_id: None,
+                        two_factor_code: None,
+                        cancel_deletion: false,
+                    };
+                    test::TestRequest::post()
+                        .uri("/users/login")
+                        .set_json(&data)
+                        .to_request()
+                };
+                let response = test::call_service(&app, login("wrongPassword")).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+                let response = test::call_service(&app, login("password")).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get().uri("/metrics").to_request();
+                let body = test::call_and_read_body(&app, req).await;
+                let metrics = std::str::from_utf8(&body).unwrap();
+                let lines: Vec<_> = metrics.lines().collect();
+                assert!(lines.contains(&"# TYPE netsblox_logins_total counter"));
+                assert!(lines.contains(&"netsblox_logins_total{result=\"failure\"} 1"));
+                assert!(lines.contains(&"netsblox_logins_total{result=\"success\"} 1"));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_token() {
+        test_utils::setup()
+            .run(|app_data| async move {
+                let mut app_data = app_data;
+                app_data.settings.security.metrics_token = Some("secret".into());
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/metrics").to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+                let req = test::TestRequest::get()
+                    .uri("/metrics")
+                    .insert_header((http::header::AUTHORIZATION, "Bearer wrong"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let req = test::TestRequest::get()
+                    .uri("/metrics")
+                    .insert_header((http::header::AUTHORIZATION, "Bearer secret"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                let body = response.into_body().try_into_bytes().unwrap();
+                assert!(std::str::from_utf8(&body)
+                    .unwrap()
+                    .contains("netsblox_clients"));
+            })
+            .await;
+    }
+}
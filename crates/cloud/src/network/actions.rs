@@ -5,12 +5,12 @@ use futures::TryStreamExt;
 use lru::LruCache;
 use mongodb::{
     bson::{doc, DateTime},
-    options::{FindOneAndUpdateOptions, ReturnDocument},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
     Collection,
 };
 use netsblox_cloud_common::{
     api::{self, SaveState},
-    LogMessage, NetworkTraceMetadata, OccupantInvite, ProjectMetadata, SentMessage,
+    LogMessage, NetworkTraceMetadata, OccupantInvite, ProjectMetadata, QueuedMessage, SentMessage,
 };
 
 use crate::{
@@ -21,32 +21,49 @@ use crate::{
 
 use super::topology::{self, TopologyActor};
 
+/// Max number of messages returned when fetching a network trace
+const MAX_TRACE_MESSAGES: i64 = 1000;
+
 pub(crate) struct NetworkActions<'a> {
     project_metadata: &'a Collection<ProjectMetadata>,
     occupant_invites: &'a Collection<OccupantInvite>,
     project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
     recorded_messages: &'a Collection<SentMessage>,
     logged_messages: &'a Collection<LogMessage>,
+    queued_messages: &'a Collection<QueuedMessage>,
     network: &'a Addr<TopologyActor>,
+    max_active_traces: usize,
+    max_room_occupants: usize,
+}
+
+/// A struct for passing data to the constructor of `NetworkActions` w/o having
+/// too many arguments
+pub(crate) struct NetworkActionData<'a> {
+    pub(crate) project_metadata: &'a Collection<ProjectMetadata>,
+    pub(crate) project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
+    pub(crate) network: &'a Addr<TopologyActor>,
+
+    pub(crate) occupant_invites: &'a Collection<OccupantInvite>,
+    pub(crate) recorded_messages: &'a Collection<SentMessage>,
+    pub(crate) logged_messages: &'a Collection<LogMessage>,
+    pub(crate) queued_messages: &'a Collection<QueuedMessage>,
+
+    pub(crate) max_active_traces: usize,
+    pub(crate) max_room_occupants: usize,
 }
 
 impl<'a> NetworkActions<'a> {
-    pub(crate) fn new(
-        project_metadata: &'a Collection<ProjectMetadata>,
-        project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
-        network: &'a Addr<TopologyActor>,
-
-        occupant_invites: &'a Collection<OccupantInvite>,
-        recorded_messages: &'a Collection<SentMessage>,
-        logged_messages: &'a Collection<LogMessage>,
-    ) -> Self {
+    pub(crate) fn new(data: NetworkActionData<'a>) -> Self {
         Self {
-            project_metadata,
-            occupant_invites,
-            project_cache,
-            recorded_messages,
-            logged_messages,
-            network,
+            project_metadata: data.project_metadata,
+            occupant_invites: data.occupant_invites,
+            project_cache: data.project_cache,
+            recorded_messages: data.recorded_messages,
+            logged_messages: data.logged_messages,
+            queued_messages: data.queued_messages,
+            network: data.network,
+            max_active_traces: data.max_active_traces,
+            max_room_occupants: data.max_room_occupants,
         }
     }
 
@@ -64,6 +81,33 @@ impl<'a> NetworkActions<'a> {
         Ok(state)
     }
 
+    /// Join the room of the given project unless it is full. Clients already
+    /// in the room (eg, switching roles) are always allowed.
+    pub(crate) async fn join_room(
+        &self,
+        _vp: &auth::ViewProject,
+        client_id: api::ClientId,
+        state: api::BrowserClientState,
+        username: Option<String>,
+    ) -> Result<(), UserError> {
+        let task = self
+            .network
+            .send(topology::JoinRoom {
+                id: client_id,
+                state,
+                username,
+                max_occupants: self.max_room_occupants,
+            })
+            .await
+            .map_err(InternalError::ActixMessageError)?;
+
+        if task.run().await {
+            Ok(())
+        } else {
+            Err(UserError::RoomFullError)
+        }
+    }
+
     /// Update a project to ensure it isn't garbage collected due to inactivity
     pub(crate) async fn activate_room(&self, vp: &auth::ViewProject) -> Result<(), UserError> {
         let query = doc! {
@@ -71,6 +115,7 @@ impl<'a> NetworkActions<'a> {
             "saveState": SaveState::Created
         };
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "saveState": SaveState::Transient,
                 "updated": DateTime::now(),
@@ -98,14 +143,35 @@ impl<'a> NetworkActions<'a> {
 
     pub(crate) async fn start_network_trace(
         &self,
-        vp: &auth::ViewProject,
+        ep: &auth::EditProject,
     ) -> Result<api::NetworkTraceMetadata, UserError> {
-        let query = doc! {"id": &vp.metadata.id};
+        let active_count = ep
+            .metadata
+            .network_traces
+            .iter()
+            .filter(|trace| trace.end_time.is_none())
+            .count();
+        if active_count >= self.max_active_traces {
+            return Err(UserError::TooManyNetworkTracesError);
+        }
+
+        // check the active traces in the query, too, in case the cached metadata is stale
+        let active_traces = doc! {
+            "$filter": {
+                "input": "$networkTraces",
+                "cond": {"$not": ["$$this.endTime"]}
+            }
+        };
+        let query = doc! {
+            "id": &ep.metadata.id,
+            "$expr": {"$lt": [{"$size": active_traces}, self.max_active_traces as i64]}
+        };
         let new_trace = NetworkTraceMetadata::new();
         let update = doc! {
             "$push": {
                 "networkTraces": &new_trace
             },
+            "$inc": {"version": 1},
             "$set": {
                 "updated": DateTime::now(),
             }
@@ -119,7 +185,7 @@ impl<'a> NetworkActions<'a> {
             .find_one_and_update(query, update, options)
             .await
             .map_err(InternalError::DatabaseConnectionError)?
-            .ok_or(UserError::ProjectNotFoundError)?;
+            .ok_or(UserError::TooManyNetworkTracesError)?;
 
         utils::update_project_cache(self.project_cache, metadata);
 
@@ -128,10 +194,10 @@ impl<'a> NetworkActions<'a> {
 
     pub(crate) async fn stop_network_trace(
         &self,
-        vp: &auth::ViewProject,
+        ep: &auth::EditProject,
         trace_id: &str,
     ) -> Result<api::NetworkTraceMetadata, UserError> {
-        let trace = vp
+        let trace = ep
             .metadata
             .network_traces
             .iter()
@@ -139,11 +205,12 @@ impl<'a> NetworkActions<'a> {
             .ok_or(UserError::NetworkTraceNotFoundError)?;
 
         let query = doc! {
-            "id": &vp.metadata.id,
+            "id": &ep.metadata.id,
             "networkTraces.id": &trace.id
         };
         let end_time = DateTime::now();
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "networkTraces.$.endTime": end_time,
                 "updated": DateTime::now(),
@@ -190,30 +257,50 @@ impl<'a> NetworkActions<'a> {
 
     pub(crate) async fn get_network_trace(
         &self,
-        vp: &auth::ViewProject,
+        ep: &auth::EditProject,
         trace_id: &str,
-    ) -> Result<Vec<api::SentMessage>, UserError> {
-        let trace = vp
+        filter: &api::TraceMessagesQuery,
+    ) -> Result<api::TraceMessages, UserError> {
+        let trace = ep
             .metadata
             .network_traces
             .iter()
             .find(|trace| trace.id == trace_id)
             .ok_or(UserError::NetworkTraceNotFoundError)?;
 
-        let start_time = trace.start_time;
+        // in-progress traces include everything up to now
         let end_time = trace.end_time.unwrap_or_else(DateTime::now);
+        let mut time = doc! {"$gt": trace.start_time, "$lt": end_time};
+        if let Some(after) = filter.after {
+            let after = DateTime::from_millis(after).max(trace.start_time);
+            time.insert("$gt", after);
+        }
+        if let Some(before) = filter.before {
+            let before = DateTime::from_millis(before).min(end_time);
+            time.insert("$lt", before);
+        }
 
         let query = doc! {
-            "projectId": &vp.metadata.id,
-            "time": {"$gt": start_time, "$lt": end_time}
+            "projectId": &ep.metadata.id,
+            "time": time,
         };
+        let limit = filter
+            .limit
+            .map(|limit| limit.clamp(1, MAX_TRACE_MESSAGES))
+            .unwrap_or(MAX_TRACE_MESSAGES);
+        // fetch an extra message to detect if the results are truncated
+        let options = FindOptions::builder()
+            .sort(doc! {"time": 1})
+            .skip(filter.skip)
+            .limit(limit + 1)
+            .build();
         let cursor = self
             .recorded_messages
-            .find(query, None)
+            .find(query, options)
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
-        let messages: Vec<api::SentMessage> = cursor
+        let mut messages: Vec<api::SentMessage> = cursor
             .try_collect::<Vec<_>>()
             .await
             .map_err(InternalError::DatabaseConnectionError)?
@@ -221,26 +308,33 @@ impl<'a> NetworkActions<'a> {
             .map(|msg| msg.into())
             .collect();
 
-        Ok(messages)
+        let truncated = messages.len() as i64 > limit;
+        messages.truncate(limit as usize);
+
+        Ok(api::TraceMessages {
+            messages,
+            truncated,
+        })
     }
 
     pub(crate) async fn delete_network_trace(
         &self,
-        vp: &auth::ViewProject,
+        ep: &auth::EditProject,
         trace_id: &str,
     ) -> Result<api::ProjectMetadata, UserError> {
-        let trace = vp
+        let trace = ep
             .metadata
             .network_traces
             .iter()
             .find(|trace| trace.id == trace_id)
             .ok_or(UserError::NetworkTraceNotFoundError)?;
 
-        let query = doc! {"id": &vp.metadata.id};
+        let query = doc! {"id": &ep.metadata.id};
         let update = doc! {
             "$pull": {
                 "networkTraces": &trace,
             },
+            "$inc": {"version": 1},
             "$set": {
                 "updated": DateTime::now(),
             }
@@ -264,7 +358,7 @@ impl<'a> NetworkActions<'a> {
             .unwrap_or(DateTime::MAX);
 
         let query = doc! {
-            "projectId": &vp.metadata.id,
+            "projectId": &ep.metadata.id,
             "time": {"$lt": earliest_start_time}
         };
 
@@ -347,6 +441,49 @@ impl<'a> NetworkActions<'a> {
 
         Ok(room_state)
     }
+
+    /// Evict all clients logged in as the given user. Returns the IDs of the
+    /// evicted clients.
+    pub(crate) async fn evict_user(
+        &self,
+        eu: &auth::EditUser,
+    ) -> Result<Vec<api::ClientId>, UserError> {
+        let task = self
+            .network
+            .send(topology::GetUserClients(eu.username.clone()))
+            .await
+            .map_err(InternalError::ActixMessageError)?;
+        let client_ids = task.run().await;
+
+        self.evict_clients(&client_ids);
+        Ok(client_ids)
+    }
+
+    /// Evict all occupants of the given room. Returns the IDs of the evicted
+    /// clients.
+    pub(crate) async fn evict_room(
+        &self,
+        ep: &auth::EditProject,
+    ) -> Result<Vec<api::ClientId>, UserError> {
+        let task = self
+            .network
+            .send(topology::GetRoomClients(ep.metadata.id.clone()))
+            .await
+            .map_err(InternalError::ActixMessageError)?;
+        let client_ids = task.run().await;
+
+        self.evict_clients(&client_ids);
+        Ok(client_ids)
+    }
+
+    fn evict_clients(&self, client_ids: &[api::ClientId]) {
+        for client_id in client_ids {
+            self.network.do_send(topology::EvictOccupant {
+                client_id: client_id.clone(),
+            });
+        }
+    }
+
     pub(crate) async fn list_rooms(
         &self,
         _lr: &auth::ListActiveRooms,
@@ -361,19 +498,95 @@ impl<'a> NetworkActions<'a> {
         Ok(rooms)
     }
 
+    /// List the active rooms with the number of occupants in each. Rooms for
+    /// projects which no longer exist are omitted.
+    pub(crate) async fn list_room_summaries(
+        &self,
+        _lr: &auth::ListActiveRooms,
+    ) -> Result<Vec<api::RoomSummary>, UserError> {
+        let task = self
+            .network
+            .send(topology::GetRoomOccupantCounts {})
+            .await
+            .map_err(InternalError::ActixMessageError)?;
+        let counts = task.run().await;
+
+        let ids: Vec<_> = counts.keys().map(|id| id.to_owned()).collect();
+        let query = doc! {"id": {"$in": ids}};
+        let summaries = self
+            .project_metadata
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|metadata| api::RoomSummary {
+                occupant_count: counts.get(&metadata.id).copied().unwrap_or_default(),
+                project_id: metadata.id,
+                owner: metadata.owner,
+                name: metadata.name,
+            })
+            .collect();
+
+        Ok(summaries)
+    }
+
     pub(crate) async fn list_external_clients(
         &self,
         _lc: &auth::ListClients,
+        app_id: Option<&api::AppId>,
     ) -> Result<Vec<api::ExternalClient>, UserError> {
         let task = self
             .network
             .send(topology::GetExternalClients {})
             .await
             .map_err(InternalError::ActixMessageError)?;
-        let clients = task.run().await;
+        let mut clients = task.run().await;
+        if let Some(app_id) = app_id {
+            clients.retain(|client| &client.app_id == app_id);
+        }
+
         Ok(clients)
     }
 
+    /// List the messages waiting for offline external clients (oldest first)
+    pub(crate) async fn list_queued_messages(
+        &self,
+        _mq: &auth::ManageMessageQueue,
+    ) -> Result<Vec<api::QueuedMessage>, UserError> {
+        let query = doc! {"expiresAt": {"$gt": DateTime::now()}};
+        let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+        let messages = self
+            .queued_messages
+            .find(query, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|msg| msg.into())
+            .collect();
+
+        Ok(messages)
+    }
+
+    /// Drop all messages waiting for offline external clients
+    pub(crate) async fn clear_queued_messages(
+        &self,
+        _mq: &auth::ManageMessageQueue,
+    ) -> Result<u64, UserError> {
+        let result = self
+            .queued_messages
+            .delete_many(doc! {}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(result.deleted_count)
+    }
+
     pub(crate) fn send_message(&self, sm: &auth::SendMessage) {
         self.network.do_send(topology::SendMessageFromServices {
             message: sm.msg.clone(),
@@ -5,13 +5,15 @@ use crate::common::{api, api::ExternalClientState};
 use crate::errors::{InternalError, UserError};
 use crate::network::actions::NetworkActions;
 use crate::{auth, utils};
-use actix::{Actor, Addr, AsyncContext, Handler, StreamHandler};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
 use actix_web::{delete, get, post};
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws::{self, CloseCode};
 use mongodb::bson::doc;
+use netsblox_cloud_common::ProjectMetadata;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use uuid::Uuid;
 
 #[post("/{client}/state")] // TODO: add token here (in a header), too?
 async fn set_client_state(
@@ -21,16 +23,14 @@ async fn set_client_state(
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     // TODO: should we allow users to set the client state for some other user?
-    let username = utils::get_username(&req);
+    let username = utils::get_username(&req).await;
     let (client_id,) = path.into_inner();
     if !client_id.as_str().starts_with('_') {
         // TODO: move this to the struct parsing
         return Err(UserError::InvalidClientIdError);
     }
 
-    let mut response = None;
-
-    let state = match body.into_inner().state {
+    match body.into_inner().state {
         ClientState::External(client_state) => {
             // append the user ID to the address
             let client_id_string = client_id.as_str().to_string();
@@ -41,27 +41,33 @@ async fn set_client_state(
                 return Err(UserError::InvalidAppIdError);
             }
 
-            response = Some(address.clone());
-            ClientState::External(ExternalClientState { address, app_id })
+            let state = ClientState::External(ExternalClientState {
+                address: address.clone(),
+                app_id,
+            });
+            app.network.do_send(topology::SetClientState {
+                id: client_id,
+                state,
+                username,
+            });
+
+            Ok(HttpResponse::Ok().body(address))
         }
         ClientState::Browser(client_state) => {
             let auth_vp =
                 auth::try_view_project(&app, &req, Some(&client_id), &client_state.project_id)
                     .await?;
 
+            // the room capacity is checked when the client is added to the room
             let actions = app.as_network_actions();
             actions.activate_room(&auth_vp).await?;
-            ClientState::Browser(client_state)
-        }
-    };
-
-    app.network.do_send(topology::SetClientState {
-        id: client_id,
-        state,
-        username,
-    });
+            actions
+                .join_room(&auth_vp, client_id, client_state, username)
+                .await?;
 
-    Ok(HttpResponse::Ok().body(response.unwrap_or_default()))
+            Ok(HttpResponse::Ok().finish())
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -95,9 +101,13 @@ async fn connect_client(
     let handler = WsSession {
         client_id,
         topology_addr: app.network.clone(),
+        max_message_size: app.settings.network.max_message_size,
     };
 
-    ws::start(handler, &req, stream).map_err(|_err| UserError::InternalError)
+    ws::WsResponseBuilder::new(handler, &req, stream)
+        .frame_size(app.settings.network.max_message_size)
+        .start()
+        .map_err(|_err| UserError::InternalError)
 }
 
 #[get("/id/{projectID}")]
@@ -115,6 +125,27 @@ async fn get_room_state(
     Ok(HttpResponse::Ok().json(state))
 }
 
+#[get("/id/{projectID}/subscribe")]
+async fn subscribe_room_state(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId,)>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+
+    let handler = RoomStateSession {
+        id: Uuid::new_v4(),
+        project: auth_vp.metadata,
+        topology_addr: app.network.clone(),
+    };
+
+    ws::WsResponseBuilder::new(handler, &req, stream)
+        .start()
+        .map_err(|_err| UserError::InternalError)
+}
+
 #[get("/")]
 async fn get_rooms(app: web::Data<AppData>, req: HttpRequest) -> Result<HttpResponse, UserError> {
     let auth_lr = auth::try_list_rooms(&app, &req).await?;
@@ -125,15 +156,31 @@ async fn get_rooms(app: web::Data<AppData>, req: HttpRequest) -> Result<HttpResp
     Ok(HttpResponse::Ok().json(rooms))
 }
 
+#[get("/summary")]
+async fn get_room_summaries(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_lr = auth::try_list_rooms(&app, &req).await?;
+
+    let actions: NetworkActions = app.as_network_actions();
+    let summaries = actions.list_room_summaries(&auth_lr).await?;
+
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
 #[get("/external")]
 async fn get_external_clients(
     app: web::Data<AppData>,
+    params: web::Query<api::ExternalClientsQuery>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let auth_lc = auth::try_list_clients(&app, &req).await?;
 
     let actions: NetworkActions = app.as_network_actions();
-    let clients = actions.list_external_clients(&auth_lc).await?;
+    let clients = actions
+        .list_external_clients(&auth_lc, params.app_id.as_ref())
+        .await?;
 
     Ok(HttpResponse::Ok().json(clients))
 }
@@ -147,11 +194,12 @@ async fn invite_occupant(
 ) -> Result<HttpResponse, UserError> {
     let (project_id,) = path.into_inner();
     let data = body.into_inner();
-    let sender = data
-        .sender
-        .clone()
-        .or_else(|| utils::get_username(&req))
-        .ok_or(UserError::LoginRequiredError)?;
+    let sender = match data.sender.clone() {
+        Some(sender) => sender,
+        None => utils::get_username(&req)
+            .await
+            .ok_or(UserError::LoginRequiredError)?,
+    };
 
     let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
     let auth_link = auth::try_invite_link(&app, &req, &sender, &data.username).await?;
@@ -180,22 +228,54 @@ async fn evict_occupant(
     Ok(HttpResponse::Ok().json(room_state))
 }
 
+#[post("/users/{username}/evict")]
+async fn evict_user(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let actions: NetworkActions = app.as_network_actions();
+    let client_ids = actions.evict_user(&auth_eu).await?;
+
+    Ok(HttpResponse::Ok().json(client_ids))
+}
+
+#[post("/id/{project_id}/evict")]
+async fn evict_room(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+    path: web::Path<(ProjectId,)>,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: NetworkActions = app.as_network_actions();
+    let client_ids = actions.evict_room(&auth_ep).await?;
+
+    Ok(HttpResponse::Ok().json(client_ids))
+}
+
 #[post("/id/{project_id}/trace/")]
 async fn start_network_trace(
     app: web::Data<AppData>,
     req: HttpRequest,
     path: web::Path<(ProjectId,)>,
 ) -> Result<HttpResponse, UserError> {
-    // TODO: do we need the client ID? Require login?
+    // TODO: do we need the client ID?
     let (project_id,) = path.into_inner();
-    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
 
     let actions: NetworkActions = app.as_network_actions();
-    let new_trace = actions.start_network_trace(&auth_vp).await?;
+    let new_trace = actions.start_network_trace(&auth_ep).await?;
 
     Ok(HttpResponse::Ok().json(new_trace))
 }
 
+/// Stop recording a network trace. `DELETE /id/{project_id}/trace/{trace_id}`
+/// already deletes the trace (and its messages) so stopping is a separate action.
 #[post("/id/{project_id}/trace/{trace_id}/stop")]
 async fn stop_network_trace(
     app: web::Data<AppData>,
@@ -203,10 +283,10 @@ async fn stop_network_trace(
     path: web::Path<(ProjectId, String)>,
 ) -> Result<HttpResponse, UserError> {
     let (project_id, trace_id) = path.into_inner();
-    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
 
     let actions: NetworkActions = app.as_network_actions();
-    let trace = actions.stop_network_trace(&auth_vp, &trace_id).await?;
+    let trace = actions.stop_network_trace(&auth_ep, &trace_id).await?;
 
     Ok(HttpResponse::Ok().json(trace))
 }
@@ -231,12 +311,16 @@ async fn get_network_trace(
     app: web::Data<AppData>,
     req: HttpRequest,
     path: web::Path<(ProjectId, String)>,
+    params: web::Query<api::TraceMessagesQuery>,
 ) -> Result<HttpResponse, UserError> {
     let (project_id, trace_id) = path.into_inner();
-    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+    // messages may contain private data so only those who can edit the project can view them
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
 
     let actions: NetworkActions = app.as_network_actions();
-    let messages = actions.get_network_trace(&auth_vp, &trace_id).await?;
+    let messages = actions
+        .get_network_trace(&auth_ep, &trace_id, &params)
+        .await?;
 
     Ok(HttpResponse::Ok().json(messages))
 }
@@ -248,10 +332,10 @@ async fn delete_network_trace(
     path: web::Path<(ProjectId, String)>,
 ) -> Result<HttpResponse, UserError> {
     let (project_id, trace_id) = path.into_inner();
-    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
 
     let actions: NetworkActions = app.as_network_actions();
-    let metadata = actions.delete_network_trace(&auth_vp, &trace_id).await?;
+    let metadata = actions.delete_network_trace(&auth_ep, &trace_id).await?;
 
     Ok(HttpResponse::Ok().json(metadata))
 }
@@ -272,6 +356,32 @@ async fn send_message(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[get("/messages/queued")]
+async fn list_queued_messages(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_mq = auth::try_manage_message_queue(&app, &req).await?;
+
+    let actions: NetworkActions = app.as_network_actions();
+    let messages = actions.list_queued_messages(&auth_mq).await?;
+
+    Ok(HttpResponse::Ok().json(messages))
+}
+
+#[delete("/messages/queued")]
+async fn clear_queued_messages(
+    app: web::Data<AppData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_mq = auth::try_manage_message_queue(&app, &req).await?;
+
+    let actions: NetworkActions = app.as_network_actions();
+    let count = actions.clear_queued_messages(&auth_mq).await?;
+
+    Ok(HttpResponse::Ok().json(count))
+}
+
 #[get("/messages/log/{username}")]
 async fn get_message_log_username(
     app: web::Data<AppData>,
@@ -309,11 +419,17 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(connect_client)
         .service(get_external_clients)
         .service(get_room_state)
+        .service(subscribe_room_state)
         .service(send_message)
+        .service(list_queued_messages)
+        .service(clear_queued_messages)
         .service(get_message_log_username)
         .service(get_rooms)
+        .service(get_room_summaries)
         .service(invite_occupant)
         .service(evict_occupant)
+        .service(evict_user)
+        .service(evict_room)
         .service(start_network_trace)
         .service(stop_network_trace)
         .service(get_network_trace)
@@ -324,6 +440,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 struct WsSession {
     client_id: ClientId,
     topology_addr: Addr<topology::TopologyActor>,
+    max_message_size: usize,
 }
 
 impl WsSession {
@@ -432,10 +549,69 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
                 }
                 ctx.close(None);
             }
+            Err(ws::ProtocolError::Overflow) => {
+                let reason = ws::CloseReason {
+                    code: CloseCode::Size,
+                    description: Some(format!(
+                        "Message exceeds max size of {} bytes.",
+                        self.max_message_size
+                    )),
+                };
+                ctx.close(Some(reason));
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Websocket session which receives (version-stamped) updates to the state of a room
+struct RoomStateSession {
+    id: Uuid,
+    project: ProjectMetadata,
+    topology_addr: Addr<topology::TopologyActor>,
+}
+
+impl Actor for RoomStateSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.topology_addr.do_send(topology::SubscribeRoomState {
+            id: self.id,
+            project: self.project.clone(),
+            addr: ctx.address().recipient(),
+        });
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> actix::Running {
+        self.topology_addr.do_send(topology::UnsubscribeRoomState {
+            id: self.id,
+            project_id: self.project.id.clone(),
+        });
+        actix::Running::Stop
+    }
+}
+
+impl Handler<ClientCommand> for RoomStateSession {
+    type Result = ();
+    fn handle(&mut self, msg: ClientCommand, ctx: &mut Self::Context) {
+        match msg {
+            ClientCommand::SendMessage(content) => ctx.text(content.to_string()),
+            ClientCommand::Close => ctx.close(None),
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RoomStateSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(_reason)) => ctx.close(None),
             _ => (),
         }
     }
 }
+
 #[cfg(test)]
 mod tests {
     use std::time::SystemTime;
@@ -445,11 +621,18 @@ mod tests {
     use actix_web::{http, test, App};
     use mongodb::bson::DateTime;
     use netsblox_cloud_common::api::BrowserClientState;
-    use netsblox_cloud_common::{LogMessage, NetworkTraceMetadata, User};
+    use netsblox_cloud_common::{
+        LogMessage, NetworkTraceMetadata, QueuedMessage, SentMessage, User,
+    };
 
     use super::*;
     use crate::test_utils;
+    use actix_codec::{Decoder, Encoder};
+    use actix_http::ws::Codec;
+    use actix_web::error::PayloadError;
+    use actix_web::web::{Bytes, BytesMut};
     use futures::TryStreamExt;
+    use std::num::NonZeroUsize;
 
     #[actix_web::test]
     #[ignore]
@@ -458,6 +641,77 @@ mod tests {
         todo!();
     }
 
+    /// Send text messages over a websocket session and get the frames sent back by the server
+    async fn send_ws_messages(messages: Vec<String>, max_size: usize) -> Vec<ws::Frame> {
+        let cache_size = NonZeroUsize::new(10).unwrap();
+        let topology_addr =
+            topology::TopologyActor::new(cache_size, Default::default(), None).start();
+        let session = WsSession {
+            client_id: ClientId::new("_client".into()),
+            topology_addr,
+            max_message_size: max_size,
+        };
+
+        let mut client_codec = Codec::new().client_mode();
+        let mut input = BytesMut::new();
+        for msg in messages {
+            client_codec
+                .encode(ws::Message::Text(msg.into()), &mut input)
+                .unwrap();
+        }
+        let input = futures::stream::once(async move { Ok::<_, PayloadError>(input.freeze()) });
+
+        let output =
+            ws::WebsocketContext::with_codec(session, input, Codec::new().max_size(max_size));
+        let chunks: Vec<Bytes> = output.try_collect().await.unwrap();
+
+        let mut output: BytesMut = chunks.concat().as_slice().into();
+        let mut frames = Vec::new();
+        while let Some(frame) = client_codec.decode(&mut output).unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    /// Create a ping message of exactly the given size (in bytes)
+    fn ping_msg(size: usize) -> String {
+        let msg = json!({"type": "ping", "padding": ""}).to_string();
+        let padding = "a".repeat(size - msg.len());
+        json!({"type": "ping", "padding": padding}).to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_ws_msg_under_max_size() {
+        let max_size = 1024;
+        let msg = ping_msg(max_size);
+        assert_eq!(msg.len(), max_size);
+
+        let frames = send_ws_messages(vec![msg], max_size).await;
+        let pong = frames
+            .iter()
+            .any(|frame| matches!(frame, ws::Frame::Text(text) if text.as_ref() == b"{\"type\": \"pong\"}"));
+        assert!(pong, "Expected pong. Received: {:?}", frames);
+        assert!(!frames
+            .iter()
+            .any(|frame| matches!(frame, ws::Frame::Close(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_ws_msg_over_max_size() {
+        let max_size = 1024;
+        let msg = ping_msg(max_size + 1);
+
+        let frames = send_ws_messages(vec![msg], max_size).await;
+        let close_code = frames.iter().find_map(|frame| match frame {
+            ws::Frame::Close(reason) => reason.as_ref().map(|reason| reason.code),
+            _ => None,
+        });
+        assert_eq!(close_code, Some(CloseCode::Size));
+        assert!(!frames
+            .iter()
+            .any(|frame| matches!(frame, ws::Frame::Text(_))));
+    }
+
     #[actix_web::test]
     #[ignore]
     async fn test_send_msg_room() {
@@ -712,16 +966,16 @@ mod tests {
                     .uri(&format!("/id/{}/trace/{}/messages", &project.id, &trace.id))
                     .to_request();
 
-                let messages: Vec<api::SentMessage> =
+                let trace_messages: api::TraceMessages =
                     test::call_and_read_body_json(&app, req).await;
-                assert_eq!(messages.len(), 20);
+                assert_eq!(trace_messages.messages.len(), 20);
+                assert!(!trace_messages.truncated);
             })
             .await;
     }
 
-    #[ignore] // FIXME: this test is flaky
     #[actix_web::test]
-    async fn test_stop_network_trace() {
+    async fn test_get_trace_messages() {
         let owner: User = api::NewUser {
             username: "owner".to_string(),
             email: "owner@netsblox.org".into(),
@@ -730,54 +984,13 @@ mod tests {
             role: None,
         }
         .into();
-
-        let r1_id = api::RoleId::new("r1".into());
-        let r2_id = api::RoleId::new("r2".into());
-        let roles: HashMap<_, _> = [
-            (
-                r1_id.clone(),
-                api::RoleData {
-                    name: "sender".into(),
-                    code: "<code/>".into(),
-                    media: "<media/>".into(),
-                },
-            ),
-            (
-                r2_id.clone(),
-                api::RoleData {
-                    name: "rcvr".into(),
-                    code: "<code/>".into(),
-                    media: "<media/>".into(),
-                },
-            ),
-        ]
-        .into_iter()
-        .collect();
-
-        let trace = NetworkTraceMetadata::new();
         let project = test_utils::project::builder()
-            .with_name("project")
-            .with_owner("owner".to_string())
-            .with_traces(&[trace.clone()])
-            .with_roles(roles)
+            .with_owner(owner.username.clone())
             .build();
 
-        let s1 = ClientState::Browser(BrowserClientState {
-            project_id: project.id.clone(),
-            role_id: r1_id,
-        });
-        let s2 = ClientState::Browser(BrowserClientState {
-            project_id: project.id.clone(),
-            role_id: r2_id,
-        });
-
-        let sender = test_utils::network::Client::new(Some(owner.username.clone()), Some(s1));
-        let rcvr = test_utils::network::Client::new(Some(owner.username.clone()), Some(s2));
-
         test_utils::setup()
             .with_users(&[owner.clone()])
             .with_projects(&[project.clone()])
-            .with_clients(&[sender.clone(), rcvr.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -787,85 +1000,86 @@ mod tests {
                 )
                 .await;
 
-                // send messages
-                let content = json! ({
-                    "type": "message",
-                    "msgType": "message",
-                    "content": {
-                        "msg": "hello!"
-                    }
-                });
-                app_data
-                    .network
-                    .send(topology::SendMessage {
-                        sender: sender.id.clone(),
-                        addresses: vec!["rcvr@project@owner".into()],
-                        content: content.clone(),
-                    })
-                    .await
-                    .unwrap();
-
-                // wait for the message to be recorded (up to a limit, ofc)
-                let max_end_time = SystemTime::now() + Duration::from_millis(150);
-                let mut is_recorded = app_data
-                    .recorded_messages
-                    .find_one(doc! {}, None)
-                    .await
-                    .unwrap()
-                    .is_some();
-
-                while !is_recorded {
-                    assert!(SystemTime::now() < max_end_time);
-                    tokio::time::sleep(Duration::from_millis(10)).await;
-
-                    is_recorded = app_data
-                        .recorded_messages
-                        .find_one(doc! {}, None)
-                        .await
-                        .unwrap()
-                        .is_some();
-                }
-
-                // stop recording messages
                 let req = test::TestRequest::post()
                     .cookie(test_utils::cookie::new(&owner.username))
-                    .uri(&format!("/id/{}/trace/{}/stop", &project.id, &trace.id))
+                    .uri(&format!("/id/{}/trace/", &project.id))
                     .to_request();
-
-                let metadata: api::NetworkTraceMetadata =
+                let trace: api::NetworkTraceMetadata =
                     test::call_and_read_body_json(&app, req).await;
-                assert_eq!(metadata.id, trace.id);
-
-                // send another message
-                app_data
-                    .network
-                    .send(topology::SendMessage {
-                        sender: rcvr.id.clone(),
-                        addresses: vec!["sender@project@owner".into()],
-                        content: content.clone(),
-                    })
-                    .await
-                    .unwrap();
 
-                // fetch sent messages
-                let req = test::TestRequest::get()
-                    .cookie(test_utils::cookie::new(&owner.username))
-                    .uri(&format!("/id/{}/trace/{}/messages", &project.id, &trace.id))
-                    .to_request();
+                // record messages while the trace is still in progress
+                let state = ClientState::Browser(BrowserClientState {
+                    project_id: project.id.clone(),
+                    role_id: api::RoleId::new("role".into()),
+                });
+                let mut times = Vec::new();
+                for i in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    let msg = SentMessage::new(
+                        project.id.clone(),
+                        state.clone(),
+                        vec![state.clone()],
+                        json!({ "index": i }),
+                    );
+                    times.push(msg.time.timestamp_millis());
+                    app_data
+                        .recorded_messages
+                        .insert_one(msg, None)
+                        .await
+                        .unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+
+                let uri = format!("/id/{}/trace/{}/messages", &project.id, &trace.id);
+                let get_messages = |query: String| {
+                    test::TestRequest::get()
+                        .cookie(test_utils::cookie::new(&owner.username))
+                        .uri(&format!("{}{}", uri, query))
+                        .to_request()
+                };
 
-                let mut messages: Vec<api::SentMessage> =
-                    test::call_and_read_body_json(&app, req).await;
+                let api::TraceMessages {
+                    messages,
+                    truncated,
+                } = test::call_and_read_body_json(&app, get_messages("".into())).await;
+                let indices: Vec<_> = messages.iter().map(|msg| &msg.content["index"]).collect();
+                assert_eq!(indices, vec![0, 1, 2]);
+                assert!(!truncated);
+
+                let api::TraceMessages {
+                    messages,
+                    truncated,
+                } = test::call_and_read_body_json(&app, get_messages("?skip=1&limit=1".into()))
+                    .await;
                 assert_eq!(messages.len(), 1);
-
-                // Check that it is the first message
-                let msg = messages.pop().unwrap();
-                assert_eq!(msg.source, sender.state.unwrap());
+                assert_eq!(messages[0].content["index"], 1);
+                assert!(truncated);
+
+                let api::TraceMessages {
+                    messages,
+                    truncated,
+                } = test::call_and_read_body_json(&app, get_messages("?skip=1&limit=2".into()))
+                    .await;
+                assert_eq!(messages.len(), 2);
+                assert!(!truncated);
+
+                let query = format!("?after={}", times[0]);
+                let api::TraceMessages { messages, .. } =
+                    test::call_and_read_body_json(&app, get_messages(query)).await;
+                let indices: Vec<_> = messages.iter().map(|msg| &msg.content["index"]).collect();
+                assert_eq!(indices, vec![1, 2]);
+
+                let query = format!("?before={}", times[2]);
+                let api::TraceMessages { messages, .. } =
+                    test::call_and_read_body_json(&app, get_messages(query)).await;
+                let indices: Vec<_> = messages.iter().map(|msg| &msg.content["index"]).collect();
+                assert_eq!(indices, vec![0, 1]);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_stop_network_trace_404() {
+    async fn test_get_trace_messages_403() {
         let owner: User = api::NewUser {
             username: "owner".to_string(),
             email: "owner@netsblox.org".into(),
@@ -874,14 +1088,921 @@ mod tests {
             role: None,
         }
         .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
 
-        let r1_id = api::RoleId::new("r1".into());
-        let r2_id = api::RoleId::new("r2".into());
-        let roles: HashMap<_, _> = [
-            (
-                r1_id.clone(),
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_state(api::PublishState::Public)
+            .with_traces(&[trace.clone()])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner, other.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .uri(&format!("/id/{}/trace/{}/messages", &project.id, &trace.id))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_start_network_trace() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // starting a trace twice creates separate traces
+                let mut trace_ids = Vec::new();
+                for _ in 0..2 {
+                    let req = test::TestRequest::post()
+                        .cookie(test_utils::cookie::new(&owner.username))
+                        .uri(&format!("/id/{}/trace/", &project.id))
+                        .to_request();
+                    let trace: api::NetworkTraceMetadata =
+                        test::call_and_read_body_json(&app, req).await;
+                    assert!(trace.end_time.is_none());
+                    trace_ids.push(trace.id);
+                }
+                assert_ne!(trace_ids[0], trace_ids[1]);
+
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(doc! {"id": &project.id}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let ids: Vec<_> = metadata.network_traces.into_iter().map(|t| t.id).collect();
+                assert_eq!(ids, trace_ids);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_start_network_trace_max_active() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let mut stopped = NetworkTraceMetadata::new();
+        stopped.end_time = Some(DateTime::now());
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_traces(&[
+                NetworkTraceMetadata::new(),
+                NetworkTraceMetadata::new(),
+                stopped,
+            ])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // stopped traces do not count toward the limit
+                let max_active = app_data.settings.network.max_active_traces;
+                assert_eq!(max_active, 3);
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_start_network_trace_403() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_state(api::PublishState::Public)
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner, other.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .uri(&format!("/id/{}/trace/", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_stop_network_trace_end_time() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_traces(&[trace.clone()])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/{}/stop", &project.id, &trace.id))
+                    .to_request();
+                let stopped: api::NetworkTraceMetadata =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(stopped.end_time.is_some());
+
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(doc! {"id": &project.id}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(metadata.network_traces[0].end_time.is_some());
+            })
+            .await;
+    }
+
+    #[ignore] // FIXME: this test is flaky
+    #[actix_web::test]
+    async fn test_stop_network_trace() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let r1_id = api::RoleId::new("r1".into());
+        let r2_id = api::RoleId::new("r2".into());
+        let roles: HashMap<_, _> = [
+            (
+                r1_id.clone(),
+                api::RoleData {
+                    name: "sender".into(),
+                    code: "<code/>".into(),
+                    media: "<media/>".into(),
+                },
+            ),
+            (
+                r2_id.clone(),
+                api::RoleData {
+                    name: "rcvr".into(),
+                    code: "<code/>".into(),
+                    media: "<media/>".into(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_name("project")
+            .with_owner("owner".to_string())
+            .with_traces(&[trace.clone()])
+            .with_roles(roles)
+            .build();
+
+        let s1 = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id: r1_id,
+        });
+        let s2 = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id: r2_id,
+        });
+
+        let sender = test_utils::network::Client::new(Some(owner.username.clone()), Some(s1));
+        let rcvr = test_utils::network::Client::new(Some(owner.username.clone()), Some(s2));
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .with_clients(&[sender.clone(), rcvr.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // send messages
+                let content = json! ({
+                    "type": "message",
+                    "msgType": "message",
+                    "content": {
+                        "msg": "hello!"
+                    }
+                });
+                app_data
+                    .network
+                    .send(topology::SendMessage {
+                        sender: sender.id.clone(),
+                        addresses: vec!["rcvr@project@owner".into()],
+                        content: content.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                // wait for the message to be recorded (up to a limit, ofc)
+                let max_end_time = SystemTime::now() + Duration::from_millis(150);
+                let mut is_recorded = app_data
+                    .recorded_messages
+                    .find_one(doc! {}, None)
+                    .await
+                    .unwrap()
+                    .is_some();
+
+                while !is_recorded {
+                    assert!(SystemTime::now() < max_end_time);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+
+                    is_recorded = app_data
+                        .recorded_messages
+                        .find_one(doc! {}, None)
+                        .await
+                        .unwrap()
+                        .is_some();
+                }
+
+                // stop recording messages
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/{}/stop", &project.id, &trace.id))
+                    .to_request();
+
+                let metadata: api::NetworkTraceMetadata =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.id, trace.id);
+
+                // send another message
+                app_data
+                    .network
+                    .send(topology::SendMessage {
+                        sender: rcvr.id.clone(),
+                        addresses: vec!["sender@project@owner".into()],
+                        content: content.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                // fetch sent messages
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/{}/messages", &project.id, &trace.id))
+                    .to_request();
+
+                let api::TraceMessages { mut messages, .. } =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(messages.len(), 1);
+
+                // Check that it is the first message
+                let msg = messages.pop().unwrap();
+                assert_eq!(msg.source, sender.state.unwrap());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_stop_network_trace_404() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let r1_id = api::RoleId::new("r1".into());
+        let r2_id = api::RoleId::new("r2".into());
+        let roles: HashMap<_, _> = [
+            (
+                r1_id.clone(),
+                api::RoleData {
+                    name: "sender".into(),
+                    code: "<code/>".into(),
+                    media: "<media/>".into(),
+                },
+            ),
+            (
+                r2_id.clone(),
+                api::RoleData {
+                    name: "rcvr".into(),
+                    code: "<code/>".into(),
+                    media: "<media/>".into(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_name("project")
+            .with_owner("owner".to_string())
+            .with_roles(roles)
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // stop recording messages
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/{}/stop", &project.id, &trace.id))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_network_trace() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .with_traces(&[trace.clone()])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/id/{}/trace/{}", &project.id, &trace.id))
+                    .to_request();
+
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert!(metadata.network_traces.is_empty());
+                // check the network trace has been removed from the project metadata
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert!(project.network_traces.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_network_trace_403() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let viewer: User = api::NewUser {
+            username: "viewer".to_string(),
+            email: "viewer@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let trace = NetworkTraceMetadata::new();
+        let project = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .with_collaborators(&["viewer"])
+            .with_collaborator_role("viewer", api::CollabRole::Viewer)
+            .with_traces(&[trace.clone()])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner, viewer.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&viewer.username))
+                    .uri(&format!("/id/{}/trace/{}", &project.id, &trace.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                // check the network trace is still on the project
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(project.network_traces.len(), 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_evict_occupant_project_owner() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_evict_occupant_project_collaborator() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_evict_occupant_group_owner() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    async fn test_evict_user() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .build();
+        let role_id = project.roles.keys().next().unwrap().clone();
+        let state = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id,
+        });
+
+        let c1 =
+            test_utils::network::Client::new(Some(owner.username.clone()), Some(state.clone()));
+        let c2 = test_utils::network::Client::new(Some(owner.username.clone()), None);
+        let c3 = test_utils::network::Client::new(Some(other.username.clone()), Some(state));
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), other.clone()])
+            .with_projects(&[project.clone()])
+            .with_clients(&[c1.clone(), c2.clone(), c3.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/users/{}/evict", &owner.username))
+                    .to_request();
+                let mut evicted: Vec<ClientId> = test::call_and_read_body_json(&app, req).await;
+                evicted.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+                let mut expected = vec![c1.id.clone(), c2.id.clone()];
+                expected.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                assert_eq!(evicted, expected);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_room_summaries() {
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(api::UserRole::Admin),
+        }
+        .into();
+        let p1 = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .with_name("p1")
+            .build();
+        let p2 = test_utils::project::builder()
+            .with_owner("other".to_string())
+            .with_name("p2")
+            .build();
+        let state = |project: &test_utils::project::ProjectFixture| {
+            ClientState::Browser(BrowserClientState {
+                project_id: project.id.clone(),
+                role_id: project.roles.keys().next().unwrap().clone(),
+            })
+        };
+
+        let c1 = test_utils::network::Client::new(Some("owner".into()), Some(state(&p1)));
+        let c2 = test_utils::network::Client::new(None, Some(state(&p1)));
+        let c3 = test_utils::network::Client::new(Some("other".into()), Some(state(&p2)));
+        let c4 = test_utils::network::Client::new(Some("other".into()), None);
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .with_projects(&[p1.clone(), p2.clone()])
+            .with_clients(&[c1, c2, c3, c4])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/summary")
+                    .to_request();
+                let mut summaries: Vec<api::RoomSummary> =
+                    test::call_and_read_body_json(&app, req).await;
+                summaries.sort_by(|a, b| a.name.cmp(&b.name));
+
+                assert_eq!(summaries.len(), 2);
+                assert_eq!(summaries[0].project_id, p1.id);
+                assert_eq!(summaries[0].owner, "owner");
+                assert_eq!(summaries[0].occupant_count, 2);
+                assert_eq!(summaries[1].project_id, p2.id);
+                assert_eq!(summaries[1].occupant_count, 1);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_room_summaries_403() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri("/summary")
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_external_clients_app_id() {
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(api::UserRole::Admin),
+        }
+        .into();
+        let state = |address: &str, app_id: &str| {
+            ClientState::External(ExternalClientState {
+                address: address.into(),
+                app_id: api::AppId::new(app_id),
+            })
+        };
+        let c1 = test_utils::network::Client::new(None, Some(state("c1", "MyApp")));
+        let c2 = test_utils::network::Client::new(None, Some(state("c2", "myapp")));
+        let c3 = test_utils::network::Client::new(None, Some(state("c3", "OtherApp")));
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .with_clients(&[c1, c2, c3])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/external?app_id=MYAPP")
+                    .to_request();
+                let clients: Vec<api::ExternalClient> =
+                    test::call_and_read_body_json(&app, req).await;
+                let mut addresses: Vec<_> = clients.iter().map(|c| c.address.as_str()).collect();
+                addresses.sort();
+                assert_eq!(addresses, vec!["c1", "c2"]);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/external")
+                    .to_request();
+                let clients: Vec<api::ExternalClient> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(clients.len(), 3);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_clear_queued_messages() {
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(api::UserRole::Admin),
+        }
+        .into();
+        let msg = QueuedMessage::new(
+            api::AppId::new("TestApp"),
+            "device@brian".into(),
+            json!({"type": "message"}),
+            Duration::from_secs(60),
+        );
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .run(|app_data| async move {
+                app_data
+                    .queued_messages
+                    .insert_one(msg, None)
+                    .await
+                    .unwrap();
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/messages/queued")
+                    .to_request();
+                let messages: Vec<api::QueuedMessage> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(messages.len(), 1);
+                assert_eq!(messages[0].address, "device@brian");
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/messages/queued")
+                    .to_request();
+                let count: u64 = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(count, 1);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri("/messages/queued")
+                    .to_request();
+                let messages: Vec<api::QueuedMessage> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(messages.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_queued_messages_403() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri("/messages/queued")
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_evict_user_403() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), other.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .uri(&format!("/users/{}/evict", &owner.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_evict_room() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let r1_id = api::RoleId::new("r1".into());
+        let r2_id = api::RoleId::new("r2".into());
+        let roles: HashMap<_, _> = [
+            (
+                r1_id.clone(),
                 api::RoleData {
-                    name: "sender".into(),
+                    name: "r1".into(),
                     code: "<code/>".into(),
                     media: "<media/>".into(),
                 },
@@ -889,7 +2010,7 @@ mod tests {
             (
                 r2_id.clone(),
                 api::RoleData {
-                    name: "rcvr".into(),
+                    name: "r2".into(),
                     code: "<code/>".into(),
                     media: "<media/>".into(),
                 },
@@ -897,17 +2018,38 @@ mod tests {
         ]
         .into_iter()
         .collect();
-
-        let trace = NetworkTraceMetadata::new();
         let project = test_utils::project::builder()
             .with_name("project")
-            .with_owner("owner".to_string())
+            .with_owner(owner.username.clone())
             .with_roles(roles)
             .build();
+        let other_project = test_utils::project::builder()
+            .with_name("other project")
+            .with_owner(owner.username.clone())
+            .build();
+
+        let s1 = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id: r1_id.clone(),
+        });
+        let s2 = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id: r2_id,
+        });
+        let s3 = ClientState::Browser(BrowserClientState {
+            project_id: other_project.id.clone(),
+            role_id: other_project.roles.keys().next().unwrap().clone(),
+        });
+
+        let c1 = test_utils::network::Client::new(Some(owner.username.clone()), Some(s1.clone()));
+        let c2 = test_utils::network::Client::new(None, Some(s1));
+        let c3 = test_utils::network::Client::new(Some(owner.username.clone()), Some(s2));
+        let c4 = test_utils::network::Client::new(Some(owner.username.clone()), Some(s3));
 
         test_utils::setup()
             .with_users(&[owner.clone()])
-            .with_projects(&[project.clone()])
+            .with_projects(&[project.clone(), other_project.clone()])
+            .with_clients(&[c1.clone(), c2.clone(), c3.clone(), c4.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -917,20 +2059,22 @@ mod tests {
                 )
                 .await;
 
-                // stop recording messages
                 let req = test::TestRequest::post()
                     .cookie(test_utils::cookie::new(&owner.username))
-                    .uri(&format!("/id/{}/trace/{}/stop", &project.id, &trace.id))
+                    .uri(&format!("/id/{}/evict", &project.id))
                     .to_request();
+                let mut evicted: Vec<ClientId> = test::call_and_read_body_json(&app, req).await;
+                evicted.sort_by(|a, b| a.as_str().cmp(b.as_str()));
 
-                let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+                let mut expected = vec![c1.id.clone(), c2.id.clone(), c3.id.clone()];
+                expected.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                assert_eq!(evicted, expected);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_delete_network_trace() {
+    async fn test_set_client_state_room_full() {
         let owner: User = api::NewUser {
             username: "owner".to_string(),
             email: "owner@netsblox.org".into(),
@@ -939,12 +2083,14 @@ mod tests {
             role: None,
         }
         .into();
-
-        let trace = NetworkTraceMetadata::new();
         let project = test_utils::project::builder()
-            .with_owner("owner".to_string())
-            .with_traces(&[trace.clone()])
+            .with_owner(owner.username.clone())
             .build();
+        let role_id = project.roles.keys().next().unwrap().clone();
+        let state = ClientState::Browser(BrowserClientState {
+            project_id: project.id.clone(),
+            role_id: role_id.clone(),
+        });
 
         test_utils::setup()
             .with_users(&[owner.clone()])
@@ -958,36 +2104,63 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
+                // fill the room
+                let capacity = app_data.settings.network.max_room_occupants;
+                let mut occupants = Vec::new();
+                for _ in 0..capacity {
+                    let client = test_utils::network::Client::new(
+                        Some(owner.username.clone()),
+                        Some(state.clone()),
+                    );
+                    occupants.push(client.id.clone());
+                    client.add_into(&app_data.network).await;
+                }
+
+                let client = test_utils::network::Client::new(None, None);
+                let client_id = client.id.clone();
+                client.add_into(&app_data.network).await;
+
+                let body = ClientStateData {
+                    state: ClientState::Browser(BrowserClientState {
+                        project_id: project.id.clone(),
+                        role_id: role_id.clone(),
+                    }),
+                };
+                let req = test::TestRequest::post()
                     .cookie(test_utils::cookie::new(&owner.username))
-                    .uri(&format!("/id/{}/trace/{}", &project.id, &trace.id))
+                    .uri(&format!("/{}/state", client_id.as_str()))
+                    .set_json(&body)
                     .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::CONFLICT);
 
-                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                assert!(metadata.network_traces.is_empty());
-                // check the network trace has been removed from the project metadata
-                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
-                assert!(project.network_traces.is_empty());
-            })
-            .await;
-    }
-
-    #[actix_web::test]
-    #[ignore]
-    async fn test_evict_occupant_project_owner() {
-        todo!();
-    }
+                // existing occupants can still change roles
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/{}/state", occupants[0].as_str()))
+                    .set_json(&body)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_evict_occupant_project_collaborator() {
-        todo!();
-    }
+                // evicting an occupant frees a slot
+                app_data
+                    .network
+                    .send(topology::EvictOccupant {
+                        client_id: occupants[1].clone(),
+                    })
+                    .await
+                    .unwrap();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_evict_occupant_group_owner() {
-        todo!();
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!("/{}/state", client_id.as_str()))
+                    .set_json(&body)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
     }
 
     #[test]
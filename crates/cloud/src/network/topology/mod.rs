@@ -5,16 +5,21 @@ pub(crate) mod network;
 use crate::app_data::AppData;
 use crate::common::api::{ClientId, ExternalClient, ProjectId, RoleData, RoomState};
 use crate::common::{api, OccupantInvite, ProjectMetadata};
-use actix::dev::OneshotSender;
+use crate::config::TopologySettings;
 use actix::prelude::*;
 use actix::{Actor, AsyncContext, Context, Handler};
+use futures::{Future, FutureExt};
 use log::warn;
 use netsblox_cloud_common::api::CollaborationInvite;
 use serde::Serialize;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::num::NonZeroUsize;
-use std::sync::Arc;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
@@ -25,14 +30,90 @@ use crate::common::api::{BrowserClientState, ClientState};
 
 pub struct TopologyActor {
     network: Arc<RwLock<Topology>>,
-    tx: Option<OneshotSender<TopologyPanic>>,
+    supervisor: TopologySupervisor,
+}
+
+/// Failures in the network topology reported to the main thread
+#[derive(Debug, PartialEq, Eq)]
+pub enum TopologyFailure {
+    /// The topology failed and its state has been reset
+    Restarted,
+    /// The topology failed and cannot be restarted. The server should be stopped.
+    Fatal,
+}
+
+/// Restarts the topology after a failure until the max number of restarts has been
+/// reached (within the configured window)
+#[derive(Clone)]
+struct TopologySupervisor {
+    settings: TopologySettings,
+    restarts: Arc<Mutex<Vec<Instant>>>,
+    tx: Option<UnboundedSender<TopologyFailure>>,
+}
+
+impl TopologySupervisor {
+    fn notify(&self, failure: TopologyFailure) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(failure);
+        }
+    }
+
+    /// Record a restart and check if it is allowed
+    fn try_restart(&self) -> bool {
+        let window = Duration::from_secs(self.settings.restart_window_secs);
+        let now = Instant::now();
+        let mut restarts = self.restarts.lock().unwrap();
+        restarts.retain(|time| now.duration_since(*time) < window);
+
+        let allowed = restarts.len() < self.settings.max_restarts as usize;
+        if allowed {
+            restarts.push(now);
+        }
+        allowed
+    }
+
+    async fn handle_failure(&self, network: &RwLock<Topology>) {
+        if self.try_restart() {
+            warn!("Failure in topology detected. Restarting.");
+            network.write().await.reset();
+            self.notify(TopologyFailure::Restarted);
+        } else {
+            log::error!("Topology exceeded max restarts. Notifying main thread.");
+            self.notify(TopologyFailure::Fatal);
+        }
+    }
 }
 
-pub struct TopologyPanic;
 impl TopologyActor {
-    pub(crate) fn new(cache_size: NonZeroUsize, tx: Option<OneshotSender<TopologyPanic>>) -> Self {
+    pub(crate) fn new(
+        cache_size: NonZeroUsize,
+        settings: TopologySettings,
+        tx: Option<UnboundedSender<TopologyFailure>>,
+    ) -> Self {
         let network = Arc::new(RwLock::new(Topology::new(cache_size)));
-        Self { network, tx }
+        let supervisor = TopologySupervisor {
+            settings,
+            restarts: Arc::new(Mutex::new(Vec::new())),
+            tx,
+        };
+        Self {
+            network,
+            supervisor,
+        }
+    }
+
+    /// Run the given future (typically an update to the topology). If it panics, the
+    /// topology will be restarted (or the failure escalated to the main thread).
+    fn spawn_supervised(&self, ctx: &mut Context<Self>, fut: impl Future<Output = ()> + 'static) {
+        let network = self.network.clone();
+        let supervisor = self.supervisor.clone();
+        let fut = async move {
+            if AssertUnwindSafe(fut).catch_unwind().await.is_err() {
+                supervisor.handle_failure(&network).await;
+            }
+        };
+        let fut = actix::fut::wrap_future(fut);
+        ctx.spawn(fut);
     }
 }
 
@@ -43,10 +124,8 @@ impl Actor for TopologyActor {
 impl Drop for TopologyActor {
     fn drop(&mut self) {
         if thread::panicking() {
-            if let Some(tx) = self.tx.take() {
-                log::warn!("Panic in topology detected. Notifying main thread.");
-                let _ = tx.send(TopologyPanic);
-            }
+            log::warn!("Panic in topology detected. Notifying main thread.");
+            self.supervisor.notify(TopologyFailure::Fatal);
         }
     }
 }
@@ -71,6 +150,22 @@ pub struct AddClient {
     pub addr: Recipient<ClientCommand>,
 }
 
+/// Subscribe to changes to the state of a room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubscribeRoomState {
+    pub id: Uuid,
+    pub project: ProjectMetadata,
+    pub addr: Recipient<ClientCommand>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UnsubscribeRoomState {
+    pub id: Uuid,
+    pub project_id: ProjectId,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct SetStorage {
@@ -127,8 +222,7 @@ impl Handler<AddClient> for TopologyActor {
             let mut topology = network.write().await;
             topology.add_client(msg);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -143,8 +237,7 @@ impl Handler<BrokenClient> for TopologyActor {
                 warn!("Unable to record broken client: {:?}", error);
             }
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -157,8 +250,7 @@ impl Handler<RemoveClient> for TopologyActor {
             let mut topology = network.write().await;
             topology.remove_client(msg).await;
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -171,8 +263,7 @@ impl Handler<SetClientState> for TopologyActor {
             let mut topology = network.write().await;
             topology.set_client_state(msg).await;
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -185,8 +276,7 @@ impl Handler<SetClientUsername> for TopologyActor {
             let mut topology = network.write().await;
             topology.set_client_username(&msg.id, msg.username);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 impl Handler<SendMessage> for TopologyActor {
@@ -198,8 +288,7 @@ impl Handler<SendMessage> for TopologyActor {
             let topology = network.read().await;
             topology.send_msg(msg).await;
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -212,8 +301,7 @@ impl Handler<SetStorage> for TopologyActor {
             let mut topology = network.write().await;
             topology.set_app_data(msg.app_data);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -226,8 +314,7 @@ impl Handler<SendRoomState> for TopologyActor {
             let mut topology = network.write().await;
             topology.send_room_state(msg);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -253,6 +340,32 @@ pub struct EvictOccupant {
     pub client_id: ClientId,
 }
 
+impl Handler<SubscribeRoomState> for TopologyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeRoomState, ctx: &mut Context<Self>) -> Self::Result {
+        let network = self.network.clone();
+        let fut = async move {
+            let mut topology = network.write().await;
+            topology.subscribe_room_state(msg);
+        };
+        self.spawn_supervised(ctx, fut);
+    }
+}
+
+impl Handler<UnsubscribeRoomState> for TopologyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnsubscribeRoomState, ctx: &mut Context<Self>) -> Self::Result {
+        let network = self.network.clone();
+        let fut = async move {
+            let mut topology = network.write().await;
+            topology.unsubscribe_room_state(msg);
+        };
+        self.spawn_supervised(ctx, fut);
+    }
+}
+
 impl Handler<EvictOccupant> for TopologyActor {
     type Result = ();
 
@@ -262,8 +375,7 @@ impl Handler<EvictOccupant> for TopologyActor {
             let mut topology = network.write().await;
             topology.evict_client(msg.client_id).await;
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -283,8 +395,7 @@ impl Handler<DisconnectClient> for TopologyActor {
             let topology = network.read().await;
             topology.disconnect_client(&client_id);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -327,8 +438,7 @@ impl Handler<CollabInviteChangeMsg> for TopologyActor {
             let json = serde_json::to_value(msg).unwrap(); // we created the message so it should be infallible
             topology.send_to_user(json, &receiver);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -365,8 +475,7 @@ impl Handler<FriendRequestChangeMsg> for TopologyActor {
             let json = serde_json::to_value(msg).unwrap(); // we created the message so it should be infallible
             topology.send_to_user(json, &receiver);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -397,8 +506,7 @@ impl Handler<ProjectDeleted> for TopologyActor {
             let json = serde_json::to_value(msg).unwrap(); // we created the message so it should be infallible
             topology.send_to_room(json, &project_id);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -419,8 +527,7 @@ impl Handler<SendOccupantInvite> for TopologyActor {
             let topology = network.read().await;
             topology.send_occupant_invite(msg);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -443,8 +550,7 @@ impl Handler<SendMessageFromServices> for TopologyActor {
             let topology = network.read().await;
             topology.send_msg_from_services(send_msg_req.message).await;
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -464,8 +570,7 @@ impl Handler<SendIDEMessage> for TopologyActor {
             let topology = network.read().await;
             topology.send_ide_msg(msg);
         };
-        let fut = actix::fut::wrap_future(fut);
-        ctx.spawn(fut);
+        self.spawn_supervised(ctx, fut);
     }
 }
 
@@ -527,6 +632,33 @@ impl Handler<GetActiveRooms> for TopologyActor {
     }
 }
 
+#[derive(Message, Clone)]
+#[rtype(result = "GetRoomOccupantCountsTask")]
+pub struct GetRoomOccupantCounts;
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct GetRoomOccupantCountsTask {
+    network: Arc<RwLock<Topology>>,
+}
+
+impl GetRoomOccupantCountsTask {
+    pub(crate) async fn run(&self) -> HashMap<ProjectId, usize> {
+        let topology = self.network.read().await;
+        topology.get_room_occupant_counts()
+    }
+}
+
+impl Handler<GetRoomOccupantCounts> for TopologyActor {
+    type Result = MessageResult<GetRoomOccupantCounts>;
+
+    fn handle(&mut self, _msg: GetRoomOccupantCounts, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(GetRoomOccupantCountsTask {
+            network: self.network.clone(),
+        })
+    }
+}
+
 #[derive(Message, Clone)]
 #[rtype(result = "GetOnlineUsersTask")]
 pub(crate) struct GetOnlineUsers(pub Option<Vec<String>>);
@@ -700,3 +832,134 @@ impl Handler<GetExternalClients> for TopologyActor {
         })
     }
 }
+
+#[derive(Message, Clone)]
+#[rtype(result = "GetUserClientsTask")]
+pub(crate) struct GetUserClients(pub String);
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct GetUserClientsTask {
+    network: Arc<RwLock<Topology>>,
+    username: String,
+}
+
+impl GetUserClientsTask {
+    pub(crate) async fn run(self) -> Vec<ClientId> {
+        let topology = self.network.read().await;
+        topology.get_user_clients(&self.username)
+    }
+}
+
+impl Handler<GetUserClients> for TopologyActor {
+    type Result = MessageResult<GetUserClients>;
+
+    fn handle(&mut self, msg: GetUserClients, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(GetUserClientsTask {
+            network: self.network.clone(),
+            username: msg.0,
+        })
+    }
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "GetRoomClientsTask")]
+pub(crate) struct GetRoomClients(pub ProjectId);
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct GetRoomClientsTask {
+    network: Arc<RwLock<Topology>>,
+    project_id: ProjectId,
+}
+
+impl GetRoomClientsTask {
+    pub(crate) async fn run(self) -> Vec<ClientId> {
+        let topology = self.network.read().await;
+        topology.get_room_clients(&self.project_id)
+    }
+}
+
+impl Handler<GetRoomClients> for TopologyActor {
+    type Result = MessageResult<GetRoomClients>;
+
+    fn handle(&mut self, msg: GetRoomClients, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(GetRoomClientsTask {
+            network: self.network.clone(),
+            project_id: msg.0,
+        })
+    }
+}
+
+/// Set the state of a browser client unless the room is already full
+#[derive(Message)]
+#[rtype(result = "JoinRoomTask")]
+pub(crate) struct JoinRoom {
+    pub(crate) id: ClientId,
+    pub(crate) state: BrowserClientState,
+    pub(crate) username: Option<String>,
+    pub(crate) max_occupants: usize,
+}
+
+pub(crate) struct JoinRoomTask {
+    network: Arc<RwLock<Topology>>,
+    msg: JoinRoom,
+}
+
+impl JoinRoomTask {
+    /// Join the room. Returns false if the room is full.
+    pub(crate) async fn run(self) -> bool {
+        let mut topology = self.network.write().await;
+        topology.join_room(self.msg).await
+    }
+}
+
+impl Handler<JoinRoom> for TopologyActor {
+    type Result = MessageResult<JoinRoom>;
+
+    fn handle(&mut self, msg: JoinRoom, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(JoinRoomTask {
+            network: self.network.clone(),
+            msg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct Fail;
+
+    impl Handler<Fail> for TopologyActor {
+        type Result = ();
+
+        fn handle(&mut self, _msg: Fail, ctx: &mut Context<Self>) -> Self::Result {
+            self.spawn_supervised(ctx, async { panic!("Simulated topology failure") });
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_restart_before_shutdown() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let settings = TopologySettings {
+            max_restarts: 1,
+            restart_window_secs: 60,
+        };
+        let cache_size = NonZeroUsize::new(10).unwrap();
+        let addr = TopologyActor::new(cache_size, settings, Some(tx)).start();
+
+        addr.send(Fail).await.unwrap();
+        assert_eq!(rx.recv().await, Some(TopologyFailure::Restarted));
+
+        // the topology should still be usable after the restart
+        let task = addr.send(GetActiveRooms).await.unwrap();
+        assert!(task.run().await.is_empty());
+
+        addr.send(Fail).await.unwrap();
+        assert_eq!(rx.recv().await, Some(TopologyFailure::Fatal));
+    }
+}
@@ -1,20 +1,24 @@
 use crate::auth;
 use crate::common::api;
 use crate::common::api::{
-    AppId, BrowserClientState, ClientState, ExternalClient, OccupantState, RoleId, RoleState,
-    RoomState,
+    AppId, BrowserClientState, ClientState, ExternalClient, ExternalClientState, OccupantState,
+    RoleId, RoleState, RoomState,
 };
+use actix::Recipient;
 use futures::future::join_all;
+use futures::TryStreamExt;
 use log::warn;
 use lru::LruCache;
-use mongodb::bson::{doc, DateTime};
-use netsblox_cloud_common::SentMessage;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use netsblox_cloud_common::{QueuedMessage, SentMessage};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime};
+use uuid::Uuid;
 
 use crate::app_data::AppData;
 use crate::common::api::{ProjectId, SaveState};
@@ -25,8 +29,8 @@ use crate::network::topology::address::ClientAddress;
 pub use super::address::DEFAULT_APP_ID;
 use super::client::{Client, ClientId, RoleRequest};
 use super::{
-    AddClient, BrokenClient, ClientCommand, RemoveClient, SendIDEMessage, SendMessage,
-    SendOccupantInvite, SendRoomState, SetClientState,
+    AddClient, BrokenClient, ClientCommand, JoinRoom, RemoveClient, SendIDEMessage, SendMessage,
+    SendOccupantInvite, SendRoomState, SetClientState, SubscribeRoomState, UnsubscribeRoomState,
 };
 
 #[derive(Clone, Debug)]
@@ -80,13 +84,24 @@ impl From<EvictionNotice> for ClientCommand {
 struct ProjectNetwork {
     id: ProjectId,
     roles: HashMap<RoleId, Vec<ClientId>>,
+    /// Version of the room state. This starts at the creation time of the room
+    /// (in milliseconds) and is incremented each time the room changes so
+    /// clients can detect missed updates.
+    version: u64,
 }
 
 impl ProjectNetwork {
     fn new(id: ProjectId) -> ProjectNetwork {
+        let version = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|dur| dur.as_millis() as u64)
+            .inspect_err(|err| log::error!("Unable to compute unix timestamp: {}", err))
+            .unwrap_or_default();
+
         ProjectNetwork {
             id,
             roles: HashMap::new(),
+            version,
         }
     }
 
@@ -117,22 +132,13 @@ impl ProjectNetwork {
             })
             .collect();
 
-        let version = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|dur| dur.as_secs())
-            .map_err(|err| {
-                log::error!("Unable to compute unix timestamp: {}", &err);
-                err
-            })
-            .unwrap_or_default();
-
         RoomState {
             id: self.id.to_owned(),
             owner: project.owner,
             name: project.name,
             roles,
             collaborators: project.collaborators,
-            version,
+            version: self.version,
         }
     }
 }
@@ -146,6 +152,8 @@ pub(crate) struct Topology {
 
     rooms: HashMap<ProjectId, ProjectNetwork>,
     external: HashMap<AppId, HashMap<String, ClientId>>,
+    /// Subscribers to changes to the state of a room (which may not be active)
+    room_subscribers: HashMap<ProjectId, HashMap<Uuid, Recipient<ClientCommand>>>,
 
     address_cache: Arc<RwLock<LruCache<ClientAddress, Vec<BrowserAddress>>>>,
     cache_size: NonZeroUsize,
@@ -167,6 +175,7 @@ impl Topology {
             states: HashMap::new(),
             usernames: HashMap::new(),
             external: HashMap::new(),
+            room_subscribers: HashMap::new(),
 
             address_cache: Arc::new(RwLock::new(LruCache::new(cache_size))),
             cache_size,
@@ -177,6 +186,39 @@ impl Topology {
         self.app_data = Some(app);
     }
 
+    /// Clear the network state (eg, after recovering from a failure). Connected clients
+    /// are closed so they reconnect and register their state again.
+    pub fn reset(&mut self) {
+        for client in self.clients.values() {
+            if let Err(err) = client.addr.do_send(ClientCommand::Close) {
+                warn!("Unable to close client {:?}: {}", client.id, err);
+            }
+        }
+
+        let subscribers = self
+            .room_subscribers
+            .values()
+            .flat_map(|subs| subs.values());
+        for addr in subscribers {
+            if let Err(err) = addr.do_send(ClientCommand::Close) {
+                warn!("Unable to close room state subscriber: {}", err);
+            }
+        }
+
+        self.clients.clear();
+        self.room_subscribers.clear();
+        self.states.clear();
+        self.usernames.clear();
+        self.rooms.clear();
+        self.external.clear();
+        self.reset_address_cache();
+
+        if let Some(app_data) = &self.app_data {
+            app_data.metrics.record_connected_clients(0);
+            app_data.metrics.record_active_rooms(0);
+        }
+    }
+
     async fn get_clients_at(&self, addr: ClientAddress) -> Vec<&Client> {
         let mut client_ids: Vec<&ClientId> = Vec::new();
         let empty = Vec::new();
@@ -212,9 +254,8 @@ impl Topology {
     fn resolve_address_from_cache(&self, addr: &ClientAddress) -> Option<Vec<BrowserAddress>> {
         self.address_cache
             .write()
-            .map_err(|err| {
-                log::error!("Unable to acquire mutex for address cache to resolve address");
-                err
+            .inspect_err(|_err| {
+                log::error!("Unable to acquire mutex for address cache to resolve address")
             })
             .ok()
             .and_then(|mut cache| cache.get(addr).map(|addresses| addresses.to_vec()))
@@ -293,13 +334,164 @@ impl Topology {
             .unwrap_or_default()
     }
 
+    /// Store the message for each external app at the address without a connected
+    /// client so it can be delivered when the client reconnects. Messages are only
+    /// queued for addresses where an external client has registered before.
+    async fn queue_offline_msg(&self, addr: &ClientAddress, content: &Value) {
+        let app = match &self.app_data {
+            Some(app) if app.settings.network.queue_offline_messages => app,
+            _ => return,
+        };
+
+        let address = addr.to_app_string();
+        let ttl = Duration::from_secs(app.settings.network.offline_message_ttl_secs);
+        let offline_apps = addr
+            .app_ids
+            .iter()
+            .filter(|app_id| *app_id != DEFAULT_APP_ID)
+            .map(|app_id| AppId::new(app_id))
+            .filter(|app_id| {
+                !self
+                    .external
+                    .get(app_id)
+                    .map(|network| network.contains_key(&address))
+                    .unwrap_or(false)
+            });
+
+        let mut messages = Vec::new();
+        for app_id in offline_apps {
+            let query = doc! {"appId": app_id.as_str(), "address": &address};
+            match app.external_registrations.find_one(query, None).await {
+                Ok(Some(_)) => messages.push(QueuedMessage::new(
+                    app_id,
+                    address.clone(),
+                    content.clone(),
+                    ttl,
+                )),
+                Ok(None) => {}
+                Err(err) => warn!("Unable to check external client registration: {}", err),
+            }
+        }
+
+        if messages.is_empty() {
+            return;
+        }
+
+        if let Err(err) = app.queued_messages.insert_many(&messages, None).await {
+            warn!("Unable to queue message for offline client: {}", err);
+            return;
+        }
+
+        for msg in &messages {
+            Self::trim_queued_msgs(app, &msg.app_id, &msg.address).await;
+        }
+    }
+
+    /// Drop the oldest messages queued at the address beyond the configured limit
+    async fn trim_queued_msgs(app: &AppData, app_id: &AppId, address: &str) {
+        let query = doc! {"appId": app_id.as_str(), "address": address};
+        let options = FindOptions::builder()
+            .sort(doc! {"createdAt": -1, "_id": -1})
+            .skip(app.settings.network.max_queued_messages)
+            .projection(doc! {"_id": 1})
+            .build();
+        let queued_messages = app.queued_messages.clone_with_type::<Document>();
+        let extra = match queued_messages.find(query, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<_>>().await,
+            Err(err) => Err(err),
+        };
+        let ids: Vec<_> = match extra {
+            Ok(docs) => docs
+                .into_iter()
+                .filter_map(|doc| doc.get("_id").cloned())
+                .collect(),
+            Err(err) => {
+                warn!("Unable to fetch queued messages: {}", err);
+                return;
+            }
+        };
+
+        if !ids.is_empty() {
+            let query = doc! {"_id": {"$in": ids}};
+            if let Err(err) = queued_messages.delete_many(query, None).await {
+                warn!("Unable to remove queued messages: {}", err);
+            }
+        }
+    }
+
+    /// Remember that an external client has registered at the address so messages
+    /// sent while it is offline can be queued
+    async fn register_external_client(&self, state: &ExternalClientState) {
+        let app = match &self.app_data {
+            Some(app) if app.settings.network.queue_offline_messages => app,
+            _ => return,
+        };
+
+        let query = doc! {"appId": state.app_id.as_str(), "address": &state.address};
+        let update = doc! {"$set": {"lastSeen": DateTime::now()}};
+        let options = UpdateOptions::builder().upsert(true).build();
+        if let Err(err) = app
+            .external_registrations
+            .update_one(query, update, options)
+            .await
+        {
+            warn!("Unable to register external client: {}", err);
+        }
+    }
+
+    /// Deliver (and remove) the messages queued while the external client was offline.
+    /// Expired messages are dropped.
+    async fn send_queued_msgs(&self, id: &ClientId, state: &ExternalClientState) {
+        let (app, client) = match (&self.app_data, self.clients.get(id)) {
+            (Some(app), Some(client)) if app.settings.network.queue_offline_messages => {
+                (app, client)
+            }
+            _ => return,
+        };
+
+        let query = doc! {"appId": state.app_id.as_str(), "address": &state.address};
+        let mut unexpired = query.clone();
+        unexpired.insert("expiresAt", doc! {"$gt": DateTime::now()});
+        let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+        let messages = match app.queued_messages.find(unexpired, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<_>>().await,
+            Err(err) => Err(err),
+        };
+        let messages = match messages {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("Unable to fetch queued messages: {}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = app.queued_messages.delete_many(query, None).await {
+            warn!("Unable to remove queued messages: {}", err);
+            return;
+        }
+
+        messages.into_iter().for_each(|msg| {
+            if let Err(err) = client.addr.do_send(ClientCommand::SendMessage(msg.content)) {
+                log::error!("Unable to send queued message to client: {}", err);
+            }
+        });
+    }
+
     pub async fn send_msg(&self, msg: SendMessage) {
         if let Some(app) = &self.app_data {
             let message = ClientCommand::SendMessage(msg.content.clone());
+            let addresses: Vec<_> = msg
+                .addresses
+                .iter()
+                .filter_map(|addr_str| ClientAddress::from_str(addr_str).ok())
+                .collect();
+            for address in &addresses {
+                self.queue_offline_msg(address, &msg.content).await;
+            }
+
             let recipients: Vec<_> = join_all(
-                msg.addresses
-                    .iter()
-                    .filter_map(|addr_str| ClientAddress::from_str(addr_str).ok())
+                addresses
+                    .into_iter()
                     .map(|address| self.get_clients_at(address)),
             )
             .await
@@ -493,6 +685,7 @@ impl Topology {
                         .insert(state.role_id.clone(), vec![msg.id.clone()]);
                 }
                 let project_id = state.project_id.to_owned();
+                self.record_active_rooms();
                 self.send_room_state_for(&project_id).await;
             }
             ClientState::External(state) => {
@@ -502,11 +695,33 @@ impl Topology {
                     .or_insert_with(HashMap::new);
 
                 app_net.insert(state.address.to_owned(), msg.id.to_owned());
+                self.register_external_client(state).await;
+                self.send_queued_msgs(&msg.id, state).await;
             }
         }
         self.states.insert(msg.id, msg.state);
     }
 
+    /// Set the state of a browser client if there is space in the room. The
+    /// capacity is checked while holding the topology lock so concurrent joins
+    /// cannot exceed it. Clients already in the room (eg, switching roles) are
+    /// always allowed.
+    pub(crate) async fn join_room(&mut self, msg: JoinRoom) -> bool {
+        let occupants = self.get_room_clients(&msg.state.project_id);
+        let is_occupant = occupants.contains(&msg.id);
+        if !is_occupant && occupants.len() >= msg.max_occupants {
+            return false;
+        }
+
+        let msg = SetClientState {
+            id: msg.id,
+            state: ClientState::Browser(msg.state),
+            username: msg.username,
+        };
+        self.set_client_state(msg).await;
+        true
+    }
+
     pub fn add_client(&mut self, msg: AddClient) {
         let client = Client::new(msg.id.clone(), msg.addr);
         self.clients.insert(msg.id, client);
@@ -621,6 +836,7 @@ impl Topology {
         //   - if multiple roles and there is a broken connection:
         //     - delete after an amount of time with no activity - maybe 10 minutes?
         self.rooms.remove(project_id);
+        self.record_active_rooms();
         if let Some(app) = &self.app_data {
             // If it has no broken connections, delete it!
             let query = doc! {"id": &project_id};
@@ -691,7 +907,8 @@ impl Topology {
         // (ie, the room or role may have been renamed - or the occupancy changed)
         self.invalidate_cached_addresses(&msg.project);
 
-        if let Some(room) = self.rooms.get(&msg.project.id) {
+        if let Some(room) = self.rooms.get_mut(&msg.project.id) {
+            room.version += 1;
             let clients = room
                 .roles
                 .values()
@@ -704,6 +921,47 @@ impl Topology {
                     log::error!("Unable to send room state to client: {}", err);
                 }
             });
+
+            self.notify_room_subscribers(room_state);
+        }
+    }
+
+    /// Send the room state to everyone subscribed to changes to the room
+    fn notify_room_subscribers(&mut self, state: RoomState) {
+        if let Some(subscribers) = self.room_subscribers.get_mut(&state.id) {
+            subscribers.retain(|_id, addr| {
+                if let Err(err) = addr.do_send(state.clone().into()) {
+                    log::warn!("Unable to send room state to subscriber: {}", err);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+
+    /// Subscribe to changes to the state of a room. The current state is sent
+    /// immediately if the room is active.
+    pub fn subscribe_room_state(&mut self, msg: SubscribeRoomState) {
+        if let Some(state) = self.get_room_state(msg.project.clone()) {
+            if let Err(err) = msg.addr.do_send(state.into()) {
+                log::warn!("Unable to send room state to subscriber: {}", err);
+                return;
+            }
+        }
+
+        self.room_subscribers
+            .entry(msg.project.id)
+            .or_default()
+            .insert(msg.id, msg.addr);
+    }
+
+    pub fn unsubscribe_room_state(&mut self, msg: UnsubscribeRoomState) {
+        if let Some(subscribers) = self.room_subscribers.get_mut(&msg.project_id) {
+            subscribers.remove(&msg.id);
+            if subscribers.is_empty() {
+                self.room_subscribers.remove(&msg.project_id);
+            }
         }
     }
 
@@ -749,10 +1007,24 @@ impl Topology {
             .map(|client| RoleRequest::new(client.addr.clone()))
     }
 
+    fn record_active_rooms(&self) {
+        if let Some(app_data) = &self.app_data {
+            app_data.metrics.record_active_rooms(self.rooms.len());
+        }
+    }
+
     pub fn get_active_rooms(&self) -> Vec<ProjectId> {
         self.rooms.keys().map(|k| k.to_owned()).collect::<Vec<_>>()
     }
 
+    /// Get the number of occupants in each active room
+    pub fn get_room_occupant_counts(&self) -> HashMap<ProjectId, usize> {
+        self.rooms
+            .iter()
+            .map(|(id, room)| (id.to_owned(), room.roles.values().map(Vec::len).sum()))
+            .collect()
+    }
+
     pub fn get_external_clients(&self) -> Vec<ExternalClient> {
         self.states
             .iter()
@@ -783,6 +1055,23 @@ impl Topology {
         }
     }
 
+    /// Get the IDs of all clients logged in as the given user
+    pub fn get_user_clients(&self, username: &str) -> Vec<ClientId> {
+        self.usernames
+            .iter()
+            .filter(|(_id, name)| name.as_str() == username)
+            .map(|(id, _name)| id.to_owned())
+            .collect()
+    }
+
+    /// Get the IDs of all clients occupying a role in the given room
+    pub fn get_room_clients(&self, project_id: &ProjectId) -> Vec<ClientId> {
+        self.rooms
+            .get(project_id)
+            .map(|room| room.roles.values().flatten().cloned().collect())
+            .unwrap_or_default()
+    }
+
     pub fn get_room_state(&self, metadata: ProjectMetadata) -> Option<RoomState> {
         self.rooms
             .get(&metadata.id)
@@ -841,6 +1130,7 @@ impl Topology {
         let recipients = match msg.target {
             api::SendMessageTarget::Address { address } => {
                 if let Ok(address) = ClientAddress::from_str(&address) {
+                    self.queue_offline_msg(&address, &msg.content).await;
                     self.get_clients_at(address).await
                 } else {
                     Vec::new()
@@ -958,21 +1248,269 @@ impl Topology {
 #[cfg(test)]
 mod tests {
     use std::num::NonZeroUsize;
+    use std::time::{Duration, SystemTime};
 
-    use mongodb::bson::doc;
+    use futures::TryStreamExt;
+    use mongodb::bson::{doc, DateTime};
+    use mongodb::options::FindOptions;
     use netsblox_cloud_common::{
         api::{self, AppId, ClientId, ClientState, ExternalClientState},
-        Group, User,
+        ExternalRegistration, Group, QueuedMessage, User,
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
 
     use crate::{
-        network::topology::{SendMessage, SetStorage},
+        app_data::AppData,
+        network::topology::{
+            AddClient, ClientCommand, GetRoomClients, JoinRoom, SendMessage,
+            SendMessageFromServices, SetClientState, SetStorage, SubscribeRoomState,
+        },
         test_utils,
     };
+    use actix::{Actor, Context, Handler};
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
 
     use super::Topology;
 
+    /// Room state subscriber which forwards the states it receives
+    struct Subscriber(mpsc::UnboundedSender<api::RoomState>);
+
+    impl Actor for Subscriber {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<ClientCommand> for Subscriber {
+        type Result = ();
+        fn handle(&mut self, msg: ClientCommand, _ctx: &mut Self::Context) {
+            if let ClientCommand::SendMessage(content) = msg {
+                let state = serde_json::from_value(content).unwrap();
+                self.0.send(state).unwrap();
+            }
+        }
+    }
+
+    /// External client which forwards the content of the messages it receives
+    struct Receiver(mpsc::UnboundedSender<Value>);
+
+    impl Actor for Receiver {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<ClientCommand> for Receiver {
+        type Result = ();
+        fn handle(&mut self, msg: ClientCommand, _ctx: &mut Self::Context) {
+            if let ClientCommand::SendMessage(content) = msg {
+                self.0.send(content).unwrap();
+            }
+        }
+    }
+
+    /// Connect an external client at the given address and return the messages it receives
+    async fn connect_external(
+        app_data: &AppData,
+        address: &str,
+        app_id: &str,
+    ) -> mpsc::UnboundedReceiver<Value> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let id = ClientId::new(format!("_{}", Uuid::new_v4()));
+        app_data
+            .network
+            .send(AddClient {
+                id: id.clone(),
+                addr: Receiver(tx).start().recipient(),
+            })
+            .await
+            .unwrap();
+        app_data
+            .network
+            .send(SetClientState {
+                id,
+                state: ClientState::External(ExternalClientState {
+                    address: address.into(),
+                    app_id: AppId::new(app_id),
+                }),
+                username: None,
+            })
+            .await
+            .unwrap();
+
+        rx
+    }
+
+    /// Record a previous registration of an external client at the given address
+    async fn register_external(app_data: &AppData, address: &str, app_id: &str) {
+        let registration = ExternalRegistration::new(AppId::new(app_id), address.into());
+        app_data
+            .external_registrations
+            .insert_one(registration, None)
+            .await
+            .unwrap();
+    }
+
+    /// Send a message (from services) to the given address
+    async fn send_to_address(app_data: &AppData, address: &str, msg_type: &str) {
+        app_data
+            .network
+            .send(SendMessageFromServices {
+                message: api::SendMessage {
+                    sender: None,
+                    target: api::SendMessageTarget::Address {
+                        address: address.into(),
+                    },
+                    content: json!({"type": "message", "msgType": msg_type}),
+                },
+            })
+            .await
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_send_queued_msg_on_reconnect() {
+        test_utils::setup()
+            .run(|app_data| async move {
+                let mut app_data = app_data;
+                app_data.settings.network.queue_offline_messages = true;
+                app_data
+                    .network
+                    .send(SetStorage {
+                        app_data: app_data.clone(),
+                    })
+                    .await
+                    .unwrap();
+                register_external(&app_data, "device@brian", "TestApp").await;
+
+                send_to_address(&app_data, "device@brian #TestApp", "hello").await;
+
+                let mut rx = connect_external(&app_data, "device@brian", "TestApp").await;
+                let content = rx.recv().await.unwrap();
+                assert_eq!(content["msgType"], "hello");
+
+                let count = app_data
+                    .queued_messages
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_no_queued_msg_for_unregistered_address() {
+        test_utils::setup()
+            .run(|app_data| async move {
+                let mut app_data = app_data;
+                app_data.settings.network.queue_offline_messages = true;
+                app_data
+                    .network
+                    .send(SetStorage {
+                        app_data: app_data.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                send_to_address(&app_data, "device@brian #TestApp", "hello").await;
+
+                let count = app_data
+                    .queued_messages
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_drop_oldest_queued_msg_over_limit() {
+        test_utils::setup()
+            .run(|app_data| async move {
+                let mut app_data = app_data;
+                app_data.settings.network.queue_offline_messages = true;
+                app_data.settings.network.max_queued_messages = 2;
+                app_data
+                    .network
+                    .send(SetStorage {
+                        app_data: app_data.clone(),
+                    })
+                    .await
+                    .unwrap();
+                register_external(&app_data, "device@brian", "TestApp").await;
+
+                for msg_type in ["first", "second", "third"] {
+                    send_to_address(&app_data, "device@brian #TestApp", msg_type).await;
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+
+                let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+                let msg_types: Vec<_> = app_data
+                    .queued_messages
+                    .find(doc! {}, options)
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|msg| msg.content["msgType"].clone())
+                    .collect();
+                assert_eq!(msg_types, vec!["second", "third"]);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_drop_expired_queued_msg() {
+        let app_id = AppId::new("TestApp");
+        let ttl = Duration::from_secs(60);
+        let mut expired = QueuedMessage::new(
+            app_id.clone(),
+            "device@brian".into(),
+            json!({"type": "message", "msgType": "expired"}),
+            ttl,
+        );
+        expired.created_at = DateTime::from_system_time(SystemTime::now() - 2 * ttl);
+        expired.expires_at = DateTime::from_system_time(SystemTime::now() - ttl);
+        let queued = QueuedMessage::new(
+            app_id,
+            "device@brian".into(),
+            json!({"type": "message", "msgType": "hello"}),
+            ttl,
+        );
+
+        test_utils::setup()
+            .run(|app_data| async move {
+                let mut app_data = app_data;
+                app_data.settings.network.queue_offline_messages = true;
+                app_data
+                    .network
+                    .send(SetStorage {
+                        app_data: app_data.clone(),
+                    })
+                    .await
+                    .unwrap();
+                app_data
+                    .queued_messages
+                    .insert_many(vec![expired, queued], None)
+                    .await
+                    .unwrap();
+
+                // the expired message would be received first since it is older
+                let mut rx = connect_external(&app_data, "device@brian", "TestApp").await;
+                let content = rx.recv().await.unwrap();
+                assert_eq!(content["msgType"], "hello");
+
+                let count = app_data
+                    .queued_messages
+                    .count_documents(doc! {}, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     #[ignore]
     async fn test_remove_client_clear_state() {
@@ -1149,5 +1687,117 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_subscribe_room_state() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .build();
+        let role_id = project.roles.keys().next().unwrap().clone();
+        let state = ClientState::Browser(api::BrowserClientState {
+            project_id: project.id.clone(),
+            role_id,
+        });
+        let c1 =
+            test_utils::network::Client::new(Some(owner.username.clone()), Some(state.clone()));
+        let c2 = test_utils::network::Client::new(None, Some(state));
+
+        test_utils::setup()
+            .with_users(&[owner])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                app_data
+                    .network
+                    .send(SetStorage {
+                        app_data: app_data.clone(),
+                    })
+                    .await
+                    .unwrap();
+
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(doc! {"id": &project.id}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                app_data
+                    .network
+                    .send(SubscribeRoomState {
+                        id: Uuid::new_v4(),
+                        project: metadata,
+                        addr: Subscriber(tx).start().recipient(),
+                    })
+                    .await
+                    .unwrap();
+
+                c1.add_into(&app_data.network).await;
+                let first = rx.recv().await.unwrap();
+                assert_eq!(first.id, project.id);
+                assert_eq!(first.roles.values().next().unwrap().occupants.len(), 1);
+
+                c2.add_into(&app_data.network).await;
+                let second = rx.recv().await.unwrap();
+                assert_eq!(second.version, first.version + 1);
+                assert_eq!(second.roles.values().next().unwrap().occupants.len(), 2);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_join_room_capacity() {
+        let project = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .build();
+        let role_id = project.roles.keys().next().unwrap().clone();
+        let clients: Vec<_> = (0..3)
+            .map(|_| test_utils::network::Client::new(None, None))
+            .collect();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let mut joins = Vec::new();
+                for client in clients {
+                    let id = client.id.clone();
+                    client.add_into(&app_data.network).await;
+
+                    let task = app_data
+                        .network
+                        .send(JoinRoom {
+                            id,
+                            state: api::BrowserClientState {
+                                project_id: project.id.clone(),
+                                role_id: role_id.clone(),
+                            },
+                            username: None,
+                            max_occupants: 2,
+                        })
+                        .await
+                        .unwrap();
+                    joins.push(task.run());
+                }
+
+                // concurrent joins cannot exceed the capacity of the room
+                let joined = futures::future::join_all(joins).await;
+                assert_eq!(joined.iter().filter(|joined| **joined).count(), 2);
+
+                let task = app_data
+                    .network
+                    .send(GetRoomClients(project.id.clone()))
+                    .await
+                    .unwrap();
+                assert_eq!(task.run().await.len(), 2);
+            })
+            .await;
+    }
+
     // TODO: Add test for broken connections?
 }
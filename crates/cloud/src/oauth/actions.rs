@@ -4,7 +4,7 @@ use futures::TryStreamExt;
 use mongodb::{bson::doc, options::ReturnDocument, Collection};
 use netsblox_cloud_common::{
     api::{self, oauth},
-    OAuthClient, OAuthToken,
+    hash_client_secret, OAuthClient, OAuthToken,
 };
 use passwords::PasswordGenerator;
 use uuid::Uuid;
@@ -12,7 +12,6 @@ use uuid::Uuid;
 use crate::{
     auth,
     errors::{InternalError, OAuthFlowError, UserError},
-    utils::sha512,
 };
 
 use super::{
@@ -85,19 +84,14 @@ impl<'a> OAuthActions<'a> {
                 .unwrap_or_else(|| format!("{}?error={}", redirect_uri, error))
         } else {
             // Check that the client exists
-            // FIXME: this needs to use the salt, too!
-            let hashed_secret = sha512(&params.client_secret);
-            let query = doc! {
-                "id": &params.client_id,
-                "hash": hashed_secret
-            };
-
+            let query = doc! {"id": &params.client_id};
             let client_exists = self
                 .clients
                 .find_one(query, None)
                 .await
                 .map_err(InternalError::DatabaseConnectionError)?
-                .is_some();
+                .map(|client| client.is_valid_secret(&params.client_secret))
+                .unwrap_or(false);
 
             // TODO: is incorrect secret a different error?
             if !client_exists {
@@ -136,12 +130,7 @@ impl<'a> OAuthActions<'a> {
         name: &str,
     ) -> Result<oauth::CreatedClientData, UserError> {
         let query = doc! {"name": &name};
-        let secret = PasswordGenerator::new()
-            .length(12)
-            .spaces(false)
-            .exclude_similar_characters(true)
-            .generate_one()
-            .map_err(|_err| InternalError::PasswordGenerationError)?;
+        let secret = generate_secret()?;
 
         let client = OAuthClient::new(name.to_owned(), secret.clone());
         let client_id = client.id.clone();
@@ -185,6 +174,33 @@ impl<'a> OAuthActions<'a> {
         Ok(client.into())
     }
 
+    /// Replace the client secret. The previous secret is no longer accepted.
+    pub(crate) async fn rotate_secret(
+        &self,
+        _cc: &auth::ManageClient,
+        client_id: &oauth::ClientId,
+    ) -> Result<oauth::CreatedClientData, UserError> {
+        let secret = generate_secret()?;
+        let (hash, salt) = hash_client_secret(secret.clone());
+
+        let query = doc! {"id": client_id};
+        let update = doc! {"$set": {"hash": hash, "salt": salt}};
+        let result = self
+            .clients
+            .update_one(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        if result.matched_count == 0 {
+            return Err(UserError::OAuthClientNotFoundError);
+        }
+
+        Ok(oauth::CreatedClientData {
+            id: client_id.to_owned(),
+            secret,
+        })
+    }
+
     pub(crate) async fn list_clients(
         &self,
         _cc: &auth::ManageClient,
@@ -206,6 +222,45 @@ impl<'a> OAuthActions<'a> {
         Ok(clients)
     }
 
+    pub(crate) async fn list_tokens(
+        &self,
+        lt: &auth::ListTokens,
+    ) -> Result<Vec<oauth::Token>, UserError> {
+        let query = doc! {"username": &lt.username};
+        let cursor = self
+            .tokens
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let tokens: Vec<oauth::Token> = cursor
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|t| t.into())
+            .collect();
+
+        Ok(tokens)
+    }
+
+    /// Revoke the token. Tokens are looked up on each use so it will no longer
+    /// be accepted once removed.
+    pub(crate) async fn revoke_token(
+        &self,
+        rt: &auth::RevokeToken,
+    ) -> Result<oauth::Token, UserError> {
+        let query = doc! {"id": &rt.id};
+        let token = self
+            .tokens
+            .find_one_and_delete(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::OAuthTokenNotFoundError)?;
+
+        Ok(token.into())
+    }
+
     // There is no witness used here since it is covered by the auth code in the params
     pub(crate) async fn create_token(
         &self,
@@ -251,3 +306,14 @@ impl<'a> OAuthActions<'a> {
         Ok(token)
     }
 }
+
+fn generate_secret() -> Result<String, UserError> {
+    let secret = PasswordGenerator::new()
+        .length(12)
+        .spaces(false)
+        .exclude_similar_characters(true)
+        .generate_one()
+        .map_err(|_err| InternalError::PasswordGenerationError)?;
+
+    Ok(secret)
+}
@@ -35,7 +35,7 @@ async fn authorization_page(
     params: web::Query<AuthorizeParams>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
-    let auth_eu = if let Some(username) = utils::get_username(&req) {
+    let auth_eu = if let Some(username) = utils::get_username(&req).await {
         auth::try_edit_user(&app, &req, None, &username).await
     } else {
         Err(UserError::LoginRequiredError)
@@ -130,6 +130,46 @@ async fn get_token(
     Ok(HttpResponse::Ok().json(token))
 }
 
+#[derive(Deserialize)]
+struct ListTokensParams {
+    username: Option<String>,
+}
+
+#[get("/tokens")]
+async fn list_tokens(
+    app: web::Data<AppData>,
+    params: web::Query<ListTokensParams>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let username = match params.into_inner().username {
+        Some(username) => username,
+        None => utils::get_username(&req)
+            .await
+            .ok_or(UserError::LoginRequiredError)?,
+    };
+    let auth_lt = auth::try_list_tokens(&app, &req, &username).await?;
+
+    let actions: OAuthActions = app.as_oauth_actions();
+    let tokens = actions.list_tokens(&auth_lt).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[delete("/tokens/{tokenId}")]
+async fn revoke_token(
+    app: web::Data<AppData>,
+    path: web::Path<(oauth::TokenId,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (token_id,) = path.into_inner();
+    let auth_rt = auth::try_revoke_token(&app, &req, &token_id).await?;
+
+    let actions: OAuthActions = app.as_oauth_actions();
+    let token = actions.revoke_token(&auth_rt).await?;
+
+    Ok(HttpResponse::Ok().json(token))
+}
+
 #[post("/clients/")]
 async fn create_client(
     app: web::Data<AppData>,
@@ -171,11 +211,232 @@ async fn remove_client(
     Ok(HttpResponse::Ok().json(client))
 }
 
+#[post("/clients/{client_id}/rotate-secret")]
+async fn rotate_client_secret(
+    app: web::Data<AppData>,
+    path: web::Path<(oauth::ClientId,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_cc = auth::try_manage_client(&app, &req).await?;
+    let (client_id,) = path.into_inner();
+
+    let actions: OAuthActions = app.as_oauth_actions();
+    let client = actions.rotate_secret(&auth_cc, &client_id).await?;
+
+    Ok(HttpResponse::Ok().json(client))
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(authorization_page)
         .service(authorize_client)
         .service(create_token)
+        .service(get_token)
+        .service(list_tokens)
+        .service(revoke_token)
         .service(create_client)
         .service(list_clients)
-        .service(remove_client);
+        .service(remove_client)
+        .service(rotate_client_secret);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use actix_web::{http, test, App};
+    use netsblox_cloud_common::{OAuthClient, OAuthToken, User};
+
+    use super::*;
+    use crate::test_utils;
+
+    #[actix_web::test]
+    async fn test_revoke_token() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let code = oauth::Code {
+                    id: oauth::CodeId::new("someCode".into()),
+                    username: user.username.clone(),
+                    client_id: oauth::ClientId::new("someClient".into()),
+                    redirect_uri: "http://localhost/callback".into(),
+                    created_at: SystemTime::now(),
+                };
+                app_data.oauth_codes.insert_one(&code, None).await.unwrap();
+
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                // issue the token
+                let params = api::oauth::CreateTokenParams {
+                    code: Some(code.id.as_str().to_owned()),
+                    redirect_uri: Some(code.redirect_uri.clone()),
+                    grant_type: Some("authorization_code".into()),
+                };
+                let req = test::TestRequest::post()
+                    .uri("/token/")
+                    .set_json(&params)
+                    .to_request();
+                let token_id: oauth::TokenId = test::call_and_read_body_json(&app, req).await;
+
+                // list the issued tokens
+                let req = test::TestRequest::get()
+                    .uri("/tokens")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let tokens: Vec<oauth::Token> = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(tokens.len(), 1);
+                assert_eq!(tokens[0].id.as_str(), token_id.as_str());
+
+                // revoke it
+                let req = test::TestRequest::delete()
+                    .uri(&format!("/tokens/{}", token_id.as_str()))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                // the token should no longer be accepted
+                let req = test::TestRequest::get()
+                    .uri(&format!("/token/{}", token_id.as_str()))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+                let req = test::TestRequest::get()
+                    .uri("/tokens")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let tokens: Vec<oauth::Token> = test::call_and_read_body_json(&app, req).await;
+                assert!(tokens.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rotate_client_secret() {
+        let admin: User = api::NewUser {
+            username: "admin".into(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(api::UserRole::Admin),
+        }
+        .into();
+        let client = OAuthClient::new("someClient".into(), "oldSecret".into());
+        let client_id = client.id.clone();
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .run(|app_data| async move {
+                app_data.oauth_clients.insert_one(client, None).await.unwrap();
+
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/clients/{}/rotate-secret", client_id))
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let created: oauth::CreatedClientData =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(created.id, client_id);
+                assert_ne!(created.secret, "oldSecret");
+
+                let authorize_uri = |secret: &str| {
+                    format!(
+                        "/admin/code?client_id={}&client_secret={}&redirect_uri=http://localhost/callback&state=someState",
+                        client_id, secret
+                    )
+                };
+
+                // the old secret should no longer be accepted
+                let req = test::TestRequest::post()
+                    .uri(&authorize_uri("oldSecret"))
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+                let req = test::TestRequest::post()
+                    .uri(&authorize_uri(&created.secret))
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_token_403() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".into(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner.clone(), other.clone()])
+            .run(|app_data| async move {
+                let token = OAuthToken::new(
+                    oauth::ClientId::new("someClient".into()),
+                    owner.username.clone(),
+                );
+                let token_id = token.id.clone();
+                app_data.oauth_tokens.insert_one(token, None).await.unwrap();
+
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .uri(&format!("/tokens/{}", token_id.as_str()))
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/tokens?username={}", &owner.username))
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
 }
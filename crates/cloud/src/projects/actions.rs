@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::BufWriter;
 use std::sync::{Arc, RwLock};
 
+use crate::app_data::metrics;
 use crate::auth;
 use crate::errors::{InternalError, UserError};
 use crate::network::topology::{self, TopologyActor};
@@ -16,17 +17,22 @@ use image::{
     codecs::png::PngEncoder, ColorType, EncodableLayout, GenericImageView, ImageEncoder,
     ImageFormat, RgbaImage,
 };
+use lazy_static::lazy_static;
 use log::warn;
 use lru::LruCache;
-use mongodb::bson::{doc, DateTime};
+use mongodb::bson::{doc, DateTime, Document};
 use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
 use mongodb::{Collection, Cursor};
-use netsblox_cloud_common::api::{BrowserClientState, RoleData, RoleId, SaveState};
+use netsblox_cloud_common::api::{
+    s3::s3_key_for_role, BrowserClientState, RoleData, RoleId, SaveState,
+};
 use netsblox_cloud_common::{
     api::{self, PublishState},
     ProjectMetadata,
 };
 use netsblox_cloud_common::{Project, RoleMetadata};
+use regex::Regex;
+use rustrict::CensorStr;
 use s3::operation::put_object::PutObjectOutput;
 use uuid::Uuid;
 
@@ -34,6 +40,7 @@ pub(crate) struct ProjectActions<'a> {
     project_metadata: &'a Collection<ProjectMetadata>,
     project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
     network: &'a Addr<TopologyActor>,
+    metrics: &'a metrics::Metrics,
 
     bucket: &'a String,
     s3: &'a s3::Client,
@@ -44,6 +51,7 @@ impl<'a> ProjectActions<'a> {
         project_metadata: &'a Collection<ProjectMetadata>,
         project_cache: &'a Arc<RwLock<LruCache<api::ProjectId, ProjectMetadata>>>,
         network: &'a Addr<TopologyActor>,
+        metrics: &'a metrics::Metrics,
 
         bucket: &'a String,
         s3: &'a s3::Client,
@@ -52,6 +60,7 @@ impl<'a> ProjectActions<'a> {
             project_metadata,
             project_cache,
             network,
+            metrics,
             bucket,
             s3,
         }
@@ -106,6 +115,8 @@ impl<'a> ProjectActions<'a> {
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
 
+        self.metrics.record_project_created();
+
         Ok(metadata.into())
     }
 
@@ -167,6 +178,33 @@ impl<'a> ProjectActions<'a> {
         Ok(project)
     }
 
+    /// Create a private copy of a project owned by the given user. The role
+    /// contents are copied so changes to the copy do not affect the original.
+    pub(crate) async fn duplicate_project(
+        &self,
+        vp: &auth::projects::ViewProject,
+        eu: &auth::EditUser,
+        name: Option<&str>,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let metadata = &vp.metadata;
+        let roles = metadata
+            .roles
+            .keys()
+            .map(|role_id| self.fetch_role_data(vp, role_id.to_owned()))
+            .collect::<FuturesUnordered<_>>()
+            .try_collect::<HashMap<RoleId, RoleData>>()
+            .await?;
+
+        let project_data = CreateProjectDataDict {
+            name: name.unwrap_or(&metadata.name).to_owned(),
+            roles,
+            save_state: Some(SaveState::Saved),
+            state: PublishState::Private,
+        };
+
+        self.create_project(eu, project_data).await
+    }
+
     pub(crate) async fn get_project_thumbnail(
         &self,
         vp: &auth::projects::ViewProject,
@@ -255,13 +293,15 @@ impl<'a> ProjectActions<'a> {
         &self,
         ep: &auth::projects::EditProject,
         new_name: &str,
+        expected_version: Option<u64>,
     ) -> Result<api::ProjectMetadata, UserError> {
         let metadata = &ep.metadata;
         let name =
             utils::get_valid_project_name(self.project_metadata, &metadata.owner, new_name).await?;
 
-        let query = doc! {"id": &metadata.id};
+        let query = versioned_query(&metadata.id, expected_version);
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "name": &name,
                 "updated": DateTime::now()
@@ -275,8 +315,15 @@ impl<'a> ProjectActions<'a> {
             .project_metadata
             .find_one_and_update(query, update, options)
             .await
-            .map_err(InternalError::DatabaseConnectionError)?
-            .ok_or(UserError::ProjectNotFoundError)?;
+            .map_err(InternalError::DatabaseConnectionError)?;
+        let updated_metadata = match updated_metadata {
+            Some(metadata) => metadata,
+            None => {
+                return Err(self
+                    .missing_project_error(&metadata.id, expected_version)
+                    .await)
+            }
+        };
 
         let metadata = utils::on_room_changed(self.network, self.project_cache, updated_metadata);
         Ok(metadata.into())
@@ -294,6 +341,8 @@ impl<'a> ProjectActions<'a> {
         let query = doc! {"id": &ep.metadata.id};
         let update = doc! {
             "$pull": {"collaborators": &collaborator},
+            "$unset": {format!("collaboratorRoles.{}", collaborator): true},
+            "$inc": {"version": 1},
             "$set": {
                 "updated": DateTime::now()
             }
@@ -313,6 +362,35 @@ impl<'a> ProjectActions<'a> {
         Ok(metadata.into())
     }
 
+    pub(crate) async fn set_collaborator_role(
+        &self,
+        ep: &auth::projects::EditProject,
+        collaborator: &str,
+        role: api::CollabRole,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let query = doc! {"id": &ep.metadata.id, "collaborators": &collaborator};
+        let update = doc! {
+            "$inc": {"version": 1},
+            "$set": {
+                format!("collaboratorRoles.{}", collaborator): role,
+                "updated": DateTime::now()
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let metadata = self
+            .project_metadata
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::CollaboratorNotFoundError)?;
+
+        let metadata = utils::on_room_changed(self.network, self.project_cache, metadata);
+        Ok(metadata.into())
+    }
+
     pub(crate) async fn set_latest_role(
         &self,
         md: &auth::projects::EditProject,
@@ -335,9 +413,9 @@ impl<'a> ProjectActions<'a> {
 
     pub(crate) async fn publish_project(
         &self,
-        ep: &auth::projects::EditProject,
+        pp: &auth::projects::PublishProject,
     ) -> Result<api::PublishState, UserError> {
-        let state = if self.is_approval_required(&ep.metadata).await? {
+        let state = if self.is_approval_required(&pp.metadata).await? {
             PublishState::PendingApproval
         } else {
             PublishState::Public
@@ -346,8 +424,9 @@ impl<'a> ProjectActions<'a> {
         let options = mongodb::options::FindOneAndUpdateOptions::builder()
             .return_document(ReturnDocument::After)
             .build();
-        let query = doc! {"id": &ep.metadata.id};
+        let query = doc! {"id": &pp.metadata.id};
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "state": &state,
                 "updated": DateTime::now()
@@ -367,11 +446,12 @@ impl<'a> ProjectActions<'a> {
 
     pub(crate) async fn unpublish_project(
         &self,
-        edit: &auth::projects::EditProject,
+        pp: &auth::projects::PublishProject,
     ) -> Result<api::PublishState, UserError> {
-        let query = doc! {"id": &edit.metadata.id};
+        let query = doc! {"id": &pp.metadata.id};
         let state = PublishState::Private;
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "state": &state,
                 "updated": DateTime::now()
@@ -449,6 +529,7 @@ impl<'a> ProjectActions<'a> {
 
         let query = doc! {"id": &ep.metadata.id};
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 &format!("roles.{}", role_id): role_md,
                 "updated": DateTime::now()
@@ -470,11 +551,13 @@ impl<'a> ProjectActions<'a> {
         ep: &auth::projects::EditProject,
         role_id: RoleId,
         name: &str,
+        expected_version: Option<u64>,
     ) -> Result<api::ProjectMetadata, UserError> {
         utils::ensure_valid_name(name)?;
         if ep.metadata.roles.contains_key(&role_id) {
-            let query = doc! {"id": &ep.metadata.id};
+            let query = versioned_query(&ep.metadata.id, expected_version);
             let update = doc! {
+                "$inc": {"version": 1},
                 "$set": {
                     format!("roles.{}.name", role_id): name,
                     "updated": DateTime::now()
@@ -488,8 +571,15 @@ impl<'a> ProjectActions<'a> {
                 .project_metadata
                 .find_one_and_update(query, update, options)
                 .await
-                .map_err(InternalError::DatabaseConnectionError)?
-                .ok_or(UserError::ProjectNotFoundError)?;
+                .map_err(InternalError::DatabaseConnectionError)?;
+            let updated_metadata = match updated_metadata {
+                Some(metadata) => metadata,
+                None => {
+                    return Err(self
+                        .missing_project_error(&ep.metadata.id, expected_version)
+                        .await)
+                }
+            };
 
             let metadata =
                 utils::on_room_changed(self.network, self.project_cache, updated_metadata);
@@ -513,6 +603,7 @@ impl<'a> ProjectActions<'a> {
             "$unset": {
                 format!("roles.{}", role_id): &""
             },
+            "$inc": {"version": 1},
             "$set": {
                 "updated": DateTime::now()
             }
@@ -539,6 +630,7 @@ impl<'a> ProjectActions<'a> {
             .await
             .into_iter()
             .collect::<Result<_, _>>()?;
+        self.delete_role_versions(role).await?;
 
         let metadata = utils::on_room_changed(self.network, self.project_cache, updated_metadata);
         Ok(metadata.into())
@@ -571,6 +663,7 @@ impl<'a> ProjectActions<'a> {
         let role_md = self
             .upload_role(&metadata.owner, &metadata.id, role_id, &role)
             .await?;
+        self.add_role_version(&role_md, &role).await?;
 
         // check if the (public) project needs to be re-approved
         let state = match metadata.state {
@@ -588,6 +681,7 @@ impl<'a> ProjectActions<'a> {
 
         let query = doc! {"id": &metadata.id};
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 &format!("roles.{}", role_id): role_md,
                 "saveState": SaveState::Saved,
@@ -611,6 +705,66 @@ impl<'a> ProjectActions<'a> {
         Ok(metadata.into())
     }
 
+    /// List the saved versions of a role (newest first).
+    pub(crate) async fn list_role_versions(
+        &self,
+        ep: &auth::projects::EditProject,
+        role_id: &RoleId,
+    ) -> Result<Vec<api::RoleVersion>, UserError> {
+        let role_md = ep
+            .metadata
+            .roles
+            .get(role_id)
+            .ok_or(UserError::RoleNotFoundError)?;
+
+        let versions = self
+            .list_version_ids(role_md)
+            .await?
+            .into_iter()
+            .rev()
+            .map(|timestamp| api::RoleVersion {
+                id: timestamp.to_string(),
+                saved_at: DateTime::from_millis(timestamp).into(),
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    /// Restore the contents of a role from a saved version. The current name
+    /// of the role is kept so renaming a role does not lose its history.
+    /// Versions are removed along with the role so deleted roles cannot be
+    /// restored.
+    pub(crate) async fn restore_role_version(
+        &self,
+        ep: &auth::projects::EditProject,
+        role_id: &RoleId,
+        version: &str,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let role_md = ep
+            .metadata
+            .roles
+            .get(role_id)
+            .ok_or(UserError::RoleNotFoundError)?;
+
+        // only accept timestamps so the version cannot be used to read other keys
+        let timestamp: i64 = version
+            .parse()
+            .map_err(|_err| UserError::RoleVersionNotFoundError)?;
+
+        let basepath = format!("{}/{}", get_versions_path(role_md), timestamp);
+        let code_path = format!("{}/code.xml", basepath);
+        let media_path = format!("{}/media.xml", basepath);
+        let (code, media) = join!(self.download(&code_path), self.download(&media_path));
+        let role = RoleData {
+            name: role_md.name.to_owned(),
+            code: code.map_err(|_err| UserError::RoleVersionNotFoundError)?,
+            media: media.map_err(|_err| UserError::RoleVersionNotFoundError)?,
+        };
+
+        self.save_role(ep, role_id, role).await
+    }
+
     pub(crate) async fn delete_project(
         &self,
         dp: &auth::projects::DeleteProject,
@@ -623,16 +777,7 @@ impl<'a> ProjectActions<'a> {
             .map_err(InternalError::DatabaseConnectionError)?
             .ok_or(UserError::ProjectNotFoundError)?;
 
-        let paths = metadata
-            .roles
-            .clone()
-            .into_values()
-            .flat_map(|role| vec![role.code, role.media]);
-
-        join_all(paths.map(move |path| self.delete(path)))
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
+        self.delete_roles(metadata.roles.values()).await?;
 
         let mut cache = self.project_cache.write().unwrap();
         cache.pop(&metadata.id);
@@ -643,6 +788,60 @@ impl<'a> ProjectActions<'a> {
         Ok(metadata.into())
     }
 
+    /// Transfer a project to a new owner. The role contents (and saved versions)
+    /// are moved to the new owner and the project is renamed if the new owner
+    /// already has a project with the same name.
+    pub(crate) async fn transfer_project(
+        &self,
+        tp: &auth::projects::TransferProject,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let metadata = &tp.metadata;
+        if metadata.owner == tp.new_owner {
+            return Ok(metadata.clone().into());
+        }
+
+        let name =
+            utils::get_valid_project_name(self.project_metadata, &tp.new_owner, &metadata.name)
+                .await?;
+
+        let mut roles = Document::new();
+        for (role_id, role_md) in &metadata.roles {
+            let new_md = self
+                .copy_role(role_md, &tp.new_owner, &metadata.id, role_id)
+                .await?;
+            roles.insert(role_id.as_str(), new_md);
+        }
+
+        // The new owner no longer needs to be a collaborator
+        let query = doc! {"id": &metadata.id, "owner": &metadata.owner};
+        let update = doc! {
+            "$inc": {"version": 1},
+            "$set": {
+                "owner": &tp.new_owner,
+                "name": &name,
+                "roles": roles,
+                "updated": DateTime::now()
+            },
+            "$pull": {"collaborators": &tp.new_owner},
+            "$unset": {format!("collaboratorRoles.{}", &tp.new_owner): true},
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let updated_metadata = self
+            .project_metadata
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::ProjectNotFoundError)?;
+
+        self.delete_roles(metadata.roles.values()).await?;
+
+        let metadata = utils::on_room_changed(self.network, self.project_cache, updated_metadata);
+        Ok(metadata.into())
+    }
+
     pub(crate) async fn list_projects(
         &self,
         lp: &auth::projects::ListProjects,
@@ -657,6 +856,28 @@ impl<'a> ProjectActions<'a> {
         get_visible_projects(cursor, lp.visibility.clone()).await
     }
 
+    /// List the (saved) projects owned by the given members of a group. Anyone
+    /// who can view the group can edit its members so all their projects are visible.
+    pub(crate) async fn list_group_projects(
+        &self,
+        _vg: &auth::ViewGroup,
+        members: &[api::User],
+        state: Option<&PublishState>,
+    ) -> Result<Vec<api::ProjectMetadata>, UserError> {
+        let usernames: Vec<_> = members.iter().map(|user| &user.username).collect();
+        let mut query = doc! {"owner": {"$in": usernames}, "saveState": SaveState::Saved};
+        if let Some(state) = state {
+            query.insert("state", state.clone());
+        }
+        let cursor = self
+            .project_metadata
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        get_visible_projects(cursor, PublishState::Private).await
+    }
+
     pub(crate) async fn list_pending_projects(
         &self,
         _mp: &auth::ModerateProjects,
@@ -684,6 +905,7 @@ impl<'a> ProjectActions<'a> {
     ) -> Result<api::ProjectMetadata, UserError> {
         let query = doc! {"id": id};
         let update = doc! {
+            "$inc": {"version": 1},
             "$set": {
                 "state": state,
                 "updated": DateTime::now(),
@@ -737,6 +959,171 @@ impl<'a> ProjectActions<'a> {
         get_visible_projects(cursor, PublishState::Public).await
     }
 
+    pub(crate) async fn star_project(
+        &self,
+        sp: &auth::StarProject,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let update = doc! {"$addToSet": {"starredBy": &sp.username}};
+        self.update_metadata(&sp.metadata.id, update).await
+    }
+
+    pub(crate) async fn unstar_project(
+        &self,
+        sp: &auth::StarProject,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let update = doc! {"$pull": {"starredBy": &sp.username}};
+        self.update_metadata(&sp.metadata.id, update).await
+    }
+
+    pub(crate) async fn add_tag(
+        &self,
+        ep: &auth::EditProject,
+        tag: &str,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        ensure_valid_tag(tag)?;
+
+        // Check the tag count in the query so concurrent requests cannot exceed the limit
+        let query = doc! {
+            "id": &ep.metadata.id,
+            "$or": [
+                {"tags": tag},
+                {"$expr": {"$lt": [{"$size": {"$ifNull": ["$tags", []]}}, MAX_TAG_COUNT as i64]}},
+            ]
+        };
+        let update = doc! {"$addToSet": {"tags": tag}, "$inc": {"version": 1}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let metadata = self
+            .project_metadata
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        match metadata {
+            Some(metadata) => {
+                let metadata = utils::update_project_cache(self.project_cache, metadata);
+                Ok(metadata.into())
+            }
+            None => {
+                let exists = self
+                    .project_metadata
+                    .find_one(doc! {"id": &ep.metadata.id}, None)
+                    .await
+                    .map_err(InternalError::DatabaseConnectionError)?
+                    .is_some();
+
+                if exists {
+                    Err(UserError::TooManyProjectTags)
+                } else {
+                    Err(UserError::ProjectNotFoundError)
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn remove_tag(
+        &self,
+        ep: &auth::EditProject,
+        tag: &str,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let update = doc! {"$pull": {"tags": tag}, "$inc": {"version": 1}};
+        self.update_metadata(&ep.metadata.id, update).await
+    }
+
+    pub(crate) async fn set_tags(
+        &self,
+        ep: &auth::EditProject,
+        tags: &[String],
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let mut unique_tags: Vec<&String> = Vec::new();
+        for tag in tags {
+            ensure_valid_tag(tag)?;
+            if !unique_tags.contains(&tag) {
+                unique_tags.push(tag);
+            }
+        }
+        if unique_tags.len() > MAX_TAG_COUNT {
+            return Err(UserError::TooManyProjectTags);
+        }
+
+        let update = doc! {"$set": {"tags": unique_tags}, "$inc": {"version": 1}};
+        self.update_metadata(&ep.metadata.id, update).await
+    }
+
+    async fn update_metadata(
+        &self,
+        id: &api::ProjectId,
+        update: mongodb::bson::Document,
+    ) -> Result<api::ProjectMetadata, UserError> {
+        let query = doc! {"id": id};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let metadata = self
+            .project_metadata
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::ProjectNotFoundError)?;
+
+        // Stars and tags don't change the room so there is no need to notify the network
+        let metadata = utils::update_project_cache(self.project_cache, metadata);
+        Ok(metadata.into())
+    }
+
+    /// List the projects starred by the given user which they can still view.
+    pub(crate) async fn list_starred_projects(
+        &self,
+        vu: &auth::ViewUser,
+    ) -> Result<Vec<api::ProjectMetadata>, UserError> {
+        let query = doc! {
+            "starredBy": &vu.username,
+            "saveState": SaveState::Saved,
+            "$or": [
+                {"owner": &vu.username},
+                {"collaborators": &vu.username},
+                {"state": {"$ne": PublishState::Private}},
+            ]
+        };
+        let projects = self
+            .project_metadata
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .map_ok(|project| project.into())
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(projects)
+    }
+
+    /// Move the projects starred by the given user to the front of the list
+    /// (preserving the relative order otherwise).
+    pub(crate) async fn sort_starred_first(
+        &self,
+        username: &str,
+        projects: &mut [api::ProjectMetadata],
+    ) -> Result<(), UserError> {
+        let ids: Vec<_> = projects.iter().map(|p| &p.id).collect();
+        let query = doc! {"starredBy": username, "id": {"$in": ids}};
+        let starred: HashSet<_> = self
+            .project_metadata
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .map_ok(|project| project.id)
+            .try_collect()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        projects.sort_by_key(|p| !starred.contains(&p.id));
+        Ok(())
+    }
+
     // Helper functions
     async fn fetch_role(&self, metadata: &RoleMetadata) -> Result<RoleData, InternalError> {
         let (code, media) = join!(
@@ -781,6 +1168,54 @@ impl<'a> ProjectActions<'a> {
         Ok(())
     }
 
+    /// Delete the contents (and saved versions) of the given roles from S3
+    async fn delete_roles(
+        &self,
+        roles: impl Iterator<Item = &RoleMetadata> + Clone,
+    ) -> Result<(), UserError> {
+        let paths = roles
+            .clone()
+            .flat_map(|role| vec![role.code.clone(), role.media.clone()]);
+
+        join_all(paths.map(move |path| self.delete(path)))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        join_all(roles.map(|role| self.delete_role_versions(role)))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(())
+    }
+
+    /// Copy the contents (and saved versions) of a role to the given owner
+    async fn copy_role(
+        &self,
+        role_md: &RoleMetadata,
+        owner: &str,
+        project_id: &api::ProjectId,
+        role_id: &api::RoleId,
+    ) -> Result<RoleMetadata, UserError> {
+        let role = self.fetch_role(role_md).await?;
+        let mut new_md = self.upload_role(owner, project_id, role_id, &role).await?;
+        new_md.updated = role_md.updated;
+
+        let src_path = get_versions_path(role_md);
+        let dst_path = get_versions_path(&new_md);
+        for timestamp in self.list_version_ids(role_md).await? {
+            for filename in ["code.xml", "media.xml"] {
+                let content = self
+                    .download(&format!("{}/{}/{}", src_path, timestamp, filename))
+                    .await?;
+                self.upload(&format!("{}/{}/{}", dst_path, timestamp, filename), content)
+                    .await?;
+            }
+        }
+
+        Ok(new_md)
+    }
+
     async fn is_approval_required(&self, metadata: &ProjectMetadata) -> Result<bool, UserError> {
         for role_md in metadata.roles.values() {
             let role = self.fetch_role(role_md).await?;
@@ -791,6 +1226,25 @@ impl<'a> ProjectActions<'a> {
         Ok(false)
     }
 
+    /// Error for an update which didn't match any project. A versioned update fails
+    /// with a conflict if the project still exists (ie, it was edited in the meantime).
+    async fn missing_project_error(
+        &self,
+        id: &api::ProjectId,
+        expected_version: Option<u64>,
+    ) -> UserError {
+        if expected_version.is_none() {
+            return UserError::ProjectNotFoundError;
+        }
+
+        let query = doc! {"id": id};
+        match self.project_metadata.find_one(query, None).await {
+            Ok(Some(_)) => UserError::ProjectVersionConflictError,
+            Ok(None) => UserError::ProjectNotFoundError,
+            Err(err) => InternalError::DatabaseConnectionError(err).into(),
+        }
+    }
+
     async fn upload_role(
         &self,
         owner: &str,
@@ -798,23 +1252,95 @@ impl<'a> ProjectActions<'a> {
         role_id: &api::RoleId,
         role: &RoleData,
     ) -> Result<RoleMetadata, UserError> {
-        let is_guest = owner.starts_with('_');
-        let top_level = if is_guest { "guests" } else { "users" };
-        let basepath = format!("{}/{}/{}/{}", top_level, owner, project_id, &role_id);
-        let src_path = format!("{}/code.xml", &basepath);
-        let media_path = format!("{}/media.xml", &basepath);
+        let basepath = s3_key_for_role(owner, &project_id.to_string(), role_id.as_str())?;
+        let src_path = basepath.join("code.xml")?;
+        let media_path = basepath.join("media.xml")?;
 
-        self.upload(&media_path, role.media.to_owned()).await?;
-        self.upload(&src_path, role.code.to_owned()).await?;
+        self.upload(media_path.as_str(), role.media.to_owned())
+            .await?;
+        self.upload(src_path.as_str(), role.code.to_owned()).await?;
 
         Ok(RoleMetadata {
             name: role.name.to_owned(),
-            code: src_path,
-            media: media_path,
+            code: src_path.into(),
+            media: media_path.into(),
             updated: DateTime::now(),
         })
     }
 
+    /// Store a snapshot of the saved role and drop the oldest snapshots
+    /// to keep storage bounded.
+    async fn add_role_version(
+        &self,
+        role_md: &RoleMetadata,
+        role: &RoleData,
+    ) -> Result<(), UserError> {
+        let basepath = format!(
+            "{}/{}",
+            get_versions_path(role_md),
+            role_md.updated.timestamp_millis()
+        );
+        self.upload(&format!("{}/media.xml", basepath), role.media.to_owned())
+            .await?;
+        self.upload(&format!("{}/code.xml", basepath), role.code.to_owned())
+            .await?;
+
+        let versions = self.list_version_ids(role_md).await?;
+        let expired = versions.len().saturating_sub(MAX_ROLE_VERSIONS);
+        for timestamp in versions.into_iter().take(expired) {
+            self.delete_role_version(role_md, timestamp).await?;
+        }
+
+        Ok(())
+    }
+
+    /// List the timestamps of the saved versions of a role (oldest first).
+    async fn list_version_ids(&self, role_md: &RoleMetadata) -> Result<Vec<i64>, UserError> {
+        let prefix = format!("{}/", get_versions_path(role_md));
+        let output = self
+            .s3
+            .list_objects_v2()
+            .bucket(self.bucket.clone())
+            .prefix(&prefix)
+            .send()
+            .await
+            .map_err(|_err| InternalError::S3Error)?;
+
+        let mut versions: Vec<_> = output
+            .contents()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|object| object.key())
+            .filter_map(|key| key.strip_prefix(&prefix)?.strip_suffix("/code.xml"))
+            .filter_map(|timestamp| timestamp.parse::<i64>().ok())
+            .collect();
+        versions.sort_unstable();
+
+        Ok(versions)
+    }
+
+    async fn delete_role_version(
+        &self,
+        role_md: &RoleMetadata,
+        timestamp: i64,
+    ) -> Result<(), UserError> {
+        let basepath = format!("{}/{}", get_versions_path(role_md), timestamp);
+        join_all([
+            self.delete(format!("{}/code.xml", basepath)),
+            self.delete(format!("{}/media.xml", basepath)),
+        ])
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()
+    }
+
+    async fn delete_role_versions(&self, role_md: &RoleMetadata) -> Result<(), UserError> {
+        for timestamp in self.list_version_ids(role_md).await? {
+            self.delete_role_version(role_md, timestamp).await?;
+        }
+        Ok(())
+    }
+
     async fn upload(&self, key: &str, body: String) -> Result<PutObjectOutput, InternalError> {
         self.s3
             .put_object()
@@ -830,6 +1356,51 @@ impl<'a> ProjectActions<'a> {
     }
 }
 
+const MAX_TAG_COUNT: usize = 20;
+const MAX_ROLE_VERSIONS: usize = 10;
+
+/// Query for a project which only matches if it has not been edited since the
+/// expected version (if provided).
+fn versioned_query(id: &api::ProjectId, expected_version: Option<u64>) -> Document {
+    let mut query = doc! {"id": id};
+    match expected_version {
+        // projects which haven't been edited since versions were added don't have one
+        Some(0) => query.insert("version", doc! {"$in": [0_i64, null]}),
+        Some(version) => query.insert("version", version as i64),
+        None => None,
+    };
+    query
+}
+
+/// Get the S3 prefix for the saved versions of a role. Versions are stored
+/// next to the role contents and keyed by the (millisecond) save time.
+fn get_versions_path(role_md: &RoleMetadata) -> String {
+    let basepath = role_md
+        .code
+        .rsplit_once('/')
+        .map(|(basepath, _filename)| basepath)
+        .unwrap_or_default();
+    format!("{}/versions", basepath)
+}
+
+fn ensure_valid_tag(tag: &str) -> Result<(), UserError> {
+    if is_valid_tag(tag) {
+        Ok(())
+    } else {
+        Err(UserError::InvalidProjectTag)
+    }
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    let max_len = 25;
+    let char_count = tag.chars().count();
+    lazy_static! {
+        static ref TAG_REGEX: Regex = Regex::new(r"^[\w\-]+$").unwrap();
+    }
+
+    char_count <= max_len && TAG_REGEX.is_match(tag) && !tag.is_inappropriate()
+}
+
 async fn get_visible_projects(
     cursor: Cursor<ProjectMetadata>,
     visibility: PublishState,
@@ -883,9 +1454,9 @@ mod tests {
 
     use futures::future::join_all;
     use mongodb::bson::{doc, DateTime};
-    use netsblox_cloud_common::api;
+    use netsblox_cloud_common::{api, ProjectMetadata};
 
-    use crate::{auth, test_utils};
+    use crate::{auth, errors::UserError, test_utils};
 
     #[actix_web::test]
     async fn test_set_pending_approval_on_save_role_name() {
@@ -1002,8 +1573,8 @@ mod tests {
                 drop(cache);
 
                 // update the publish state
-                let ep = auth::EditProject::test(metadata.clone());
-                actions.publish_project(&ep).await.unwrap();
+                let pp = auth::PublishProject::test(metadata.clone());
+                actions.publish_project(&pp).await.unwrap();
 
                 // ensure the correct state is cached
                 let mut cache = actions.project_cache.write().unwrap();
@@ -1042,7 +1613,7 @@ mod tests {
 
                 let ep = auth::EditProject::test(metadata.clone());
                 actions
-                    .rename_role(&ep, role_id, "secondRole")
+                    .rename_role(&ep, role_id, "secondRole", None)
                     .await
                     .unwrap();
 
@@ -1355,7 +1926,10 @@ mod tests {
 
                 let auth_ep = auth::EditProject::test(metadata);
                 let new_name = "new project name";
-                actions.rename_project(&auth_ep, new_name).await.unwrap();
+                actions
+                    .rename_project(&auth_ep, new_name, None)
+                    .await
+                    .unwrap();
 
                 // Check the cache
                 let mut cache = actions.project_cache.write().unwrap();
@@ -1395,10 +1969,52 @@ mod tests {
 
                 let auth_ep = auth::EditProject::test(metadata.clone());
                 let new_name = "new project name";
-                let renamed = actions.rename_project(&auth_ep, new_name).await.unwrap();
+                let renamed = actions
+                    .rename_project(&auth_ep, new_name, None)
+                    .await
+                    .unwrap();
 
                 assert_ne!(metadata.updated, renamed.updated.into());
             })
             .await;
     }
+
+    #[actix_web::test]
+    async fn test_rename_project_deleted_with_version() {
+        let metadata =
+            ProjectMetadata::new("sender", "project", HashMap::new(), api::SaveState::Saved);
+
+        test_utils::setup()
+            .run(|app_data| async move {
+                let actions = app_data.as_project_actions();
+
+                // the project was deleted after the permissions were checked
+                let auth_ep = auth::EditProject::test(metadata);
+                let result = actions
+                    .rename_project(&auth_ep, "new project name", Some(0))
+                    .await;
+
+                assert!(matches!(result, Err(UserError::ProjectNotFoundError)));
+            })
+            .await;
+    }
+
+    #[test]
+    fn test_is_valid_tag() {
+        assert!(super::is_valid_tag("homework"));
+        assert!(super::is_valid_tag("unit-3_robots"));
+    }
+
+    #[test]
+    fn test_is_valid_tag_chars() {
+        assert!(!super::is_valid_tag("two words"));
+        assert!(!super::is_valid_tag("tag!"));
+        assert!(!super::is_valid_tag(""));
+    }
+
+    #[test]
+    fn test_is_valid_tag_length() {
+        assert!(super::is_valid_tag(&"a".repeat(25)));
+        assert!(!super::is_valid_tag(&"a".repeat(26)));
+    }
 }
@@ -6,7 +6,8 @@ use crate::common::api::{
 use crate::errors::{InternalError, UserError};
 use crate::projects::actions::ProjectActions;
 use crate::{auth, utils};
-use actix_web::{delete, get, patch, post, HttpRequest};
+use actix_web::http::header;
+use actix_web::{delete, get, patch, post, put, HttpRequest};
 use actix_web::{web, HttpResponse};
 use mongodb::bson::doc;
 use reqwest::Method;
@@ -20,7 +21,7 @@ async fn create_project(
 ) -> Result<HttpResponse, UserError> {
     let project_data = body.into_inner();
 
-    let current_user = utils::get_username(&req);
+    let current_user = utils::get_username(&req).await;
     let client_id = project_data.client_id.clone();
     let owner = project_data
         .owner
@@ -36,17 +37,36 @@ async fn create_project(
     Ok(HttpResponse::Ok().json(metadata))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListProjectsParams {
+    /// List the projects starred by the current user first
+    #[serde(default)]
+    starred_first: bool,
+    /// Only list projects with the given tag
+    tag: Option<String>,
+}
+
 #[get("/user/{owner}")]
 async fn list_user_projects(
     app: web::Data<AppData>,
     path: web::Path<(String,)>,
+    params: web::Query<ListProjectsParams>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (username,) = path.into_inner();
     let auth_lp = auth::try_list_projects(&app, &req, &username).await?;
 
     let actions: ProjectActions = app.as_project_actions();
-    let projects = actions.list_projects(&auth_lp).await?;
+    let mut projects = actions.list_projects(&auth_lp).await?;
+    if let Some(tag) = &params.tag {
+        projects.retain(|project| project.tags.contains(tag));
+    }
+    if let (true, Some(current_user)) = (params.starred_first, utils::get_username(&req).await) {
+        actions
+            .sort_starred_first(&current_user, &mut projects)
+            .await?;
+    }
 
     Ok(HttpResponse::Ok().json(projects))
 }
@@ -55,13 +75,37 @@ async fn list_user_projects(
 async fn list_shared_projects(
     app: web::Data<AppData>,
     path: web::Path<(String,)>,
+    params: web::Query<ListProjectsParams>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (username,) = path.into_inner();
     let auth_lp = auth::try_list_projects(&app, &req, &username).await?;
 
     let actions: ProjectActions = app.as_project_actions();
-    let projects = actions.list_shared_projects(&auth_lp).await?;
+    let mut projects = actions.list_shared_projects(&auth_lp).await?;
+    if let Some(tag) = &params.tag {
+        projects.retain(|project| project.tags.contains(tag));
+    }
+    if let (true, Some(current_user)) = (params.starred_first, utils::get_username(&req).await) {
+        actions
+            .sort_starred_first(&current_user, &mut projects)
+            .await?;
+    }
+
+    Ok(HttpResponse::Ok().json(projects))
+}
+
+#[get("/starred/{username}")]
+async fn list_starred_projects(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+    let auth_vu = auth::try_view_user(&app, &req, None, &username).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let projects = actions.list_starred_projects(&auth_vu).await?;
 
     Ok(HttpResponse::Ok().json(projects))
 }
@@ -116,7 +160,7 @@ async fn get_project_metadata(
     let actions: ProjectActions = app.as_project_actions();
     let metadata = actions.get_project_metadata(&auth_vp);
 
-    Ok(HttpResponse::Ok().json(metadata))
+    Ok(metadata_response(&req, metadata))
 }
 
 #[get("/user/{owner}/{name}/xml")]
@@ -155,7 +199,43 @@ async fn get_project_id_metadata(
     let actions: ProjectActions = app.as_project_actions();
     let metadata = actions.get_project_metadata(&auth_vp);
 
-    Ok(HttpResponse::Ok().json(metadata))
+    Ok(metadata_response(&req, metadata))
+}
+
+/// Respond with the project metadata (and ETag) or "304 Not Modified" if the
+/// client already has the current version (using If-None-Match).
+fn metadata_response(req: &HttpRequest, metadata: api::ProjectMetadata) -> HttpResponse {
+    let etag = metadata_etag(&metadata);
+    let is_cached = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        })
+        .unwrap_or(false);
+
+    if is_cached {
+        HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish()
+    } else {
+        HttpResponse::Ok()
+            .insert_header((header::ETAG, etag))
+            .json(metadata)
+    }
+}
+
+/// ETag for the project metadata. This is derived from the project version but
+/// the save state is included since it can change (eg, when the last client
+/// disconnects) without an edit.
+fn metadata_etag(metadata: &api::ProjectMetadata) -> String {
+    let data = (metadata.version, &metadata.save_state);
+    let text = serde_json::to_string(&data).unwrap_or_default();
+    format!("\"{}\"", &utils::sha512(&text)[..32])
 }
 
 #[get("/id/{projectID}")]
@@ -197,9 +277,9 @@ async fn publish_project(
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (project_id,) = path.into_inner();
-    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+    let auth_pp = auth::try_publish_project(&app, &req, &project_id).await?;
     let actions: ProjectActions = app.as_project_actions();
-    let state = actions.publish_project(&auth_ep).await?;
+    let state = actions.publish_project(&auth_pp).await?;
     Ok(HttpResponse::Ok().json(state))
 }
 
@@ -210,12 +290,88 @@ async fn unpublish_project(
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (project_id,) = path.into_inner();
-    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+    let auth_pp = auth::try_unpublish_project(&app, &req, &project_id).await?;
     let actions: ProjectActions = app.as_project_actions();
-    let state = actions.unpublish_project(&auth_ep).await?;
+    let state = actions.unpublish_project(&auth_pp).await?;
     Ok(HttpResponse::Ok().json(state))
 }
 
+#[post("/id/{projectID}/star")]
+async fn star_project(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_sp = auth::try_star_project(&app, &req, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    actions.star_project(&auth_sp).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/id/{projectID}/star")]
+async fn unstar_project(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_sp = auth::try_star_project(&app, &req, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    actions.unstar_project(&auth_sp).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[put("/id/{projectID}/tags")]
+async fn set_project_tags(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId,)>,
+    body: web::Json<Vec<String>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions.set_tags(&auth_ep, &body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+#[post("/id/{projectID}/tags/{tag}")]
+async fn add_project_tag(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, tag) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions.add_tag(&auth_ep, &tag).await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+#[delete("/id/{projectID}/tags/{tag}")]
+async fn remove_project_tag(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, tag) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions.remove_tag(&auth_ep, &tag).await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
 #[get("/mod/pending/")]
 async fn list_pending_projects(
     app: web::Data<AppData>,
@@ -244,6 +400,9 @@ async fn set_project_state(
         .set_project_state(&auth_mp, &id, state.into_inner())
         .await?;
 
+    app.audit_request(&req, api::AuditAction::SetProjectState, &id.to_string())
+        .await;
+
     Ok(HttpResponse::Ok().json(project))
 }
 
@@ -261,6 +420,44 @@ async fn delete_project(
     Ok(HttpResponse::Ok().json(project))
 }
 
+#[post("/id/{projectID}/duplicate")]
+async fn duplicate_project(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId,)>,
+    body: web::Json<api::DuplicateProjectData>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id,) = path.into_inner();
+    let auth_vp = auth::try_view_project(&app, &req, None, &project_id).await?;
+
+    // the copy is owned by the current user
+    let username = utils::get_username(&req)
+        .await
+        .ok_or(UserError::LoginRequiredError)?;
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions
+        .duplicate_project(&auth_vp, &auth_eu, body.into_inner().name.as_deref())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+#[post("/id/{projectID}/transfer/{newOwner}")]
+async fn transfer_project(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, new_owner) = path.into_inner();
+    let auth_tp = auth::try_transfer_project(&app, &req, &project_id, &new_owner).await?;
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions.transfer_project(&auth_tp).await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
 #[patch("/id/{projectID}")]
 async fn update_project(
     app: web::Data<AppData>,
@@ -274,7 +471,9 @@ async fn update_project(
     let auth_ep = auth::try_edit_project(&app, &req, body.client_id, &project_id).await?;
 
     let actions: ProjectActions = app.as_project_actions();
-    let metadata = actions.rename_project(&auth_ep, &body.name).await?;
+    let metadata = actions
+        .rename_project(&auth_ep, &body.name, body.expected_version)
+        .await?;
     Ok(HttpResponse::Ok().json(metadata))
 }
 
@@ -483,7 +682,9 @@ async fn rename_role(
     let auth_ep = auth::try_edit_project(&app, &req, body.client_id, &project_id).await?;
 
     let actions: ProjectActions = app.as_project_actions();
-    let metadata = actions.rename_role(&auth_ep, role_id, &body.name).await?;
+    let metadata = actions
+        .rename_role(&auth_ep, role_id, &body.name, body.expected_version)
+        .await?;
     Ok(HttpResponse::Ok().json(metadata))
 }
 
@@ -531,6 +732,38 @@ async fn report_latest_role(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[get("/id/{projectID}/{roleID}/versions")]
+async fn list_role_versions(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, RoleId)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, role_id) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let versions = actions.list_role_versions(&auth_ep, &role_id).await?;
+
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+#[post("/id/{projectID}/{roleID}/restore/{version}")]
+async fn restore_role_version(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, RoleId, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, role_id, version) = path.into_inner();
+    let auth_ep = auth::try_edit_project(&app, &req, None, &project_id).await?;
+
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions
+        .restore_role_version(&auth_ep, &role_id, &version)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
 #[get("/id/{projectID}/collaborators/")]
 async fn list_collaborators(
     app: web::Data<AppData>,
@@ -560,12 +793,32 @@ async fn remove_collaborator(
     Ok(HttpResponse::Ok().json(metadata))
 }
 
+#[post("/id/{projectID}/collaborators/{username}/role")]
+async fn set_collaborator_role(
+    app: web::Data<AppData>,
+    path: web::Path<(ProjectId, String)>,
+    body: web::Json<api::CollabRole>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (project_id, username) = path.into_inner();
+    let edit_proj = auth::try_edit_project(&app, &req, None, &project_id).await?;
+    let actions: ProjectActions = app.as_project_actions();
+    let metadata = actions
+        .set_collaborator_role(&edit_proj, &username, body.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(create_project)
         .service(update_project)
         .service(delete_project)
+        .service(transfer_project)
+        .service(duplicate_project)
         .service(list_user_projects)
         .service(list_shared_projects)
+        .service(list_starred_projects)
         .service(list_public_projects)
         .service(get_project)
         .service(get_project_xml)
@@ -575,6 +828,11 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(get_project_id_metadata)
         .service(publish_project)
         .service(unpublish_project)
+        .service(star_project)
+        .service(unstar_project)
+        .service(set_project_tags)
+        .service(add_project_tag)
+        .service(remove_project_tag)
         .service(list_pending_projects)
         .service(set_project_state)
         .service(get_latest_project)
@@ -584,11 +842,14 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(get_role)
         .service(get_latest_role)
         .service(report_latest_role)
+        .service(list_role_versions)
+        .service(restore_role_version)
         .service(create_role)
         .service(save_role)
         .service(rename_role)
         .service(delete_role)
         .service(list_collaborators)
+        .service(set_collaborator_role)
         .service(remove_collaborator);
 }
 
@@ -598,6 +859,7 @@ mod tests {
     use crate::test_utils;
     use actix_web::{http, test, App};
     use netsblox_cloud_common::{api::UserRole, User};
+    use std::sync::{Arc, Mutex};
 
     #[actix_web::test]
     #[ignore]
@@ -904,6 +1166,72 @@ mod tests {
         todo!();
     }
 
+    #[actix_web::test]
+    async fn test_get_project_metadata_not_modified() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::User),
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(user.username.clone())
+            .with_name("project name")
+            .build();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/metadata", &project.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+                // unchanged
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/metadata", &project.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .insert_header((header::IF_NONE_MATCH, etag.clone()))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_MODIFIED);
+
+                // changed
+                let req = test::TestRequest::post()
+                    .uri(&format!("/id/{}/tags/games", &project.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/metadata", &project.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .insert_header((header::IF_NONE_MATCH, etag.clone()))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                assert_ne!(response.headers().get(header::ETAG).unwrap(), &etag);
+                let metadata: api::ProjectMetadata = test::read_body_json(response).await;
+                assert_eq!(metadata.tags, vec!["games".to_owned()]);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     #[ignore]
     async fn test_get_project_thumbnail() {
@@ -965,6 +1293,7 @@ mod tests {
                 let update_data = api::UpdateProjectData {
                     name: "new name".into(),
                     client_id: None,
+                    expected_version: None,
                 };
                 let req = test::TestRequest::patch()
                     .cookie(test_utils::cookie::new(&admin.username))
@@ -1024,6 +1353,7 @@ mod tests {
                 let update_data = api::UpdateProjectData {
                     name: existing.name.clone(),
                     client_id: None,
+                    expected_version: None,
                 };
                 let req = test::TestRequest::patch()
                     .cookie(test_utils::cookie::new(&admin.username))
@@ -1052,45 +1382,370 @@ mod tests {
         todo!();
     }
 
+    /// Users for testing the permissions of project routes: the project owner, a
+    /// collaborator, an unrelated user and an admin.
+    fn permission_test_users() -> Vec<User> {
+        [
+            ("owner", UserRole::User),
+            ("collaborator", UserRole::User),
+            ("other", UserRole::User),
+            ("admin", UserRole::Admin),
+        ]
+        .into_iter()
+        .map(|(name, role)| {
+            api::NewUser {
+                username: name.into(),
+                email: format!("{}@netsblox.org", name),
+                password: None,
+                group_id: None,
+                role: Some(role),
+            }
+            .into()
+        })
+        .collect()
+    }
+
+    /// Make a request to the given path (where "{id}" is replaced with the project ID)
+    /// for a project owned by "owner" with "collaborator" as a collaborator and two
+    /// roles ("role1" and "role2"). Returns the response status.
+    async fn call_mutation_route(
+        request: test::TestRequest,
+        path: &'static str,
+        caller: Option<&'static str>,
+    ) -> http::StatusCode {
+        let roles = ["role1", "role2"]
+            .into_iter()
+            .map(|name| {
+                let data = api::RoleData {
+                    name: name.into(),
+                    code: "<code/>".into(),
+                    media: "<media/>".into(),
+                };
+                (api::RoleId::new(name.into()), data)
+            })
+            .collect();
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_collaborators(&["collaborator"])
+            .with_roles(roles)
+            .build();
+
+        let status = Arc::new(Mutex::new(None));
+        let status_c = status.clone();
+        test_utils::setup()
+            .with_users(&permission_test_users())
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let mut req = request.uri(&path.replace("{id}", &project.id.to_string()));
+                if let Some(username) = caller {
+                    req = req.cookie(test_utils::cookie::new(username));
+                }
+
+                let response = test::call_service(&app, req.to_request()).await;
+                *status_c.lock().unwrap() = Some(response.status());
+            })
+            .await;
+
+        let status = status.lock().unwrap().take();
+        status.unwrap()
+    }
+
+    /// Check the status of a mutation for each kind of caller
+    async fn assert_mutation_permissions(
+        request: fn() -> test::TestRequest,
+        path: &'static str,
+        expected: [(Option<&'static str>, http::StatusCode); 5],
+    ) {
+        for (caller, status) in expected {
+            assert_eq!(
+                call_mutation_route(request(), path, caller).await,
+                status,
+                "{:?}",
+                caller
+            );
+        }
+    }
+
     #[actix_web::test]
-    #[ignore]
-    async fn test_publish_project() {
-        todo!();
+    async fn test_rename_project_permissions() {
+        let request = || {
+            test::TestRequest::patch().set_json(UpdateProjectData {
+                name: "new name".into(),
+                client_id: None,
+                expected_version: None,
+            })
+        };
+        assert_mutation_permissions(
+            request,
+            "/id/{id}",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::OK),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_publish_project_403() {
-        todo!();
+    async fn test_delete_project_permissions() {
+        // collaborators cannot delete the project
+        assert_mutation_permissions(
+            test::TestRequest::delete,
+            "/id/{id}",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::FORBIDDEN),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_publish_project_admin() {
-        todo!();
+    async fn test_rename_role_permissions() {
+        let request = || {
+            test::TestRequest::patch().set_json(UpdateRoleData {
+                name: "newRole".into(),
+                client_id: None,
+                expected_version: None,
+            })
+        };
+        assert_mutation_permissions(
+            request,
+            "/id/{id}/role1",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::OK),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_unpublish_project() {
-        todo!();
+    async fn test_delete_role_permissions() {
+        assert_mutation_permissions(
+            test::TestRequest::delete,
+            "/id/{id}/role1",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::OK),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_unpublish_project_403() {
-        todo!();
+    async fn test_remove_collaborator_permissions() {
+        assert_mutation_permissions(
+            test::TestRequest::delete,
+            "/id/{id}/collaborators/collaborator",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::OK),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_unpublish_project_admin() {
-        todo!();
+    async fn test_publish_project_permissions() {
+        // publishing is not available to collaborators
+        assert_mutation_permissions(
+            test::TestRequest::post,
+            "/id/{id}/publish",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::FORBIDDEN),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
     }
 
     #[actix_web::test]
-    async fn test_list_pending() {
-        let user: User = api::NewUser {
+    async fn test_unpublish_project_permissions() {
+        assert_mutation_permissions(
+            test::TestRequest::post,
+            "/id/{id}/unpublish",
+            [
+                (Some("owner"), http::StatusCode::OK),
+                (Some("collaborator"), http::StatusCode::FORBIDDEN),
+                (Some("admin"), http::StatusCode::OK),
+                (Some("other"), http::StatusCode::FORBIDDEN),
+                (None, http::StatusCode::UNAUTHORIZED),
+            ],
+        )
+        .await;
+    }
+
+    /// Call the publish (or unpublish) route for a project owned by "owner" (with
+    /// "collaborator" as a collaborator) and return the status and resulting state.
+    async fn call_visibility_route(
+        action: &'static str,
+        caller: Option<&'static str>,
+    ) -> (http::StatusCode, api::PublishState) {
+        let users = permission_test_users();
+        let initial_state = if action == "publish" {
+            api::PublishState::Private
+        } else {
+            api::PublishState::Public
+        };
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_collaborators(&["collaborator"])
+            .with_state(initial_state)
+            .build();
+
+        let result = Arc::new(Mutex::new(None));
+        let result_c = result.clone();
+        test_utils::setup()
+            .with_users(&users)
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let mut req =
+                    test::TestRequest::post().uri(&format!("/id/{}/{}", &project.id, action));
+                if let Some(username) = caller {
+                    req = req.cookie(test_utils::cookie::new(username));
+                }
+
+                let response = test::call_service(&app, req.to_request()).await;
+                let metadata = app_data.get_project_metadatum(&project.id).await.unwrap();
+                *result_c.lock().unwrap() = Some((response.status(), metadata.state));
+            })
+            .await;
+
+        let result = result.lock().unwrap().take();
+        result.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_publish_project() {
+        let (status, state) = call_visibility_route("publish", Some("owner")).await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(state, api::PublishState::Public);
+    }
+
+    #[actix_web::test]
+    async fn test_publish_project_403() {
+        // collaborators can edit but not publish
+        let (status, state) = call_visibility_route("publish", Some("collaborator")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        assert_eq!(state, api::PublishState::Private);
+
+        let (status, _state) = call_visibility_route("publish", Some("other")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+
+        let (status, _state) = call_visibility_route("publish", None).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_publish_project_admin() {
+        let (status, state) = call_visibility_route("publish", Some("admin")).await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(state, api::PublishState::Public);
+    }
+
+    #[actix_web::test]
+    async fn test_publish_project_unverified() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_owner(user.username.clone())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_projects(&[project.clone()])
+            .run(|mut app_data| async move {
+                app_data.settings.security.require_email_verification = true;
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/id/{}/publish", &project.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_unpublish_project() {
+        let (status, state) = call_visibility_route("unpublish", Some("owner")).await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(state, api::PublishState::Private);
+    }
+
+    #[actix_web::test]
+    async fn test_unpublish_project_403() {
+        let (status, state) = call_visibility_route("unpublish", Some("collaborator")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        assert_eq!(state, api::PublishState::Public);
+
+        let (status, _state) = call_visibility_route("unpublish", Some("other")).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+
+        let (status, _state) = call_visibility_route("unpublish", None).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_unpublish_project_admin() {
+        let (status, state) = call_visibility_route("unpublish", Some("admin")).await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(state, api::PublishState::Private);
+    }
+
+    #[actix_web::test]
+    async fn test_list_pending() {
+        let user: User = api::NewUser {
             username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
@@ -1208,21 +1863,30 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_rename_project_owner() {
-        let username = "user1";
+    async fn test_duplicate_project() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
         let project = test_utils::project::builder()
-            .with_name("old_name".into())
-            .with_owner(username.to_string())
+            .with_owner(user.username.to_string())
+            .with_name("project")
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
             .build();
-        let id = project.id.clone();
-        let new_name = "new project";
-        let project_update = UpdateProjectData {
-            name: new_name.into(),
-            client_id: None,
-        };
 
         test_utils::setup()
-            .with_projects(&[project])
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1232,44 +1896,67 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}", id))
-                    .set_json(&project_update)
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/duplicate", &project.id))
+                    .set_json(api::DuplicateProjectData::default())
                     .to_request();
+                let copy: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_ne!(copy.id, project.id);
+                assert_eq!(copy.owner, user.username);
+                assert_eq!(copy.name, "project (2)");
 
+                // editing the copy doesn't change the original
+                let data = api::RoleData {
+                    name: "role".into(),
+                    code: "<new code/>".into(),
+                    media: "<new media/>".into(),
+                };
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &copy.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
 
-                let query = doc! {"id": id};
-                let project = app_data
-                    .project_metadata
-                    .find_one(query, None)
-                    .await
-                    .unwrap()
-                    .unwrap();
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .to_request();
+                let role: api::RoleData = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(&role.code, "<code/>");
+                assert_eq!(&role.media, "<media/>");
 
-                assert_eq!(project.name, new_name);
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &copy.id, &role_id))
+                    .to_request();
+                let role: api::RoleData = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(&role.code, "<new code/>");
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_rename_project_invalid_name() {
-        let username = "user1";
+    async fn test_duplicate_public_project() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
         let project = test_utils::project::builder()
-            .with_name("old name".into())
-            .with_owner(username.to_string())
+            .with_owner("owner".into())
+            .with_name("public project")
+            .with_state(api::PublishState::Public)
             .build();
-        let id = project.id.clone();
-        let new_name = "shit";
-        let project_update = UpdateProjectData {
-            name: new_name.into(),
-            client_id: None,
-        };
 
         test_utils::setup()
-            .with_projects(&[project])
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1279,94 +1966,231 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}", id))
-                    .set_json(&project_update)
+                let data = api::DuplicateProjectData {
+                    name: Some("my copy".into()),
+                };
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/duplicate", &project.id))
+                    .set_json(&data)
                     .to_request();
-
-                let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
-
-                let query = doc! {"id": id};
-                let project = app_data
-                    .project_metadata
-                    .find_one(query, None)
-                    .await
-                    .unwrap()
-                    .unwrap();
-
-                assert_eq!(project.name, "old name".to_string());
+                let copy: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(copy.owner, user.username);
+                assert_eq!(copy.name, "my copy");
+                assert_eq!(copy.state, api::PublishState::Private);
+
+                let original = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(original.owner, "owner");
+                assert_eq!(original.state, api::PublishState::Public);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_rename_project_401() {
-        let new_name = "some new name";
-        let project_update = UpdateProjectData {
-            name: new_name.into(),
-            client_id: None,
-        };
-        let id = "abc123";
+    async fn test_duplicate_project_403() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
         let project = test_utils::project::builder()
-            .with_name("old_name".into())
-            .with_id(ProjectId::new(id.to_string()))
+            .with_owner("owner".into())
             .build();
 
         test_utils::setup()
-            .with_projects(&[project])
-            .run(|app_data| async {
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
-                        .app_data(web::Data::new(app_data))
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
                         .configure(config),
                 )
                 .await;
 
-                let req = test::TestRequest::patch()
-                    .uri(&format!("/id/{}", &id))
-                    .set_json(&project_update)
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/duplicate", &project.id))
+                    .set_json(api::DuplicateProjectData::default())
                     .to_request();
-
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
             })
             .await;
     }
 
+    /// Users for testing project transfers: an owner and a recipient
+    fn transfer_users() -> Vec<User> {
+        ["owner", "recipient"]
+            .into_iter()
+            .map(|name| {
+                api::NewUser {
+                    username: name.into(),
+                    email: format!("{}@netsblox.org", name),
+                    password: None,
+                    group_id: None,
+                    role: None,
+                }
+                .into()
+            })
+            .collect()
+    }
+
     #[actix_web::test]
-    async fn test_rename_project_admin() {
-        let owner: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let admin: User = api::NewUser {
-            username: "admin".to_string(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
+    async fn test_transfer_project() {
         let project = test_utils::project::builder()
-            .with_name("old_name".into())
-            .with_owner(owner.username.clone())
+            .with_owner("owner".into())
+            .with_name("project")
+            .with_collaborators(&["recipient"])
             .build();
-        let id = project.id.clone();
-        let new_name = "new project";
-        let project_update = UpdateProjectData {
-            name: new_name.into(),
-            client_id: None,
-        };
 
         test_utils::setup()
-            .with_projects(&[project])
-            .with_users(&[owner, admin.clone()])
+            .with_users(&transfer_users())
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("owner"))
+                    .uri(&format!("/id/{}/transfer/recipient", &project.id))
+                    .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.owner, "recipient");
+                assert_eq!(metadata.name, "project");
+                assert!(metadata.collaborators.is_empty());
+
+                let metadata = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(metadata.owner, "recipient");
+                assert!(metadata
+                    .roles
+                    .values()
+                    .all(|role| role.code.starts_with("users/recipient/")));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_project_name_collision() {
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_name("project")
+            .build();
+        let existing = test_utils::project::builder()
+            .with_owner("recipient".into())
+            .with_name("project")
+            .build();
+
+        test_utils::setup()
+            .with_users(&transfer_users())
+            .with_projects(&[project.clone(), existing])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("owner"))
+                    .uri(&format!("/id/{}/transfer/recipient", &project.id))
+                    .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.owner, "recipient");
+                assert_eq!(metadata.name, "project (2)");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_project_403() {
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_collaborators(&["recipient"])
+            .build();
+
+        test_utils::setup()
+            .with_users(&transfer_users())
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // collaborators cannot take ownership of the project
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("recipient"))
+                    .uri(&format!("/id/{}/transfer/recipient", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let metadata = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(metadata.owner, "owner");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_transfer_project_user_404() {
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .build();
+
+        test_utils::setup()
+            .with_users(&transfer_users())
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("owner"))
+                    .uri(&format!("/id/{}/transfer/nonExistentUser", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_project_owner() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_name("old_name".into())
+            .with_owner(username.to_string())
+            .build();
+        let id = project.id.clone();
+        let new_name = "new project";
+        let project_update = UpdateProjectData {
+            name: new_name.into(),
+            client_id: None,
+            expected_version: None,
+        };
+
+        test_utils::setup()
+            .with_projects(&[project])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1377,137 +2201,1258 @@ mod tests {
                 .await;
 
                 let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(&admin.username))
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}", id))
+                    .set_json(&project_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"id": id};
+                let project = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                assert_eq!(project.name, new_name);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_project_stale_version() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_name("old_name".into())
+            .with_owner(username.to_string())
+            .build();
+        let id = project.id.clone();
+
+        test_utils::setup()
+            .with_projects(&[project])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let version = 0;
+                let first_update = UpdateProjectData {
+                    name: "first name".into(),
+                    client_id: None,
+                    expected_version: Some(version),
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}", id))
+                    .set_json(&first_update)
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(project.name, first_update.name);
+                assert_eq!(project.version, version + 1);
+
+                // a second client which loaded the same version should lose the race
+                let stale_update = UpdateProjectData {
+                    name: "second name".into(),
+                    client_id: None,
+                    expected_version: Some(version),
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}", id))
+                    .set_json(&stale_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::CONFLICT);
+
+                let query = doc! {"id": id};
+                let project = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                assert_eq!(project.name, first_update.name);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_project_invalid_name() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_name("old name".into())
+            .with_owner(username.to_string())
+            .build();
+        let id = project.id.clone();
+        let new_name = "shit";
+        let project_update = UpdateProjectData {
+            name: new_name.into(),
+            client_id: None,
+            expected_version: None,
+        };
+
+        test_utils::setup()
+            .with_projects(&[project])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(username))
                     .uri(&format!("/id/{}", id))
                     .set_json(&project_update)
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::OK);
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+                let query = doc! {"id": id};
+                let project = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                assert_eq!(project.name, "old name".to_string());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_project_401() {
+        let new_name = "some new name";
+        let project_update = UpdateProjectData {
+            name: new_name.into(),
+            client_id: None,
+            expected_version: None,
+        };
+        let id = "abc123";
+        let project = test_utils::project::builder()
+            .with_name("old_name".into())
+            .with_id(ProjectId::new(id.to_string()))
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project])
+            .run(|app_data| async {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::patch()
+                    .uri(&format!("/id/{}", &id))
+                    .set_json(&project_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_project_admin() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+        let project = test_utils::project::builder()
+            .with_name("old_name".into())
+            .with_owner(owner.username.clone())
+            .build();
+        let id = project.id.clone();
+        let new_name = "new project";
+        let project_update = UpdateProjectData {
+            name: new_name.into(),
+            client_id: None,
+            expected_version: None,
+        };
+
+        test_utils::setup()
+            .with_projects(&[project])
+            .with_users(&[owner, admin.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri(&format!("/id/{}", id))
+                    .set_json(&project_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_list_user_projects() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_list_user_projects_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_list_user_projects_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_view_shared_projects() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_view_shared_projects_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_view_shared_projects_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_create_role() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_create_role_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_create_role_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_create_role_room_update() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_role() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_role_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_role_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_latest_role() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_latest_role_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_get_latest_role_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    async fn test_rename_role() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = UpdateRoleData {
+                    name: "new_name".into(),
+                    client_id: None,
+                    expected_version: None,
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, data.name);
+
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, data.name);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_role_stale_version() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+        let id = project.id.clone();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // the project is updated by another client
+                let version = 0;
+                let current_update = UpdateProjectData {
+                    name: "renamed project".into(),
+                    client_id: None,
+                    expected_version: Some(version),
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}", &id))
+                    .set_json(&current_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let stale_update = UpdateRoleData {
+                    name: "new_name".into(),
+                    client_id: None,
+                    expected_version: Some(version),
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &id, &role_id))
+                    .set_json(&stale_update)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::CONFLICT);
+
+                let query = doc! {"id": &id};
+                let project = app_data
+                    .project_metadata
+                    .find_one(query, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, "role");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_role_invalid_name() {
+        let username = "user1";
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(username.to_string())
+            .with_collaborators(&["user2", "user3"])
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = UpdateRoleData {
+                    name: "$ .1 damn".into(),
+                    client_id: None,
+                    expected_version: None,
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, "role".to_string());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_role_no_perms() {
+        let username = "user1";
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .with_collaborators(&["user2", "user3"])
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = UpdateRoleData {
+                    name: "X".into(),
+                    client_id: None,
+                    expected_version: None,
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, "role".to_string());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_rename_role_admin() {
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner("owner".to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_users(&[admin.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = UpdateRoleData {
+                    name: "new_name".into(),
+                    client_id: None,
+                    expected_version: None,
+                };
+                let req = test::TestRequest::patch()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, data.name);
+
+                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
+                let role = project.roles.get(&role_id).unwrap();
+                assert_eq!(role.name, "new_name".to_string());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_save_role() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::RoleData {
+                    name: "new name".into(),
+                    code: "<new code/>".into(),
+                    media: "<new media/>".into(),
+                };
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+
+                // Check the contents of the role
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .to_request();
+                let role: api::RoleData = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(&role.name, "new name");
+                assert_eq!(&role.code, "<new code/>");
+                assert_eq!(&role.media, "<new media/>");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_restore_role_version() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // save twice
+                for code in ["<first code/>", "<second code/>"] {
+                    let data = api::RoleData {
+                        name: "role".into(),
+                        code: code.into(),
+                        media: "<media/>".into(),
+                    };
+                    let req = test::TestRequest::post()
+                        .cookie(test_utils::cookie::new(&user.username))
+                        .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                        .set_json(&data)
+                        .to_request();
+                    let response = test::call_service(&app, req).await;
+                    assert_eq!(response.status(), http::StatusCode::OK);
+                }
+
+                // list the versions
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}/versions", &project.id, &role_id))
+                    .to_request();
+                let versions: Vec<api::RoleVersion> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(versions.len(), 2);
+
+                // restore the first (oldest) version
+                let first = versions.last().unwrap();
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!(
+                        "/id/{}/{}/restore/{}",
+                        &project.id, &role_id, &first.id
+                    ))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .to_request();
+                let role: api::RoleData = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(&role.code, "<first code/>");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_restore_role_version_404() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!(
+                        "/id/{}/{}/restore/notAVersion",
+                        &project.id, &role_id
+                    ))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_save_role_403() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone(), other.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::RoleData {
+                    name: "new name".into(),
+                    code: "<new code/>".into(),
+                    media: "<new media/>".into(),
+                };
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_save_role_admin() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone(), admin.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::RoleData {
+                    name: "new name".into(),
+                    code: "<new code/>".into(),
+                    media: "<new media/>".into(),
+                };
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .set_json(&data)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_role() {
+        let user: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let role_id = api::RoleId::new("someRole".into());
+        let role_data = api::RoleData {
+            name: "role".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let role2_id = api::RoleId::new("role2Id".into());
+        let role2_data = api::RoleData {
+            name: "role2".into(),
+            code: "<code/>".into(),
+            media: "<media/>".into(),
+        };
+        let project = test_utils::project::builder()
+            .with_owner(user.username.to_string())
+            .with_roles(
+                [(role_id.clone(), role_data), (role2_id.clone(), role2_data)]
+                    .into_iter()
+                    .collect(),
+            )
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let role_opt = project.roles.get(&role_id);
+                assert!(role_opt.is_none(), "Role not deleted");
+
+                let role2_opt = project.roles.get(&role2_id);
+                assert!(role2_opt.is_some(), "Other role deleted!");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_delete_role_403() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_delete_role_admin() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_delete_role_room_update() {
+        todo!();
+    }
+
+    #[actix_web::test]
+    async fn test_remove_collaborator() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_owner(username.to_string())
+            .with_collaborators(&["user2", "user3"])
+            .build();
+
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                    .to_request();
+
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let expected = ["user3"];
+                project
+                    .collaborators
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(i, name)| assert_eq!(name, expected[i]));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn test_remove_collaborator_invalid_name() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_collaborators(&["user2", "user3"])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&owner.username))
+                    .uri(&format!(
+                        "/id/{}/collaborators/notACollaborator",
+                        &project.id
+                    ))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                // Currently, this doesn't detect if the project was changed
+                // and just returns "OK" if the project doesn't have that
+                // collaborator after the call.
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_remove_collaborator_403() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let other: User = api::NewUser {
+            username: "other".to_string(),
+            email: "other@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_collaborators(&["user2", "user3"])
+            .build();
+
+        test_utils::setup()
+            .with_users(&[owner, other.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&other.username))
+                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
             })
             .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_list_user_projects() {
-        todo!();
-    }
-
-    #[actix_web::test]
-    #[ignore]
-    async fn test_list_user_projects_403() {
-        todo!();
-    }
+    async fn test_remove_collaborator_admin() {
+        let owner: User = api::NewUser {
+            username: "owner".to_string(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_list_user_projects_admin() {
-        todo!();
-    }
+        let project = test_utils::project::builder()
+            .with_owner(owner.username.clone())
+            .with_collaborators(&["user2", "user3"])
+            .build();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_view_shared_projects() {
-        todo!();
-    }
+        test_utils::setup()
+            .with_users(&[owner, admin.clone()])
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_view_shared_projects_403() {
-        todo!();
-    }
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                    .to_request();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_view_shared_projects_admin() {
-        todo!();
+                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let expected = ["user3"];
+                project
+                    .collaborators
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(i, name)| assert_eq!(name, expected[i]));
+            })
+            .await;
     }
 
     #[actix_web::test]
     #[ignore]
-    async fn test_create_role() {
+    async fn test_remove_collaborator_room_update() {
         todo!();
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_create_role_403() {
-        todo!();
-    }
+    async fn test_list_collaborators() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_owner(username.to_string())
+            .with_collaborators(&["user2", "user3"])
+            .build();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_create_role_admin() {
-        todo!();
-    }
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_create_role_room_update() {
-        todo!();
-    }
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(username))
+                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                    .to_request();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_get_role() {
-        todo!();
+                let collaborators: Vec<String> = test::call_and_read_body_json(&app, req).await;
+                collaborators
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(i, name)| assert_eq!(name, project.collaborators[i]));
+            })
+            .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_get_role_403() {
-        todo!();
-    }
+    async fn test_list_collaborators_403() {
+        let username = "user1";
+        let project = test_utils::project::builder()
+            .with_owner(username.to_string())
+            .with_collaborators(&["user2", "user3"])
+            .build();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_get_role_admin() {
-        todo!();
-    }
+        test_utils::setup()
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_get_latest_role() {
-        todo!();
-    }
+                let req = test::TestRequest::get()
+                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                    .to_request();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_get_latest_role_403() {
-        todo!();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+            })
+            .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_get_latest_role_admin() {
-        todo!();
-    }
+    async fn test_list_collaborators_admin() {
+        let admin: User = api::NewUser {
+            username: "admin".into(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
 
-    #[actix_web::test]
-    async fn test_rename_role() {
-        let user: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "admin@netsblox.org".into(),
             password: None,
             group_id: None,
-            role: None,
+            role: Some(UserRole::Admin),
         }
         .into();
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
         let project = test_utils::project::builder()
-            .with_owner(user.username.to_string())
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner(owner.username.clone())
+            .with_collaborators(&["user2", "user3"])
             .build();
 
         test_utils::setup()
             .with_projects(&[project.clone()])
-            .with_users(&[user.clone()])
+            .with_users(&[admin.clone(), owner])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1517,43 +3462,108 @@ mod tests {
                 )
                 .await;
 
-                let data = UpdateRoleData {
-                    name: "new_name".into(),
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                    .to_request();
+
+                let collaborators: Vec<String> = test::call_and_read_body_json(&app, req).await;
+                collaborators
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(i, name)| assert_eq!(name, project.collaborators[i]));
+            })
+            .await;
+    }
+
+    /// Users for testing collaborator roles: an owner, an editor and a viewer
+    fn collaborator_role_users() -> Vec<User> {
+        ["owner", "editor", "viewer"]
+            .into_iter()
+            .map(|name| {
+                api::NewUser {
+                    username: name.into(),
+                    email: format!("{}@netsblox.org", name),
+                    password: None,
+                    group_id: None,
+                    role: None,
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_collaborator_role_viewer() {
+        let project = test_utils::project::builder()
+            .with_owner("owner".into())
+            .with_name("initial name")
+            .with_collaborators(&["editor", "viewer"])
+            .with_collaborator_role("viewer", api::CollabRole::Viewer)
+            .build();
+
+        test_utils::setup()
+            .with_users(&collaborator_role_users())
+            .with_projects(&[project.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                // viewers can fetch the project
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new("viewer"))
+                    .uri(&format!("/id/{}/metadata", &project.id))
+                    .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(
+                    metadata.collaborator_roles.get("viewer"),
+                    Some(&api::CollabRole::Viewer)
+                );
+
+                // but cannot rename it...
+                let update_data = api::UpdateProjectData {
+                    name: "new name".into(),
                     client_id: None,
+                    expected_version: None,
                 };
                 let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(&user.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                    .cookie(test_utils::cookie::new("viewer"))
+                    .uri(&format!("/id/{}", &project.id))
+                    .set_json(&update_data)
                     .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
 
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, data.name);
+                // or delete it
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new("viewer"))
+                    .uri(&format!("/id/{}", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
 
-                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, data.name);
+                let metadata = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(metadata.name, "initial name");
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_rename_role_invalid_name() {
-        let username = "user1";
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_collaborator_role_editor() {
         let project = test_utils::project::builder()
-            .with_owner(username.to_string())
-            .with_collaborators(&["user2", "user3"])
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("owner".into())
+            .with_name("initial name")
+            .with_collaborators(&["editor", "viewer"])
+            .with_collaborator_role("viewer", api::CollabRole::Viewer)
             .build();
 
         test_utils::setup()
+            .with_users(&collaborator_role_users())
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -1564,42 +3574,42 @@ mod tests {
                 )
                 .await;
 
-                let data = UpdateRoleData {
-                    name: "$ .1 damn".into(),
+                // collaborators without an explicit role are editors
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new("editor"))
+                    .uri(&format!("/id/{}/metadata", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let update_data = api::UpdateProjectData {
+                    name: "new name".into(),
                     client_id: None,
+                    expected_version: None,
                 };
                 let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                    .cookie(test_utils::cookie::new("editor"))
+                    .uri(&format!("/id/{}", &project.id))
+                    .set_json(&update_data)
                     .to_request();
-
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+                assert_eq!(response.status(), http::StatusCode::OK);
 
-                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, "role".to_string());
+                let metadata = app_data.get_project_metadatum(&project.id).await.unwrap();
+                assert_eq!(metadata.name, "new name");
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_rename_role_no_perms() {
-        let username = "user1";
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_set_collaborator_role() {
         let project = test_utils::project::builder()
-            .with_owner("owner".to_string())
-            .with_collaborators(&["user2", "user3"])
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("owner".into())
+            .with_collaborators(&["editor", "viewer"])
             .build();
 
         test_utils::setup()
+            .with_users(&collaborator_role_users())
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -1610,50 +3620,38 @@ mod tests {
                 )
                 .await;
 
-                let data = UpdateRoleData {
-                    name: "X".into(),
-                    client_id: None,
-                };
-                let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("owner"))
+                    .uri(&format!("/id/{}/collaborators/viewer/role", &project.id))
+                    .set_json(api::CollabRole::Viewer)
                     .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(
+                    metadata.collaborator_roles.get("viewer"),
+                    Some(&api::CollabRole::Viewer)
+                );
 
+                // the new viewer can no longer make changes
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("viewer"))
+                    .uri(&format!("/id/{}/collaborators/editor/role", &project.id))
+                    .set_json(api::CollabRole::Viewer)
+                    .to_request();
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
-
-                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, "role".to_string());
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_rename_role_admin() {
-        let admin: User = api::NewUser {
-            username: "admin".to_string(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_set_collaborator_role_404() {
         let project = test_utils::project::builder()
-            .with_owner("owner".to_string())
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("owner".into())
+            .with_collaborators(&["editor"])
             .build();
 
         test_utils::setup()
-            .with_users(&[admin.clone()])
+            .with_users(&collaborator_role_users())
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -1664,51 +3662,26 @@ mod tests {
                 )
                 .await;
 
-                let data = UpdateRoleData {
-                    name: "new_name".into(),
-                    client_id: None,
-                };
-                let req = test::TestRequest::patch()
-                    .cookie(test_utils::cookie::new(&admin.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("owner"))
+                    .uri(&format!("/id/{}/collaborators/viewer/role", &project.id))
+                    .set_json(api::CollabRole::Viewer)
                     .to_request();
-
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, data.name);
-
-                let project = app_data.get_project_metadatum(&project.id).await.unwrap();
-                let role = project.roles.get(&role_id).unwrap();
-                assert_eq!(role.name, "new_name".to_string());
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_save_role() {
-        let user: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_star_project() {
         let project = test_utils::project::builder()
-            .with_owner(user.username.to_string())
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("user1".to_string())
+            .with_state(api::PublishState::Public)
             .build();
 
         test_utils::setup()
             .with_projects(&[project.clone()])
-            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1718,64 +3691,43 @@ mod tests {
                 )
                 .await;
 
-                let data = api::RoleData {
-                    name: "new name".into(),
-                    code: "<new code/>".into(),
-                    media: "<new media/>".into(),
-                };
                 let req = test::TestRequest::post()
-                    .cookie(test_utils::cookie::new(&user.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                    .cookie(test_utils::cookie::new("user2"))
+                    .uri(&format!("/id/{}/star", &project.id))
                     .to_request();
 
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(doc! {"id": &project.id}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(metadata.starred_by, vec!["user2".to_string()]);
 
-                // Check the contents of the role
                 let req = test::TestRequest::get()
-                    .cookie(test_utils::cookie::new(&user.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
+                    .cookie(test_utils::cookie::new("user2"))
+                    .uri("/starred/user2")
                     .to_request();
-                let role: api::RoleData = test::call_and_read_body_json(&app, req).await;
-                assert_eq!(&role.name, "new name");
-                assert_eq!(&role.code, "<new code/>");
-                assert_eq!(&role.media, "<new media/>");
+
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(projects.len(), 1);
+                assert_eq!(projects[0].id, project.id);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_save_role_403() {
-        let user: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let other: User = api::NewUser {
-            username: "other".to_string(),
-            email: "other@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_star_project_403() {
         let project = test_utils::project::builder()
-            .with_owner(user.username.to_string())
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
             .with_projects(&[project.clone()])
-            .with_users(&[user.clone(), other.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1785,15 +3737,9 @@ mod tests {
                 )
                 .await;
 
-                let data = api::RoleData {
-                    name: "new name".into(),
-                    code: "<new code/>".into(),
-                    media: "<new media/>".into(),
-                };
                 let req = test::TestRequest::post()
-                    .cookie(test_utils::cookie::new(&other.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                    .cookie(test_utils::cookie::new("user2"))
+                    .uri(&format!("/id/{}/star", &project.id))
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
@@ -1803,37 +3749,13 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_save_role_admin() {
-        let user: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let admin: User = api::NewUser {
-            username: "admin".to_string(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+    async fn test_unstar_project() {
         let project = test_utils::project::builder()
-            .with_owner(user.username.to_string())
-            .with_roles([(role_id.clone(), role_data)].into_iter().collect())
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
             .with_projects(&[project.clone()])
-            .with_users(&[user.clone(), admin.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1843,57 +3765,41 @@ mod tests {
                 )
                 .await;
 
-                let data = api::RoleData {
-                    name: "new name".into(),
-                    code: "<new code/>".into(),
-                    media: "<new media/>".into(),
-                };
                 let req = test::TestRequest::post()
-                    .cookie(test_utils::cookie::new(&admin.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .set_json(&data)
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/star", &project.id))
                     .to_request();
-
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
-            })
-            .await;
-    }
 
-    #[actix_web::test]
-    async fn test_delete_role() {
-        let user: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let role_id = api::RoleId::new("someRole".into());
-        let role_data = api::RoleData {
-            name: "role".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
-        let role2_id = api::RoleId::new("role2Id".into());
-        let role2_data = api::RoleData {
-            name: "role2".into(),
-            code: "<code/>".into(),
-            media: "<media/>".into(),
-        };
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/star", &project.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri("/starred/user1")
+                    .to_request();
+
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(projects.is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_starred_projects_hides_private() {
+        // user2 starred the project while it was public
         let project = test_utils::project::builder()
-            .with_owner(user.username.to_string())
-            .with_roles(
-                [(role_id.clone(), role_data), (role2_id.clone(), role2_data)]
-                    .into_iter()
-                    .collect(),
-            )
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
             .with_projects(&[project.clone()])
-            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1903,45 +3809,74 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
-                    .cookie(test_utils::cookie::new(&user.username))
-                    .uri(&format!("/id/{}/{}", &project.id, &role_id))
-                    .to_request();
+                let query = doc! {"id": &project.id};
+                let update = doc! {"$set": {"starredBy": ["user2"]}};
+                app_data
+                    .project_metadata
+                    .update_one(query, update, None)
+                    .await
+                    .unwrap();
 
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                let role_opt = project.roles.get(&role_id);
-                assert!(role_opt.is_none(), "Role not deleted");
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new("user2"))
+                    .uri("/starred/user2")
+                    .to_request();
 
-                let role2_opt = project.roles.get(&role2_id);
-                assert!(role2_opt.is_some(), "Other role deleted!");
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(projects.is_empty());
             })
             .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_delete_role_403() {
-        todo!();
-    }
+    async fn test_list_user_projects_starred_first() {
+        let projects: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                test_utils::project::builder()
+                    .with_owner("user1".to_string())
+                    .with_name(name)
+                    .build()
+            })
+            .collect();
+        let starred = projects[2].clone();
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_delete_role_admin() {
-        todo!();
-    }
+        test_utils::setup()
+            .with_projects(&projects)
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
 
-    #[actix_web::test]
-    #[ignore]
-    async fn test_delete_role_room_update() {
-        todo!();
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/star", &starred.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri("/user/user1?starredFirst=true")
+                    .to_request();
+
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(projects.len(), 3);
+                assert_eq!(projects[0].id, starred.id);
+            })
+            .await;
     }
 
     #[actix_web::test]
-    async fn test_remove_collaborator() {
-        let username = "user1";
+    async fn test_add_project_tag() {
         let project = test_utils::project::builder()
-            .with_owner(username.to_string())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
@@ -1955,41 +3890,32 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/homework", &project.id))
                     .to_request();
 
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                let expected = ["user3"];
-                project
-                    .collaborators
-                    .into_iter()
-                    .enumerate()
-                    .for_each(|(i, name)| assert_eq!(name, expected[i]));
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.tags, vec!["homework".to_string()]);
+
+                let metadata = app_data
+                    .project_metadata
+                    .find_one(doc! {"id": &project.id}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(metadata.tags, vec!["homework".to_string()]);
             })
             .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_remove_collaborator_invalid_name() {
-        let owner: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-
+    async fn test_add_project_tag_invalid() {
         let project = test_utils::project::builder()
-            .with_owner(owner.username.clone())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
-            .with_users(&[owner.clone()])
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -2000,49 +3926,25 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
-                    .cookie(test_utils::cookie::new(&owner.username))
-                    .uri(&format!(
-                        "/id/{}/collaborators/notACollaborator",
-                        &project.id
-                    ))
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/bad%20tag!", &project.id))
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                // Currently, this doesn't detect if the project was changed
-                // and just returns "OK" if the project doesn't have that
-                // collaborator after the call.
-                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_remove_collaborator_403() {
-        let owner: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let other: User = api::NewUser {
-            username: "other".to_string(),
-            email: "other@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-
+    async fn test_add_project_tag_403() {
         let project = test_utils::project::builder()
-            .with_owner(owner.username.clone())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
+            .with_state(api::PublishState::Public)
             .build();
 
         test_utils::setup()
-            .with_users(&[owner, other.clone()])
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -2053,9 +3955,9 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
-                    .cookie(test_utils::cookie::new(&other.username))
-                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user2"))
+                    .uri(&format!("/id/{}/tags/homework", &project.id))
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
@@ -2065,31 +3967,12 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_remove_collaborator_admin() {
-        let owner: User = api::NewUser {
-            username: "owner".to_string(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let admin: User = api::NewUser {
-            username: "admin".to_string(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-
+    async fn test_remove_project_tag() {
         let project = test_utils::project::builder()
-            .with_owner(owner.username.clone())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
-            .with_users(&[owner, admin.clone()])
             .with_projects(&[project.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
@@ -2100,34 +3983,28 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::delete()
-                    .cookie(test_utils::cookie::new(&admin.username))
-                    .uri(&format!("/id/{}/collaborators/user2", &project.id))
+                let req = test::TestRequest::put()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags", &project.id))
+                    .set_json(vec!["homework", "robots", "homework"])
                     .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.tags, vec!["homework", "robots"]);
 
-                let project: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
-                let expected = ["user3"];
-                project
-                    .collaborators
-                    .into_iter()
-                    .enumerate()
-                    .for_each(|(i, name)| assert_eq!(name, expected[i]));
+                let req = test::TestRequest::delete()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/homework", &project.id))
+                    .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.tags, vec!["robots"]);
             })
             .await;
     }
 
     #[actix_web::test]
-    #[ignore]
-    async fn test_remove_collaborator_room_update() {
-        todo!();
-    }
-
-    #[actix_web::test]
-    async fn test_list_collaborators() {
-        let username = "user1";
+    async fn test_set_project_tags_too_many() {
         let project = test_utils::project::builder()
-            .with_owner(username.to_string())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
@@ -2141,26 +4018,23 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::get()
-                    .cookie(test_utils::cookie::new(username))
-                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                let tags: Vec<_> = (0..21).map(|i| format!("tag{}", i)).collect();
+                let req = test::TestRequest::put()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags", &project.id))
+                    .set_json(tags)
                     .to_request();
 
-                let collaborators: Vec<String> = test::call_and_read_body_json(&app, req).await;
-                collaborators
-                    .into_iter()
-                    .enumerate()
-                    .for_each(|(i, name)| assert_eq!(name, project.collaborators[i]));
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_list_collaborators_403() {
-        let username = "user1";
+    async fn test_add_project_tag_too_many() {
         let project = test_utils::project::builder()
-            .with_owner(username.to_string())
-            .with_collaborators(&["user2", "user3"])
+            .with_owner("user1".to_string())
             .build();
 
         test_utils::setup()
@@ -2168,48 +4042,52 @@ mod tests {
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
+                        .wrap(test_utils::cookie::middleware())
                         .app_data(web::Data::new(app_data.clone()))
                         .configure(config),
                 )
                 .await;
 
-                let req = test::TestRequest::get()
-                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                let tags: Vec<_> = (0..20).map(|i| format!("tag{}", i)).collect();
+                let req = test::TestRequest::put()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags", &project.id))
+                    .set_json(tags)
                     .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
 
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/homework", &project.id))
+                    .to_request();
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+                // existing tags can still be added
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/tag0", &project.id))
+                    .to_request();
+                let metadata: api::ProjectMetadata = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(metadata.tags.len(), 20);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_list_collaborators_admin() {
-        let admin: User = api::NewUser {
-            username: "admin".into(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-
-        let owner: User = api::NewUser {
-            username: "owner".into(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-        let project = test_utils::project::builder()
-            .with_owner(owner.username.clone())
-            .with_collaborators(&["user2", "user3"])
+    async fn test_list_user_projects_by_tag() {
+        let tagged = test_utils::project::builder()
+            .with_owner("user1".to_string())
+            .with_name("tagged")
+            .build();
+        let untagged = test_utils::project::builder()
+            .with_owner("user1".to_string())
+            .with_name("untagged")
             .build();
 
         test_utils::setup()
-            .with_projects(&[project.clone()])
-            .with_users(&[admin.clone(), owner])
+            .with_projects(&[tagged.clone(), untagged])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -2219,16 +4097,22 @@ mod tests {
                 )
                 .await;
 
+                let req = test::TestRequest::post()
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri(&format!("/id/{}/tags/homework", &tagged.id))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
                 let req = test::TestRequest::get()
-                    .cookie(test_utils::cookie::new(&admin.username))
-                    .uri(&format!("/id/{}/collaborators/", &project.id))
+                    .cookie(test_utils::cookie::new("user1"))
+                    .uri("/user/user1?tag=homework")
                     .to_request();
 
-                let collaborators: Vec<String> = test::call_and_read_body_json(&app, req).await;
-                collaborators
-                    .into_iter()
-                    .enumerate()
-                    .for_each(|(i, name)| assert_eq!(name, project.collaborators[i]));
+                let projects: Vec<api::ProjectMetadata> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(projects.len(), 1);
+                assert_eq!(projects[0].id, tagged.id);
             })
             .await;
     }
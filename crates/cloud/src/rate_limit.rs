@@ -0,0 +1,125 @@
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::errors::UserError;
+
+/// Fixed window rate limiter keyed by an arbitrary string (eg, an email or IP
+/// address). Only the most recently used keys are tracked so memory use is bounded.
+pub(crate) struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    requests: Mutex<LruCache<String, RequestWindow>>,
+}
+
+struct RequestWindow {
+    start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(max_requests: u32, window: Duration) -> Self {
+        let capacity = NonZeroUsize::new(10_000).unwrap();
+        Self {
+            max_requests,
+            window,
+            requests: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Record a request for the given key. Fails if the limit has already been
+    /// reached for the current window.
+    pub(crate) fn check(&self, key: &str) -> Result<(), UserError> {
+        self.with_window(key, |window, max_requests| {
+            if window.count >= max_requests {
+                Err(UserError::TooManyRequestsError)
+            } else {
+                window.count += 1;
+                Ok(())
+            }
+        })
+    }
+
+    /// Fail if the limit has been reached for the current window without recording
+    /// a request. Used with `record` to only count some requests (eg, failures).
+    pub(crate) fn ensure_available(&self, key: &str) -> Result<(), UserError> {
+        self.with_window(key, |window, max_requests| {
+            if window.count >= max_requests {
+                Err(UserError::TooManyRequestsError)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Record a request for the given key (even if the limit has been reached)
+    pub(crate) fn record(&self, key: &str) {
+        self.with_window(key, |window, _max_requests| window.count += 1)
+    }
+
+    fn with_window<T>(&self, key: &str, f: impl FnOnce(&mut RequestWindow, u32) -> T) -> T {
+        let now = Instant::now();
+        let mut requests = self.requests.lock().unwrap();
+        let window = requests.get_or_insert_mut(key.to_owned(), || RequestWindow {
+            start: now,
+            count: 0,
+        });
+
+        if now.duration_since(window.start) >= self.window {
+            window.start = now;
+            window.count = 0;
+        }
+
+        f(window, self.max_requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceed_limit() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check("user@netsblox.org").is_ok());
+        assert!(limiter.check("user@netsblox.org").is_ok());
+        assert!(matches!(
+            limiter.check("user@netsblox.org"),
+            Err(UserError::TooManyRequestsError)
+        ));
+    }
+
+    #[test]
+    fn test_limit_per_key() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.check("user@netsblox.org").is_ok());
+        assert!(limiter.check("other@netsblox.org").is_ok());
+    }
+
+    #[test]
+    fn test_ensure_available_after_record() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.ensure_available("user").is_ok());
+        assert!(limiter.ensure_available("user").is_ok());
+
+        limiter.record("user");
+        assert!(matches!(
+            limiter.ensure_available("user"),
+            Err(UserError::TooManyRequestsError)
+        ));
+    }
+
+    #[test]
+    fn test_reset_after_window() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+        assert!(limiter.check("user@netsblox.org").is_ok());
+        assert!(limiter.check("user@netsblox.org").is_err());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.check("user@netsblox.org").is_ok());
+    }
+}
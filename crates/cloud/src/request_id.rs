@@ -0,0 +1,172 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    HttpMessage,
+};
+use netsblox_cloud_common::api;
+use uuid::Uuid;
+
+use crate::errors::UserError;
+
+/// Max length of a request ID provided by the client
+const MAX_ID_LEN: usize = 64;
+
+/// ID used to correlate a request (and any resulting error) with the server logs
+#[derive(Clone, Debug)]
+pub(crate) struct RequestId(String);
+
+/// Use the request ID provided by the client (if valid) or generate a new one.
+/// The ID is stored in the request extensions so it can be logged.
+pub(crate) fn assign(req: &ServiceRequest) -> RequestId {
+    let id = req
+        .headers()
+        .get(api::REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_id(id))
+        .map(|id| id.to_owned())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let id = RequestId(id);
+    req.extensions_mut().insert(id.clone());
+    id
+}
+
+/// Get the request ID (if one has been assigned)
+pub(crate) fn get(req: &ServiceRequest) -> String {
+    req.extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// Add the request ID to the response headers and to the body of any user
+/// errors so it can be included when reporting issues.
+pub(crate) fn add_to_response<B>(
+    res: ServiceResponse<B>,
+    id: &RequestId,
+) -> ServiceResponse<BoxBody>
+where
+    B: MessageBody + 'static,
+{
+    let error_body = res
+        .response()
+        .error()
+        .and_then(|err| err.as_error::<UserError>())
+        .filter(|err| !matches!(err, UserError::OAuthFlowError(_)))
+        .and_then(|err| {
+            let mut body = err.to_body();
+            body.request_id = Some(id.0.clone());
+            serde_json::to_string(&body).ok()
+        });
+
+    let mut res = match error_body {
+        Some(body) => res.map_body(|_head, _body| BoxBody::new(body)),
+        None => res.map_into_boxed_body(),
+    };
+
+    // the ID only contains valid header characters (see `is_valid_id`)
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        let name = HeaderName::from_static("x-request-id");
+        res.headers_mut().insert(name, value);
+    }
+
+    res
+}
+
+/// Only accept IDs which are safe to include in logs and headers
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::Service, http::header::ContentType, http::StatusCode, test, web, App, HttpResponse,
+    };
+
+    use super::*;
+
+    async fn not_found() -> Result<HttpResponse, UserError> {
+        Err(UserError::ProjectNotFoundError)
+    }
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok()
+            .insert_header(ContentType::plaintext())
+            .body("ok")
+    }
+
+    /// Send the request and return the status, request ID and body of the response
+    async fn send(req: test::TestRequest) -> (StatusCode, String, String) {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| {
+                    let id = assign(&req);
+                    let fut = srv.call(req);
+                    async move { fut.await.map(|res| add_to_response(res, &id)) }
+                })
+                .route("/error", web::get().to(not_found))
+                .route("/ok", web::get().to(ok)),
+        )
+        .await;
+
+        let response = test::call_service(&app, req.to_request()).await;
+        let status = response.status();
+        let id = response
+            .headers()
+            .get(api::REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = test::read_body(response).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        (status, id, body)
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_round_trip() {
+        let req = test::TestRequest::get()
+            .uri("/error")
+            .insert_header((api::REQUEST_ID_HEADER, "someRequestId"));
+
+        let (status, id, body) = send(req).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(id, "someRequestId");
+        let body: api::ErrorBody = serde_json::from_str(&body).unwrap();
+        assert_eq!(body.code, "ProjectNotFoundError");
+        assert_eq!(body.message, "Project not found.");
+        assert_eq!(body.request_id.as_deref(), Some("someRequestId"));
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_generated() {
+        let req = test::TestRequest::get().uri("/ok");
+
+        let (status, id, body) = send(req).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(Uuid::parse_str(&id).is_ok());
+        assert_eq!(body, "ok");
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_invalid() {
+        let req = test::TestRequest::get()
+            .uri("/error")
+            .insert_header((api::REQUEST_ID_HEADER, "bad id\twith spaces"));
+
+        let (_status, id, body) = send(req).await;
+
+        assert!(Uuid::parse_str(&id).is_ok());
+        let body: api::ErrorBody = serde_json::from_str(&body).unwrap();
+        assert_eq!(body.request_id, Some(id));
+    }
+}
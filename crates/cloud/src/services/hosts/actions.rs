@@ -1,22 +1,39 @@
-use futures::TryStreamExt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use actix_web::rt::time;
+use futures::{future, TryStreamExt};
 use lazy_static::lazy_static;
 use mongodb::{bson::doc, options::UpdateOptions, Collection};
-use netsblox_cloud_common::{api, AuthorizedServiceHost};
+use netsblox_cloud_common::{api, AuthorizedServiceHost, Group, User};
 use regex::Regex;
+use reqwest::{redirect, Url};
+use tokio::net;
 
 use crate::{
     auth,
     errors::{InternalError, UserError},
 };
 
+/// Max time to wait for a service host to respond when checking if it is reachable
+const HOST_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub(crate) struct HostActions<'a> {
     authorized_services: &'a Collection<AuthorizedServiceHost>,
+    users: &'a Collection<User>,
+    groups: &'a Collection<Group>,
 }
 
 impl<'a> HostActions<'a> {
-    pub(crate) fn new(authorized_services: &'a Collection<AuthorizedServiceHost>) -> Self {
+    pub(crate) fn new(
+        authorized_services: &'a Collection<AuthorizedServiceHost>,
+        users: &'a Collection<User>,
+        groups: &'a Collection<Group>,
+    ) -> Self {
         Self {
             authorized_services,
+            users,
+            groups,
         }
     }
 
@@ -49,6 +66,10 @@ impl<'a> HostActions<'a> {
         ensure_valid_service_id(&host.id)?;
 
         let query = doc! {"id": &host.id};
+        let host = api::AuthorizedServiceHost {
+            url: normalize_url(&host.url),
+            ..host
+        };
         let host: AuthorizedServiceHost = host.into();
         let update = doc! {"$setOnInsert": &host};
         let options = UpdateOptions::builder().upsert(true).build();
@@ -80,6 +101,128 @@ impl<'a> HostActions<'a> {
 
         Ok(host.into())
     }
+
+    /// Get the (non-private) authorized hosts available to all clients by default.
+    pub(crate) async fn get_default_hosts(&self) -> Result<Vec<api::ServiceHost>, UserError> {
+        let query = doc! {"visibility": {"$ne": "private"}};
+        let hosts = self
+            .authorized_services
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|host| host.into())
+            .collect();
+
+        Ok(hosts)
+    }
+
+    /// Get the hosts registered by the user and the groups they own or belong to.
+    pub(crate) async fn get_registered_hosts(
+        &self,
+        vu: &auth::ViewUser,
+    ) -> Result<Vec<api::ServiceHost>, UserError> {
+        let query = doc! {"username": &vu.username};
+        let user = self
+            .users
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        let mut groups = self
+            .groups
+            .find(doc! {"owner": &vu.username}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        if let Some(group_id) = user.group_id {
+            if let Some(in_group) = self
+                .groups
+                .find_one(doc! {"id": group_id}, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?
+            {
+                groups.push(in_group);
+            }
+        };
+
+        let hosts = user
+            .services_hosts
+            .unwrap_or_default()
+            .into_iter()
+            .chain(
+                groups
+                    .into_iter()
+                    .flat_map(|g| g.services_hosts.unwrap_or_default()),
+            )
+            .collect();
+
+        Ok(hosts)
+    }
+
+    /// Get all the hosts available to the user: their own hosts, followed by
+    /// those of their groups and then the default hosts.
+    pub(crate) async fn get_effective_hosts(
+        &self,
+        vu: &auth::ViewUser,
+    ) -> Result<Vec<api::ServiceHost>, UserError> {
+        let mut hosts = self.get_registered_hosts(vu).await?;
+        hosts.extend(self.get_default_hosts().await?);
+
+        Ok(merge_hosts(hosts))
+    }
+
+    /// Find the users and groups that have registered the given authorized host.
+    pub(crate) async fn get_host_usage(
+        &self,
+        _vh: &auth::ViewAuthHosts,
+        host_id: &str,
+    ) -> Result<api::ServiceHostUsage, UserError> {
+        let query = doc! {"id": &host_id};
+        let host = self
+            .authorized_services
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::ServiceHostNotFoundError)?;
+
+        // hosts registered before URLs were normalized may have a trailing slash
+        let url = normalize_url(&host.url);
+        let query = doc! {"servicesHosts.url": {"$in": [&url, format!("{}/", url)]}};
+
+        let users = self
+            .users
+            .find(query.clone(), None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|user| user.username)
+            .collect();
+
+        let groups = self
+            .groups
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|group| group.into())
+            .collect();
+
+        Ok(api::ServiceHostUsage { users, groups })
+    }
 }
 
 pub fn ensure_valid_service_id(id: &str) -> Result<(), UserError> {
@@ -98,3 +241,294 @@ pub fn ensure_valid_service_id(id: &str) -> Result<(), UserError> {
         Err(UserError::InvalidServiceHostIDError)
     }
 }
+
+/// Normalize the URL of a service host (lowercase host, no default port or
+/// trailing slash) so the same host isn't registered under different URLs.
+pub(crate) fn normalize_url(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_err| url.to_owned())
+        .trim_end_matches('/')
+        .to_owned()
+}
+
+/// Normalize the URLs of the given hosts, dropping any duplicates.
+pub(crate) fn normalize_hosts(hosts: Vec<api::ServiceHost>) -> Vec<api::ServiceHost> {
+    let mut normalized: Vec<api::ServiceHost> = Vec::with_capacity(hosts.len());
+    for host in hosts {
+        let url = normalize_url(&host.url);
+        if !normalized.iter().any(|host| host.url == url) {
+            normalized.push(api::ServiceHost {
+                url,
+                categories: host.categories,
+            });
+        }
+    }
+    normalized
+}
+
+/// Combine hosts with equivalent URLs, merging their categories. Hosts are
+/// kept in the order in which they are first listed.
+pub(crate) fn merge_hosts(hosts: Vec<api::ServiceHost>) -> Vec<api::ServiceHost> {
+    let mut merged: Vec<api::ServiceHost> = Vec::with_capacity(hosts.len());
+    for host in hosts {
+        let url = normalize_url(&host.url);
+        match merged.iter_mut().find(|existing| existing.url == url) {
+            Some(existing) => {
+                for category in host.categories {
+                    if !existing.categories.contains(&category) {
+                        existing.categories.push(category);
+                    }
+                }
+            }
+            None => merged.push(api::ServiceHost {
+                url,
+                categories: host.categories,
+            }),
+        }
+    }
+    merged
+}
+
+/// Ensure each of the service hosts responds to a request for its list of services.
+/// Only http(s) hosts are allowed and, unless `allow_private` is set, hosts cannot
+/// resolve to loopback, private or link-local addresses so users can't use the
+/// check to probe internal services.
+pub(crate) async fn ensure_hosts_reachable(
+    hosts: &[api::ServiceHost],
+    allow_private: bool,
+) -> Result<(), UserError> {
+    let checks = hosts.iter().map(|host| async move {
+        time::timeout(
+            HOST_CHECK_TIMEOUT,
+            ensure_host_reachable(&host.url, allow_private),
+        )
+        .await
+        .unwrap_or_else(|_elapsed| Err(UserError::ServiceHostUnreachableError(host.url.clone())))
+    });
+    future::try_join_all(checks).await?;
+
+    Ok(())
+}
+
+async fn ensure_host_reachable(url: &str, allow_private: bool) -> Result<(), UserError> {
+    let disallowed = || UserError::DisallowedServiceHostError(url.to_owned());
+    let unreachable = || UserError::ServiceHostUnreachableError(url.to_owned());
+
+    let parsed = Url::parse(url).map_err(|_err| disallowed())?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(disallowed());
+    }
+    let port = parsed.port_or_known_default().ok_or_else(disallowed)?;
+    let host = parsed.host_str().ok_or_else(disallowed)?;
+    let ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>();
+    let (domain, addrs): (Option<&str>, Vec<SocketAddr>) = match ip {
+        Ok(ip) => (None, vec![SocketAddr::new(ip, port)]),
+        Err(_) => {
+            let addrs = net::lookup_host((host, port))
+                .await
+                .map_err(|_err| unreachable())?
+                .collect();
+            (Some(host), addrs)
+        }
+    };
+
+    if addrs.is_empty() {
+        return Err(unreachable());
+    }
+    if !allow_private && addrs.iter().any(|addr| !is_public_ip(&addr.ip())) {
+        return Err(disallowed());
+    }
+
+    // Use the addresses checked above rather than resolving the domain again
+    let mut builder = reqwest::Client::builder().redirect(redirect::Policy::none());
+    if let Some(domain) = domain {
+        builder = builder.resolve_to_addrs(domain, &addrs);
+    }
+    let client = builder.build().map_err(|_err| UserError::InternalError)?;
+
+    let is_reachable = client
+        .get(parsed)
+        .send()
+        .await
+        .map(|response| response.status().is_success())
+        .unwrap_or(false);
+
+    if is_reachable {
+        Ok(())
+    } else {
+        Err(unreachable())
+    }
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(&IpAddr::V4(ip)),
+            None => {
+                let is_unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+                let is_link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || is_unique_local || is_link_local)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("http://Localhost:8000/"),
+            "http://localhost:8000"
+        );
+        assert_eq!(
+            normalize_url("https://example.com:443"),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_url("http://example.com/services/"),
+            "http://example.com/services"
+        );
+    }
+
+    #[test]
+    fn test_normalize_hosts_duplicates() {
+        let hosts = vec![
+            api::ServiceHost {
+                url: "http://localhost:8000/".into(),
+                categories: vec!["first".into()],
+            },
+            api::ServiceHost {
+                url: "http://localhost:8000".into(),
+                categories: vec!["second".into()],
+            },
+        ];
+
+        let hosts = normalize_hosts(hosts);
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].url, "http://localhost:8000");
+        assert_eq!(hosts[0].categories, vec!["first".to_string()]);
+    }
+
+    #[test]
+    fn test_merge_hosts() {
+        let hosts = vec![
+            api::ServiceHost {
+                url: "http://localhost:8000".into(),
+                categories: vec!["user".into()],
+            },
+            api::ServiceHost {
+                url: "http://other.com".into(),
+                categories: vec!["group".into()],
+            },
+            api::ServiceHost {
+                url: "http://localhost:8000/".into(),
+                categories: vec!["group".into(), "user".into()],
+            },
+        ];
+
+        let hosts = merge_hosts(hosts);
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].url, "http://localhost:8000");
+        assert_eq!(
+            hosts[0].categories,
+            vec!["user".to_string(), "group".to_string()]
+        );
+        assert_eq!(hosts[1].url, "http://other.com");
+    }
+
+    #[actix_web::test]
+    async fn test_ensure_hosts_reachable() {
+        let (url, server) = test_utils::services::start_host();
+        let hosts = vec![api::ServiceHost {
+            url,
+            categories: Vec::new(),
+        }];
+
+        let result = ensure_hosts_reachable(&hosts, true).await;
+        server.stop(false).await;
+        assert!(result.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_ensure_hosts_reachable_down() {
+        let hosts = vec![api::ServiceHost {
+            url: test_utils::services::unused_url(),
+            categories: Vec::new(),
+        }];
+
+        let result = ensure_hosts_reachable(&hosts, true).await;
+        assert!(matches!(
+            result,
+            Err(UserError::ServiceHostUnreachableError(..))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_ensure_hosts_reachable_private() {
+        let (url, server) = test_utils::services::start_host();
+        let hosts = vec![api::ServiceHost {
+            url,
+            categories: Vec::new(),
+        }];
+
+        let result = ensure_hosts_reachable(&hosts, false).await;
+        server.stop(false).await;
+        assert!(matches!(
+            result,
+            Err(UserError::DisallowedServiceHostError(..))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_ensure_hosts_reachable_scheme() {
+        let hosts = vec![api::ServiceHost {
+            url: "file:///etc/passwd".into(),
+            categories: Vec::new(),
+        }];
+
+        let result = ensure_hosts_reachable(&hosts, true).await;
+        assert!(matches!(
+            result,
+            Err(UserError::DisallowedServiceHostError(..))
+        ));
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        let private = [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ];
+        for ip in private {
+            assert!(!is_public_ip(&ip.parse().unwrap()), "{} is not public", ip);
+        }
+
+        assert!(is_public_ip(&"93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip(
+            &"2606:2800:220:1:248:1893:25c8:1946".parse().unwrap()
+        ));
+    }
+}
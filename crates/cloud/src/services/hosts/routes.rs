@@ -2,14 +2,14 @@ use crate::app_data::AppData;
 use crate::auth;
 use crate::common::api;
 use crate::common::api::{GroupId, ServiceHost};
-use crate::errors::{InternalError, UserError};
+use crate::errors::UserError;
 use crate::groups::actions::GroupActions;
-use crate::services::hosts::actions::HostActions;
+use crate::services::hosts::actions::{self, HostActions};
 use crate::users::actions::UserActions;
 use actix_web::{delete, get, post, HttpRequest};
 use actix_web::{web, HttpResponse};
-use futures::TryStreamExt;
 use mongodb::bson::doc;
+use serde::Deserialize;
 
 #[get("/group/{id}")]
 async fn list_group_hosts(
@@ -26,16 +26,29 @@ async fn list_group_hosts(
     Ok(HttpResponse::Ok().json(group.services_hosts.unwrap_or_default()))
 }
 
+#[derive(Deserialize)]
+struct SetHostsParams {
+    /// Check that each host is reachable before saving
+    #[serde(default)]
+    validate: bool,
+}
+
 #[post("/group/{id}")]
 async fn set_group_hosts(
     app: web::Data<AppData>,
     path: web::Path<(GroupId,)>,
+    params: web::Query<SetHostsParams>,
     hosts: web::Json<Vec<ServiceHost>>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (id,) = path.into_inner();
 
     let auth_eg = auth::try_edit_group(&app, &req, &id).await?;
+    let hosts = actions::normalize_hosts(hosts.into_inner());
+    if params.validate {
+        let allow_private = app.settings.security.allow_private_service_hosts;
+        actions::ensure_hosts_reachable(&hosts, allow_private).await?;
+    }
 
     let actions: GroupActions = app.as_group_actions();
     let group = actions.set_group_hosts(&auth_eg, &hosts).await?;
@@ -62,11 +75,17 @@ async fn list_user_hosts(
 async fn set_user_hosts(
     app: web::Data<AppData>,
     path: web::Path<(String,)>,
+    params: web::Query<SetHostsParams>,
     hosts: web::Json<Vec<ServiceHost>>,
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (username,) = path.into_inner();
     let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+    let hosts = actions::normalize_hosts(hosts.into_inner());
+    if params.validate {
+        let allow_private = app.settings.security.allow_private_service_hosts;
+        actions::ensure_hosts_reachable(&hosts, allow_private).await?;
+    }
 
     let actions: UserActions = app.as_user_actions();
     let user = actions.set_hosts(&auth_eu, &hosts).await?;
@@ -81,47 +100,28 @@ async fn list_all_hosts(
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (username,) = path.into_inner();
+    let auth_vu = auth::try_view_user(&app, &req, None, &username).await?;
 
-    // FIXME: Update this
-    let _auth_vu = auth::try_view_user(&app, &req, None, &username).await?;
-
-    // let actions: UserActions = app.into();
-    // let user = actions.get_all_hosts(&auth_vu).await?;
-
-    let query = doc! {"username": &username};
-    let user = app
-        .users
-        .find_one(query, None)
-        .await
-        .map_err(InternalError::DatabaseConnectionError)?
-        .ok_or(UserError::UserNotFoundError)?;
-
-    let mut groups = app
-        .groups
-        .find(doc! {"owner": &username}, None)
-        .await
-        .map_err(InternalError::DatabaseConnectionError)?
-        .try_collect::<Vec<_>>()
-        .await
-        .map_err(InternalError::DatabaseConnectionError)?;
-
-    if let Some(group_id) = user.group_id {
-        if let Some(in_group) = app
-            .groups
-            .find_one(doc! {"id": group_id}, None)
-            .await
-            .map_err(InternalError::DatabaseConnectionError)?
-        {
-            groups.push(in_group);
-        }
-    };
-
-    let services_hosts = user.services_hosts.unwrap_or_default().into_iter().chain(
-        groups
-            .into_iter()
-            .flat_map(|g| g.services_hosts.unwrap_or_default()),
-    );
-    Ok(HttpResponse::Ok().json(services_hosts.collect::<Vec<_>>()))
+    let actions: HostActions = app.as_host_actions();
+    let hosts = actions.get_registered_hosts(&auth_vu).await?;
+
+    Ok(HttpResponse::Ok().json(actions::normalize_hosts(hosts)))
+}
+
+/// List all the hosts available to the user, including the default hosts.
+#[get("/effective/{username}")]
+async fn list_effective_hosts(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+    let auth_vu = auth::try_view_user(&app, &req, None, &username).await?;
+
+    let actions: HostActions = app.as_host_actions();
+    let hosts = actions.get_effective_hosts(&auth_vu).await?;
+
+    Ok(HttpResponse::Ok().json(hosts))
 }
 
 #[get("/authorized/")]
@@ -137,6 +137,21 @@ async fn get_authorized_hosts(
     Ok(HttpResponse::Ok().json(hosts))
 }
 
+#[get("/authorized/{id}/usage")]
+async fn get_host_usage(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (host_id,) = path.into_inner();
+    let auth_vah = auth::try_view_auth_hosts(&app, &req).await?;
+
+    let actions: HostActions = app.as_host_actions();
+    let usage = actions.get_host_usage(&auth_vah, &host_id).await?;
+
+    Ok(HttpResponse::Ok().json(usage))
+}
+
 #[post("/authorized/")]
 async fn authorize_host(
     app: web::Data<AppData>,
@@ -163,6 +178,9 @@ async fn unauthorize_host(
     let actions: HostActions = app.as_host_actions();
     let host = actions.unauthorize(&auth_ah, &host_id).await?;
 
+    app.audit_request(&req, api::AuditAction::UnauthorizeHost, &host_id)
+        .await;
+
     Ok(HttpResponse::Ok().json(host))
 }
 
@@ -172,15 +190,17 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(list_user_hosts)
         .service(set_user_hosts)
         .service(list_all_hosts)
+        .service(list_effective_hosts)
         .service(authorize_host)
         .service(get_authorized_hosts)
+        .service(get_host_usage)
         .service(unauthorize_host);
 }
 
 #[cfg(test)]
 mod test {
     use actix_web::{body::MessageBody, http, test, App};
-    use netsblox_cloud_common::{Group, User};
+    use netsblox_cloud_common::{AuthorizedServiceHost, Group, User};
 
     use super::*;
     use crate::test_utils;
@@ -247,6 +267,169 @@ mod test {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_set_user_hosts_normalized() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let cats = vec!["custom".into()];
+                let hosts = vec![
+                    ServiceHost {
+                        url: "http://Service1.com:80/".into(),
+                        categories: cats.clone(),
+                    },
+                    ServiceHost {
+                        url: "http://service1.com".into(),
+                        categories: cats.clone(),
+                    },
+                ];
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&hosts)
+                    .to_request();
+
+                let user: api::User = test::call_and_read_body_json(&app, req).await;
+                let hosts = user.services_hosts.unwrap_or_default();
+                assert_eq!(hosts.len(), 1);
+                assert_eq!(hosts[0].url, "http://service1.com");
+
+                // unregister the host using an equivalent URL
+                let hosts: Vec<_> = hosts
+                    .into_iter()
+                    .filter(|host| {
+                        actions::normalize_url(&host.url)
+                            != actions::normalize_url("http://SERVICE1.com/")
+                    })
+                    .collect();
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&hosts)
+                    .to_request();
+
+                let user: api::User = test::call_and_read_body_json(&app, req).await;
+                assert!(user.services_hosts.unwrap_or_default().is_empty());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_set_user_hosts_validate() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                // the test hosts are on localhost
+                let mut app_data = app_data;
+                app_data.settings.security.allow_private_service_hosts = true;
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let (url, server) = test_utils::services::start_host();
+                let hosts = vec![ServiceHost {
+                    url,
+                    categories: vec!["custom".into()],
+                }];
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}?validate=true", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&hosts)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                server.stop(false).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_set_user_hosts_validate_unreachable() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                // the test hosts are on localhost
+                let mut app_data = app_data;
+                app_data.settings.security.allow_private_service_hosts = true;
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let hosts = vec![ServiceHost {
+                    url: test_utils::services::unused_url(),
+                    categories: vec!["custom".into()],
+                }];
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}?validate=true", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&hosts)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+                // Check that the hosts were not saved
+                let query = doc! {"username": &user.username};
+                let user = app_data.users.find_one(query, None).await.unwrap().unwrap();
+                assert!(user.services_hosts.unwrap_or_default().is_empty());
+
+                // The check can be skipped (eg, for offline setups)
+                let req = test::TestRequest::post()
+                    .uri(&format!("/user/{}", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .set_json(&hosts)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_set_group_hosts() {
         let user: User = api::NewUser {
@@ -317,4 +500,192 @@ mod test {
             })
             .await;
     }
+
+    #[actix_web::test]
+    async fn test_list_effective_hosts() {
+        let owner: User = api::NewUser {
+            username: "owner".into(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let mut group = Group::new(owner.username.clone(), "some_group".into());
+        group.services_hosts = Some(vec![
+            ServiceHost {
+                url: "http://shared.com".into(),
+                categories: vec!["group".into()],
+            },
+            ServiceHost {
+                url: "http://group.com".into(),
+                categories: vec!["group".into()],
+            },
+        ]);
+        let mut member: User = api::NewUser {
+            username: "member".into(),
+            email: "member@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+        member.services_hosts = Some(vec![ServiceHost {
+            url: "http://shared.com/".into(),
+            categories: vec!["user".into()],
+        }]);
+        let public_host = AuthorizedServiceHost::new(
+            "http://public.com".into(),
+            "public".into(),
+            api::ServiceHostScope::Public(vec!["default".into()]),
+        );
+        let private_host = AuthorizedServiceHost::new(
+            "http://private.com".into(),
+            "private".into(),
+            api::ServiceHostScope::Private,
+        );
+
+        test_utils::setup()
+            .with_users(&[owner, member.clone()])
+            .with_groups(&[group])
+            .with_authorized_services(&[public_host, private_host])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/effective/{}", &member.username))
+                    .cookie(test_utils::cookie::new(&member.username))
+                    .to_request();
+
+                let hosts: Vec<ServiceHost> = test::call_and_read_body_json(&app, req).await;
+                let urls: Vec<_> = hosts.iter().map(|host| host.url.as_str()).collect();
+                assert_eq!(
+                    urls,
+                    vec!["http://shared.com", "http://group.com", "http://public.com"]
+                );
+                // user categories take precedence but group categories are merged in
+                assert_eq!(
+                    hosts[0].categories,
+                    vec!["user".to_string(), "group".to_string()]
+                );
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_host_usage() {
+        let admin: User = api::NewUser {
+            username: "admin".into(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(api::UserRole::Admin),
+        }
+        .into();
+        let host = AuthorizedServiceHost::new(
+            "http://service1.com".into(),
+            "service1".into(),
+            api::ServiceHostScope::Private,
+        );
+        let categories = vec!["custom".to_string()];
+        let mut u1: User = api::NewUser {
+            username: "u1".into(),
+            email: "u1@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        u1.services_hosts = Some(vec![ServiceHost {
+            url: "http://service1.com/".into(),
+            categories: categories.clone(),
+        }]);
+        let mut u2: User = api::NewUser {
+            username: "u2".into(),
+            email: "u2@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        u2.services_hosts = Some(vec![ServiceHost {
+            url: "http://service2.com".into(),
+            categories: categories.clone(),
+        }]);
+        let mut group = Group::new(u2.username.clone(), "some_group".into());
+        group.services_hosts = Some(vec![ServiceHost {
+            url: "http://service1.com".into(),
+            categories,
+        }]);
+
+        test_utils::setup()
+            .with_users(&[admin.clone(), u1.clone(), u2])
+            .with_groups(&[group.clone()])
+            .with_authorized_services(&[host.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/authorized/{}/usage", &host.id))
+                    .cookie(test_utils::cookie::new(&admin.username))
+                    .to_request();
+
+                let usage: api::ServiceHostUsage = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(usage.users, vec![u1.username]);
+                assert_eq!(usage.groups.len(), 1);
+                assert_eq!(usage.groups[0].id, group.id);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_host_usage_403() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let host = AuthorizedServiceHost::new(
+            "http://service1.com".into(),
+            "service1".into(),
+            api::ServiceHostScope::Private,
+        );
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_authorized_services(&[host.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .wrap(test_utils::cookie::middleware())
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/authorized/{}/usage", &host.id))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
 }
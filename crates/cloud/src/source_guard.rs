@@ -0,0 +1,124 @@
+use actix_web::{dev::ServiceRequest, error::ErrorForbidden, http::Method};
+
+/// Header identifying the application which sent the request
+const SOURCE_HEADER: &str = "x-source";
+
+/// Application which sent a request (from the `x-source` header)
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RequestSource {
+    Unknown,
+    Named(String),
+}
+
+impl RequestSource {
+    pub(crate) fn from_request(req: &ServiceRequest) -> Self {
+        req.headers()
+            .get(SOURCE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|name| RequestSource::Named(name.to_owned()))
+            .unwrap_or(RequestSource::Unknown)
+    }
+}
+
+/// Only allow GET requests from restricted sources (eg, the old NetsBlox server)
+#[derive(Clone)]
+pub(crate) struct SourceGuard {
+    restricted_sources: Vec<String>,
+}
+
+impl SourceGuard {
+    pub(crate) fn new(restricted_sources: &[String]) -> Self {
+        Self {
+            restricted_sources: restricted_sources.to_vec(),
+        }
+    }
+
+    pub(crate) fn is_allowed(&self, method: &Method, source: &RequestSource) -> bool {
+        if *method == Method::GET {
+            return true;
+        }
+
+        match source {
+            RequestSource::Named(name) => !self.restricted_sources.contains(name),
+            RequestSource::Unknown => true,
+        }
+    }
+
+    pub(crate) fn check(&self, req: &ServiceRequest) -> Result<(), actix_web::Error> {
+        let source = RequestSource::from_request(req);
+        if self.is_allowed(req.method(), &source) {
+            Ok(())
+        } else {
+            Err(ErrorForbidden("Operation is not allowed"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{dev::Service, http::StatusCode, test as actix_test, web, App, HttpResponse};
+
+    use super::*;
+
+    fn guard() -> SourceGuard {
+        SourceGuard::new(&["NetsBlox".to_owned()])
+    }
+
+    #[test]
+    fn test_allow_get() {
+        let source = RequestSource::Named("NetsBlox".into());
+        assert!(guard().is_allowed(&Method::GET, &source));
+        assert!(guard().is_allowed(&Method::GET, &RequestSource::Unknown));
+    }
+
+    #[test]
+    fn test_deny_restricted_source() {
+        let source = RequestSource::Named("NetsBlox".into());
+        assert!(!guard().is_allowed(&Method::POST, &source));
+        assert!(!guard().is_allowed(&Method::DELETE, &source));
+    }
+
+    #[test]
+    fn test_allow_other_sources() {
+        let source = RequestSource::Named("NetsBloxEditor".into());
+        assert!(guard().is_allowed(&Method::POST, &source));
+        assert!(guard().is_allowed(&Method::POST, &RequestSource::Unknown));
+    }
+
+    #[test]
+    fn test_no_restricted_sources() {
+        let guard = SourceGuard::new(&[]);
+        let source = RequestSource::Named("NetsBlox".into());
+        assert!(guard.is_allowed(&Method::POST, &source));
+    }
+
+    #[actix_web::test]
+    async fn test_check_request() {
+        let guard = guard();
+        let app = actix_test::init_service(
+            App::new()
+                .wrap_fn(move |req, srv| {
+                    let result = guard.check(&req).map(|_| srv.call(req));
+                    async {
+                        match result {
+                            Ok(fut) => fut.await,
+                            Err(err) => Err(err),
+                        }
+                    }
+                })
+                .route("/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::post()
+            .uri("/")
+            .insert_header((SOURCE_HEADER, "NetsBlox"))
+            .to_request();
+        let err = actix_test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(err.error_response().status(), StatusCode::FORBIDDEN);
+
+        let req = actix_test::TestRequest::post().uri("/").to_request();
+        let response = actix_test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
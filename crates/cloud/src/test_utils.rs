@@ -2,10 +2,13 @@ use std::sync::{Arc, Mutex};
 
 use futures::{future::join_all, Future};
 use lazy_static::lazy_static;
-use mongodb::{bson::doc, Client};
+use mongodb::{
+    bson::{doc, Document},
+    Client,
+};
 use netsblox_cloud_common::{
     api, AuthorizedServiceHost, BannedAccount, CollaborationInvite, FriendLink, Group, Library,
-    LogMessage, MagicLink, User,
+    LogMessage, MagicLink, User, UserSession,
 };
 
 use crate::{
@@ -144,6 +147,8 @@ impl TestSetupBuilder {
                 id,
                 owner,
                 name,
+                collaborators,
+                collaborator_roles,
                 roles,
                 traces,
                 state,
@@ -164,16 +169,19 @@ impl TestSetupBuilder {
                 .unwrap();
 
             let query = doc! {"id": &metadata.id};
-            let update = if traces.is_empty() {
-                doc! {"$set": {"id": &id}}
-            } else {
-                doc! {
-                    "$set": {
-                        "id": &id,
-                        "networkTraces": &traces
-                    },
-                }
+            let mut roles = Document::new();
+            collaborator_roles.into_iter().for_each(|(name, role)| {
+                roles.insert(name, role);
+            });
+            let mut update = doc! {
+                "id": &id,
+                "collaborators": &collaborators,
+                "collaboratorRoles": roles,
             };
+            if !traces.is_empty() {
+                update.insert("networkTraces", &traces);
+            }
+            let update = doc! {"$set": update};
             app_data
                 .project_metadata
                 .update_one(query, update, None)
@@ -207,6 +215,12 @@ impl TestSetupBuilder {
                 .unwrap();
         }
         if !self.users.is_empty() {
+            // record a session for each user so their test cookies are valid
+            let sessions = self.users.iter().map(|user| UserSession {
+                id: cookie::session_id(&user.username),
+                ..UserSession::new(user.username.clone(), None, None)
+            });
+            app_data.sessions.insert_many(sessions, None).await.unwrap();
             app_data.users.insert_many(self.users, None).await.unwrap();
         }
         if !self.friends.is_empty() {
@@ -271,10 +285,23 @@ pub(crate) mod cookie {
     use serde_json::json;
 
     static COOKIE_NAME: &str = "test_netsblox";
-    pub(crate) fn new(username: &str) -> Cookie {
+    pub(crate) fn new(username: &str) -> Cookie<'static> {
         let data = json!({
             "username": format!("\"{}\"", username),  // FIXME: this shouldn't need extra quotes...
+            "sessionId": format!("\"{}\"", session_id(username)),
         });
+        encrypt(data)
+    }
+
+    /// Create a cookie from before sessions were recorded (ie, without a session ID)
+    pub(crate) fn legacy(username: &str) -> Cookie<'static> {
+        let data = json!({
+            "username": format!("\"{}\"", username),
+        });
+        encrypt(data)
+    }
+
+    fn encrypt(data: serde_json::Value) -> Cookie<'static> {
         let cookie = Cookie::new(COOKIE_NAME, data.to_string());
 
         // Use the cookie jar to encrypt & sign the cookie
@@ -286,6 +313,11 @@ pub(crate) mod cookie {
         cookie.to_owned()
     }
 
+    /// ID of the session recorded for the given user during test setup
+    pub(crate) fn session_id(username: &str) -> String {
+        format!("test-session-{}", username)
+    }
+
     pub(crate) fn middleware() -> SessionMiddleware<CookieSessionStore> {
         let secret_key = Key::from(&[0; 64]);
         SessionMiddleware::builder(CookieSessionStore::default(), secret_key)
@@ -308,6 +340,7 @@ pub(crate) mod project {
         owner: Option<String>,
         name: Option<String>,
         collaborators: Vec<String>,
+        collaborator_roles: HashMap<String, api::CollabRole>,
         roles: HashMap<api::RoleId, api::RoleData>,
         traces: Vec<NetworkTraceMetadata>,
         state: PublishState,
@@ -344,6 +377,11 @@ pub(crate) mod project {
             self
         }
 
+        pub(crate) fn with_collaborator_role(mut self, name: &str, role: api::CollabRole) -> Self {
+            self.collaborator_roles.insert(name.to_owned(), role);
+            self
+        }
+
         pub(crate) fn with_state(mut self, state: PublishState) -> Self {
             self.state = state;
             self
@@ -372,6 +410,7 @@ pub(crate) mod project {
                 owner,
                 name: self.name.unwrap_or("my project".into()),
                 collaborators: self.collaborators,
+                collaborator_roles: self.collaborator_roles,
                 roles: self.roles,
                 state: self.state,
                 //save_state: api::SaveState::Saved,
@@ -386,6 +425,7 @@ pub(crate) mod project {
         pub(crate) owner: String,
         pub(crate) name: String,
         pub(crate) collaborators: std::vec::Vec<String>,
+        pub(crate) collaborator_roles: HashMap<String, api::CollabRole>,
         //pub(crate) save_state: api::SaveState,
         pub(crate) roles: HashMap<RoleId, RoleData>,
         pub(crate) traces: Vec<NetworkTraceMetadata>,
@@ -414,6 +454,7 @@ pub(crate) mod project {
             owner: None,
             name: None,
             collaborators: Vec::new(),
+            collaborator_roles: HashMap::new(),
             roles: HashMap::new(),
             traces: Vec::new(),
             state: PublishState::Private,
@@ -484,3 +525,36 @@ pub(crate) mod network {
         }
     }
 }
+
+pub(crate) mod services {
+    use std::net::TcpListener;
+
+    use actix_web::{dev::ServerHandle, web, App, HttpResponse, HttpServer};
+
+    /// Start a minimal services host listing no services. Returns the URL
+    /// of the host and a handle to stop it.
+    pub(crate) fn start_host() -> (String, ServerHandle) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().json(Vec::<String>::new()) }),
+            )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (url, handle)
+    }
+
+    /// Get the URL of a local address where nothing is listening.
+    pub(crate) fn unused_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+}
@@ -0,0 +1,248 @@
+//! Helpers for TOTP-based two-factor authentication (RFC 6238)
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use mongodb::{bson::doc, Collection};
+use netsblox_cloud_common::{TwoFactorAuth, User};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::{InternalError, UserError},
+    utils,
+};
+
+const ISSUER: &str = "NetsBlox";
+const SECRET_LEN: usize = 20;
+const NONCE_LEN: usize = 12;
+const TIME_STEP_SECS: u64 = 30;
+const CODE_DIGITS: u32 = 6;
+/// Number of time steps before (or after) the current one to accept
+/// to tolerate clock skew between the server and the authenticator app
+const ALLOWED_SKEW_STEPS: i64 = 1;
+const BACKUP_CODE_COUNT: usize = 10;
+const BACKUP_CODE_LEN: usize = 10;
+
+/// Key used to encrypt TOTP secrets before storing them in the database
+#[derive(Clone)]
+pub(crate) struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub(crate) fn new(secret: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(secret);
+        Self(hasher.finalize().into())
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::from_slice(&self.0))
+    }
+
+    /// Encrypt the data and return the nonce and ciphertext (base64-encoded)
+    pub(crate) fn encrypt(&self, data: &[u8]) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .expect("Unable to encrypt TOTP secret");
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+        base64::encode(bytes)
+    }
+
+    pub(crate) fn decrypt(&self, data: &str) -> Result<Vec<u8>, InternalError> {
+        let bytes = base64::decode(data).map_err(InternalError::Base64DecodeError)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(InternalError::TwoFactorDecryptError);
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_err| InternalError::TwoFactorDecryptError)
+    }
+}
+
+pub(crate) fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Get the URI used to add the account to an authenticator app (often shown as a QR code)
+pub(crate) fn provisioning_uri(username: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={CODE_DIGITS}&period={TIME_STEP_SECS}",
+        issuer = ISSUER,
+        secret = BASE32_NOPAD.encode(secret),
+    )
+}
+
+/// Generate single-use backup codes in case the user loses access to their authenticator
+pub(crate) fn generate_backup_codes() -> Vec<String> {
+    (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(BACKUP_CODE_LEN)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn hash_backup_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    utils::sha512(&code)
+}
+
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    code % 10u32.pow(CODE_DIGITS)
+}
+
+/// Get the current TOTP code for the given secret (as shown by an authenticator app)
+#[cfg(test)]
+pub(crate) fn current_code(secret: &[u8]) -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    format!("{:06}", hotp(secret, secs / TIME_STEP_SECS))
+}
+
+/// Check if the code is a valid TOTP code for the given secret at the given time
+pub(crate) fn verify_totp(secret: &[u8], code: &str, time: SystemTime) -> bool {
+    let code = code.trim();
+    if code.len() != CODE_DIGITS as usize {
+        return false;
+    }
+    let code: u32 = match code.parse() {
+        Ok(code) => code,
+        Err(_) => return false,
+    };
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let step = (secs / TIME_STEP_SECS) as i64;
+
+    (-ALLOWED_SKEW_STEPS..=ALLOWED_SKEW_STEPS)
+        .map(|skew| step + skew)
+        .filter(|counter| *counter >= 0)
+        .any(|counter| hotp(secret, counter as u64) == code)
+}
+
+/// Check the given TOTP (or backup) code for a user with two-factor auth enabled.
+/// Backup codes are removed once used.
+pub(crate) async fn verify_code(
+    users: &Collection<User>,
+    key: &EncryptionKey,
+    username: &str,
+    two_factor: &TwoFactorAuth,
+    code: &str,
+) -> Result<(), UserError> {
+    let secret = key.decrypt(&two_factor.secret)?;
+    if verify_totp(&secret, code, SystemTime::now()) {
+        return Ok(());
+    }
+
+    let hash = hash_backup_code(code);
+    if !two_factor.backup_codes.contains(&hash) {
+        return Err(UserError::InvalidTwoFactorCodeError);
+    }
+
+    // only accept the backup code if we were the ones to remove it
+    let query = doc! {"username": username, "twoFactor.backupCodes": &hash};
+    let update = doc! {"$pull": {"twoFactor.backupCodes": &hash}};
+    let result = users
+        .update_one(query, update, None)
+        .await
+        .map_err(InternalError::DatabaseConnectionError)?;
+
+    if result.modified_count == 1 {
+        Ok(())
+    } else {
+        Err(UserError::InvalidTwoFactorCodeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp_rfc4226() {
+        let expected = [755224, 287082, 359152, 969429, 338314];
+        for (counter, code) in expected.into_iter().enumerate() {
+            assert_eq!(hotp(RFC_SECRET, counter as u64), code);
+        }
+    }
+
+    #[test]
+    fn test_verify_totp_rfc6238() {
+        let time = UNIX_EPOCH + Duration::from_secs(59);
+        assert!(verify_totp(RFC_SECRET, "287082", time));
+    }
+
+    #[test]
+    fn test_verify_totp_clock_skew() {
+        let time = UNIX_EPOCH + Duration::from_secs(59 + TIME_STEP_SECS);
+        assert!(verify_totp(RFC_SECRET, "287082", time));
+
+        let time = UNIX_EPOCH + Duration::from_secs(59 + 2 * TIME_STEP_SECS);
+        assert!(!verify_totp(RFC_SECRET, "287082", time));
+    }
+
+    #[test]
+    fn test_verify_totp_invalid_format() {
+        let time = UNIX_EPOCH + Duration::from_secs(59);
+        assert!(!verify_totp(RFC_SECRET, "28708", time));
+        assert!(!verify_totp(RFC_SECRET, "abcdef", time));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = EncryptionKey::new("some secret");
+        let secret = generate_secret();
+        let encrypted = key.encrypt(&secret);
+        assert_eq!(key.decrypt(&encrypted).unwrap(), secret);
+
+        let other_key = EncryptionKey::new("another secret");
+        assert!(other_key.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_hash_backup_code_normalized() {
+        assert_eq!(
+            hash_backup_code("abcde-12345"),
+            hash_backup_code("ABCDE12345 ")
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
 };
 
 use crate::auth;
@@ -13,20 +14,23 @@ use lettre::{
 };
 use lru::LruCache;
 use mongodb::{
-    bson::doc,
-    options::{FindOneAndUpdateOptions, ReturnDocument},
+    bson::{doc, DateTime},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
     Collection,
 };
-use netsblox_cloud_common::{api, BannedAccount, SetPasswordToken, User};
+use netsblox_cloud_common::{
+    api, BannedAccount, EmailVerificationToken, SetPasswordToken, TwoFactorAuth, User, UserSession,
+};
 use nonempty::NonEmpty;
 use regex::Regex;
 use rustrict::CensorStr;
 
 use crate::{
     app_data::metrics,
+    config::PasswordPolicy,
     errors::{InternalError, UserError},
     network::topology::{self, TopologyActor},
-    utils,
+    two_factor, utils,
 };
 
 use super::{email_template, strategies};
@@ -35,6 +39,8 @@ pub(crate) struct UserActions<'a> {
     users: &'a Collection<User>,
     banned_accounts: &'a Collection<BannedAccount>,
     password_tokens: &'a Collection<SetPasswordToken>,
+    email_verification_tokens: &'a Collection<EmailVerificationToken>,
+    sessions: &'a Collection<UserSession>,
     metrics: &'a metrics::Metrics,
 
     network: &'a Addr<TopologyActor>,
@@ -45,6 +51,11 @@ pub(crate) struct UserActions<'a> {
     mailer: &'a SmtpTransport,
     sender: &'a Mailbox,
     public_url: &'a String,
+
+    two_factor_key: &'a two_factor::EncryptionKey,
+
+    password_policy: &'a PasswordPolicy,
+    breached_passwords: &'a HashSet<String>,
 }
 
 /// A struct for passing data to the constructor of `UserActions` w/o either 1) making
@@ -53,6 +64,8 @@ pub(crate) struct UserActionData<'a> {
     pub(crate) users: &'a Collection<User>,
     pub(crate) banned_accounts: &'a Collection<BannedAccount>,
     pub(crate) password_tokens: &'a Collection<SetPasswordToken>,
+    pub(crate) email_verification_tokens: &'a Collection<EmailVerificationToken>,
+    pub(crate) sessions: &'a Collection<UserSession>,
     pub(crate) metrics: &'a metrics::Metrics,
 
     pub(crate) network: &'a Addr<TopologyActor>,
@@ -62,6 +75,11 @@ pub(crate) struct UserActionData<'a> {
     pub(crate) mailer: &'a SmtpTransport,
     pub(crate) sender: &'a Mailbox,
     pub(crate) public_url: &'a String,
+
+    pub(crate) two_factor_key: &'a two_factor::EncryptionKey,
+
+    pub(crate) password_policy: &'a PasswordPolicy,
+    pub(crate) breached_passwords: &'a HashSet<String>,
 }
 
 impl<'a> UserActions<'a> {
@@ -70,6 +88,8 @@ impl<'a> UserActions<'a> {
             users: data.users,
             banned_accounts: data.banned_accounts,
             password_tokens: data.password_tokens,
+            email_verification_tokens: data.email_verification_tokens,
+            sessions: data.sessions,
             metrics: data.metrics,
 
             network: data.network,
@@ -79,23 +99,17 @@ impl<'a> UserActions<'a> {
             mailer: data.mailer,
             sender: data.sender,
             public_url: data.public_url,
+
+            two_factor_key: data.two_factor_key,
+
+            password_policy: data.password_policy,
+            breached_passwords: data.breached_passwords,
         }
     }
 
     pub(crate) async fn create_user(&self, cu: auth::CreateUser) -> Result<api::User, UserError> {
-        ensure_valid_email(&cu.data.email)?;
+        self.ensure_valid_new_user(&cu.data).await?;
         let user: User = cu.data.into();
-        ensure_valid_username(&user.username)?;
-
-        let query = doc! {"email": &user.email};
-        if let Some(_account) = self
-            .banned_accounts
-            .find_one(query, None)
-            .await
-            .map_err(InternalError::DatabaseConnectionError)?
-        {
-            return Err(UserError::InvalidEmailAddress);
-        }
 
         let query = doc! {"username": &user.username};
         let update = doc! {"$setOnInsert": &user};
@@ -122,6 +136,62 @@ impl<'a> UserActions<'a> {
         }
     }
 
+    /// Check that a user could be created with the given data (without checking
+    /// if the username is available).
+    pub(crate) async fn ensure_valid_new_user(&self, data: &api::NewUser) -> Result<(), UserError> {
+        ensure_valid_email(&data.email)?;
+        if let Some(password) = &data.password {
+            self.ensure_valid_password(password)?;
+        }
+        ensure_valid_username(&data.username)?;
+
+        self.ensure_email_not_banned(&data.email).await
+    }
+
+    pub(crate) async fn user_exists(&self, username: &str) -> Result<bool, UserError> {
+        let query = doc! {"username": username};
+        let user = self
+            .users
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(user.is_some())
+    }
+
+    /// Check that the email address hasn't been blacklisted by banning an account.
+    /// Addresses are compared case-insensitively (ignoring surrounding whitespace).
+    async fn ensure_email_not_banned(&self, email: &str) -> Result<(), UserError> {
+        let pattern = format!("^{}$", regex::escape(&normalize_email(email)));
+        let query = doc! {"email": {"$regex": pattern, "$options": "i"}};
+        let account = self
+            .banned_accounts
+            .find_one(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        match account {
+            Some(_account) => Err(UserError::BannedEmailError),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn get_password_rules(&self) -> api::PasswordRules {
+        self.password_policy.into()
+    }
+
+    fn ensure_valid_password(&self, password: &str) -> Result<(), UserError> {
+        self.get_password_rules()
+            .check(password)
+            .map_err(UserError::WeakPasswordError)?;
+
+        if self.breached_passwords.contains(password) {
+            return Err(UserError::BreachedPasswordError);
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn get_user(&self, vu: &auth::ViewUser) -> Result<api::User, UserError> {
         let query = doc! {"username": &vu.username};
         let user = self
@@ -134,7 +204,33 @@ impl<'a> UserActions<'a> {
         Ok(user.into())
     }
 
+    /// Schedule the user for deletion. The account is removed by
+    /// `delete_expired_users` once the grace period has passed and can be
+    /// recovered until then (by logging in with `cancel_deletion` set). All
+    /// the user's sessions are revoked.
     pub(crate) async fn delete_user(&self, eu: &auth::EditUser) -> Result<api::User, UserError> {
+        let delete_at = SystemTime::now() + ACCOUNT_DELETION_GRACE_PERIOD;
+        let query = doc! {"username": &eu.username};
+        let update = doc! {"$set": {"deleteAt": DateTime::from_system_time(delete_at)}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let user = self
+            .users
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        self.revoke(doc! {"username": &eu.username, "revoked": false})
+            .await?;
+
+        Ok(user.into())
+    }
+
+    /// Remove the user immediately (without a grace period). This is only used to
+    /// undo creating an account, such as when a group import fails.
+    pub(crate) async fn remove_user(&self, eu: &auth::EditUser) -> Result<api::User, UserError> {
         let query = doc! {"username": &eu.username};
         let user = self
             .users
@@ -150,6 +246,177 @@ impl<'a> UserActions<'a> {
         Ok(user.into())
     }
 
+    pub(crate) async fn cancel_user_deletion(
+        &self,
+        eu: &auth::EditUser,
+    ) -> Result<api::User, UserError> {
+        let query = doc! {"username": &eu.username};
+        let update = doc! {"$unset": {"deleteAt": true}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let user = self
+            .users
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        Ok(user.into())
+    }
+
+    /// Generate a new TOTP secret and backup codes for the user. Two-factor auth
+    /// is not enabled until a valid code has been provided to `confirm_two_factor`.
+    pub(crate) async fn enroll_two_factor(
+        &self,
+        mt: &auth::ManageTwoFactor,
+    ) -> Result<api::TwoFactorEnrollment, UserError> {
+        let user = self
+            .users
+            .find_one(doc! {"username": &mt.username}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        if user.two_factor.map(|tf| tf.enabled).unwrap_or(false) {
+            return Err(UserError::TwoFactorAlreadyEnabledError);
+        }
+
+        let secret = two_factor::generate_secret();
+        let backup_codes = two_factor::generate_backup_codes();
+        let two_factor = TwoFactorAuth {
+            secret: self.two_factor_key.encrypt(&secret),
+            enabled: false,
+            backup_codes: backup_codes
+                .iter()
+                .map(|code| two_factor::hash_backup_code(code))
+                .collect(),
+        };
+
+        // make sure it wasn't enabled concurrently
+        let query = doc! {"username": &mt.username, "twoFactor.enabled": {"$ne": true}};
+        let update = doc! {"$set": {"twoFactor": two_factor}};
+        let result = self
+            .users
+            .update_one(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        if result.matched_count == 0 {
+            return Err(UserError::TwoFactorAlreadyEnabledError);
+        }
+
+        Ok(api::TwoFactorEnrollment {
+            provisioning_uri: two_factor::provisioning_uri(&mt.username, &secret),
+            backup_codes,
+        })
+    }
+
+    /// Enable two-factor auth after checking a code from the user's authenticator.
+    pub(crate) async fn confirm_two_factor(
+        &self,
+        mt: &auth::ManageTwoFactor,
+        code: &str,
+    ) -> Result<(), UserError> {
+        let two_factor = self
+            .get_two_factor(&mt.username)
+            .await?
+            .ok_or(UserError::TwoFactorNotEnabledError)?;
+
+        if two_factor.enabled {
+            return Err(UserError::TwoFactorAlreadyEnabledError);
+        }
+
+        let secret = self.two_factor_key.decrypt(&two_factor.secret)?;
+        if !two_factor::verify_totp(&secret, code, SystemTime::now()) {
+            return Err(UserError::InvalidTwoFactorCodeError);
+        }
+
+        let query = doc! {"username": &mt.username, "twoFactor.secret": &two_factor.secret};
+        let update = doc! {"$set": {"twoFactor.enabled": true}};
+        self.users
+            .update_one(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(())
+    }
+
+    /// Disable two-factor auth. Requires a valid TOTP (or backup) code.
+    pub(crate) async fn disable_two_factor(
+        &self,
+        mt: &auth::ManageTwoFactor,
+        code: &str,
+    ) -> Result<(), UserError> {
+        let two_factor = self
+            .get_two_factor(&mt.username)
+            .await?
+            .filter(|tf| tf.enabled)
+            .ok_or(UserError::TwoFactorNotEnabledError)?;
+
+        two_factor::verify_code(
+            self.users,
+            self.two_factor_key,
+            &mt.username,
+            &two_factor,
+            code,
+        )
+        .await?;
+
+        let query = doc! {"username": &mt.username};
+        let update = doc! {"$unset": {"twoFactor": true}};
+        self.users
+            .update_one(query, update, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        Ok(())
+    }
+
+    async fn get_two_factor(&self, username: &str) -> Result<Option<TwoFactorAuth>, UserError> {
+        let user = self
+            .users
+            .find_one(doc! {"username": username}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        Ok(user.two_factor)
+    }
+
+    /// Remove all accounts whose deletion grace period has passed.
+    pub(crate) async fn delete_expired_users(&self) -> Result<(), UserError> {
+        let query = doc! {"deleteAt": {"$lte": DateTime::now()}};
+        let usernames: Vec<_> = self
+            .users
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|user| user.username)
+            .collect();
+
+        for username in usernames {
+            // make sure the deletion wasn't cancelled in the meantime
+            let query = doc! {"username": &username, "deleteAt": {"$lte": DateTime::now()}};
+            let user = self
+                .users
+                .find_one_and_delete(query, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?;
+
+            if let Some(group_id) = user.and_then(|user| user.group_id) {
+                utils::group_members_updated(self.users, self.friend_cache.clone(), &group_id)
+                    .await;
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn login(&self, request: api::LoginRequest) -> Result<api::User, UserError> {
         //let client_id = request.client_id.clone();
         let user = strategies::login(self.users, request.credentials).await?;
@@ -157,6 +424,87 @@ impl<'a> UserActions<'a> {
         Ok(user.into())
     }
 
+    /// List the active (unrevoked) sessions for the given user. `current` is the ID
+    /// of the session used to make the request, if known.
+    pub(crate) async fn list_sessions(
+        &self,
+        eu: &auth::EditUser,
+        current: Option<&str>,
+    ) -> Result<Vec<api::SessionInfo>, UserError> {
+        let query = doc! {"username": &eu.username, "revoked": false};
+        let sessions = self
+            .sessions
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|session| api::SessionInfo {
+                current: current.map(|id| id == session.id).unwrap_or(false),
+                id: session.id,
+                created_at: session.created_at.to_system_time(),
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+            })
+            .collect();
+
+        Ok(sessions)
+    }
+
+    /// Revoke the given session (or all sessions if unspecified) for the user.
+    /// Returns the IDs of the revoked sessions.
+    pub(crate) async fn revoke_sessions(
+        &self,
+        eu: &auth::EditUser,
+        session_id: Option<&str>,
+    ) -> Result<Vec<String>, UserError> {
+        let mut query = doc! {"username": &eu.username, "revoked": false};
+        if let Some(id) = session_id {
+            query.insert("id", id);
+        }
+
+        let revoked = self.revoke(query).await?;
+        if session_id.is_some() && revoked.is_empty() {
+            return Err(UserError::SessionNotFoundError);
+        }
+
+        Ok(revoked)
+    }
+
+    /// End the given session so it can't be reused (eg, with a copy of the cookie)
+    pub(crate) async fn end_session(&self, session_id: &str) -> Result<(), UserError> {
+        self.revoke(doc! {"id": session_id, "revoked": false})
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke(&self, query: mongodb::bson::Document) -> Result<Vec<String>, UserError> {
+        let ids: Vec<_> = self
+            .sessions
+            .find(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|session| session.id)
+            .collect();
+
+        if !ids.is_empty() {
+            let query = doc! {"id": {"$in": &ids}};
+            let update = doc! {"$set": {"revoked": true}};
+            self.sessions
+                .update_many(query, update, None)
+                .await
+                .map_err(InternalError::DatabaseConnectionError)?;
+        }
+
+        Ok(ids)
+    }
+
     pub(crate) fn logout(&self, client_id: &api::ClientId) {
         self.network.do_send(topology::SetClientUsername {
             id: client_id.clone(),
@@ -203,14 +551,88 @@ impl<'a> UserActions<'a> {
         Ok(())
     }
 
+    pub(crate) async fn send_email_verification(
+        &self,
+        eu: &auth::EditUser,
+    ) -> Result<(), UserError> {
+        let user = self
+            .users
+            .find_one(doc! {"username": &eu.username}, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::UserNotFoundError)?;
+
+        if user.verified {
+            return Err(UserError::EmailAlreadyVerifiedError);
+        }
+
+        // Only the most recent verification link is valid
+        let token = EmailVerificationToken::new(user.username.clone(), user.email.clone());
+        let query = doc! {"username": &user.username};
+        self.email_verification_tokens
+            .delete_many(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+        self.email_verification_tokens
+            .insert_one(&token, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?;
+
+        let email = VerifyEmail {
+            sender: self.sender.clone(),
+            public_url: self.public_url.clone(),
+            user,
+            token,
+        };
+
+        utils::send_email(self.mailer, email)?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn verify_email(&self, secret: &str) -> Result<api::User, UserError> {
+        let query = doc! {"secret": secret};
+        let token = self
+            .email_verification_tokens
+            .find_one_and_delete(query, None)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::EmailVerificationTokenNotFoundError)?;
+
+        // expired tokens may not have been removed by the database yet
+        let expires_at = token.created_at.to_system_time() + EMAIL_VERIFICATION_TOKEN_TTL;
+        if expires_at < SystemTime::now() {
+            return Err(UserError::EmailVerificationTokenNotFoundError);
+        }
+
+        let query = doc! {"username": &token.username, "email": &token.email};
+        let update = doc! {"$set": {"verified": true}};
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let user = self
+            .users
+            .find_one_and_update(query, update, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .ok_or(UserError::EmailVerificationTokenNotFoundError)?;
+
+        Ok(user.into())
+    }
+
     pub(crate) async fn update_user(&self, eu: &auth::UpdateUser) -> Result<api::User, UserError> {
         let query = doc! {"username": &eu.username};
 
         // Get a doc with just the fields to set
-        let update_fields = utils::fields_with_values(&eu.update)
+        let mut update_fields = utils::fields_with_values(&eu.update)
             .and_then(|obj| if obj.is_empty() { None } else { Some(obj) })
             .ok_or(UserError::UserUpdateFieldRequiredError)?;
 
+        // a new email address needs to be verified again
+        if update_fields.contains_key("email") {
+            update_fields.insert("verified".into(), false.into());
+        }
+
         let update = doc! {
           "$set": mongodb::bson::to_document(&update_fields).unwrap()
         };
@@ -234,6 +656,8 @@ impl<'a> UserActions<'a> {
         sp: &auth::SetPassword,
         password: String,
     ) -> Result<api::User, UserError> {
+        self.ensure_valid_password(&password)?;
+
         let query = doc! {"username": &sp.username};
         let user = self
             .users
@@ -320,6 +744,50 @@ impl<'a> UserActions<'a> {
         Ok(account.into())
     }
 
+    pub(crate) async fn list_banned_accounts(
+        &self,
+        _lb: &auth::ListBannedAccounts,
+        params: &api::ListBannedAccountsQuery,
+    ) -> Result<Vec<api::BannedAccount>, UserError> {
+        let mut username_query = doc! {};
+        if let Some(after) = &params.after {
+            username_query.insert("$gt", after);
+        }
+        if let Some(username) = &params.username {
+            username_query.insert("$regex", regex::escape(username));
+            username_query.insert("$options", "i");
+        }
+
+        let mut query = doc! {};
+        if !username_query.is_empty() {
+            query.insert("username", username_query);
+        }
+        if let Some(email) = &params.email {
+            query.insert(
+                "email",
+                doc! {"$regex": regex::escape(email), "$options": "i"},
+            );
+        }
+
+        let options = FindOptions::builder()
+            .sort(doc! {"username": 1})
+            .limit(params.limit)
+            .build();
+        let accounts = self
+            .banned_accounts
+            .find(query, options)
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(InternalError::DatabaseConnectionError)?
+            .into_iter()
+            .map(|account| account.into())
+            .collect();
+
+        Ok(accounts)
+    }
+
     pub(crate) async fn link_account(
         &self,
         eu: &auth::EditUser,
@@ -379,11 +847,19 @@ impl<'a> UserActions<'a> {
     pub(crate) async fn list_users(
         &self,
         _lu: &auth::ListUsers,
+        params: &api::ListUsersQuery,
     ) -> Result<Vec<api::User>, UserError> {
-        let query = doc! {};
+        let query = match &params.after {
+            Some(after) => doc! {"username": {"$gt": after}},
+            None => doc! {},
+        };
+        let options = FindOptions::builder()
+            .sort(doc! {"username": 1})
+            .limit(params.limit)
+            .build();
         let cursor = self
             .users
-            .find(query, None)
+            .find(query, options)
             .await
             .map_err(InternalError::DatabaseConnectionError)?;
         let users: Vec<_> = cursor
@@ -459,6 +935,12 @@ impl<'a> UserActions<'a> {
     }
 }
 
+/// Time before an email verification link expires
+pub(crate) const EMAIL_VERIFICATION_TOKEN_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Time before an account scheduled for deletion is actually removed
+const ACCOUNT_DELETION_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
 fn ensure_valid_email(email: &str) -> Result<(), UserError> {
     email
         .parse::<Address>()
@@ -466,6 +948,11 @@ fn ensure_valid_email(email: &str) -> Result<(), UserError> {
 
     Ok(())
 }
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
 fn ensure_valid_username(name: &str) -> Result<(), UserError> {
     if !is_valid_username(name) {
         Err(UserError::InvalidUsername)
@@ -529,6 +1016,44 @@ impl TryFrom<SetPasswordEmail> for lettre::Message {
     }
 }
 
+struct VerifyEmail {
+    sender: Mailbox,
+    user: User,
+    token: EmailVerificationToken,
+    public_url: String,
+}
+
+impl VerifyEmail {
+    fn render(&self) -> MultiPart {
+        let url = format!("{}/users/verify/{}", self.public_url, &self.token.secret);
+        email_template::verify_email(&self.user.username, &url)
+    }
+}
+
+impl TryFrom<VerifyEmail> for lettre::Message {
+    type Error = UserError;
+
+    fn try_from(email: VerifyEmail) -> Result<Self, UserError> {
+        let subject = "Verify Your Email Address";
+        let body = email.render();
+        let to_email = email.token.email;
+        let message = Message::builder()
+            .from(email.sender)
+            .to(Mailbox::new(
+                None,
+                to_email
+                    .parse::<Address>()
+                    .map_err(|_err| UserError::InvalidEmailAddress)?,
+            ))
+            .subject(subject.to_string())
+            .date_now()
+            .multipart(body)
+            .map_err(|_err| InternalError::EmailBuildError)?;
+
+        Ok(message)
+    }
+}
+
 struct ForgotUsernameEmail {
     sender: Mailbox,
     usernames: NonEmpty<String>,
@@ -610,6 +1135,46 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_delete_expired_users() {
+        let mut expired: User = api::NewUser {
+            username: "expired".into(),
+            email: "expired@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        expired.delete_at = Some(DateTime::from_millis(0));
+        let mut pending: User = api::NewUser {
+            username: "pending".into(),
+            email: "pending@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        pending.delete_at = Some(DateTime::from_system_time(
+            SystemTime::now() + ACCOUNT_DELETION_GRACE_PERIOD,
+        ));
+
+        test_utils::setup()
+            .with_users(&[expired, pending])
+            .run(|app_data| async move {
+                let actions = app_data.as_user_actions();
+                actions.delete_expired_users().await.unwrap();
+
+                let query = doc! {"username": "expired"};
+                let user = actions.users.find_one(query, None).await.unwrap();
+                assert!(user.is_none(), "Expired user not deleted.");
+
+                let query = doc! {"username": "pending"};
+                let user = actions.users.find_one(query, None).await.unwrap();
+                assert!(user.is_some(), "User deleted before grace period ended.");
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_update_user_email() {
         let user: User = api::NewUser {
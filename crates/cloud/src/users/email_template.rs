@@ -36,6 +36,41 @@ pub(crate) fn set_password_email(username: &str, url: &str) -> MultiPart {
     MultiPart::alternative_plain_html(txt, html)
 }
 
+pub(crate) fn verify_email(username: &str, url: &str) -> MultiPart {
+    let html = format!(
+        "<h1>Verify Your Email Address</h1>
+        <p>
+            Click the link below to verify the email address for {username}. If you did not create a NetsBlox account, this email can be ignored.
+            <br/>
+            <br/>
+            <a href=\"{url}\">{url}</a>
+            <br/>
+            <br/>
+            Cheers,<br/>
+            the NetsBlox team
+        </p>
+        ",
+        username = username,
+        url = url
+    );
+    let txt = format!(
+        "Verify Your Email Address
+
+        Click the link below to verify the email address for {username}. If you did not create a NetsBlox account, this email can be ignored.
+
+
+        {url}
+
+
+        Cheers,
+        the NetsBlox team",
+        username = username,
+        url = url
+    );
+
+    MultiPart::alternative_plain_html(txt, html)
+}
+
 pub(crate) fn forgot_username_email(email: &str, usernames: &NonEmpty<String>) -> MultiPart {
     if usernames.len() > 1 {
         multi_usernames_email(email, usernames)
@@ -14,11 +14,15 @@ use mongodb::bson::doc;
 use serde::Deserialize;
 
 #[get("/")]
-async fn list_users(app: web::Data<AppData>, req: HttpRequest) -> Result<HttpResponse, UserError> {
+async fn list_users(
+    app: web::Data<AppData>,
+    params: web::Query<api::ListUsersQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
     let auth_lu = auth::try_list_users(&app, &req).await?;
 
     let actions: UserActions = app.as_user_actions();
-    let users = actions.list_users(&auth_lu).await?;
+    let users = actions.list_users(&auth_lu, &params).await?;
 
     Ok(HttpResponse::Ok().json(users))
 }
@@ -29,7 +33,7 @@ async fn create_user(
     req: HttpRequest,
     user_data: web::Json<api::NewUser>,
 ) -> Result<HttpResponse, UserError> {
-    let req_addr = req.peer_addr().map(|addr| addr.ip());
+    let req_addr = app.get_client_ip(&req);
     if let Some(addr) = req_addr {
         app.ensure_not_tor_ip(&addr).await?;
     }
@@ -49,9 +53,8 @@ async fn login(
     req: HttpRequest,
     app: web::Data<AppData>,
     request: web::Json<api::LoginRequest>,
-    session: Session,
 ) -> Result<HttpResponse, UserError> {
-    let req_addr = req.peer_addr().map(|addr| addr.ip());
+    let req_addr = app.get_client_ip(&req);
     if let Some(addr) = req_addr {
         app.ensure_not_tor_ip(&addr).await?;
     }
@@ -60,10 +63,27 @@ async fn login(
 
     let actions: UserActions = app.as_user_actions();
     let client_id = request.client_id.clone();
-    let user = actions.login(request).await?;
+    let two_factor_code = request.two_factor_code.clone();
+    let cancel_deletion = request.cancel_deletion;
+    let user = actions
+        .login(request)
+        .await
+        .inspect_err(|_err| app.metrics.record_failed_login())?;
+
+    if two_factor_code.is_some() {
+        app.ensure_within_rate_limit(req_addr.as_ref(), &user.username)?;
+    }
 
     let helper = app.as_login_helper();
-    helper.login(session, &user, client_id).await?;
+    helper
+        .login(
+            &req,
+            &user,
+            client_id,
+            two_factor_code.as_deref(),
+            cancel_deletion,
+        )
+        .await?;
 
     Ok(HttpResponse::Ok().json(user))
 }
@@ -81,21 +101,26 @@ async fn logout(
     app: web::Data<AppData>,
     params: web::Query<LogoutQueryParams>,
     session: Session,
-) -> HttpResponse {
+) -> Result<HttpResponse, UserError> {
+    let session_id = session.get::<String>("sessionId").unwrap_or(None);
     session.purge();
 
+    let actions: UserActions = app.as_user_actions();
+    if let Some(session_id) = session_id {
+        actions.end_session(&session_id).await?;
+    }
+
     if let Some(client_id) = &params.client_id {
         // FIXME: this method should be updated as it currently could be used to half logout other users...
-        let actions: UserActions = app.as_user_actions();
         actions.logout(client_id);
     }
 
-    HttpResponse::Ok().finish()
+    Ok(HttpResponse::Ok().finish())
 }
 
 #[get("/whoami")]
 async fn whoami(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    if let Some(username) = utils::get_username(&req) {
+    if let Some(username) = utils::get_username(&req).await {
         Ok(HttpResponse::Ok().body(username))
     } else {
         Err(UserError::PermissionsError)
@@ -125,6 +150,9 @@ async fn ban_user(
     let actions: UserActions = app.as_user_actions();
     let account = actions.ban_user(&auth_bu).await?;
 
+    app.audit_request(&req, api::AuditAction::BanUser, &username)
+        .await;
+
     Ok(HttpResponse::Ok().json(account))
 }
 
@@ -140,9 +168,34 @@ async fn unban_user(
     let actions: UserActions = app.as_user_actions();
     let account = actions.unban_user(&auth_bu).await?;
 
+    app.audit_request(&req, api::AuditAction::UnbanUser, &username)
+        .await;
+
     Ok(HttpResponse::Ok().json(account))
 }
 
+#[get("/banned")]
+async fn list_banned_accounts(
+    app: web::Data<AppData>,
+    params: web::Query<api::ListBannedAccountsQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let auth_lb = auth::try_list_banned_accounts(&app, &req).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    let accounts = actions.list_banned_accounts(&auth_lb, &params).await?;
+
+    Ok(HttpResponse::Ok().json(accounts))
+}
+
+#[get("/password-policy")]
+async fn get_password_rules(app: web::Data<AppData>) -> Result<HttpResponse, UserError> {
+    let actions: UserActions = app.as_user_actions();
+    let rules = actions.get_password_rules();
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
 #[post("/{username}/delete")]
 async fn delete_user(
     app: web::Data<AppData>,
@@ -156,21 +209,168 @@ async fn delete_user(
     let actions: UserActions = app.as_user_actions();
     let user = actions.delete_user(&auth_eu).await?;
 
+    // only record deletions performed on behalf of another user
+    if utils::get_username(&req).await.as_deref() != Some(username.as_str()) {
+        app.audit_request(&req, api::AuditAction::DeleteUser, &username)
+            .await;
+    }
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[post("/{username}/delete/cancel")]
+async fn cancel_user_deletion(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    let user = actions.cancel_user_deletion(&auth_eu).await?;
+
     Ok(HttpResponse::Ok().json(user))
 }
 
+#[get("/{username}/sessions")]
+async fn list_sessions(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+    session: Session,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let current = session.get::<String>("sessionId").unwrap_or(None);
+    let actions: UserActions = app.as_user_actions();
+    let sessions = actions.list_sessions(&auth_eu, current.as_deref()).await?;
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+#[post("/{username}/sessions/revoke")]
+async fn revoke_sessions(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    data: web::Json<api::RevokeSessionsData>,
+    req: HttpRequest,
+    session: Session,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    let revoked = actions
+        .revoke_sessions(&auth_eu, data.session_id.as_deref())
+        .await?;
+
+    // log out the caller if they revoked their own session
+    let current = session.get::<String>("sessionId").unwrap_or(None);
+    if current.map(|id| revoked.contains(&id)).unwrap_or(false) {
+        session.purge();
+    }
+
+    Ok(HttpResponse::Ok().json(revoked))
+}
+
+#[post("/{username}/2fa/enroll")]
+async fn enroll_two_factor(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_mt = auth::try_manage_two_factor(&app, &req, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    let enrollment = actions.enroll_two_factor(&auth_mt).await?;
+
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+#[post("/{username}/2fa/verify")]
+async fn verify_two_factor(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    code: web::Json<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_mt = auth::try_manage_two_factor(&app, &req, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    actions.confirm_two_factor(&auth_mt, &code).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[post("/{username}/2fa/disable")]
+async fn disable_two_factor(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    code: web::Json<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_mt = auth::try_manage_two_factor(&app, &req, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    actions.disable_two_factor(&auth_mt, &code).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[post("/{username}/send-verification")]
+async fn send_email_verification(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+
+    let auth_eu = auth::try_edit_user(&app, &req, None, &username).await?;
+
+    let actions: UserActions = app.as_user_actions();
+    actions.send_email_verification(&auth_eu).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/verify/{token}")]
+async fn verify_email(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, UserError> {
+    let (token,) = path.into_inner();
+
+    let actions: UserActions = app.as_user_actions();
+    let user = actions.verify_email(&token).await?;
+
+    Ok(HttpResponse::Ok().body(format!("Email address verified for {}.", user.username)))
+}
+
 #[post("/{username}/password")]
 async fn reset_password(
     app: web::Data<AppData>,
     req: HttpRequest,
     path: web::Path<(String,)>,
 ) -> Result<HttpResponse, UserError> {
-    let req_addr = req.peer_addr().map(|addr| addr.ip());
+    let req_addr = app.get_client_ip(&req);
     if let Some(addr) = req_addr {
         app.ensure_not_tor_ip(&addr).await?;
     }
 
     let (username,) = path.into_inner();
+    app.ensure_within_rate_limit(req_addr.as_ref(), &username)?;
+
     let actions: UserActions = app.as_user_actions();
     actions.reset_password(&username).await?;
 
@@ -203,8 +403,14 @@ async fn change_password(
     req: HttpRequest,
 ) -> Result<HttpResponse, UserError> {
     let (username,) = path.into_inner();
+    let params = params.into_inner();
+    if params.token.is_some() {
+        // limit attempts to guess reset tokens
+        let req_addr = app.get_client_ip(&req);
+        app.ensure_within_rate_limit(req_addr.as_ref(), &username)?;
+    }
 
-    let auth_sp = auth::try_set_password(&app, &req, &username, params.into_inner().token).await?;
+    let auth_sp = auth::try_set_password(&app, &req, &username, params.token).await?;
     let actions: UserActions = app.as_user_actions();
     let user = actions.set_password(&auth_sp, data.into_inner()).await?;
 
@@ -226,6 +432,47 @@ async fn view_user(
     Ok(HttpResponse::Ok().json(user))
 }
 
+#[get("/{username}/export")]
+async fn export_user(
+    app: web::Data<AppData>,
+    path: web::Path<(String,)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UserError> {
+    let (username,) = path.into_inner();
+    let auth_ex = auth::try_export_user(&app, &req, &username).await?;
+    let auth_vu = auth::ViewUser::from_export_user(&auth_ex);
+
+    let actions: UserActions = app.as_user_actions();
+    let user = actions.get_user(&auth_vu).await?;
+    let service_settings = actions.get_service_settings(&auth_vu).await?;
+
+    let auth_lp = auth::ListProjects::from_export_user(&auth_ex);
+    let projects = app.as_project_actions().list_projects(&auth_lp).await?;
+
+    let auth_ll = auth::ListLibraries::from_export_user(&auth_ex);
+    let libraries = app
+        .as_library_actions()
+        .list_user_libraries(&auth_ll)
+        .await?;
+
+    let friends = app.as_friend_actions().list_friends(&auth_vu).await?;
+    let collaboration_invites = app
+        .as_collab_invite_actions()
+        .export_invites(&auth_ex)
+        .await?;
+
+    let export = api::UserExport {
+        user,
+        projects,
+        libraries,
+        friends,
+        collaboration_invites,
+        service_settings,
+    };
+
+    Ok(HttpResponse::Ok().json(export))
+}
+
 #[patch("/{username}")]
 async fn update_user(
     app: web::Data<AppData>,
@@ -285,29 +532,43 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(login)
         .service(logout)
         .service(delete_user)
+        .service(cancel_user_deletion)
+        .service(list_sessions)
+        .service(revoke_sessions)
+        .service(enroll_two_factor)
+        .service(verify_two_factor)
+        .service(disable_two_factor)
+        .service(send_email_verification)
+        .service(verify_email)
         .service(forgot_username)
         .service(ban_user)
         .service(unban_user)
+        .service(list_banned_accounts)
+        .service(get_password_rules)
         .service(reset_password)
         .service(change_password_page)
         .service(change_password)
         .service(whoami)
         .service(view_user)
+        .service(export_user)
         .service(link_account)
         .service(unlink_account);
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
-    use crate::{errors::InternalError, network::topology, test_utils};
+    use crate::{
+        config::PasswordPolicy, errors::InternalError, network::topology, rate_limit::RateLimiter,
+        test_utils, two_factor,
+    };
 
     use super::*;
     use actix_web::{http, test, App};
     use netsblox_cloud_common::{
         api::{BannedAccount, Credentials, UserRole},
-        Group, User,
+        CollaborationInvite, EmailVerificationToken, Group, User,
     };
 
     #[actix_web::test]
@@ -565,6 +826,144 @@ mod tests {
             .await;
     }
 
+    #[actix_web::test]
+    async fn test_create_user_banned_email() {
+        let moderator: User = api::NewUser {
+            username: "moderator".into(),
+            email: "moderator@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Moderator),
+        }
+        .into();
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[moderator, user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/user/ban")
+                    .cookie(test_utils::cookie::new("moderator"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let user_data = api::NewUser {
+                    username: "newUser".into(),
+                    email: "User@NetsBlox.org".into(),
+                    password: Some("pwd".into()),
+                    group_id: None,
+                    role: None,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/create")
+                    .set_json(&user_data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let query = doc! {"username": "newUser"};
+                let result = app_data.users.find_one(query, None).await.unwrap();
+                assert!(result.is_none(), "User created");
+
+                let user_data = api::NewUser {
+                    email: "other@netsblox.org".into(),
+                    ..user_data
+                };
+                let req = test::TestRequest::post()
+                    .uri("/create")
+                    .set_json(&user_data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    fn strict_password_policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+            breached_passwords: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_create_user_password_policy() {
+        test_utils::setup()
+            .run(|mut app_data| async move {
+                app_data.settings.security.password_policy = strict_password_policy();
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let cases = [
+                    ("tooShort", "aB3", http::StatusCode::BAD_REQUEST),
+                    ("noUppercase", "password123", http::StatusCode::BAD_REQUEST),
+                    ("compliant", "Password123", http::StatusCode::OK),
+                ];
+                for (username, password, status) in cases {
+                    let user_data = api::NewUser {
+                        username: username.into(),
+                        email: "test@gmail.com".into(),
+                        password: Some(password.into()),
+                        group_id: None,
+                        role: None,
+                    };
+                    let req = test::TestRequest::post()
+                        .uri("/create")
+                        .set_json(&user_data)
+                        .to_request();
+
+                    let response = test::call_service(&app, req).await;
+                    assert_eq!(response.status(), status, "{}", username);
+                }
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_get_password_rules() {
+        test_utils::setup()
+            .run(|mut app_data| async move {
+                app_data.settings.security.password_policy = strict_password_policy();
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/password-policy")
+                    .to_request();
+                let rules: api::PasswordRules = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(rules, (&strict_password_policy()).into());
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_create_member_unauth() {
         let owner_name = String::from("admin");
@@ -723,6 +1122,8 @@ mod tests {
                 let credentials = api::LoginRequest {
                     credentials: Credentials::NetsBlox { username, password },
                     client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -770,6 +1171,8 @@ mod tests {
                         password,
                     },
                     client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -811,6 +1214,8 @@ mod tests {
                         password: "badpwd".into(),
                     },
                     client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -842,6 +1247,8 @@ mod tests {
                 let credentials = api::LoginRequest {
                     credentials: Credentials::NetsBlox { username, password },
                     client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -887,6 +1294,8 @@ mod tests {
                         password,
                     },
                     client_id: Some(client.id),
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -940,6 +1349,8 @@ mod tests {
                 let credentials = api::LoginRequest {
                     credentials: Credentials::NetsBlox { username, password },
                     client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
                 };
                 let req = test::TestRequest::post()
                     .uri("/login")
@@ -954,21 +1365,11 @@ mod tests {
             .await;
     }
 
-    //     #[actix_web::test]
-    //     async fn test_login_with_strategy() {
-    //         todo!();
-    //     }
-
-    //     #[actix_web::test]
-    //     async fn test_login_with_strategy_403() {
-    //         todo!();
-    //     }
-
     #[actix_web::test]
-    async fn test_logout() {
+    async fn test_login_pending_deletion() {
         let username: String = "user".into();
         let password: String = "password".into();
-        let user: User = api::NewUser {
+        let mut user: User = api::NewUser {
             username: username.clone(),
             email: "user@netsblox.org".into(),
             password: Some(password.clone()),
@@ -976,34 +1377,56 @@ mod tests {
             role: None,
         }
         .into();
+        user.delete_at = Some(mongodb::bson::DateTime::now());
 
         test_utils::setup()
-            .with_users(&[user.clone()])
-            .run(|app_data| async {
+            .with_users(&[user])
+            .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
                         .wrap(test_utils::cookie::middleware())
-                        .app_data(web::Data::new(app_data))
+                        .app_data(web::Data::new(app_data.clone()))
                         .configure(config),
                 )
                 .await;
-                let req = test::TestRequest::post()
-                    .uri("/logout")
-                    .cookie(test_utils::cookie::new(&username))
+                let mut credentials = api::LoginRequest {
+                    credentials: Credentials::NetsBlox {
+                        username: username.clone(),
+                        password,
+                    },
+                    client_id: None,
+                    two_factor_code: None,
+                    cancel_deletion: false,
+                };
+                let req = test::TestRequest::post()
+                    .uri("/login")
+                    .set_json(&credentials)
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::OK);
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
                 let cookie = response.headers().get(http::header::SET_COOKIE);
-                assert!(cookie.is_some());
-                let cookie_data = cookie.unwrap().to_str().unwrap();
-                assert!(cookie_data.starts_with("test_netsblox=;"));
+                assert!(cookie.is_none());
+
+                // the deletion can be cancelled when logging in
+                credentials.cancel_deletion = true;
+                let req = test::TestRequest::post()
+                    .uri("/login")
+                    .set_json(&credentials)
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"username": &username};
+                let user = app_data.users.find_one(query, None).await.unwrap().unwrap();
+                assert!(user.delete_at.is_none(), "User deletion not cancelled");
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_logout_set_client_username() {
+    async fn test_list_revoke_sessions() {
         let username: String = "user".into();
         let password: String = "password".into();
         let user: User = api::NewUser {
@@ -1014,11 +1437,274 @@ mod tests {
             role: None,
         }
         .into();
-        let client = test_utils::network::Client::new(Some(username.clone()), None);
 
         test_utils::setup()
-            .with_users(&[user.clone()])
-            .with_clients(&[client.clone()])
+            .with_users(&[user])
+            .run(|app_data| async move {
+                // only keep the sessions created by logging in
+                app_data.sessions.delete_many(doc! {}, None).await.unwrap();
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                // login twice (eg, from two different computers)
+                let mut cookies = Vec::new();
+                for _ in 0..2 {
+                    let req = login_request(&username, &password, None).to_request();
+                    let response = test::call_service(&app, req).await;
+                    assert_eq!(response.status(), http::StatusCode::OK);
+                    let cookie = response.response().cookies().next().unwrap().into_owned();
+                    cookies.push(cookie);
+                }
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/sessions", &username))
+                    .cookie(cookies[0].clone())
+                    .to_request();
+                let sessions: Vec<api::SessionInfo> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(sessions.len(), 2);
+                let current = sessions.iter().find(|s| s.current).unwrap();
+                let other = sessions.iter().find(|s| !s.current).unwrap();
+
+                let data = api::RevokeSessionsData {
+                    session_id: Some(other.id.clone()),
+                };
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/sessions/revoke", &username))
+                    .cookie(cookies[0].clone())
+                    .set_json(&data)
+                    .to_request();
+                let revoked: Vec<String> = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(revoked, vec![other.id.clone()]);
+
+                // the revoked session is no longer authorized
+                let req = test::TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(cookies[1].clone())
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let req = test::TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(cookies[0].clone())
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/sessions", &username))
+                    .cookie(cookies[0].clone())
+                    .to_request();
+                let sessions: Vec<api::SessionInfo> =
+                    test::call_and_read_body_json(&app, req).await;
+                assert_eq!(sessions.len(), 1);
+                assert_eq!(sessions[0].id, current.id);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_all_sessions() {
+        let username: String = "user".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: Some(password.clone()),
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                // only keep the sessions created by logging in
+                app_data.sessions.delete_many(doc! {}, None).await.unwrap();
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = login_request(&username, &password, None).to_request();
+                let response = test::call_service(&app, req).await;
+                let cookie = response.response().cookies().next().unwrap().into_owned();
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/sessions/revoke", &username))
+                    .cookie(cookie.clone())
+                    .set_json(api::RevokeSessionsData::default())
+                    .to_request();
+                let revoked: Vec<String> = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(revoked.len(), 1);
+
+                // revoking the current session logs the caller out
+                let req = test::TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(cookie)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_session_404() {
+        let username: String = "user".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let data = api::RevokeSessionsData {
+                    session_id: Some("unknownSession".into()),
+                };
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/sessions/revoke", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(&data)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_reject_expired_session() {
+        let username: String = "user".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                // sessions are removed from the database once they expire
+                app_data.sessions.delete_many(doc! {}, None).await.unwrap();
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_reject_cookie_without_session_id() {
+        let username: String = "user".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(test_utils::cookie::legacy(&username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    fn login_request(username: &str, password: &str, code: Option<&str>) -> test::TestRequest {
+        let credentials = api::LoginRequest {
+            credentials: Credentials::NetsBlox {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            },
+            client_id: None,
+            two_factor_code: code.map(|code| code.to_owned()),
+            cancel_deletion: false,
+        };
+        test::TestRequest::post()
+            .uri("/login")
+            .set_json(&credentials)
+    }
+
+    fn get_totp_secret(enrollment: &api::TwoFactorEnrollment) -> Vec<u8> {
+        let secret = enrollment
+            .provisioning_uri
+            .split("secret=")
+            .nth(1)
+            .and_then(|params| params.split('&').next())
+            .unwrap();
+        data_encoding::BASE32_NOPAD
+            .decode(secret.as_bytes())
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_two_factor_enroll_verify_login() {
+        let username: String = "teacher".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "teacher@netsblox.org".into(),
+            password: Some(password.clone()),
+            group_id: None,
+            role: Some(UserRole::Teacher),
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1027,44 +1713,960 @@ mod tests {
                         .configure(config),
                 )
                 .await;
+
                 let req = test::TestRequest::post()
-                    .uri(&format!("/logout?clientId={}", client.id.as_str()))
+                    .uri(&format!("/{}/2fa/enroll", &username))
                     .cookie(test_utils::cookie::new(&username))
                     .to_request();
+                let enrollment: api::TwoFactorEnrollment =
+                    test::call_and_read_body_json(&app, req).await;
+                assert!(enrollment.provisioning_uri.starts_with("otpauth://totp/"));
+                let secret = get_totp_secret(&enrollment);
 
+                // not enabled until verified
+                let req = login_request(&username, &password, None).to_request();
                 let response = test::call_service(&app, req).await;
                 assert_eq!(response.status(), http::StatusCode::OK);
 
-                // Give it a little time for the actix message to be received
-                // (message passing is async)
-                tokio::time::sleep(Duration::from_millis(10)).await;
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/verify", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(two_factor::current_code(&secret))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
 
-                let task = app_data
-                    .network
-                    .send(topology::GetOnlineUsers(None))
+                // code required
+                let req = login_request(&username, &password, None).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+
+                let code = two_factor::current_code(&secret);
+                let req = login_request(&username, &password, Some(&code)).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_some());
+
+                // backup codes can only be used once
+                let backup_code = enrollment.backup_codes.first().unwrap();
+                let req = login_request(&username, &password, Some(backup_code)).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = login_request(&username, &password, Some(backup_code)).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let user = app_data
+                    .users
+                    .find_one(doc! {"username": &username}, None)
                     .await
-                    .map_err(InternalError::ActixMessageError)
+                    .unwrap()
                     .unwrap();
-                let online_friends = task.run().await;
-
-                assert_eq!(online_friends.len(), 0);
+                let two_factor = user.two_factor.unwrap();
+                assert!(two_factor.enabled);
+                assert_eq!(
+                    two_factor.backup_codes.len(),
+                    enrollment.backup_codes.len() - 1
+                );
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_delete_user_admin() {
-        let admin: User = api::NewUser {
-            username: "admin".into(),
-            email: "admin@netsblox.org".into(),
-            password: None,
+    async fn test_two_factor_wrong_code() {
+        let username: String = "teacher".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "teacher@netsblox.org".into(),
+            password: Some(password.clone()),
             group_id: None,
-            role: Some(UserRole::Admin),
+            role: Some(UserRole::Teacher),
         }
         .into();
-        let other_username = "other_user";
-        let other_user: User = api::NewUser {
-            username: other_username.to_string(),
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/enroll", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+                let enrollment: api::TwoFactorEnrollment =
+                    test::call_and_read_body_json(&app, req).await;
+                let secret = get_totp_secret(&enrollment);
+                let code = two_factor::current_code(&secret);
+                let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/verify", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(&wrong_code)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/verify", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(&code)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = login_request(&username, &password, Some(&wrong_code)).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+
+                // disabling requires a valid code
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/disable", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(&wrong_code)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let user = app_data
+                    .users
+                    .find_one(doc! {"username": &username}, None)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(user.two_factor.unwrap().enabled);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_two_factor_lockout() {
+        let username: String = "teacher".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "teacher@netsblox.org".into(),
+            password: Some(password.clone()),
+            group_id: None,
+            role: Some(UserRole::Teacher),
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/enroll", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+                let enrollment: api::TwoFactorEnrollment =
+                    test::call_and_read_body_json(&app, req).await;
+                let secret = get_totp_secret(&enrollment);
+                let code = two_factor::current_code(&secret);
+                let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/verify", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .set_json(&code)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let max_failures = app_data
+                    .settings
+                    .security
+                    .rate_limits
+                    .max_two_factor_failures;
+                for _ in 0..max_failures {
+                    let req = login_request(&username, &password, Some(&wrong_code)).to_request();
+                    let response = test::call_service(&app, req).await;
+                    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+                }
+
+                // even the correct code is rejected once locked out
+                let code = two_factor::current_code(&secret);
+                let req = login_request(&username, &password, Some(&code)).to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+                assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_enroll_two_factor_403() {
+        let username: String = "user".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/2fa/enroll", &username))
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    //     #[actix_web::test]
+    //     async fn test_login_with_strategy() {
+    //         todo!();
+    //     }
+
+    //     #[actix_web::test]
+    //     async fn test_login_with_strategy_403() {
+    //         todo!();
+    //     }
+
+    #[actix_web::test]
+    async fn test_logout() {
+        let username: String = "user".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: Some(password.clone()),
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data))
+                        .configure(config),
+                )
+                .await;
+                let req = test::TestRequest::post()
+                    .uri("/logout")
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                let cookie = response.headers().get(http::header::SET_COOKIE);
+                assert!(cookie.is_some());
+                let cookie_data = cookie.unwrap().to_str().unwrap();
+                assert!(cookie_data.starts_with("test_netsblox=;"));
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_logout_set_client_username() {
+        let username: String = "user".into();
+        let password: String = "password".into();
+        let user: User = api::NewUser {
+            username: username.clone(),
+            email: "user@netsblox.org".into(),
+            password: Some(password.clone()),
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let client = test_utils::network::Client::new(Some(username.clone()), None);
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_clients(&[client.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+                let req = test::TestRequest::post()
+                    .uri(&format!("/logout?clientId={}", client.id.as_str()))
+                    .cookie(test_utils::cookie::new(&username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                // Give it a little time for the actix message to be received
+                // (message passing is async)
+                tokio::time::sleep(Duration::from_millis(10)).await;
+
+                let task = app_data
+                    .network
+                    .send(topology::GetOnlineUsers(None))
+                    .await
+                    .map_err(InternalError::ActixMessageError)
+                    .unwrap();
+                let online_friends = task.run().await;
+
+                assert_eq!(online_friends.len(), 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_users_page() {
+        let users: Vec<User> = vec![
+            api::NewUser {
+                username: "admin".into(),
+                email: "admin@netsblox.org".into(),
+                password: None,
+                group_id: None,
+                role: Some(UserRole::Admin),
+            }
+            .into(),
+            api::NewUser {
+                username: "bob".into(),
+                email: "bob@netsblox.org".into(),
+                password: None,
+                group_id: None,
+                role: None,
+            }
+            .into(),
+            api::NewUser {
+                username: "carol".into(),
+                email: "carol@netsblox.org".into(),
+                password: None,
+                group_id: None,
+                role: None,
+            }
+            .into(),
+            api::NewUser {
+                username: "dave".into(),
+                email: "dave@netsblox.org".into(),
+                password: None,
+                group_id: None,
+                role: None,
+            }
+            .into(),
+        ];
+
+        test_utils::setup()
+            .with_users(&users)
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/?after=admin&limit=2")
+                    .cookie(test_utils::cookie::new("admin"))
+                    .to_request();
+
+                let users: Vec<api::User> = test::call_and_read_body_json(&app, req).await;
+                let usernames: Vec<_> = users.iter().map(|user| user.username.as_str()).collect();
+                assert_eq!(usernames, vec!["bob", "carol"]);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_user_admin() {
+        let admin: User = api::NewUser {
+            username: "admin".into(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+        let other_username = "other_user";
+        let other_user: User = api::NewUser {
+            username: other_username.to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[admin, other_user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let cookie = test_utils::cookie::new("admin");
+                let req = test::TestRequest::post()
+                    .uri("/other_user/delete")
+                    .cookie(cookie)
+                    .to_request();
+
+                let user: api::User = test::call_and_read_body_json(&app, req).await;
+                assert!(user.delete_at.is_some(), "Deletion time not in response");
+
+                let query = doc! {"username": other_username};
+                let result = app_data
+                    .users
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                let user = result.expect("User removed before the grace period");
+                assert!(user.delete_at.is_some(), "User not scheduled for deletion");
+
+                // the user is logged out until the deletion is cancelled
+                let query = doc! {"username": other_username, "revoked": false};
+                let count = app_data
+                    .sessions
+                    .count_documents(query, None)
+                    .await
+                    .unwrap();
+                assert_eq!(count, 0);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_user_unauth() {
+        let other_username = "other_user";
+        let other_user: User = api::NewUser {
+            username: other_username.to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[other_user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/other_user/delete")
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+                let query = doc! {"username": other_username};
+                let result = app_data
+                    .users
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                let user = result.expect("User deleted");
+                assert!(user.delete_at.is_none(), "User scheduled for deletion");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_user_group_owner() {
+        let owner_name = "owner".to_string();
+        let owner: User = api::NewUser {
+            username: owner_name.clone(),
+            email: "owner@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let group = Group::new(owner_name.clone(), "some_group".into());
+        let other_username = "other_user";
+        let other_user: User = api::NewUser {
+            username: other_username.to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: Some(group.id.clone()),
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[owner, other_user])
+            .with_groups(&[group])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/other_user/delete")
+                    .cookie(test_utils::cookie::new(&owner_name))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"username": other_username};
+                let result = app_data
+                    .users
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                let user = result.expect("User removed before the grace period");
+                assert!(user.delete_at.is_some(), "User not scheduled for deletion");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_cancel_user_deletion() {
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/user/delete")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let req = test::TestRequest::post()
+                    .uri("/user/delete/cancel")
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let query = doc! {"username": &user.username};
+                let user = app_data
+                    .users
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user")
+                    .expect("User not found");
+
+                assert!(user.delete_at.is_none(), "User deletion not cancelled");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_user_other_user() {
+        let user1: User = api::NewUser {
+            username: "user1".to_string(),
+            email: "user1@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let user1_name = user1.username.clone();
+        let user2: User = api::NewUser {
+            username: "user2".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user1, user2])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/user2/delete")
+                    .cookie(test_utils::cookie::new(&user1_name))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let query = doc! {"username": "user2"};
+                let result = app_data
+                    .users
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                let user = result.expect("User deleted");
+                assert!(user.delete_at.is_none(), "User scheduled for deletion");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_ban_user() {
+        let admin: User = api::NewUser {
+            username: "admin".to_string(),
+            email: "admin@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Admin),
+        }
+        .into();
+        let admin_name = admin.username.clone();
+        let some_user: User = api::NewUser {
+            username: "some_user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[admin, some_user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/some_user/ban")
+                    .cookie(test_utils::cookie::new(&admin_name))
+                    .to_request();
+
+                // This will panic if the response isn't a banned account; no assert needed
+                let _account: BannedAccount = test::call_and_read_body_json(&app, req).await;
+
+                let query = doc! {"username": "some_user"};
+                let result = app_data
+                    .banned_accounts
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                assert!(result.is_some(), "User not banned");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_banned_accounts() {
+        let moderator: User = api::NewUser {
+            username: "moderator".to_string(),
+            email: "moderator@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Moderator),
+        }
+        .into();
+        let bob: User = api::NewUser {
+            username: "bob".to_string(),
+            email: "bob@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let carol: User = api::NewUser {
+            username: "carol".to_string(),
+            email: "carol@example.com".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[moderator, bob, carol])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                for username in ["bob", "carol"] {
+                    let req = test::TestRequest::post()
+                        .uri(&format!("/{}/ban", username))
+                        .cookie(test_utils::cookie::new("moderator"))
+                        .to_request();
+                    let response = test::call_service(&app, req).await;
+                    assert_eq!(response.status(), http::StatusCode::OK);
+                }
+
+                let req = test::TestRequest::get()
+                    .uri("/banned")
+                    .cookie(test_utils::cookie::new("moderator"))
+                    .to_request();
+                let accounts: Vec<BannedAccount> = test::call_and_read_body_json(&app, req).await;
+                let usernames: Vec<_> =
+                    accounts.iter().map(|acct| acct.username.as_str()).collect();
+                assert_eq!(usernames, vec!["bob", "carol"]);
+                assert!(accounts
+                    .iter()
+                    .all(|acct| acct.banned_at > std::time::SystemTime::UNIX_EPOCH));
+
+                // search by email
+                let req = test::TestRequest::get()
+                    .uri("/banned?email=EXAMPLE.com")
+                    .cookie(test_utils::cookie::new("moderator"))
+                    .to_request();
+                let accounts: Vec<BannedAccount> = test::call_and_read_body_json(&app, req).await;
+                let usernames: Vec<_> =
+                    accounts.iter().map(|acct| acct.username.as_str()).collect();
+                assert_eq!(usernames, vec!["carol"]);
+
+                // paginate
+                let req = test::TestRequest::get()
+                    .uri("/banned?after=bob&limit=1")
+                    .cookie(test_utils::cookie::new("moderator"))
+                    .to_request();
+                let accounts: Vec<BannedAccount> = test::call_and_read_body_json(&app, req).await;
+                let usernames: Vec<_> =
+                    accounts.iter().map(|acct| acct.username.as_str()).collect();
+                assert_eq!(usernames, vec!["carol"]);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_list_banned_accounts_403() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri("/banned")
+                    .cookie(test_utils::cookie::new("user"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_ban_user_audit_log() {
+        let moderator: User = api::NewUser {
+            username: "moderator".to_string(),
+            email: "moderator@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: Some(UserRole::Moderator),
+        }
+        .into();
+        let some_user: User = api::NewUser {
+            username: "some_user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[moderator, some_user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/some_user/ban")
+                    .cookie(test_utils::cookie::new("moderator"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+
+                let entry = app_data
+                    .audit_log
+                    .find_one(doc! {}, None)
+                    .await
+                    .unwrap()
+                    .expect("Audit log entry not found");
+
+                assert_eq!(entry.actor, "moderator");
+                assert_eq!(entry.action, api::AuditAction::BanUser);
+                assert_eq!(entry.target, "some_user");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_ban_user_403() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let user_name = user.username.clone();
+        let some_user: User = api::NewUser {
+            username: "some_user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user, some_user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri("/some_user/ban")
+                    .cookie(test_utils::cookie::new(&user_name))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+                let query = doc! {"username": "some_user"};
+                let result = app_data
+                    .banned_accounts
+                    .find_one(query, None)
+                    .await
+                    .expect("Could not query for user");
+
+                assert!(result.is_none(), "User banned");
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    #[ignore] // ignore until we can test fns using the mailer
+    async fn test_reset_password() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/password", &user.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_reset_password_429() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
             group_id: None,
@@ -1073,8 +2675,10 @@ mod tests {
         .into();
 
         test_utils::setup()
-            .with_users(&[admin, other_user])
-            .run(|app_data| async move {
+            .with_users(&[user.clone()])
+            .run(|mut app_data| async move {
+                let window = Duration::from_millis(200);
+                app_data.ip_rate_limiter = Arc::new(RateLimiter::new(1, window));
                 let app = test::init_service(
                     App::new()
                         .wrap(test_utils::cookie::middleware())
@@ -1083,32 +2687,36 @@ mod tests {
                 )
                 .await;
 
-                let cookie = test_utils::cookie::new("admin");
+                let addr = "127.0.0.1:8080".parse().unwrap();
                 let req = test::TestRequest::post()
-                    .uri("/other_user/delete")
-                    .cookie(cookie)
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(addr)
                     .to_request();
+                test::call_service(&app, req).await;
 
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(addr)
+                    .to_request();
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::OK);
-
-                let query = doc! {"username": other_username};
-                let result = app_data
-                    .users
-                    .find_one(query, None)
-                    .await
-                    .expect("Could not query for user");
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
 
-                assert!(result.is_none(), "User not deleted");
+                // the counter is reset after the window
+                tokio::time::sleep(window).await;
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(addr)
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_delete_user_unauth() {
-        let other_username = "other_user";
-        let other_user: User = api::NewUser {
-            username: other_username.to_string(),
+    async fn test_reset_password_429_trusted_proxy() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
             group_id: None,
@@ -1117,60 +2725,80 @@ mod tests {
         .into();
 
         test_utils::setup()
-            .with_users(&[other_user])
-            .run(|app_data| async move {
+            .with_users(&[user.clone()])
+            .run(|mut app_data| async move {
+                let window = Duration::from_secs(60);
+                app_data.ip_rate_limiter = Arc::new(RateLimiter::new(1, window));
+                app_data.email_rate_limiter = Arc::new(RateLimiter::new(100, window));
+                app_data.settings.security.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
                 let app = test::init_service(
                     App::new()
+                        .wrap(test_utils::cookie::middleware())
                         .app_data(web::Data::new(app_data.clone()))
                         .configure(config),
                 )
                 .await;
 
+                // clients behind the trusted proxy are limited separately
+                let proxy = "127.0.0.1:8080".parse().unwrap();
+                for client in ["10.0.0.1", "10.0.0.2"] {
+                    let req = test::TestRequest::post()
+                        .uri(&format!("/{}/password", &user.username))
+                        .peer_addr(proxy)
+                        .insert_header(("X-Forwarded-For", client))
+                        .to_request();
+                    let response = test::call_service(&app, req).await;
+                    assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+                }
+
                 let req = test::TestRequest::post()
-                    .uri("/other_user/delete")
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(proxy)
+                    .insert_header(("X-Forwarded-For", "10.0.0.1"))
                     .to_request();
-
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
-
-                let query = doc! {"username": other_username};
-                let result = app_data
-                    .users
-                    .find_one(query, None)
-                    .await
-                    .expect("Could not query for user");
-
-                assert!(result.is_some(), "User deleted");
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+
+                // the header is ignored for other (untrusted) addresses
+                let addr = "192.168.0.1:8080".parse().unwrap();
+                for _ in 0..2 {
+                    let req = test::TestRequest::post()
+                        .uri(&format!("/{}/password", &user.username))
+                        .peer_addr(addr)
+                        .insert_header(("X-Forwarded-For", "10.0.0.3"))
+                        .to_request();
+                    test::call_service(&app, req).await;
+                }
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(addr)
+                    .insert_header(("X-Forwarded-For", "10.0.0.4"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_delete_user_group_owner() {
-        let owner_name = "owner".to_string();
-        let owner: User = api::NewUser {
-            username: owner_name.clone(),
-            email: "owner@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let group = Group::new(owner_name.clone(), "some_group".into());
-        let other_username = "other_user";
-        let other_user: User = api::NewUser {
-            username: other_username.to_string(),
+    async fn test_reset_password_429_spoofed_forwarded_for() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
-            group_id: Some(group.id.clone()),
+            group_id: None,
             role: None,
         }
         .into();
 
         test_utils::setup()
-            .with_users(&[owner, other_user])
-            .with_groups(&[group])
-            .run(|app_data| async move {
+            .with_users(&[user.clone()])
+            .run(|mut app_data| async move {
+                let window = Duration::from_secs(60);
+                app_data.ip_rate_limiter = Arc::new(RateLimiter::new(1, window));
+                app_data.email_rate_limiter = Arc::new(RateLimiter::new(100, window));
+                app_data.settings.security.trusted_proxies =
+                    vec!["127.0.0.1".parse().unwrap(), "10.0.0.254".parse().unwrap()];
                 let app = test::init_service(
                     App::new()
                         .wrap(test_utils::cookie::middleware())
@@ -1179,39 +2807,31 @@ mod tests {
                 )
                 .await;
 
+                // the client sets the leftmost hops; the proxies append the real address
+                let proxy = "127.0.0.1:8080".parse().unwrap();
                 let req = test::TestRequest::post()
-                    .uri("/other_user/delete")
-                    .cookie(test_utils::cookie::new(&owner_name))
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(proxy)
+                    .insert_header(("X-Forwarded-For", "1.2.3.4, 10.0.0.1, 10.0.0.254"))
                     .to_request();
-
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::OK);
-
-                let query = doc! {"username": other_username};
-                let result = app_data
-                    .users
-                    .find_one(query, None)
-                    .await
-                    .expect("Could not query for user");
+                assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
 
-                assert!(result.is_none(), "User not deleted");
+                let req = test::TestRequest::post()
+                    .uri(&format!("/{}/password", &user.username))
+                    .peer_addr(proxy)
+                    .insert_header(("X-Forwarded-For", "5.6.7.8, 10.0.0.1, 10.0.0.254"))
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_delete_user_other_user() {
-        let user1: User = api::NewUser {
-            username: "user1".to_string(),
-            email: "user1@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-        let user1_name = user1.username.clone();
-        let user2: User = api::NewUser {
-            username: "user2".to_string(),
+    async fn test_send_email_verification() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
             group_id: None,
@@ -1220,7 +2840,7 @@ mod tests {
         .into();
 
         test_utils::setup()
-            .with_users(&[user1, user2])
+            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1231,48 +2851,45 @@ mod tests {
                 .await;
 
                 let req = test::TestRequest::post()
-                    .uri("/user2/delete")
-                    .cookie(test_utils::cookie::new(&user1_name))
+                    .uri(&format!("/{}/send-verification", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+                assert_eq!(response.status(), http::StatusCode::OK);
 
-                let query = doc! {"username": "user2"};
-                let result = app_data
-                    .users
+                let query = doc! {"username": &user.username};
+                let token = app_data
+                    .email_verification_tokens
                     .find_one(query, None)
                     .await
-                    .expect("Could not query for user");
-
-                assert!(result.is_some(), "User deleted");
+                    .unwrap()
+                    .expect("No verification token issued");
+                assert_eq!(token.email, user.email);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_ban_user() {
-        let admin: User = api::NewUser {
-            username: "admin".to_string(),
-            email: "admin@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: Some(UserRole::Admin),
-        }
-        .into();
-        let admin_name = admin.username.clone();
-        let some_user: User = api::NewUser {
-            username: "some_user".to_string(),
+    async fn test_verify_email() {
+        let user: User = api::NewUser {
+            username: "user".to_string(),
             email: "user@netsblox.org".into(),
             password: None,
             group_id: None,
             role: None,
         }
         .into();
+        let token = EmailVerificationToken::new(user.username.clone(), user.email.clone());
 
         test_utils::setup()
-            .with_users(&[admin, some_user])
+            .with_users(&[user.clone()])
             .run(|app_data| async move {
+                app_data
+                    .email_verification_tokens
+                    .insert_one(&token, None)
+                    .await
+                    .unwrap();
                 let app = test::init_service(
                     App::new()
                         .wrap(test_utils::cookie::middleware())
@@ -1281,28 +2898,30 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::post()
-                    .uri("/some_user/ban")
-                    .cookie(test_utils::cookie::new(&admin_name))
+                let req = test::TestRequest::get()
+                    .uri(&format!("/verify/{}", &token.secret))
                     .to_request();
 
-                // This will panic if the response isn't a banned account; no assert needed
-                let _account: BannedAccount = test::call_and_read_body_json(&app, req).await;
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
 
-                let query = doc! {"username": "some_user"};
-                let result = app_data
-                    .banned_accounts
-                    .find_one(query, None)
-                    .await
-                    .expect("Could not query for user");
+                let query = doc! {"username": &user.username};
+                let user = app_data.users.find_one(query, None).await.unwrap().unwrap();
+                assert!(user.verified, "Email not verified");
 
-                assert!(result.is_some(), "User not banned");
+                // tokens can only be used once
+                let req = test::TestRequest::get()
+                    .uri(&format!("/verify/{}", &token.secret))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
             })
             .await;
     }
 
     #[actix_web::test]
-    async fn test_ban_user_403() {
+    async fn test_verify_email_expired() {
         let user: User = api::NewUser {
             username: "user".to_string(),
             email: "user@netsblox.org".into(),
@@ -1311,19 +2930,17 @@ mod tests {
             role: None,
         }
         .into();
-        let user_name = user.username.clone();
-        let some_user: User = api::NewUser {
-            username: "some_user".to_string(),
-            email: "user@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
+        let mut token = EmailVerificationToken::new(user.username.clone(), user.email.clone());
+        token.created_at = mongodb::bson::DateTime::from_millis(0);
 
         test_utils::setup()
-            .with_users(&[user, some_user])
+            .with_users(&[user.clone()])
             .run(|app_data| async move {
+                app_data
+                    .email_verification_tokens
+                    .insert_one(&token, None)
+                    .await
+                    .unwrap();
                 let app = test::init_service(
                     App::new()
                         .wrap(test_utils::cookie::middleware())
@@ -1332,40 +2949,23 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::post()
-                    .uri("/some_user/ban")
-                    .cookie(test_utils::cookie::new(&user_name))
+                let req = test::TestRequest::get()
+                    .uri(&format!("/verify/{}", &token.secret))
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
-
-                let query = doc! {"username": "some_user"};
-                let result = app_data
-                    .banned_accounts
-                    .find_one(query, None)
-                    .await
-                    .expect("Could not query for user");
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
 
-                assert!(result.is_none(), "User banned");
+                let query = doc! {"username": &user.username};
+                let user = app_data.users.find_one(query, None).await.unwrap().unwrap();
+                assert!(!user.verified, "Email verified with expired token");
             })
             .await;
     }
 
     #[actix_web::test]
-    #[ignore] // ignore until we can test fns using the mailer
-    async fn test_reset_password() {
-        let user: User = api::NewUser {
-            username: "user".to_string(),
-            email: "user@netsblox.org".into(),
-            password: None,
-            group_id: None,
-            role: None,
-        }
-        .into();
-
+    async fn test_verify_email_invalid() {
         test_utils::setup()
-            .with_users(&[user.clone()])
             .run(|app_data| async move {
                 let app = test::init_service(
                     App::new()
@@ -1375,12 +2975,12 @@ mod tests {
                 )
                 .await;
 
-                let req = test::TestRequest::post()
-                    .uri(&format!("/{}/password", &user.username))
+                let req = test::TestRequest::get()
+                    .uri("/verify/notAToken")
                     .to_request();
 
                 let response = test::call_service(&app, req).await;
-                assert_eq!(response.status(), http::StatusCode::OK);
+                assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
             })
             .await;
     }
@@ -1440,4 +3040,118 @@ mod tests {
             })
             .await;
     }
+
+    #[actix_web::test]
+    async fn test_export_user() {
+        let mut user: User = api::NewUser {
+            username: "user1".into(),
+            email: "user1@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        user.service_settings
+            .insert("someHost".into(), "settings".into());
+        let project = test_utils::project::builder()
+            .with_owner(user.username.clone())
+            .build();
+        let library = netsblox_cloud_common::Library {
+            owner: user.username.clone(),
+            name: "my library".into(),
+            notes: "".into(),
+            blocks: "<blocks/>".into(),
+            state: api::PublishState::Private,
+            downloads: 0,
+        };
+        let sent =
+            CollaborationInvite::new(user.username.clone(), "user2".into(), project.id.clone());
+        let received =
+            CollaborationInvite::new("user3".into(), user.username.clone(), project.id.clone());
+        let unrelated =
+            CollaborationInvite::new("user3".into(), "user2".into(), project.id.clone());
+
+        test_utils::setup()
+            .with_users(&[user.clone()])
+            .with_projects(&[project.clone()])
+            .with_libraries(&[library])
+            .with_collab_invites(&[sent.clone(), received.clone(), unrelated])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/export", &user.username))
+                    .cookie(test_utils::cookie::new(&user.username))
+                    .to_request();
+
+                let export: api::UserExport = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(export.user.username, user.username);
+                assert_eq!(export.projects.len(), 1);
+                assert_eq!(export.projects[0].id, project.id);
+                assert_eq!(export.libraries.len(), 1);
+                assert_eq!(export.libraries[0].name, "my library");
+                assert_eq!(
+                    export.service_settings.get("someHost"),
+                    Some(&"settings".to_string())
+                );
+
+                let mut invite_ids: Vec<_> = export
+                    .collaboration_invites
+                    .into_iter()
+                    .map(|invite| invite.id)
+                    .collect();
+                invite_ids.sort();
+                let mut expected = vec![sent.id, received.id];
+                expected.sort();
+                assert_eq!(invite_ids, expected);
+            })
+            .await;
+    }
+
+    #[actix_web::test]
+    async fn test_export_user_403() {
+        let user1: User = api::NewUser {
+            username: "user1".into(),
+            email: "user1@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let user2: User = api::NewUser {
+            username: "user2".into(),
+            email: "user2@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+
+        test_utils::setup()
+            .with_users(&[user1.clone(), user2.clone()])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .configure(config),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{}/export", &user1.username))
+                    .cookie(test_utils::cookie::new(&user2.username))
+                    .to_request();
+
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
 }
@@ -188,6 +188,9 @@ async fn create_account(
         role: UserRole::User,
         services_hosts: None,
         service_settings: HashMap::new(),
+        delete_at: None,
+        verified: false,
+        two_factor: None,
     };
 
     let update = doc!("$setOnInsert": &user);
@@ -1,12 +1,15 @@
 use actix::Addr;
 use actix_session::SessionExt;
-use actix_web::HttpRequest;
+use actix_web::{web, HttpMessage, HttpRequest};
 use futures::TryStreamExt;
 use lazy_static::lazy_static;
 use lettre::{Message, SmtpTransport, Transport};
 use log::error;
 use lru::LruCache;
-use mongodb::{bson::doc, Collection};
+use mongodb::{
+    bson::{doc, DateTime},
+    Collection,
+};
 use netsblox_cloud_common::{
     api::{self, GroupId, UserRole},
     AuthorizedServiceHost, FriendLink, Group, ProjectMetadata, User,
@@ -19,10 +22,13 @@ use sha2::{Digest, Sha512};
 use std::{
     borrow::Borrow,
     collections::HashSet,
+    net::{IpAddr, SocketAddr},
     sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
 };
 
 use crate::{
+    app_data::AppData,
     errors::{InternalError, UserError},
     network::topology::{self, TopologyActor},
 };
@@ -290,9 +296,69 @@ fn get_cached_friends(
     cache.get(username).map(|friends| friends.to_owned())
 }
 
-pub(crate) fn get_username(req: &HttpRequest) -> Option<String> {
+/// Username for the session of the current request (stored in the request
+/// extensions so the session is only checked once per request)
+#[derive(Clone)]
+struct SessionUsername(Option<String>);
+
+/// Get the username for the current session (if logged in and not revoked)
+pub(crate) async fn get_username(req: &HttpRequest) -> Option<String> {
+    let cached = req.extensions().get::<SessionUsername>().cloned();
+    if let Some(SessionUsername(username)) = cached {
+        return username;
+    }
+
     let session = req.get_session();
-    session.get::<String>("username").unwrap_or(None)
+    let app = req.app_data::<web::Data<AppData>>()?;
+    let username = app.get_session_username(&session).await;
+    req.extensions_mut()
+        .insert(SessionUsername(username.clone()));
+    username
+}
+
+/// Get the IP address of the client making the request. Requests from trusted
+/// proxies use the X-Forwarded-For header, starting from the right (most recent)
+/// hop since earlier entries can be set by the client. Trusted proxies are skipped
+/// and the first untrusted address is used.
+pub(crate) fn get_client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer_addr = req.peer_addr().map(|addr| addr.ip())?;
+    if !trusted_proxies.contains(&peer_addr) {
+        return Some(peer_addr);
+    }
+
+    let hops: Vec<_> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim())
+        .collect();
+
+    let mut client_addr = peer_addr;
+    for hop in hops.into_iter().rev() {
+        let addr = hop
+            .parse::<IpAddr>()
+            .ok()
+            .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()));
+
+        match addr {
+            Some(addr) if trusted_proxies.contains(&addr) => client_addr = addr,
+            Some(addr) => return Some(addr),
+            // the hop cannot be trusted so use the last one which can be
+            None => break,
+        }
+    }
+
+    Some(client_addr)
+}
+
+/// Invites created before the returned time have expired
+pub(crate) fn invite_expiry_cutoff(ttl: Duration) -> DateTime {
+    // a ttl longer than the time since the epoch never expires
+    let cutoff = SystemTime::now()
+        .checked_sub(ttl)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    DateTime::from_system_time(cutoff)
 }
 
 pub(crate) async fn get_authorized_host(
@@ -311,6 +377,12 @@ pub(crate) async fn get_authorized_host(
         })
         .map(|(id, secret)| doc! {"id": id, "secret": secret});
 
+    // An empty query would match any host
+    let query = match query {
+        Some(query) => query,
+        None => return Ok(None),
+    };
+
     let host = authorized_services
         .find_one(query, None)
         .await
@@ -392,6 +464,67 @@ mod tests {
 
     use super::*;
 
+    #[actix_web::test]
+    async fn test_get_username_once_per_request() {
+        use actix_web::{get, http, test, App, HttpResponse};
+        use netsblox_cloud_common::User;
+
+        #[get("/test")]
+        async fn username_test(app: web::Data<AppData>, req: HttpRequest) -> HttpResponse {
+            let username = get_username(&req).await;
+
+            // the session is not checked again during the same request
+            let update = doc! {"$set": {"revoked": true}};
+            app.sessions
+                .update_many(doc! {}, update, None)
+                .await
+                .unwrap();
+            assert_eq!(get_username(&req).await, username);
+
+            HttpResponse::Ok().json(username)
+        }
+
+        let user: User = api::NewUser {
+            username: "user".into(),
+            email: "user@netsblox.org".into(),
+            password: None,
+            group_id: None,
+            role: None,
+        }
+        .into();
+        let username = user.username.clone();
+
+        test_utils::setup()
+            .with_users(&[user])
+            .run(|app_data| async move {
+                let app = test::init_service(
+                    App::new()
+                        .wrap(test_utils::cookie::middleware())
+                        .app_data(web::Data::new(app_data.clone()))
+                        .service(username_test),
+                )
+                .await;
+
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&username))
+                    .uri("/test")
+                    .to_request();
+                let response = test::call_service(&app, req).await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                let name: Option<String> = test::read_body_json(response).await;
+                assert_eq!(name, Some(username.clone()));
+
+                // ...but is checked for the next one
+                let req = test::TestRequest::get()
+                    .cookie(test_utils::cookie::new(&username))
+                    .uri("/test")
+                    .to_request();
+                let name: Option<String> = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(name, None);
+            })
+            .await;
+    }
+
     #[actix_web::test]
     async fn test_update_project_cache_ignore_stale() {
         // This issue was discovered around old projects hanging around in the project cache
@@ -606,4 +739,29 @@ mod tests {
         assert_eq!(obj.keys().count(), 1);
         assert!(obj.get("maybe").is_none(), "Has a key with a null value");
     }
+
+    #[actix_web::test]
+    async fn test_get_client_ip_forwarded_for() {
+        let trusted: Vec<IpAddr> =
+            vec!["127.0.0.1".parse().unwrap(), "10.0.0.254".parse().unwrap()];
+        let client_ip = |peer: &str, forwarded_for: &str| {
+            let req = actix_web::test::TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .insert_header(("X-Forwarded-For", forwarded_for))
+                .to_http_request();
+            get_client_ip(&req, &trusted).map(|addr| addr.to_string())
+        };
+
+        // spoofed hops (on the left) are ignored
+        let ip = client_ip("127.0.0.1:8080", "1.2.3.4, 10.0.0.1, 10.0.0.254");
+        assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+
+        // the header is ignored for untrusted peers
+        let ip = client_ip("192.168.0.1:8080", "10.0.0.1");
+        assert_eq!(ip.as_deref(), Some("192.168.0.1"));
+
+        // invalid hops are not trusted
+        let ip = client_ip("127.0.0.1:8080", "10.0.0.1, unknown, 10.0.0.254");
+        assert_eq!(ip.as_deref(), Some("10.0.0.254"));
+    }
 }
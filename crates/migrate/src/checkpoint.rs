@@ -0,0 +1,176 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+/// Number of documents to process between saving the checkpoint
+pub(crate) const CHECKPOINT_INTERVAL: u64 = 100;
+
+/// Progress of each migration (the id of the last document processed) so an
+/// interrupted migration can be resumed without rescanning completed documents.
+/// Documents which were skipped are tracked separately so they are retried when
+/// resuming. Documents must be processed in order of their ids.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    #[serde(skip)]
+    path: PathBuf,
+    last_ids: HashMap<String, ObjectId>,
+    #[serde(default)]
+    skipped_ids: HashMap<String, BTreeSet<ObjectId>>,
+}
+
+impl Checkpoint {
+    /// Create a new checkpoint, discarding any existing progress
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            last_ids: HashMap::new(),
+            skipped_ids: HashMap::new(),
+        }
+    }
+
+    /// Load the checkpoint from the given path (if it exists)
+    pub(crate) fn load(path: &Path) -> io::Result<Self> {
+        let mut checkpoint = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Checkpoint::default(),
+            Err(err) => return Err(err),
+        };
+        checkpoint.path = path.to_owned();
+
+        Ok(checkpoint)
+    }
+
+    /// Record the given document as migrated
+    pub(crate) fn record(&mut self, migration: &str, id: ObjectId) {
+        self.advance(migration, id);
+        if let Some(skipped) = self.skipped_ids.get_mut(migration) {
+            skipped.remove(&id);
+        }
+    }
+
+    /// Record the given document as skipped so it is retried when resuming
+    pub(crate) fn skip(&mut self, migration: &str, id: ObjectId) {
+        self.advance(migration, id);
+        self.skipped_ids
+            .entry(migration.to_owned())
+            .or_default()
+            .insert(id);
+    }
+
+    fn advance(&mut self, migration: &str, id: ObjectId) {
+        let last_id = self.last_ids.entry(migration.to_owned()).or_insert(id);
+        if id > *last_id {
+            *last_id = id;
+        }
+    }
+
+    /// Add a filter to the query to skip any documents already migrated
+    pub(crate) fn resume_query(&self, migration: &str, mut query: Document) -> Document {
+        if let Some(id) = self.last_ids.get(migration) {
+            let skipped: Vec<_> = self
+                .skipped_ids
+                .get(migration)
+                .map(|ids| ids.iter().collect())
+                .unwrap_or_default();
+
+            if skipped.is_empty() {
+                query.insert("_id", doc! {"$gt": id});
+            } else {
+                let filter = vec![doc! {"_id": {"$gt": id}}, doc! {"_id": {"$in": skipped}}];
+                query.insert("$or", filter);
+            }
+        }
+        query
+    }
+
+    /// Save the checkpoint. It is written to a temporary file first so an
+    /// interruption while saving does not corrupt the existing checkpoint.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string(&self)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("migrate-checkpoint-{}.json", ObjectId::new()))
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let path = temp_path();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+
+        let query = checkpoint.resume_query("projects", doc! {"transient": false});
+        assert_eq!(query, doc! {"transient": false});
+    }
+
+    #[test]
+    fn test_resume_interrupted() {
+        let path = temp_path();
+        let ids: Vec<_> = (0..5).map(|_| ObjectId::new()).collect();
+
+        // process the first 3 documents then stop
+        let mut checkpoint = Checkpoint::new(&path);
+        for id in &ids[..3] {
+            checkpoint.record("projects", *id);
+        }
+        checkpoint.save().unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        let query = checkpoint.resume_query("projects", doc! {"transient": false});
+        assert_eq!(query, doc! {"transient": false, "_id": {"$gt": ids[2]}});
+
+        let last_id = query
+            .get_document("_id")
+            .unwrap()
+            .get_object_id("$gt")
+            .unwrap();
+        let remaining: Vec<_> = ids.iter().filter(|id| **id > last_id).collect();
+        assert_eq!(remaining, ids[3..].iter().collect::<Vec<_>>());
+
+        let query = checkpoint.resume_query("users", doc! {});
+        assert_eq!(query, doc! {});
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_retries_skipped() {
+        let path = temp_path();
+        let ids: Vec<_> = (0..4).map(|_| ObjectId::new()).collect();
+
+        let mut checkpoint = Checkpoint::new(&path);
+        checkpoint.record("projects", ids[0]);
+        checkpoint.skip("projects", ids[1]);
+        checkpoint.record("projects", ids[2]);
+        checkpoint.save().unwrap();
+        drop(checkpoint);
+
+        let mut checkpoint = Checkpoint::load(&path).unwrap();
+        let query = checkpoint.resume_query("projects", doc! {});
+        let expected = doc! {
+            "$or": [{"_id": {"$gt": ids[2]}}, {"_id": {"$in": [ids[1]]}}]
+        };
+        assert_eq!(query, expected);
+
+        // retrying the skipped document doesn't move the checkpoint back
+        checkpoint.record("projects", ids[1]);
+        let query = checkpoint.resume_query("projects", doc! {});
+        assert_eq!(query, doc! {"_id": {"$gt": ids[2]}});
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -34,6 +34,10 @@ pub struct Config {
     pub source: StorageConfig,
     pub target: StorageConfig,
     pub sleep: Option<u64>,
+    /// Maximum number of concurrent S3 operations (per S3 endpoint)
+    pub concurrency: Option<usize>,
+    /// Maximum number of times to retry a failed S3 request
+    pub retries: Option<u32>,
 }
 
 impl Config {
@@ -1,24 +1,31 @@
+mod checkpoint;
 mod config;
 mod origin;
+mod report;
+mod storage;
 
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 use std::{collections::HashMap, thread};
 
+use crate::checkpoint::{Checkpoint, CHECKPOINT_INTERVAL};
 use crate::config::Config;
+use crate::report::Report;
+use crate::storage::{LimitedStore, ObjectStore, RetryStore, S3Store, StorageError};
 use aws_config::SdkConfig;
 use aws_credential_types::{provider::SharedCredentialsProvider, Credentials as S3Credentials};
 use aws_sdk_s3::{self as s3, config::Region};
 use clap::Parser;
-use cloud::api::SaveState;
+use cloud::api::{s3::s3_key_for_role, SaveState};
 use derive_more::{Display, Error};
 use futures::{future::join_all, stream::StreamExt};
 use indicatif::ProgressBar;
 use mongodb::bson::Bson;
 use mongodb::{
-    bson::{doc, DateTime},
-    options::UpdateOptions,
-    Client, Database,
+    bson::{doc, DateTime, Document},
+    options::{FindOptions, UpdateOptions},
+    Client, Collection, Database,
 };
 use netsblox_cloud_common as cloud;
 
@@ -57,6 +64,16 @@ struct Args {
     /// Only migrate the given user (for testing purposes)
     #[clap(long)]
     user: Option<String>,
+    /// Resume an interrupted migration from the checkpoint
+    #[clap(long)]
+    resume: bool,
+    /// Path to the checkpoint file used to resume interrupted migrations
+    #[clap(long, default_value = "migrate-checkpoint.json")]
+    checkpoint: PathBuf,
+    /// Report the actions which would be taken (and any anomalies found)
+    /// without writing to the target database or S3
+    #[clap(long)]
+    dry_run: bool,
 }
 
 #[tokio::main]
@@ -66,20 +83,61 @@ async fn main() {
     let config = Config::load(&args.config_path).unwrap();
     let src_db = connect_db(&config.source.database.url).await;
     let dst_db = connect_db(&config.target.database.url).await;
+    let mut checkpoint = if args.resume {
+        Checkpoint::load(&args.checkpoint).expect("Unable to load checkpoint")
+    } else {
+        Checkpoint::new(&args.checkpoint)
+    };
+    let dry_run = args.dry_run;
+    let mut report = Report::default();
 
     if let Some(migration) = args.only {
         match migration {
-            Migration::Users => migrate_users(&src_db, &dst_db, args.user).await,
-            Migration::Libraries => migrate_libraries(&src_db, &dst_db).await,
-            Migration::Projects => migrate_projects(&config, &src_db, &dst_db, args.user).await,
-            Migration::BannedAccounts => migrate_banned_accts(&src_db, &dst_db).await,
+            Migration::Users => {
+                migrate_users(&src_db, &dst_db, args.user, dry_run, &mut report).await
+            }
+            Migration::Libraries => migrate_libraries(&src_db, &dst_db, dry_run, &mut report).await,
+            Migration::Projects => {
+                migrate_projects(
+                    &config,
+                    &src_db,
+                    &dst_db,
+                    args.user,
+                    &mut checkpoint,
+                    dry_run,
+                    &mut report,
+                )
+                .await
+            }
+            Migration::BannedAccounts => {
+                migrate_banned_accts(&src_db, &dst_db, dry_run, &mut report).await
+            }
         }
     } else {
         // migrate everything
-        migrate_users(&src_db, &dst_db, args.user.clone()).await;
-        migrate_libraries(&src_db, &dst_db).await;
-        migrate_banned_accts(&src_db, &dst_db).await;
-        migrate_projects(&config, &src_db, &dst_db, args.user).await;
+        migrate_users(&src_db, &dst_db, args.user.clone(), dry_run, &mut report).await;
+        migrate_libraries(&src_db, &dst_db, dry_run, &mut report).await;
+        migrate_banned_accts(&src_db, &dst_db, dry_run, &mut report).await;
+        migrate_projects(
+            &config,
+            &src_db,
+            &dst_db,
+            args.user,
+            &mut checkpoint,
+            dry_run,
+            &mut report,
+        )
+        .await;
+    }
+
+    if dry_run {
+        println!("Dry run complete. Nothing was written to the target.");
+        print!("{}", report);
+    } else if !report.skipped().is_empty() {
+        println!("Skipped {} project(s):", report.skipped().len());
+        for reason in report.skipped() {
+            println!("  {}", reason);
+        }
     }
 }
 
@@ -111,17 +169,51 @@ async fn connect_db(mongo_uri: &str) -> Database {
         .expect("Could not connect to default source database")
 }
 
+/// Default maximum number of concurrent S3 operations
+const DEFAULT_CONCURRENCY: usize = 8;
+/// Default maximum number of retries for a failed S3 request
+const DEFAULT_RETRIES: u32 = 3;
+/// Delay before the first retry of a failed S3 request (doubled for each retry)
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Anomalies in a source project which prevent it from being migrated
+#[derive(Debug, Display)]
+enum ProjectError {
+    #[display(fmt = "{} has no roles", _0)]
+    NoRoles(String),
+    #[display(fmt = "{} is missing {}", _0, _1)]
+    MissingObject(String, String),
+    #[display(fmt = "{} could not be copied: {}", _0, _1)]
+    Storage(String, StorageError),
+}
+
+impl ProjectError {
+    fn storage(label: &str, err: StorageError) -> Self {
+        match err {
+            StorageError::NotFound(key) => ProjectError::MissingObject(label.to_owned(), key),
+            err => ProjectError::Storage(label.to_owned(), err),
+        }
+    }
+}
+
+fn project_label(metadata: &origin::ProjectMetadata) -> String {
+    format!(
+        "Project {} ({}/{})",
+        metadata.id, metadata.owner, metadata.name
+    )
+}
+
 async fn download(
-    client: &s3::Client,
-    bucket: &str,
+    store: &impl ObjectStore,
     metadata: origin::ProjectMetadata,
-) -> cloud::Project {
+) -> Result<cloud::Project, ProjectError> {
     let updated = metadata
         .last_update_at
         .map(|timestamp| DateTime::from_millis(timestamp as i64))
         .unwrap_or_else(DateTime::now);
 
     let state = metadata.state();
+    let label = project_label(&metadata);
     let project_id = cloud::api::ProjectId::new(metadata.id.to_string());
     let owner = metadata.owner;
     let name = metadata.name;
@@ -130,15 +222,20 @@ async fn download(
         metadata
             .roles
             .into_iter()
-            .map(|(id, role)| download_role(client, bucket, id, role)),
+            .map(|(id, role)| download_role(store, &label, id, role)),
     )
     .await
     .into_iter()
+    .collect::<Result<Vec<_>, _>>()?
+    .into_iter()
     .flatten()
     .collect();
 
-    assert!(!roles.is_empty(), "{:?} has no roles!", project_id);
-    cloud::Project {
+    if roles.is_empty() {
+        return Err(ProjectError::NoRoles(label));
+    }
+
+    Ok(cloud::Project {
         id: project_id,
         owner,
         name,
@@ -148,20 +245,53 @@ async fn download(
         origin_time: updated,
         save_state: SaveState::Saved,
         roles,
+    })
+}
+
+/// Copy the project data to the target store and return the new metadata.
+/// Projects which cannot be copied are skipped (and recorded in the report)
+/// rather than aborting the migration.
+async fn copy_project(
+    src: &impl ObjectStore,
+    dst: &impl ObjectStore,
+    metadata: origin::ProjectMetadata,
+    dry_run: bool,
+    report: &mut Report,
+) -> Option<cloud::ProjectMetadata> {
+    let label = project_label(&metadata);
+    let result = match download(src, metadata).await {
+        Ok(_project) if dry_run => Ok(None),
+        Ok(project) => upload(dst, project)
+            .await
+            .map(Some)
+            .map_err(|err| ProjectError::storage(&label, err)),
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(metadata) => {
+            report.count("projects (new)");
+            metadata
+        }
+        Err(err) => {
+            report.skip(err.to_string());
+            None
+        }
     }
 }
 
 async fn download_role(
-    client: &s3::Client,
-    bucket: &str,
+    store: &impl ObjectStore,
+    label: &str,
     id: String,
     role_md: origin::RoleMetadata,
-) -> Option<(cloud::api::RoleId, cloud::api::RoleData)> {
+) -> Result<Option<(cloud::api::RoleId, cloud::api::RoleData)>, ProjectError> {
     if let (Some(code), Some(media), Some(name)) =
         (role_md.source_code, role_md.media, role_md.project_name)
     {
-        let code = download_s3(client, bucket, &code).await;
-        let media = download_s3(client, bucket, &media).await;
+        let to_project_err = |err| ProjectError::storage(label, err);
+        let code = store.get(&code).await.map_err(to_project_err)?;
+        let media = store.get(&media).await.map_err(to_project_err)?;
 
         let role = cloud::api::RoleData {
             name: name.to_owned(),
@@ -170,32 +300,48 @@ async fn download_role(
         };
         let role_id = cloud::api::RoleId::new(id.to_owned());
 
-        Some((role_id, role))
+        Ok(Some((role_id, role)))
     } else {
-        None
+        Ok(None)
     }
 }
 
+/// Count an upsert as either a new or existing document for the report
+async fn count_upsert<T>(
+    collection: &Collection<T>,
+    query: Document,
+    kind: &str,
+    report: &mut Report,
+) {
+    let exists = collection
+        .count_documents(query, None)
+        .await
+        .unwrap_or_else(|err| panic!("Unable to count {}: {:?}", kind, err))
+        > 0;
+    let action = if exists { "existing" } else { "new" };
+    report.count(&format!("{} ({})", kind, action));
+}
+
 async fn upload(
-    client: &s3::Client,
-    bucket: &str,
+    store: &impl ObjectStore,
     project: cloud::Project,
-) -> cloud::ProjectMetadata {
+) -> Result<cloud::ProjectMetadata, StorageError> {
     let role_iter = project.roles.iter();
     let owner = project.owner;
     let name = project.name;
     let role_ids = role_iter.clone().map(|(k, _value)| k.to_owned());
-    let role_data =
-        join_all(role_iter.map(|(_id, data)| upload_role(client, bucket, &owner, &name, data)))
-            .await
-            .into_iter();
+    let role_data = join_all(role_iter.map(|(_id, data)| upload_role(store, &owner, &name, data)))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
     let roles: HashMap<_, _> = role_ids.zip(role_data).collect();
 
-    cloud::ProjectMetadata {
+    Ok(cloud::ProjectMetadata {
         id: project.id,
         owner,
         name,
         collaborators: project.collaborators,
+        collaborator_roles: HashMap::new(),
         updated: project.updated,
         origin_time: project.origin_time,
         state: project.state,
@@ -203,64 +349,43 @@ async fn upload(
         delete_at: None,
         network_traces: Vec::new(),
         roles,
-    }
+        starred_by: Vec::new(),
+        tags: Vec::new(),
+        version: 0,
+    })
 }
 
 async fn upload_role(
-    client: &s3::Client,
-    bucket: &str,
+    store: &impl ObjectStore,
     owner: &str,
     project_name: &str,
     role: &cloud::api::RoleData,
-) -> cloud::RoleMetadata {
-    let is_guest = owner.starts_with('_');
-    let top_level = if is_guest { "guests" } else { "users" };
-    let basepath = format!("{}/{}/{}/{}", top_level, owner, project_name, &role.name);
-    let src_path = format!("{}/code.xml", &basepath);
-    let media_path = format!("{}/media.xml", &basepath);
-
-    upload_s3(client, bucket, &media_path, role.media.to_owned()).await;
-    upload_s3(client, bucket, &src_path, role.code.to_owned()).await;
-
-    cloud::RoleMetadata {
+) -> Result<cloud::RoleMetadata, StorageError> {
+    let to_storage_err = |err| StorageError::InvalidKey(format!("{}/{}", owner, project_name), err);
+    let basepath = s3_key_for_role(owner, project_name, &role.name).map_err(to_storage_err)?;
+    let src_path = basepath.join("code.xml").map_err(to_storage_err)?;
+    let media_path = basepath.join("media.xml").map_err(to_storage_err)?;
+
+    store
+        .put(media_path.as_str(), role.media.to_owned())
+        .await?;
+    store.put(src_path.as_str(), role.code.to_owned()).await?;
+
+    Ok(cloud::RoleMetadata {
         name: role.name.to_owned(),
-        code: src_path,
-        media: media_path,
+        code: src_path.into(),
+        media: media_path.into(),
         updated: DateTime::now(),
-    }
-}
-
-async fn upload_s3(client: &s3::Client, bucket: &str, key: &str, body: String) {
-    client
-        .put_object()
-        .bucket(bucket.to_owned())
-        .key(key)
-        .body(String::into_bytes(body).into())
-        .send()
-        .await
-        .unwrap();
-}
-
-async fn download_s3(client: &s3::Client, bucket: &str, key: &str) -> String {
-    let output = client
-        .get_object()
-        .bucket(bucket.to_owned())
-        .key(key)
-        .send()
-        .await
-        .unwrap();
-
-    let bytes: Vec<u8> = output
-        .body
-        .collect()
-        .await
-        .map(|data| data.to_vec())
-        .expect("Could not download from s3");
-
-    String::from_utf8(bytes).expect("convert u8 body to string")
+    })
 }
 
-async fn migrate_users(src_db: &Database, dst_db: &Database, target_user: Option<String>) {
+async fn migrate_users(
+    src_db: &Database,
+    dst_db: &Database,
+    target_user: Option<String>,
+    dry_run: bool,
+    report: &mut Report,
+) {
     let src_users = src_db.collection::<origin::User>("users");
     let dst_users = dst_db.collection::<cloud::User>("users");
     let count = src_users
@@ -280,6 +405,11 @@ async fn migrate_users(src_db: &Database, dst_db: &Database, target_user: Option
         let src_hash = src_user.hash.clone();
         let new_user: cloud::User = src_user.into();
         let query = doc! {"username": &new_user.username};
+        if dry_run {
+            count_upsert(&dst_users, query, "users", report).await;
+            progress.inc(1);
+            continue;
+        }
 
         // set the user password to the current password on editor
         // (set the salt to None and keep the hash)
@@ -326,6 +456,13 @@ async fn migrate_users(src_db: &Database, dst_db: &Database, target_user: Option
 
     while let Some(group) = cursor.next().await {
         let group = group.expect("Unable to retrieve group");
+        if dry_run {
+            let query = doc! {"id": group.id.to_string()};
+            count_upsert(&dst_groups, query, "groups", report).await;
+            progress.inc(1);
+            continue;
+        }
+
         if let Some(usernames) = group.members.clone() {
             for name in usernames {
                 let query = doc! {"username": &name};
@@ -359,7 +496,12 @@ async fn migrate_users(src_db: &Database, dst_db: &Database, target_user: Option
     drop(dst_groups);
 }
 
-async fn migrate_libraries(src_db: &Database, dst_db: &Database) {
+async fn migrate_libraries(
+    src_db: &Database,
+    dst_db: &Database,
+    dry_run: bool,
+    report: &mut Report,
+) {
     let src_libraries = src_db.collection::<origin::Library>("libraries");
     let dst_libraries = dst_db.collection::<cloud::Library>("libraries");
 
@@ -379,6 +521,12 @@ async fn migrate_libraries(src_db: &Database, dst_db: &Database) {
             "owner": &new_lib.owner,
             "name": &new_lib.name
         };
+        if dry_run {
+            count_upsert(&dst_libraries, query, "libraries", report).await;
+            progress.inc(1);
+            continue;
+        }
+
         let update = doc! {"$setOnInsert": &new_lib};
         let opts = UpdateOptions::builder().upsert(true).build();
         dst_libraries.update_one(query, update, opts).await.unwrap();
@@ -391,7 +539,12 @@ async fn migrate_libraries(src_db: &Database, dst_db: &Database) {
     drop(dst_libraries);
 }
 
-async fn migrate_banned_accts(src_db: &Database, dst_db: &Database) {
+async fn migrate_banned_accts(
+    src_db: &Database,
+    dst_db: &Database,
+    dry_run: bool,
+    report: &mut Report,
+) {
     let src_bans = src_db.collection::<origin::BannedAccount>("bannedAccounts");
     let dst_bans = dst_db.collection::<cloud::BannedAccount>("bannedAccounts");
 
@@ -410,6 +563,12 @@ async fn migrate_banned_accts(src_db: &Database, dst_db: &Database) {
         let query = doc! {
             "username": &new_acct.username,
         };
+        if dry_run {
+            count_upsert(&dst_bans, query, "banned accounts", report).await;
+            progress.inc(1);
+            continue;
+        }
+
         let update = doc! {"$setOnInsert": &new_acct};
         let opts = UpdateOptions::builder().upsert(true).build();
         dst_bans.update_one(query, update, opts).await.unwrap();
@@ -425,11 +584,27 @@ async fn migrate_projects(
     src_db: &Database,
     dst_db: &Database,
     user: Option<String>,
+    checkpoint: &mut Checkpoint,
+    dry_run: bool,
+    report: &mut Report,
 ) {
     let src_projects = src_db.collection::<origin::ProjectMetadata>("projects");
     let dst_projects = dst_db.collection::<cloud::ProjectMetadata>("projects");
-    let src_s3 = get_s3_client(&config.source.s3);
-    let dst_s3 = get_s3_client(&config.target.s3);
+    let concurrency = config.concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+    let retries = config.retries.unwrap_or(DEFAULT_RETRIES);
+    // retry outside of the concurrency limit so backoff doesn't hold a permit
+    let src_s3 = S3Store::new(
+        get_s3_client(&config.source.s3),
+        config.source.s3.bucket.clone(),
+    );
+    let src_s3 = LimitedStore::new(src_s3, concurrency);
+    let src_s3 = RetryStore::new(src_s3, retries, RETRY_BACKOFF);
+    let dst_s3 = S3Store::new(
+        get_s3_client(&config.target.s3),
+        config.target.s3.bucket.clone(),
+    );
+    let dst_s3 = LimitedStore::new(dst_s3, concurrency);
+    let dst_s3 = RetryStore::new(dst_s3, retries, RETRY_BACKOFF);
 
     let query = if let Some(username) = user {
         doc! {
@@ -439,6 +614,7 @@ async fn migrate_projects(
     } else {
         doc! {"transient": false} // FIXME: what if transient isn't set
     };
+    let query = checkpoint.resume_query("projects", query);
     let count = src_projects
         .count_documents(query.clone(), None)
         .await
@@ -446,39 +622,292 @@ async fn migrate_projects(
     let progress = ProgressBar::new(count);
     progress.println("Migrating projects...");
 
-    let mut cursor = src_projects.find(query, None).await.unwrap();
+    // process the projects in order so the checkpoint can be used to resume
+    let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+    let mut cursor = src_projects.find(query, options).await.unwrap();
 
     while let Some(metadata) = cursor.next().await {
         let metadata = metadata.unwrap();
+        let id = metadata.id;
         let query = doc! {
             "owner": &metadata.owner,
             "name": &metadata.name,
         };
         let dst_project = dst_projects.find_one(query.clone(), None).await.unwrap();
+        let mut migrated = true;
         let needs_throttle = if let Some(dst_proj) = dst_project {
             // check the public state
             let state = metadata.state();
             if state != dst_proj.state {
-                let update = doc! {"$set": {"state": state}};
-                dst_projects.update_one(query, update, None).await.unwrap();
-                true
+                report.count("projects (updated)");
+                if !dry_run {
+                    let update = doc! {"$set": {"state": state}};
+                    dst_projects.update_one(query, update, None).await.unwrap();
+                }
+                !dry_run
             } else {
+                report.count("projects (unchanged)");
                 false
             }
         } else {
-            let project = download(&src_s3, &config.source.s3.bucket, metadata).await;
-            let metadata = upload(&dst_s3, &config.target.s3.bucket, project).await;
-            dst_projects.insert_one(&metadata, None).await.unwrap();
+            let copied = copy_project(&src_s3, &dst_s3, metadata, dry_run, report).await;
+            if let Some(metadata) = copied {
+                dst_projects.insert_one(&metadata, None).await.unwrap();
+            } else {
+                migrated = false;
+            }
             true
         };
 
         progress.inc(1);
+        // a dry run shouldn't affect where a later migration resumes
+        if !dry_run {
+            if migrated {
+                checkpoint.record("projects", id);
+            } else {
+                checkpoint.skip("projects", id);
+            }
+            if progress.position().is_multiple_of(CHECKPOINT_INTERVAL) {
+                checkpoint.save().expect("Unable to save checkpoint");
+            }
+        }
 
         if needs_throttle {
             // throttle to about 2k req/sec to avoid 503 errors from AWS
             thread::sleep(Duration::from_millis(config.sleep.unwrap_or(10)));
         }
     }
+    if !dry_run {
+        checkpoint.save().expect("Unable to save checkpoint");
+    }
     progress.println("Project migration complete.");
     progress.finish();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+
+    /// In-memory object store which records the max number of concurrent operations
+    #[derive(Default)]
+    struct MockStore {
+        objects: Mutex<HashMap<String, String>>,
+        active: AtomicUsize,
+        max_active: AtomicUsize,
+    }
+
+    impl MockStore {
+        async fn track<T>(&self, op: impl FnOnce() -> T) -> T {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let result = op();
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+    }
+
+    impl ObjectStore for MockStore {
+        async fn put(&self, key: &str, body: String) -> Result<(), StorageError> {
+            self.track(|| {
+                self.objects.lock().unwrap().insert(key.to_owned(), body);
+            })
+            .await;
+            Ok(())
+        }
+
+        async fn get(&self, key: &str) -> Result<String, StorageError> {
+            self.track(|| self.objects.lock().unwrap().get(key).cloned())
+                .await
+                .ok_or_else(|| StorageError::NotFound(key.to_owned()))
+        }
+    }
+
+    fn project(role_count: usize) -> cloud::Project {
+        let roles = (0..role_count)
+            .map(|i| {
+                let role = cloud::api::RoleData {
+                    name: format!("role{}", i),
+                    code: format!("<project name=\"role{}\"/>", i),
+                    media: "<media/>".into(),
+                };
+                (cloud::api::RoleId::new(format!("id{}", i)), role)
+            })
+            .collect();
+
+        cloud::Project {
+            id: cloud::api::ProjectId::new("someProject".into()),
+            owner: "brian".into(),
+            name: "myProject".into(),
+            updated: DateTime::now(),
+            state: cloud::api::PublishState::Private,
+            collaborators: Vec::new(),
+            origin_time: DateTime::now(),
+            save_state: SaveState::Saved,
+            roles,
+        }
+    }
+
+    fn origin_project(roles: HashMap<String, origin::RoleMetadata>) -> origin::ProjectMetadata {
+        origin::ProjectMetadata {
+            id: ObjectId::new(),
+            name: "myProject".into(),
+            owner: "brian".into(),
+            collaborators: Vec::new(),
+            roles,
+            last_update_at: None,
+            public: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_concurrency_limit() {
+        let store = LimitedStore::new(MockStore::default(), 3);
+
+        let metadata = upload(&store, project(10)).await.unwrap();
+
+        assert_eq!(metadata.roles.len(), 10);
+        let max_active = store.inner().max_active.load(Ordering::SeqCst);
+        assert!(max_active <= 3, "{} concurrent operations", max_active);
+        assert!(max_active > 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_concurrency_limit() {
+        let store = LimitedStore::new(MockStore::default(), 2);
+        let uploaded = upload(&store, project(10)).await.unwrap();
+        store.inner().max_active.store(0, Ordering::SeqCst);
+
+        let roles = uploaded
+            .roles
+            .into_iter()
+            .map(|(id, role)| {
+                let role_md = origin::RoleMetadata {
+                    project_name: Some(role.name),
+                    source_code: Some(role.code),
+                    media: Some(role.media),
+                };
+                (id.as_str().to_owned(), role_md)
+            })
+            .collect();
+        let metadata = origin_project(roles);
+
+        let project = download(&store, metadata).await.unwrap();
+
+        assert_eq!(project.roles.len(), 10);
+        let max_active = store.inner().max_active.load(Ordering::SeqCst);
+        assert!(max_active <= 2, "{} concurrent operations", max_active);
+    }
+
+    #[tokio::test]
+    async fn test_download_no_roles() {
+        let store = MockStore::default();
+        let metadata = origin_project(HashMap::new());
+        let id = metadata.id;
+
+        let err = download(&store, metadata).await.unwrap_err();
+
+        assert!(matches!(err, ProjectError::NoRoles(_)));
+        let expected = format!("Project {} (brian/myProject) has no roles", id);
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_download_missing_object() {
+        let store = MockStore::default();
+        store.put("code.xml", "<project/>".into()).await.unwrap();
+        let role_md = origin::RoleMetadata {
+            project_name: Some("myRole".into()),
+            source_code: Some("code.xml".into()),
+            media: Some("media.xml".into()),
+        };
+        let roles = HashMap::from([("someRole".to_owned(), role_md)]);
+
+        let err = download(&store, origin_project(roles)).await.unwrap_err();
+
+        match err {
+            ProjectError::MissingObject(_, key) => assert_eq!(key, "media.xml"),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_project_skip_missing_media() {
+        let src = MockStore::default();
+        let dst = MockStore::default();
+        let uploaded = upload(&src, project(2)).await.unwrap();
+        let roles: HashMap<_, _> = uploaded
+            .roles
+            .into_iter()
+            .map(|(id, role)| {
+                let role_md = origin::RoleMetadata {
+                    project_name: Some(role.name),
+                    source_code: Some(role.code),
+                    media: Some(role.media),
+                };
+                (id.as_str().to_owned(), role_md)
+            })
+            .collect();
+        let broken_roles = roles
+            .iter()
+            .map(|(id, role_md)| {
+                let role_md = origin::RoleMetadata {
+                    project_name: role_md.project_name.clone(),
+                    source_code: role_md.source_code.clone(),
+                    media: Some(format!("missing/{}/media.xml", id)),
+                };
+                (id.to_owned(), role_md)
+            })
+            .collect();
+
+        let mut report = Report::default();
+        let broken = origin_project(broken_roles);
+        let broken_id = broken.id;
+        let copied = copy_project(&src, &dst, broken, false, &mut report).await;
+        assert!(copied.is_none());
+        assert!(dst.objects.lock().unwrap().is_empty());
+
+        // later projects are still migrated
+        let copied = copy_project(&src, &dst, origin_project(roles), false, &mut report).await;
+        assert_eq!(copied.unwrap().roles.len(), 2);
+        assert_eq!(report.get_count("projects (new)"), 1);
+        assert_eq!(report.skipped().len(), 1);
+        assert!(report.skipped()[0].contains(&broken_id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_upload_escape_malicious_names() {
+        let names = ["..", "../../guests/alice", "role/../../other"];
+        for name in names {
+            let store = MockStore::default();
+            let mut project = project(1);
+            project.name = name.into();
+
+            upload(&store, project).await.unwrap();
+
+            let objects = store.objects.lock().unwrap();
+            assert_eq!(objects.len(), 2);
+            for key in objects.keys() {
+                let segments: Vec<_> = key.split('/').collect();
+                assert_eq!(&segments[..2], &["users", "brian"], "{}", key);
+                assert_eq!(segments.len(), 5, "{}", key);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_reject_empty_name() {
+        let store = MockStore::default();
+        let mut project = project(1);
+        project.name = "".into();
+
+        let err = upload(&store, project).await.unwrap_err();
+
+        assert!(matches!(err, StorageError::InvalidKey(..)));
+        assert!(store.objects.lock().unwrap().is_empty());
+    }
+}
@@ -52,6 +52,9 @@ impl From<User> for cloud::User {
                     .collect::<Vec<_>>()
             }),
             service_settings: HashMap::new(),
+            delete_at: None,
+            verified: false,
+            two_factor: None,
         }
     }
 }
@@ -117,6 +120,7 @@ impl From<Library> for cloud::Library {
             blocks: lib.blocks,
             notes: lib.notes,
             state,
+            downloads: 0,
         }
     }
 }
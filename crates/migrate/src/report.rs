@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, fmt};
+
+/// Summary of the actions taken (or that would be taken, for a dry run) and any
+/// documents skipped due to anomalies in the source data.
+#[derive(Default)]
+pub(crate) struct Report {
+    counts: BTreeMap<String, u64>,
+    skipped: Vec<String>,
+}
+
+impl Report {
+    /// Record an action (such as "users (new)")
+    pub(crate) fn count(&mut self, action: &str) {
+        *self.counts.entry(action.to_owned()).or_default() += 1;
+    }
+
+    /// Record a document which could not be migrated (and why)
+    pub(crate) fn skip(&mut self, reason: String) {
+        self.skipped.push(reason);
+    }
+
+    pub(crate) fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get_count(&self, action: &str) -> u64 {
+        self.counts.get(action).copied().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Actions:")?;
+        if self.counts.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+        for (action, count) in &self.counts {
+            writeln!(f, "  {}: {}", action, count)?;
+        }
+
+        if !self.skipped.is_empty() {
+            writeln!(f, "Skipped ({}):", self.skipped.len())?;
+            for reason in &self.skipped {
+                writeln!(f, "  {}", reason)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_actions() {
+        let mut report = Report::default();
+        report.count("users (new)");
+        report.count("users (new)");
+        report.count("projects (new)");
+
+        assert_eq!(report.get_count("users (new)"), 2);
+        assert_eq!(report.get_count("projects (new)"), 1);
+        assert_eq!(report.get_count("libraries (new)"), 0);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut report = Report::default();
+        report.count("users (new)");
+        report.count("projects (new)");
+        report.skip("Project abc (brian/myProject) has no roles".into());
+
+        let expected = "Actions:\n  projects (new): 1\n  users (new): 1\n\
+            Skipped (1):\n  Project abc (brian/myProject) has no roles\n";
+        assert_eq!(report.to_string(), expected);
+    }
+
+    #[test]
+    fn test_display_empty() {
+        let report = Report::default();
+        assert_eq!(report.to_string(), "Actions:\n  (none)\n");
+    }
+}
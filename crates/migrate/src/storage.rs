@@ -0,0 +1,243 @@
+use std::{future::Future, time::Duration};
+
+use aws_sdk_s3 as s3;
+use cloud::api::s3::S3KeyError;
+use derive_more::Display;
+use netsblox_cloud_common as cloud;
+use tokio::sync::Semaphore;
+
+#[derive(Debug, Display)]
+pub(crate) enum StorageError {
+    #[display(fmt = "{} not found", _0)]
+    NotFound(String),
+    #[display(fmt = "Request for {} failed: {}", _0, _1)]
+    RequestFailed(String, String),
+    #[display(fmt = "Invalid key for {}: {}", _0, _1)]
+    InvalidKey(String, S3KeyError),
+}
+
+/// Storage for the project data (code, media)
+pub(crate) trait ObjectStore {
+    async fn put(&self, key: &str, body: String) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<String, StorageError>;
+}
+
+pub(crate) struct S3Store {
+    client: s3::Client,
+    bucket: String,
+}
+
+impl S3Store {
+    pub(crate) fn new(client: s3::Client, bucket: String) -> Self {
+        Self { client, bucket }
+    }
+}
+
+impl ObjectStore for S3Store {
+    async fn put(&self, key: &str, body: String) -> Result<(), StorageError> {
+        self.client
+            .put_object()
+            .bucket(self.bucket.to_owned())
+            .key(key)
+            .body(String::into_bytes(body).into())
+            .send()
+            .await
+            .map_err(|err| StorageError::RequestFailed(key.to_owned(), err.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<String, StorageError> {
+        let result = self
+            .client
+            .get_object()
+            .bucket(self.bucket.to_owned())
+            .key(key)
+            .send()
+            .await;
+
+        let output = match result.map_err(|err| err.into_service_error()) {
+            Ok(output) => output,
+            Err(err) if err.is_no_such_key() => return Err(StorageError::NotFound(key.to_owned())),
+            Err(err) => return Err(StorageError::RequestFailed(key.to_owned(), err.to_string())),
+        };
+
+        let bytes: Vec<u8> = output
+            .body
+            .collect()
+            .await
+            .map(|data| data.to_vec())
+            .map_err(|err| StorageError::RequestFailed(key.to_owned(), err.to_string()))?;
+
+        String::from_utf8(bytes)
+            .map_err(|err| StorageError::RequestFailed(key.to_owned(), err.to_string()))
+    }
+}
+
+/// Object store which limits the number of concurrent operations (across all
+/// projects and roles) to avoid 503 errors from S3.
+pub(crate) struct LimitedStore<S> {
+    store: S,
+    permits: Semaphore,
+}
+
+impl<S> LimitedStore<S> {
+    pub(crate) fn new(store: S, max_concurrency: usize) -> Self {
+        Self {
+            store,
+            permits: Semaphore::new(max_concurrency.max(1)),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn inner(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: ObjectStore> ObjectStore for LimitedStore<S> {
+    async fn put(&self, key: &str, body: String) -> Result<(), StorageError> {
+        let _permit = self.permits.acquire().await.expect("Semaphore closed");
+        self.store.put(key, body).await
+    }
+
+    async fn get(&self, key: &str) -> Result<String, StorageError> {
+        let _permit = self.permits.acquire().await.expect("Semaphore closed");
+        self.store.get(key).await
+    }
+}
+
+/// Object store which retries failed requests (such as 503 errors from S3)
+/// with exponential backoff.
+pub(crate) struct RetryStore<S> {
+    store: S,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+impl<S> RetryStore<S> {
+    pub(crate) fn new(store: S, max_retries: u32, backoff: Duration) -> Self {
+        Self {
+            store,
+            max_retries,
+            backoff,
+        }
+    }
+
+    async fn retry<T, F>(&self, mut op: impl FnMut() -> F) -> Result<T, StorageError>
+    where
+        F: Future<Output = Result<T, StorageError>>,
+    {
+        let mut delay = self.backoff;
+        let mut attempts = 0;
+        loop {
+            match op().await {
+                Err(StorageError::RequestFailed(..)) if attempts < self.max_retries => {
+                    attempts += 1;
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: ObjectStore> ObjectStore for RetryStore<S> {
+    async fn put(&self, key: &str, body: String) -> Result<(), StorageError> {
+        self.retry(|| self.store.put(key, body.clone())).await
+    }
+
+    async fn get(&self, key: &str) -> Result<String, StorageError> {
+        self.retry(|| self.store.get(key)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Store which fails the first few requests before succeeding
+    struct FlakyStore {
+        failures: u32,
+        attempts: AtomicU32,
+    }
+
+    impl FlakyStore {
+        fn new(failures: u32) -> Self {
+            Self {
+                failures,
+                attempts: AtomicU32::new(0),
+            }
+        }
+
+        fn attempt(&self, key: &str) -> Result<(), StorageError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+            if attempt < self.failures {
+                Err(StorageError::RequestFailed(key.to_owned(), "503".into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl ObjectStore for FlakyStore {
+        async fn put(&self, key: &str, _body: String) -> Result<(), StorageError> {
+            self.attempt(key)
+        }
+
+        async fn get(&self, key: &str) -> Result<String, StorageError> {
+            self.attempt(key).map(|_| "<project/>".to_owned())
+        }
+    }
+
+    fn retry_store(failures: u32, max_retries: u32) -> RetryStore<FlakyStore> {
+        RetryStore::new(
+            FlakyStore::new(failures),
+            max_retries,
+            Duration::from_millis(1),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let store = retry_store(2, 3);
+
+        let code = store.get("code.xml").await.unwrap();
+
+        assert_eq!(code, "<project/>");
+        assert_eq!(store.store.attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted() {
+        let store = retry_store(5, 3);
+
+        let result = store.put("code.xml", "<project/>".into()).await;
+
+        assert!(matches!(result, Err(StorageError::RequestFailed(..))));
+        assert_eq!(store.store.attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_not_found() {
+        struct EmptyStore(AtomicU32);
+        impl ObjectStore for EmptyStore {
+            async fn put(&self, _key: &str, _body: String) -> Result<(), StorageError> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> Result<String, StorageError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(StorageError::NotFound(key.to_owned()))
+            }
+        }
+        let store = RetryStore::new(EmptyStore(AtomicU32::new(0)), 3, Duration::from_millis(1));
+
+        let result = store.get("code.xml").await;
+
+        assert!(matches!(result, Err(StorageError::NotFound(_))));
+        assert_eq!(store.store.0.load(Ordering::SeqCst), 1);
+    }
+}